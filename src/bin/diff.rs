@@ -0,0 +1,166 @@
+use std::{env, process::exit};
+extern crate fits;
+
+use fits::header::Header;
+use fits::BasicFits;
+
+// At most this many differing pixels are listed, the rest are only counted
+const MAX_PIXELS: usize = 20;
+
+fn usage() -> ! {
+    println!("Usage: diff <first.fits> <second.fits> [--tolerance <value>]");
+    exit(2);
+}
+
+fn open(filename: &String) -> BasicFits {
+    match BasicFits::open(filename) {
+        Ok(fits) => fits,
+        Err(e) => {
+            println!(
+                "Something went wrong while reading the file {}: {}",
+                filename, e
+            );
+            exit(2);
+        }
+    }
+}
+
+// Keywords (other than COMMENT and HISTORY) that are missing from one of the headers or have
+// a different value
+fn diff_keywords(a: &Header, b: &Header, names: (&str, &str)) -> Vec<String> {
+    let mut lines = Vec::new();
+    for (name, value, _comment) in a.value_keywords() {
+        match b.get_value(name) {
+            Some(other) if other == value => (),
+            Some(other) => lines.push(format!(
+                "KEYWORD {}: {} vs {}",
+                name,
+                value.to_string().trim(),
+                other.to_string().trim()
+            )),
+            None => lines.push(format!("KEYWORD {}: only in {}", name, names.0)),
+        }
+    }
+    for (name, _value, _comment) in b.value_keywords() {
+        if a.get_value(name).is_none() {
+            lines.push(format!("KEYWORD {}: only in {}", name, names.1));
+        }
+    }
+    lines
+}
+
+// Pixels whose physical values differ by more than `tolerance`, or that are NaN (blank) in only
+// one of the files. Pixels are given as FITS coordinates, starting at 1 with NAXIS1 first.
+fn diff_data(a: &BasicFits, b: &BasicFits, tolerance: f64) -> Vec<String> {
+    if a.header.axes != b.header.axes {
+        return vec![format!(
+            "DATA shape: {:?} vs {:?}",
+            a.header.axes, b.header.axes
+        )];
+    }
+
+    let (x, y) = (a.physical_data(), b.physical_data());
+    let diff = &x - &y;
+    let mut lines = Vec::new();
+    let mut count = 0;
+    for (i, d) in diff.iter().enumerate() {
+        if d.abs() > tolerance || x[i].is_nan() != y[i].is_nan() {
+            count += 1;
+            if count <= MAX_PIXELS {
+                let pixel = coordinates(i, &a.header.axes);
+                lines.push(format!("PIXEL {:?}: {} vs {}", pixel, x[i], y[i]));
+            }
+        }
+    }
+    if count > MAX_PIXELS {
+        lines.push(format!("... {} more pixels", count - MAX_PIXELS));
+    }
+    if count > 0 {
+        lines.push(format!(
+            "DATA: {} of {} pixels differ by more than {}",
+            count,
+            diff.len(),
+            tolerance
+        ));
+    }
+    lines
+}
+
+// The FITS coordinates of the `index`th value of the data
+fn coordinates(mut index: usize, axes: &[usize]) -> Vec<usize> {
+    axes.iter()
+        .map(|n| {
+            let coordinate = index % n + 1;
+            index /= n;
+            coordinate
+        })
+        .collect()
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let mut filenames = Vec::new();
+    let mut tolerance = 0.;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if arg == "--tolerance" {
+            tolerance = match args.next().map(|t| t.parse::<f64>()) {
+                Some(Ok(t)) if t >= 0. => t,
+                _ => usage(),
+            };
+        } else {
+            filenames.push(arg);
+        }
+    }
+    let [first, second] = filenames[..] else {
+        usage();
+    };
+
+    let (a, b) = (open(first), open(second));
+    let mut lines = diff_keywords(&a.header, &b.header, (first, second));
+    lines.extend(diff_data(&a, &b, tolerance));
+
+    for line in lines.iter() {
+        println!("{}", line);
+    }
+    if !lines.is_empty() {
+        exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn diff_keywords_test() {
+        let axes = "SIMPLE  =                    T\n\
+                    BITPIX  =                  -64\n\
+                    NAXIS   =                    0\n";
+        let a = Header::from_fits_text(&format!(
+            "{}EXPTIME =                300.0\nOBSERVER= 'Hubble'\nHISTORY one",
+            axes
+        ))
+        .unwrap();
+        let b = Header::from_fits_text(&format!(
+            "{}EXPTIME =                600.0\nFILTER  = 'R'\nHISTORY two",
+            axes
+        ))
+        .unwrap();
+        assert_eq!(
+            diff_keywords(&a, &b, ("a.fits", "b.fits")),
+            [
+                "KEYWORD EXPTIME: 300.0 vs 600.0",
+                "KEYWORD OBSERVER: only in a.fits",
+                "KEYWORD FILTER: only in b.fits",
+            ]
+        );
+        assert!(diff_keywords(&a, &a, ("a.fits", "a.fits")).is_empty());
+    }
+
+    #[test]
+    fn coordinates_test() {
+        assert_eq!(coordinates(0, &[3, 2]), [1, 1]);
+        assert_eq!(coordinates(4, &[3, 2]), [2, 2]);
+    }
+}
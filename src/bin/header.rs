@@ -0,0 +1,147 @@
+use std::{env, process::exit};
+extern crate fits;
+
+use fits::parsing::header::{Keyword, Value};
+
+#[derive(Debug, PartialEq)]
+enum Format {
+    Table,
+    Json,
+    Csv,
+}
+
+fn usage() -> ! {
+    println!("Usage: header <file.fits> [--keyword <name>] [--format table|json|csv]");
+    exit(1);
+}
+
+// The name, value and comment of a keyword as text. COMMENT and HISTORY records have no
+// value, their text is the comment.
+fn fields(keyword: &Keyword) -> (&str, String, &str) {
+    let value = |value: &Value| match value {
+        Value::Undefined => String::new(),
+        value => value.to_string().trim().to_string(),
+    };
+    match keyword {
+        Keyword::History(text) | Keyword::Comment(text) => {
+            (keyword.keyword_name(), String::new(), text)
+        }
+        Keyword::Value(name, v, comment)
+        | Keyword::Continue(name, v, comment)
+        | Keyword::Hierarch(name, v, comment) => (name, value(v), comment),
+    }
+}
+
+// Aligned columns: name (8 characters), value (20) and comment (40)
+fn table_row(keyword: &Keyword) -> String {
+    let (name, value, comment) = fields(keyword);
+    format!("{:<8} {:<20} {:<40}", name, value, comment)
+        .trim_end()
+        .to_string()
+}
+
+// Fields are quoted when they contain a separator or quote, quotes are doubled
+fn csv_row(keyword: &Keyword) -> String {
+    let (name, value, comment) = fields(keyword);
+    let quote = |field: &str| {
+        if field.contains([',', '"', '\n']) {
+            format!("\"{}\"", field.replace('"', "\"\""))
+        } else {
+            field.to_string()
+        }
+    };
+    format!("{},{},{}", quote(name), quote(&value), quote(comment))
+}
+
+#[cfg(feature = "serde")]
+fn json(keywords: &[&Keyword]) -> String {
+    serde_json::to_string_pretty(keywords).expect("keywords can always be serialized")
+}
+
+#[cfg(not(feature = "serde"))]
+fn json(_keywords: &[&Keyword]) -> String {
+    println!("JSON output requires the serde feature");
+    exit(1);
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let mut filename = None;
+    let mut name = None;
+    let mut format = Format::Table;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--keyword" => name = Some(args.next().unwrap_or_else(|| usage())),
+            "--format" => {
+                format = match args.next().map(String::as_str) {
+                    Some("table") => Format::Table,
+                    Some("json") => Format::Json,
+                    Some("csv") => Format::Csv,
+                    _ => usage(),
+                }
+            }
+            _ if filename.is_none() => filename = Some(arg),
+            _ => usage(),
+        }
+    }
+    let Some(filename) = filename else {
+        usage();
+    };
+
+    let header = match fits::BasicFits::open(filename) {
+        Ok(fits) => fits.header,
+        Err(e) => {
+            println!(
+                "Something went wrong while reading the file {}: {}",
+                filename, e
+            );
+            exit(1);
+        }
+    };
+    let keywords: Vec<&Keyword> = header
+        .iter()
+        .filter(|kw| name.is_none_or(|name| kw.keyword_name().eq_ignore_ascii_case(name)))
+        .collect();
+
+    match format {
+        Format::Table => keywords.iter().for_each(|kw| println!("{}", table_row(kw))),
+        Format::Csv => {
+            println!("name,value,comment");
+            keywords.iter().for_each(|kw| println!("{}", csv_row(kw)));
+        }
+        Format::Json => println!("{}", json(&keywords)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keyword(name: &str, value: Value, comment: &str) -> Keyword {
+        Keyword::Value(name.to_string(), value, comment.to_string())
+    }
+
+    #[test]
+    fn table_row_test() {
+        let row = table_row(&keyword("NAXIS", Value::Integer(2), "number of axes"));
+        assert_eq!(
+            row,
+            format!("{:<8} {:<20} {}", "NAXIS", "2", "number of axes")
+        );
+        let row = table_row(&keyword("OBJECT", Value::Str("M31".to_string()), ""));
+        assert_eq!(row, "OBJECT   'M31'");
+        let row = table_row(&Keyword::History("reduced".to_string()));
+        assert_eq!(row, format!("HISTORY  {:<20} reduced", ""));
+    }
+
+    #[test]
+    fn csv_row_test() {
+        let row = csv_row(&keyword("EXPTIME", Value::Float(30.0), "seconds"));
+        assert_eq!(row, "EXPTIME,30.0,seconds");
+        let row = csv_row(&keyword("OBJECT", Value::Str("M31".to_string()), "a, b"));
+        assert_eq!(row, "OBJECT,'M31',\"a, b\"");
+        let row = csv_row(&Keyword::Comment("say \"hi\"".to_string()));
+        assert_eq!(row, "COMMENT,,\"say \"\"hi\"\"\"");
+    }
+}
@@ -1,6 +1,7 @@
 use std::{env, process::exit};
 
 extern crate fits;
+use fits::stats;
 use ndarray::Array2;
 use viuer::Config;
 
@@ -25,8 +26,8 @@ mod image_util {
 
     // Normalized the f64 array to be between MIN_GRAY and MAX_GRAY
     pub fn normalize(data: &Array2<f64>) -> Array2<f64> {
-        let max = data.iter().fold(f64::MAX, |a, &b| a.min(b));
-        let min = data.iter().fold(f64::MIN, |a, &b| a.max(b));
+        let min = data.iter().fold(f64::INFINITY, |a, &b| a.min(b));
+        let max = data.iter().fold(f64::NEG_INFINITY, |a, &b| a.max(b));
         let norm = (data - min) / (max - min);
         norm*(MAX_GRAY - MIN_GRAY) + MIN_GRAY
     }
@@ -89,56 +90,72 @@ fn main() {
         exit(1);
     };
 
-    if let Some(fits) = fits::BasicFits::open(&filename) {
-        let h = fits.header;
-
-        // h.print_keywords();
-        println!("File {}: ", filename);
-        println!(" ");
-        // println!("SIMPLE {}", h.simple);
-        // println!("BITPIX {:?}", h.bitpix);
-        println!("NAXIS  {}", h.naxis.get());
-        println!("Axes   {:?}", h.axes);
-
-        // Calculate some basic statistics of the data:
-        let data = &fits.data;
-        let sum: f64 = data.into_iter().sum::<f64>();
-        let avg: f64 = sum / data.len() as f64;
-        let rem = data - avg;
-        let var: f64 = (&rem * &rem).into_iter().sum::<f64>() / data.len() as f64;
-        let min = data.fold(f64::MAX, |a, &b| a.min(b));
-        let max = data.fold(f64::MIN, |a, &b| a.max(b));
-
-        println!("-------Data Stuff:");
-        println!("Sum: {:.2e}", sum);
-        println!("Avg: {:.2e}", avg);
-        println!("Std: {:.2e}", var.sqrt());
-        println!("Min / max : {} / {}", min, max);
-        println!("IMAGE:");
-
-
-        if *h.naxis.get() == 2 {
-            let axis = (h.axes[0], h.axes[1]);
-            let data2d = data.clone().into_shape(axis).unwrap();
-            
-            // let data2d = data2d; // normalize to 0
-                                       
-            // TODO: find a nice scheme to automatically normalize the image
-            // something like ZScale (is complicated), or cutting percentiles (requires)
-            // a histogram implementation.
-            let vmin = 1000.;
-            let vmax = 10000.;
-            let data2d = data2d.map(|e| e.clamp(vmin, vmax));
-            let data2d = data2d.map(|x| (1. + x).log10()); // Log1p
-
-            plot_image_term(&data2d);
+    match fits::BasicFits::open(&filename) {
+        Ok(fits) => {
+            let h = &fits.header;
+
+            // h.print_keywords();
+            println!("File {}: ", filename);
+            println!(" ");
+            // println!("SIMPLE {}", h.simple);
+            // println!("BITPIX {:?}", h.bitpix);
+            println!("NAXIS  {}", h.naxis());
+            println!("Axes   {:?}", h.axes);
+
+            // Calculate some basic statistics of the data:
+            let data = &fits.data.to_f64();
+            let sum = fits.data_sum();
+
+            println!("-------Data Stuff:");
+            println!("Sum: {:.2e}", sum);
+            println!("Avg: {:.2e}", stats::mean(data));
+            println!("Std: {:.2e}", stats::std_dev(data));
+            println!("Median: {:.2e}", stats::median(data));
+            if data.iter().any(|x| !x.is_nan()) {
+                println!("Min / max : {} / {}", stats::min(data), stats::max(data));
+            }
+            println!("IMAGE:");
+
+
+            if let Ok(data2d) = fits.reshape_to_2d() {
+                
+                // let data2d = data2d; // normalize to 0
+                                           
+                // TODO: find a nice scheme to automatically normalize the image
+                // something like ZScale (is complicated), or cutting percentiles (requires)
+                // a histogram implementation.
+                let vmin = 1000.;
+                let vmax = 10000.;
+                let data2d = data2d.map(|e| e.clamp(vmin, vmax));
+                let data2d = data2d.map(|x| (1. + x).log10()); // Log1p
+
+                plot_image_term(&data2d);
+            }
+        }
+        Err(e) => {
+            println!(
+                "Something went wrong while reading the file {}: {}",
+                filename, e
+            );
+            exit(1);
         }
-    } else {
-        println!(
-            "Something went wrong while reading the file {}...",
-            filename
-        );
-        exit(1);
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalize_test() {
+        let data = Array2::from_shape_vec((2, 2), vec![10., 20., 30., 50.]).unwrap();
+        let norm = image_util::normalize(&data);
+        assert_eq!(norm[[0, 0]], 0.);
+        assert_eq!(norm[[1, 1]], 255.);
+        assert_eq!(norm[[0, 1]], 255. / 4.);
+
+        let data = Array2::from_shape_vec((1, 2), vec![1., 5.]).unwrap();
+        let norm = image_util::normalize(&data);
+        assert_eq!(norm.into_raw_vec(), [0., 255.]);
+    }
+}
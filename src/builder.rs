@@ -0,0 +1,353 @@
+use std::ops::Range;
+use std::sync::Arc;
+
+use ndarray::s;
+
+use crate::header::{is_mandatory_keyword, wrap_text, Bitpix, Header, TEXT_SIZE};
+use crate::image::{from_array2, to_array2};
+use crate::parsing::header::{set_keyword, validate_keyword_name, Keyword, Value};
+use crate::{BasicFits, DataArray, FitsError, GenericData, KeywordList};
+
+/// Edit a copy of an existing FITS file, e.g. to change keywords or crop the image,
+/// without touching the original.
+pub struct FitsBuilder {
+    keywords: KeywordList,
+    axes: Vec<usize>,
+    data: DataArray,
+    modified: Vec<String>,
+}
+
+impl FitsBuilder {
+    pub fn from_existing(fits: &BasicFits) -> Self {
+        FitsBuilder {
+            keywords: fits.header.keywords.clone(),
+            axes: fits.header.axes.clone(),
+            data: fits.data.as_ref().clone(),
+            modified: Vec::new(),
+        }
+    }
+
+    /// Update the first keyword called `name`, or append it if it does not exist yet.
+    pub fn set_keyword(mut self, name: &str, value: Value, comment: &str) -> Self {
+        set_keyword(&mut self.keywords, name, value, Some(comment));
+        if !self.modified.iter().any(|m| m == name) {
+            self.modified.push(name.to_string());
+        }
+        self
+    }
+
+    /// Crop a 2D image to the given (0-based) row and column ranges. The reference pixel
+    /// (CRPIX1, CRPIX2) is moved along, so the WCS stays valid.
+    pub fn crop(mut self, rows: Range<usize>, cols: Range<usize>) -> Result<Self, FitsError> {
+        let axes = &self.axes;
+        self.data = match &self.data {
+            DataArray::U8(d) => crop_image(d, axes, &rows, &cols)?.into(),
+            DataArray::I16(d) => crop_image(d, axes, &rows, &cols)?.into(),
+            DataArray::U16(d) => crop_image(d, axes, &rows, &cols)?.into(),
+            DataArray::I32(d) => crop_image(d, axes, &rows, &cols)?.into(),
+            DataArray::U32(d) => crop_image(d, axes, &rows, &cols)?.into(),
+            DataArray::I64(d) => crop_image(d, axes, &rows, &cols)?.into(),
+            DataArray::F32(d) => crop_image(d, axes, &rows, &cols)?.into(),
+            DataArray::F64(d) => crop_image(d, axes, &rows, &cols)?.into(),
+        };
+        self.axes = vec![cols.len(), rows.len()];
+        set_keyword(
+            &mut self.keywords,
+            "NAXIS1",
+            Value::Integer(cols.len() as i64),
+            None,
+        );
+        set_keyword(
+            &mut self.keywords,
+            "NAXIS2",
+            Value::Integer(rows.len() as i64),
+            None,
+        );
+        for (key, offset) in [("CRPIX1", cols.start), ("CRPIX2", rows.start)] {
+            let crpix = self.keywords.iter_mut().find_map(|kw| match kw {
+                Keyword::Value(k, v, _c) if k == key => Some(v),
+                _ => None,
+            });
+            if let Some(v) = crpix {
+                *v = Value::Float(v.as_f64_lossy() - offset as f64);
+            }
+        }
+        Ok(self)
+    }
+
+    pub fn add_history(mut self, text: &str) -> Self {
+        self.keywords.push(Keyword::History(text.to_string()));
+        self
+    }
+
+    /// Names of the keywords changed through `set_keyword`, in order of first change.
+    pub fn modified_keywords(&self) -> &[String] {
+        &self.modified
+    }
+
+    /// Create the edited file. If any keywords were changed, a HISTORY record listing them
+    /// is appended to the header.
+    pub fn build(mut self) -> Result<BasicFits, FitsError> {
+        if !self.modified.is_empty() {
+            let text = format!("Modified keywords: {}", self.modified.join(", "));
+            self.keywords.push(Keyword::History(text));
+        }
+        let header = Header::from_keyword_list(self.keywords)?;
+        Ok(BasicFits {
+            header,
+            data: Arc::new(self.data),
+        })
+    }
+}
+
+/// Construct a header from scratch. SIMPLE, BITPIX and the axes are required, NAXIS and
+/// NAXISn are derived from the axes.
+#[derive(Default)]
+pub struct HeaderBuilder {
+    simple: Option<bool>,
+    bitpix: Option<Bitpix>,
+    axes: Option<Vec<usize>>,
+    keywords: KeywordList,
+}
+
+impl HeaderBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn simple(mut self, simple: bool) -> Self {
+        self.simple = Some(simple);
+        self
+    }
+
+    pub fn bitpix(mut self, bitpix: Bitpix) -> Self {
+        self.bitpix = Some(bitpix);
+        self
+    }
+
+    /// The length of each axis, starting with NAXIS1.
+    pub fn naxis(mut self, axes: Vec<usize>) -> Self {
+        self.axes = Some(axes);
+        self
+    }
+
+    /// Set a (non-mandatory) keyword, replacing an earlier value of the same keyword.
+    pub fn keyword(mut self, name: &str, value: Value, comment: &str) -> Self {
+        set_keyword(&mut self.keywords, name, value, Some(comment));
+        self
+    }
+
+    /// Append COMMENT records, wrapping long text.
+    pub fn comment(mut self, text: &str) -> Self {
+        let records = wrap_text(text, TEXT_SIZE).into_iter().map(Keyword::Comment);
+        self.keywords.extend(records);
+        self
+    }
+
+    /// Append HISTORY records, wrapping long text.
+    pub fn history(mut self, text: &str) -> Self {
+        let records = wrap_text(text, TEXT_SIZE).into_iter().map(Keyword::History);
+        self.keywords.extend(records);
+        self
+    }
+
+    /// Create the header, with the mandatory keywords first.
+    pub fn build(self) -> Result<Header, FitsError> {
+        let simple = self.simple.ok_or(FitsError::MissingKeyword("SIMPLE"))?;
+        let bitpix = self.bitpix.ok_or(FitsError::MissingKeyword("BITPIX"))?;
+        let axes = self.axes.ok_or(FitsError::MissingKeyword("NAXIS"))?;
+
+        for kw in self.keywords.iter() {
+            if let Keyword::Value(name, _v, _c) = kw {
+                validate_keyword_name(name, false)?;
+                if is_mandatory_keyword(name) {
+                    return Err(FitsError::MandatoryKeyword(name.clone()));
+                }
+            }
+        }
+
+        let value = |name: &str, value: Value, comment: &str| {
+            Keyword::Value(name.to_string(), value, comment.to_string())
+        };
+        let mut keywords = vec![
+            value(
+                "SIMPLE",
+                Value::Boolean(simple),
+                "conforms to FITS standard",
+            ),
+            value("BITPIX", Value::Integer(bitpix.to_int()), "array data type"),
+            value(
+                "NAXIS",
+                Value::Integer(axes.len() as i64),
+                "number of array dimensions",
+            ),
+        ];
+        for (i, n) in axes.iter().enumerate() {
+            keywords.push(value(
+                &format!("NAXIS{}", i + 1),
+                Value::Integer(*n as i64),
+                "",
+            ));
+        }
+        keywords.extend(self.keywords);
+        Header::from_keyword_list(keywords)
+    }
+}
+
+// Crop a 2D image in its flattened form, keeping the element type
+fn crop_image<T: Clone>(
+    data: &GenericData<T>,
+    axes: &[usize],
+    rows: &Range<usize>,
+    cols: &Range<usize>,
+) -> Result<GenericData<T>, FitsError> {
+    let image = to_array2(data, axes).ok_or(FitsError::InvalidNaxis(axes.len()))?;
+    let (nrows, ncols) = image.dim();
+    if rows.start > rows.end || cols.start > cols.end || rows.end > nrows || cols.end > ncols {
+        return Err(FitsError::OutOfBounds);
+    }
+    Ok(from_array2(
+        image.slice(s![rows.clone(), cols.clone()]).to_owned(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::image::tests::test_fits;
+    use ndarray::Array2;
+
+    fn history(fits: &BasicFits) -> Vec<&str> {
+        fits.header
+            .keywords
+            .iter()
+            .filter_map(|kw| match kw {
+                Keyword::History(h) => Some(h.as_str()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn from_existing_test() {
+        let data = Array2::from_shape_fn((4, 6), |(y, x)| (10 * y + x) as f64);
+        let original = test_fits(&data);
+
+        let edited = FitsBuilder::from_existing(&original)
+            .set_keyword("OBJECT", Value::Str("M31".to_string()), "target")
+            .crop(1..3, 2..5)
+            .unwrap()
+            .add_history("cropped around the core")
+            .build()
+            .unwrap();
+
+        assert_eq!(edited.header.axes, vec![3, 2]);
+        assert_eq!(edited.header.get_value("NAXIS1"), Some(&Value::Integer(3)));
+        assert_eq!(edited.header.get_value("NAXIS2"), Some(&Value::Integer(2)));
+        assert_eq!(
+            edited.data.to_f64().as_slice().unwrap(),
+            &[12., 13., 14., 22., 23., 24.]
+        );
+        assert_eq!(
+            edited.header.get_value("OBJECT"),
+            Some(&Value::Str("M31".to_string()))
+        );
+        assert_eq!(
+            history(&edited),
+            vec!["cropped around the core", "Modified keywords: OBJECT"]
+        );
+
+        // The original is left untouched
+        assert_eq!(original.header.axes, vec![6, 4]);
+        assert!(history(&original).is_empty());
+    }
+
+    #[test]
+    fn header_builder_test() {
+        let header = HeaderBuilder::new()
+            .simple(true)
+            .bitpix(Bitpix::Int16)
+            .naxis(vec![10, 20])
+            .keyword("OBJECT", Value::Str("M31".to_string()), "target")
+            .comment("synthetic")
+            .keyword("OBJECT", Value::Str("M32".to_string()), "target")
+            .history("created")
+            .build()
+            .unwrap();
+        assert_eq!(header.bitpix, Bitpix::Int16);
+        assert_eq!(header.axes, vec![10, 20]);
+        assert_eq!(header.get_int("NAXIS"), Some(2));
+        assert_eq!(header.get_int("NAXIS2"), Some(20));
+        assert_eq!(header.get_str("OBJECT"), Some("M32"));
+        assert_eq!(header.comments().collect::<Vec<_>>(), ["synthetic"]);
+        assert_eq!(header.history().collect::<Vec<_>>(), ["created"]);
+        assert!(header.validate().is_empty());
+
+        let empty = HeaderBuilder::new().simple(true).bitpix(Bitpix::Int8);
+        let empty = empty.naxis(vec![]).build().unwrap();
+        assert_eq!(header.keywords.len(), 8);
+        assert_eq!(empty.keywords.len(), 3);
+    }
+
+    #[test]
+    fn header_builder_error_test() {
+        let complete = || {
+            HeaderBuilder::new()
+                .simple(true)
+                .bitpix(Bitpix::Float64)
+                .naxis(vec![5])
+        };
+        assert!(matches!(
+            HeaderBuilder::new()
+                .bitpix(Bitpix::Int8)
+                .naxis(vec![])
+                .build(),
+            Err(FitsError::MissingKeyword("SIMPLE"))
+        ));
+        assert!(matches!(
+            HeaderBuilder::new()
+                .simple(true)
+                .bitpix(Bitpix::Int8)
+                .build(),
+            Err(FitsError::MissingKeyword("NAXIS"))
+        ));
+        assert!(matches!(
+            complete().keyword("NAXIS1", Value::Integer(3), "").build(),
+            Err(FitsError::MandatoryKeyword(k)) if k == "NAXIS1"
+        ));
+        assert!(matches!(
+            complete().keyword("lower", Value::Integer(3), "").build(),
+            Err(FitsError::InvalidKeywordName(k)) if k == "lower"
+        ));
+        assert!(matches!(
+            complete().naxis(vec![1; 1000]).build(),
+            Err(FitsError::InvalidNaxis(1000))
+        ));
+    }
+
+    #[test]
+    fn crop_error_test() {
+        let original = test_fits(&Array2::zeros((4, 6)));
+        assert!(matches!(
+            FitsBuilder::from_existing(&original).crop(0..5, 0..2),
+            Err(FitsError::OutOfBounds)
+        ));
+    }
+
+    #[test]
+    fn crop_keeps_type_test() {
+        let mut original = test_fits(&Array2::zeros((2, 3)));
+        let values = ndarray::arr1(&[0i16, 1, 2, 10, 11, 12]).into_dyn();
+        original.data = Arc::new(DataArray::I16(values));
+
+        let cropped = FitsBuilder::from_existing(&original)
+            .crop(0..2, 1..3)
+            .unwrap()
+            .build()
+            .unwrap();
+        assert_eq!(
+            *cropped.data,
+            DataArray::I16(ndarray::arr1(&[1i16, 2, 11, 12]).into_dyn())
+        );
+    }
+}
@@ -0,0 +1,242 @@
+// The CHECKSUM and DATASUM keywords, used to verify the integrity of an HDU.
+// See appendix J of the FITS standard (2018).
+use std::borrow::Cow;
+use std::fmt;
+
+use crate::definitions::{HEADER_END_KEYWORD, HEADER_KEYWORD_SIZE};
+use crate::parsing::header::{parse_header_slice, Keyword, Value};
+
+// Columns 12-27 of the CHECKSUM record hold the encoded checksum, right after the quote
+const CHECKSUM_VALUE: std::ops::Range<usize> = 11..27;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ChecksumError {
+    /// The bytes do not start with a valid header.
+    InvalidHdu,
+    /// The header has no CHECKSUM keyword.
+    MissingChecksum,
+    /// The HDU as a whole does not sum to the value stored in CHECKSUM. `computed` is the
+    /// CHECKSUM that would match the current contents.
+    HeaderChecksumMismatch { stored: String, computed: String },
+    /// The data does not sum to the value stored in DATASUM.
+    DataChecksumMismatch { stored: String, computed: String },
+}
+
+impl fmt::Display for ChecksumError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ChecksumError::InvalidHdu => write!(f, "invalid HDU"),
+            ChecksumError::MissingChecksum => write!(f, "keyword 'CHECKSUM' not found"),
+            ChecksumError::HeaderChecksumMismatch { stored, computed } => write!(
+                f,
+                "CHECKSUM mismatch: stored '{}', computed '{}'",
+                stored, computed
+            ),
+            ChecksumError::DataChecksumMismatch { stored, computed } => write!(
+                f,
+                "DATASUM mismatch: stored '{}', computed '{}'",
+                stored, computed
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ChecksumError {}
+
+/// Verify an HDU (its header followed by its data) against its CHECKSUM keyword and, if
+/// present, its DATASUM keyword. With a correct CHECKSUM the whole HDU sums to -0.
+pub fn verify_checksum(hdu_bytes: &[u8]) -> Result<(), ChecksumError> {
+    let (keywords, header_size) =
+        parse_header_slice(hdu_bytes).map_err(|_| ChecksumError::InvalidHdu)?;
+    let stored = |key: &str| {
+        keywords.iter().find_map(|kw| match kw {
+            Keyword::Value(k, Value::Str(s), _c) if k == key => Some(s.trim().to_string()),
+            _ => None,
+        })
+    };
+
+    if let Some(stored) = stored("DATASUM") {
+        let computed = compute_datasum(&hdu_bytes[header_size..]);
+        if stored != computed {
+            return Err(ChecksumError::DataChecksumMismatch { stored, computed });
+        }
+    }
+
+    let stored = stored("CHECKSUM").ok_or(ChecksumError::MissingChecksum)?;
+    if ones_complement_sum(hdu_bytes) != u32::MAX {
+        let computed = compute_checksum(hdu_bytes);
+        return Err(ChecksumError::HeaderChecksumMismatch { stored, computed });
+    }
+    Ok(())
+}
+
+/// The value for the CHECKSUM keyword of an HDU, as a 16 character string. The header
+/// should already contain CHECKSUM (and DATASUM, if used) in fixed format, i.e. with the
+/// value starting in column 11; its current value is ignored.
+pub fn compute_checksum(hdu_bytes: &[u8]) -> String {
+    let sum = ones_complement_sum(&zero_checksum(hdu_bytes));
+    encode(!sum)
+}
+
+/// The value for the DATASUM keyword: the checksum of the data as a decimal number.
+pub fn compute_datasum(data: &[u8]) -> String {
+    ones_complement_sum(data).to_string()
+}
+
+/// The 32-bit 1's complement sum of `bytes`, taken as big endian 32-bit integers. The
+/// carry out of the highest bit is added back into the lowest.
+pub fn ones_complement_sum(bytes: &[u8]) -> u32 {
+    let mut sum: u64 = 0;
+    for chunk in bytes.chunks(4) {
+        let mut word = [0u8; 4];
+        word[..chunk.len()].copy_from_slice(chunk);
+        sum += u64::from(u32::from_be_bytes(word));
+        sum = (sum & 0xffff_ffff) + (sum >> 32);
+    }
+    sum as u32
+}
+
+// The HDU with the value of its CHECKSUM record set to '0000000000000000'
+fn zero_checksum(hdu_bytes: &[u8]) -> Cow<'_, [u8]> {
+    let records = hdu_bytes.chunks_exact(HEADER_KEYWORD_SIZE);
+    let header = records.take_while(|record| !record.starts_with(HEADER_END_KEYWORD.as_bytes()));
+    let position = header.enumerate().find_map(|(i, record)| {
+        let fixed = record.starts_with(b"CHECKSUM= '") && record[CHECKSUM_VALUE.end] == b'\'';
+        fixed.then_some(i * HEADER_KEYWORD_SIZE)
+    });
+    match position {
+        Some(start) => {
+            let mut bytes = hdu_bytes.to_vec();
+            let value = start + CHECKSUM_VALUE.start..start + CHECKSUM_VALUE.end;
+            bytes[value].fill(b'0');
+            Cow::Owned(bytes)
+        }
+        None => Cow::Borrowed(hdu_bytes),
+    }
+}
+
+// Encode a 32-bit value as 16 alphanumeric characters whose 1's complement sum (at an
+// offset of 3 bytes, where the value of CHECKSUM starts) adds `value` to that of
+// '0000000000000000'.
+fn encode(value: u32) -> String {
+    // Punctuation between the digits and the letters, which is avoided
+    const EXCLUDE: [u8; 13] = [
+        0x3a, 0x3b, 0x3c, 0x3d, 0x3e, 0x3f, 0x40, 0x5b, 0x5c, 0x5d, 0x5e, 0x5f, 0x60,
+    ];
+    let mut ascii = [0u8; 16];
+    for (i, byte) in value.to_be_bytes().into_iter().enumerate() {
+        // Spread every byte over 4 characters, then shift pairs away from punctuation
+        // without changing their sum
+        let mut ch = [b'0' + byte / 4; 4];
+        ch[0] += byte % 4;
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for j in [0, 2] {
+                if EXCLUDE.contains(&ch[j]) || EXCLUDE.contains(&ch[j + 1]) {
+                    ch[j] += 1;
+                    ch[j + 1] -= 1;
+                    changed = true;
+                }
+            }
+        }
+        for (j, c) in ch.into_iter().enumerate() {
+            ascii[4 * j + i] = c;
+        }
+    }
+    // Rotate right by one byte, to align with the start of the value in the record
+    (0..16).map(|i| ascii[(i + 15) % 16] as char).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::writing::{data_to_bytes, header_to_bytes};
+    use crate::DataArray;
+    use ndarray::arr1;
+
+    fn card(name: &str, value: Value) -> Keyword {
+        Keyword::Value(name.to_string(), value, String::new())
+    }
+
+    // An HDU with the given CHECKSUM and DATASUM
+    fn hdu(checksum: &str, datasum: &str) -> Vec<u8> {
+        let keywords = vec![
+            card("SIMPLE", Value::Boolean(true)),
+            card("BITPIX", Value::Integer(16)),
+            card("NAXIS", Value::Integer(1)),
+            card("NAXIS1", Value::Integer(3)),
+            card("CHECKSUM", Value::Str(checksum.to_string())),
+            card("DATASUM", Value::Str(datasum.to_string())),
+        ];
+        let mut bytes = header_to_bytes(&keywords);
+        let data = DataArray::I16(arr1(&[1, -2, 300]).into_dyn());
+        bytes.extend(data_to_bytes(&data));
+        bytes
+    }
+
+    #[test]
+    fn ones_complement_sum_test() {
+        assert_eq!(ones_complement_sum(&[]), 0);
+        assert_eq!(ones_complement_sum(&[0, 0, 1, 2, 0, 0, 0, 3]), 0x105);
+        // The carry wraps around
+        assert_eq!(
+            ones_complement_sum(&[0xff, 0xff, 0xff, 0xff, 0, 0, 0, 1]),
+            1
+        );
+        assert_eq!(ones_complement_sum(&[0x80, 0, 0, 0, 0x80, 0, 0, 0]), 1);
+        // A partial word is padded with zeros
+        assert_eq!(ones_complement_sum(&[0, 0, 0, 1, 2]), 0x0200_0001);
+    }
+
+    #[test]
+    fn checksum_test() {
+        let zeros = "0000000000000000";
+        let datasum = compute_datasum(&hdu(zeros, "0")[2880..]);
+        // The data words are 0x0001FFFE (1, -2) and 0x012C0000 (300, padding)
+        assert_eq!(datasum, (0x0001_fffe + 0x012c_0000).to_string());
+
+        let checksum = compute_checksum(&hdu(zeros, &datasum));
+        assert_eq!(checksum.len(), 16);
+        assert!(checksum.chars().all(|c| c.is_ascii_alphanumeric()));
+
+        let signed = hdu(&checksum, &datasum);
+        assert_eq!(ones_complement_sum(&signed), u32::MAX);
+        assert_eq!(verify_checksum(&signed), Ok(()));
+        // The current CHECKSUM is ignored when computing it again
+        assert_eq!(compute_checksum(&signed), checksum);
+
+        let mut corrupted = signed.clone();
+        corrupted[2880] ^= 1;
+        assert!(matches!(
+            verify_checksum(&corrupted),
+            Err(ChecksumError::DataChecksumMismatch { stored, .. }) if stored == datasum
+        ));
+
+        // A changed header only shows in CHECKSUM
+        let mut changed = signed.clone();
+        changed[3 * 80 + 29] = b'4'; // NAXIS1 = 4
+        assert_eq!(
+            verify_checksum(&changed),
+            Err(ChecksumError::HeaderChecksumMismatch {
+                stored: checksum.clone(),
+                computed: compute_checksum(&changed)
+            })
+        );
+    }
+
+    #[test]
+    fn checksum_error_test() {
+        assert_eq!(
+            verify_checksum(b"not a header"),
+            Err(ChecksumError::InvalidHdu)
+        );
+
+        let bytes = header_to_bytes(&vec![
+            card("SIMPLE", Value::Boolean(true)),
+            card("BITPIX", Value::Integer(8)),
+            card("NAXIS", Value::Integer(0)),
+        ]);
+        assert_eq!(verify_checksum(&bytes), Err(ChecksumError::MissingChecksum));
+    }
+}
@@ -0,0 +1,595 @@
+// Tile compressed images (as written by fpack): an image split into tiles, each compressed
+// separately and stored in a row of a BINTABLE extension with ZIMAGE = T.
+// See section 10 of the FITS standard (2018).
+//
+// Supported are RICE_1, GZIP_1, GZIP_2 and NOCOMPRESS. Quantized floating point images
+// (with a ZSCALE column) are not supported yet.
+use std::io::Read;
+
+use flate2::read::GzDecoder;
+
+use crate::definitions::BLOCK_SIZE;
+use crate::header::Header;
+use crate::parsing::header::{Keyword, Value};
+use crate::parsing::read_data;
+use crate::table::{TForm, TFormType};
+use crate::{DataArray, FitsError};
+
+// Keywords of the table that do not carry over to the image
+const TABLE_KEYWORDS: [&str; 24] = [
+    "XTENSION", "BITPIX", "NAXIS", "PCOUNT", "GCOUNT", "TFIELDS", "THEAP", "ZIMAGE", "ZCMPTYPE",
+    "ZBITPIX", "ZNAXIS", "ZQUANTIZ", "ZDITHER0", "ZBLANK", "ZSIMPLE", "ZEXTEND", "ZBLOCKED",
+    "ZTENSION", "ZPCOUNT", "ZGCOUNT", "ZHECKSUM", "ZDATASUM", "CHECKSUM", "DATASUM",
+];
+// The same, for indexed keywords like TFORMn
+const INDEXED_TABLE_KEYWORDS: [&str; 13] = [
+    "NAXIS", "TTYPE", "TFORM", "TUNIT", "TNULL", "TSCAL", "TZERO", "TDIM", "TDISP", "ZNAXIS",
+    "ZTILE", "ZNAME", "ZVAL",
+];
+
+/// Whether the header of a BINTABLE extension describes a tile compressed image.
+pub fn is_compressed_image(header: &Header) -> bool {
+    header.get_bool("ZIMAGE") == Some(true)
+}
+
+/// Decompress the image stored in a BINTABLE extension, given its header and data (rows and
+/// heap). Returns the header and data of the equivalent IMAGE extension.
+pub fn decompress(header: &Header, data: &[u8]) -> Result<(Header, DataArray), FitsError> {
+    let image_header = image_header(header)?;
+    let algorithm = header
+        .get_str("ZCMPTYPE")
+        .ok_or(FitsError::MissingKeyword("ZCMPTYPE"))?
+        .trim_end();
+    if find_column(header, "ZSCALE")?.is_some() {
+        return Err(FitsError::UnsupportedCompression(
+            "quantized floating point data".to_string(),
+        ));
+    }
+    let compressed = byte_column(header, "COMPRESSED_DATA")?.ok_or(FitsError::InvalidHeader)?;
+    let gzip_compressed = byte_column(header, "GZIP_COMPRESSED_DATA")?;
+
+    let axes = &image_header.axes;
+    let tile = (1..=axes.len())
+        .map(|n| match header.get_int(&format!("ZTILE{}", n)) {
+            Some(size) if size > 0 => Ok(size as usize),
+            Some(_) => Err(FitsError::InvalidHeader),
+            // By default the tiles are rows
+            None => Ok(if n == 1 { axes[0].max(1) } else { 1 }),
+        })
+        .collect::<Result<Vec<usize>, FitsError>>()?;
+    let tiles_per_axis: Vec<usize> = axes
+        .iter()
+        .zip(&tile)
+        .map(|(a, t)| a.div_ceil(*t))
+        .collect();
+    let n_tiles = if axes.is_empty() {
+        0
+    } else {
+        tiles_per_axis
+            .iter()
+            .try_fold(1usize, |n, &tiles| n.checked_mul(tiles))
+            .ok_or(FitsError::InvalidHeader)?
+    };
+
+    let table = Table::new(header, data)?;
+    // Every tile is stored in a row of the table
+    if n_tiles > table.rows {
+        return Err(FitsError::UnexpectedEof);
+    }
+    let bytepix = image_header.bitpix.bytes_per_pixel();
+    let size = axes
+        .iter()
+        .try_fold(bytepix, |size, &n| size.checked_mul(n))
+        .ok_or(FitsError::InvalidHeader)?;
+    let mut image = vec![0u8; size];
+    for row in 0..n_tiles {
+        // Position and size of the tile, the tiles are numbered with the first axis fastest
+        let mut start = Vec::with_capacity(axes.len());
+        let mut rest = row;
+        for (i, n) in tiles_per_axis.iter().enumerate() {
+            start.push(rest % n * tile[i]);
+            rest /= n;
+        }
+        let shape: Vec<usize> = (0..axes.len())
+            .map(|i| tile[i].min(axes[i] - start[i]))
+            .collect();
+        let n_pixels = shape.iter().product::<usize>();
+
+        let bytes = table.cell(row, &compressed)?;
+        let pixels = match gzip_compressed {
+            // Tiles that could not be compressed otherwise fall back to gzip
+            Some(ref column) if bytes.is_empty() => gunzip(table.cell(row, column)?)?,
+            _ => decompress_tile(header, algorithm, bytes, n_pixels, bytepix)?,
+        };
+        if pixels.len() != n_pixels * bytepix {
+            return Err(FitsError::UnexpectedEof);
+        }
+        place_tile(&mut image, axes, &start, &shape, &pixels, bytepix);
+    }
+
+    let data = read_data(&image_header, &mut image.chunks(BLOCK_SIZE))?;
+    Ok((image_header, data))
+}
+
+// The header of the uncompressed image, from the ZBITPIX, ZNAXIS and ZNAXISn keywords and
+// the keywords that are not about the table itself
+fn image_header(table: &Header) -> Result<Header, FitsError> {
+    let card = |name: &str, value: Value| Keyword::Value(name.to_string(), value, String::new());
+    let zbitpix = table
+        .get_int("ZBITPIX")
+        .ok_or(FitsError::MissingKeyword("ZBITPIX"))?;
+    let znaxis = table
+        .get_int("ZNAXIS")
+        .ok_or(FitsError::MissingKeyword("ZNAXIS"))?;
+
+    let mut keywords = vec![
+        card("XTENSION", Value::Str("IMAGE   ".to_string())),
+        card("BITPIX", Value::Integer(zbitpix)),
+        card("NAXIS", Value::Integer(znaxis)),
+    ];
+    for n in 1..=znaxis {
+        let name = format!("ZNAXIS{}", n);
+        let size = table
+            .get_int(&name)
+            .ok_or(FitsError::KeywordNotFound(name))?;
+        keywords.push(card(&format!("NAXIS{}", n), Value::Integer(size)));
+    }
+    keywords.push(card("PCOUNT", Value::Integer(0)));
+    keywords.push(card("GCOUNT", Value::Integer(1)));
+    if let Some(blank) = table.get_int("ZBLANK") {
+        keywords.push(card("BLANK", Value::Integer(blank)));
+    }
+    keywords.extend(
+        table
+            .keywords
+            .iter()
+            .filter(|kw| !is_table_keyword(kw.keyword_name()))
+            .cloned(),
+    );
+    Header::from_keyword_list(keywords)
+}
+
+fn is_table_keyword(name: &str) -> bool {
+    let indexed = |prefix: &&str| match name.strip_prefix(prefix) {
+        Some(n) => !n.is_empty() && n.chars().all(|c| c.is_ascii_digit()),
+        None => false,
+    };
+    TABLE_KEYWORDS.contains(&name) || INDEXED_TABLE_KEYWORDS.iter().any(indexed)
+}
+
+fn decompress_tile(
+    header: &Header,
+    algorithm: &str,
+    bytes: &[u8],
+    n_pixels: usize,
+    bytepix: usize,
+) -> Result<Vec<u8>, FitsError> {
+    match algorithm {
+        "RICE_1" | "RICE_ONE" => {
+            let blocksize = parameter(header, "BLOCKSIZE").unwrap_or(32);
+            let rice_bytepix = parameter(header, "BYTEPIX").unwrap_or(4);
+            if header.get_int("ZBITPIX").unwrap_or(0) < 0 {
+                return Err(FitsError::UnsupportedCompression(
+                    "RICE_1 for floating point data".to_string(),
+                ));
+            }
+            let values = rice_decode(bytes, n_pixels, rice_bytepix, blocksize)?;
+            Ok(values
+                .into_iter()
+                .flat_map(|v| v.to_be_bytes()[8 - bytepix..].to_vec())
+                .collect())
+        }
+        "GZIP_1" => gunzip(bytes),
+        "GZIP_2" => Ok(unshuffle(&gunzip(bytes)?, bytepix)),
+        "NOCOMPRESS" => Ok(bytes.to_vec()),
+        _ => Err(FitsError::UnsupportedCompression(algorithm.to_string())),
+    }
+}
+
+// A parameter of the compression algorithm, given by a ZNAMEi and ZVALi pair
+fn parameter(header: &Header, name: &str) -> Option<usize> {
+    (1..)
+        .map_while(|i| Some((i, header.get_str(&format!("ZNAME{}", i))?)))
+        .find(|(_i, zname)| zname.trim_end() == name)
+        .and_then(|(i, _zname)| header.get_int(&format!("ZVAL{}", i)))
+        .map(|value| value as usize)
+}
+
+fn gunzip(bytes: &[u8]) -> Result<Vec<u8>, FitsError> {
+    let mut out = Vec::new();
+    GzDecoder::new(bytes).read_to_end(&mut out)?;
+    Ok(out)
+}
+
+// GZIP_2 stores the most significant bytes of all values first, then the next bytes, etc.
+fn unshuffle(bytes: &[u8], bytepix: usize) -> Vec<u8> {
+    let n = bytes.len() / bytepix;
+    let mut out = vec![0u8; bytes.len()];
+    for (j, plane) in bytes.chunks(n.max(1)).take(bytepix).enumerate() {
+        for (i, b) in plane.iter().enumerate() {
+            out[i * bytepix + j] = *b;
+        }
+    }
+    out
+}
+
+// Copy the pixels of a tile (first axis fastest) into the image
+fn place_tile(
+    image: &mut [u8],
+    axes: &[usize],
+    start: &[usize],
+    shape: &[usize],
+    pixels: &[u8],
+    bytepix: usize,
+) {
+    let run = shape[0] * bytepix;
+    for (i, line) in pixels.chunks(run).enumerate() {
+        // Index of the first pixel of this line of the tile in the image
+        let mut rest = i;
+        let mut index = start[0];
+        let mut stride = axes[0];
+        for d in 1..axes.len() {
+            index += (start[d] + rest % shape[d]) * stride;
+            rest /= shape[d];
+            stride *= axes[d];
+        }
+        image[index * bytepix..index * bytepix + run].copy_from_slice(line);
+    }
+}
+
+/// Decode `n_pixels` values compressed with the Rice algorithm, as integers of `bytepix`
+/// (1, 2 or 4) bytes, in blocks of `blocksize` pixels.
+///
+/// The first value is stored as is. Every block then starts with the number of low bits
+/// `fs` that are stored directly for each difference between successive pixels, the high
+/// bits are stored as a unary number.
+pub fn rice_decode(
+    bytes: &[u8],
+    n_pixels: usize,
+    bytepix: usize,
+    blocksize: usize,
+) -> Result<Vec<i64>, FitsError> {
+    let (fsbits, fsmax, bbits) = match bytepix {
+        1 => (3, 6, 8),
+        2 => (4, 14, 16),
+        4 => (5, 25, 32),
+        _ => {
+            return Err(FitsError::UnsupportedCompression(format!(
+                "RICE_1 with BYTEPIX = {}",
+                bytepix
+            )))
+        }
+    };
+    let mask = u32::MAX >> (32 - bbits);
+    let signed = |x: u32| match bytepix {
+        // FITS bytes are unsigned
+        1 => x as u8 as i64,
+        2 => x as u16 as i16 as i64,
+        _ => x as i32 as i64,
+    };
+
+    let mut bits = BitReader { bytes, position: 0 };
+    let mut last = bits.read(bbits)?;
+    let mut values = Vec::with_capacity(n_pixels);
+    while values.len() < n_pixels {
+        let fs = bits.read(fsbits)? as i64 - 1;
+        for _ in 0..blocksize.max(1).min(n_pixels - values.len()) {
+            let diff = if fs < 0 {
+                // All differences in the block are zero
+                0
+            } else if fs == fsmax {
+                // High entropy: the differences are stored as is
+                bits.read(bbits)?
+            } else {
+                let high = bits.unary()?;
+                (high << fs) | bits.read(fs as u32)?
+            };
+            // Undo the mapping of signed differences onto unsigned ones
+            let diff = if diff & 1 == 0 {
+                diff >> 1
+            } else {
+                !(diff >> 1)
+            };
+            last = diff.wrapping_add(last) & mask;
+            values.push(signed(last));
+        }
+    }
+    Ok(values)
+}
+
+// Reads bits, most significant first
+struct BitReader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl BitReader<'_> {
+    fn bit(&mut self) -> Result<u32, FitsError> {
+        let byte = self
+            .bytes
+            .get(self.position / 8)
+            .ok_or(FitsError::UnexpectedEof)?;
+        let bit = (byte >> (7 - self.position % 8)) & 1;
+        self.position += 1;
+        Ok(bit as u32)
+    }
+
+    // The next `n` (at most 32) bits as a number
+    fn read(&mut self, n: u32) -> Result<u32, FitsError> {
+        let mut value: u64 = 0;
+        for _ in 0..n {
+            value = (value << 1) | self.bit()? as u64;
+        }
+        Ok(value as u32)
+    }
+
+    // The number of zeros before the next one
+    fn unary(&mut self) -> Result<u32, FitsError> {
+        let mut zeros = 0;
+        while self.bit()? == 0 {
+            zeros += 1;
+        }
+        Ok(zeros)
+    }
+}
+
+// Location of a column in the rows of the table
+struct Column {
+    offset: usize,
+    format: TForm,
+    // Q instead of P: the descriptors have 64 bit instead of 32 bit numbers
+    wide: bool,
+}
+
+// The first column called `name`, which should be a variable length byte (B) array like
+// fpack writes
+fn byte_column(header: &Header, name: &str) -> Result<Option<Column>, FitsError> {
+    match find_column(header, name)? {
+        Some(column) if column.format == TForm::Variable(TFormType::B) => Ok(Some(column)),
+        Some(_) => Err(FitsError::UnsupportedCompression(format!(
+            "column {} is not a byte array",
+            name
+        ))),
+        None => Ok(None),
+    }
+}
+
+// The first column called `name`
+fn find_column(header: &Header, name: &str) -> Result<Option<Column>, FitsError> {
+    let fields = header.get_int("TFIELDS").unwrap_or(0);
+    let mut offset = 0;
+    for n in 1..=fields {
+        let tform = header
+            .get_str(&format!("TFORM{}", n))
+            .ok_or(FitsError::InvalidHeader)?;
+        let format = TForm::from_tform(tform).ok_or(FitsError::InvalidHeader)?;
+        let wide = tform.contains('Q');
+        if header.get_str(&format!("TTYPE{}", n)).map(str::trim_end) == Some(name) {
+            return Ok(Some(Column {
+                offset,
+                format,
+                wide,
+            }));
+        }
+        offset += match format {
+            TForm::Variable(_) if wide => 16,
+            TForm::Variable(_) => 8,
+            TForm::Fixed(repeat, TFormType::X) => repeat.div_ceil(8),
+            TForm::Fixed(repeat, kind) => repeat * kind.size(),
+        };
+    }
+    Ok(None)
+}
+
+// The rows of a binary table, followed by the heap
+struct Table<'a> {
+    data: &'a [u8],
+    row_size: usize,
+    rows: usize,
+    heap: usize,
+}
+
+impl<'a> Table<'a> {
+    fn new(header: &Header, data: &'a [u8]) -> Result<Self, FitsError> {
+        let (row_size, rows) = match header.axes[..] {
+            [row_size, rows] => (row_size, rows),
+            _ => return Err(FitsError::InvalidHeader),
+        };
+        let heap = match header.get_int("THEAP") {
+            Some(heap) => usize::try_from(heap).ok(),
+            None => row_size.checked_mul(rows),
+        }
+        .ok_or(FitsError::InvalidHeader)?;
+        Ok(Table {
+            data,
+            row_size,
+            rows,
+            heap,
+        })
+    }
+
+    // The bytes of a variable length array in `column`
+    fn cell(&self, row: usize, column: &Column) -> Result<&'a [u8], FitsError> {
+        if row >= self.rows {
+            return Err(FitsError::UnexpectedEof);
+        }
+        let start = row
+            .checked_mul(self.row_size)
+            .and_then(|start| start.checked_add(column.offset))
+            .ok_or(FitsError::UnexpectedEof)?;
+        let descriptor = self
+            .data
+            .get(start..start.saturating_add(if column.wide { 16 } else { 8 }))
+            .ok_or(FitsError::UnexpectedEof)?;
+        let (count, offset) = if column.wide {
+            let count = i64::from_be_bytes(descriptor[..8].try_into().unwrap());
+            let offset = i64::from_be_bytes(descriptor[8..].try_into().unwrap());
+            (usize::try_from(count), usize::try_from(offset))
+        } else {
+            let count = i32::from_be_bytes(descriptor[..4].try_into().unwrap());
+            let offset = i32::from_be_bytes(descriptor[4..].try_into().unwrap());
+            (usize::try_from(count), usize::try_from(offset))
+        };
+        // The descriptor is read from the file, so a negative or huge count or offset has
+        // to fail like any other array outside the heap
+        let (Ok(count), Ok(offset)) = (count, offset) else {
+            return Err(FitsError::UnexpectedEof);
+        };
+        let start = self
+            .heap
+            .checked_add(offset)
+            .ok_or(FitsError::UnexpectedEof)?;
+        let end = start.checked_add(count).ok_or(FitsError::UnexpectedEof)?;
+        self.data.get(start..end).ok_or(FitsError::UnexpectedEof)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::header::Bitpix;
+    use flate2::{write::GzEncoder, Compression};
+    use ndarray::arr1;
+    use std::io::Write;
+
+    fn card(name: &str, value: Value) -> Keyword {
+        Keyword::Value(name.to_string(), value, String::new())
+    }
+
+    fn gzip(bytes: &[u8]) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(bytes).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    // A 3 x 2 image of 16 bit integers, compressed in two tiles of one row each
+    fn compressed_table(algorithm: &str, tiles: &[Vec<u8>]) -> (Header, Vec<u8>) {
+        let mut rows = Vec::new();
+        let mut heap: Vec<u8> = Vec::new();
+        for tile in tiles {
+            rows.extend((tile.len() as i32).to_be_bytes());
+            rows.extend((heap.len() as i32).to_be_bytes());
+            heap.extend(tile);
+        }
+        let header = Header::from_keyword_list(vec![
+            card("XTENSION", Value::Str("BINTABLE".to_string())),
+            card("BITPIX", Value::Integer(8)),
+            card("NAXIS", Value::Integer(2)),
+            card("NAXIS1", Value::Integer(8)),
+            card("NAXIS2", Value::Integer(tiles.len() as i64)),
+            card("PCOUNT", Value::Integer(heap.len() as i64)),
+            card("GCOUNT", Value::Integer(1)),
+            card("TFIELDS", Value::Integer(1)),
+            card("TTYPE1", Value::Str("COMPRESSED_DATA".to_string())),
+            card("TFORM1", Value::Str("1PB(4)".to_string())),
+            card("ZIMAGE", Value::Boolean(true)),
+            card("ZCMPTYPE", Value::Str(algorithm.to_string())),
+            card("ZBITPIX", Value::Integer(16)),
+            card("ZNAXIS", Value::Integer(2)),
+            card("ZNAXIS1", Value::Integer(3)),
+            card("ZNAXIS2", Value::Integer(2)),
+            card("ZTILE1", Value::Integer(3)),
+            card("ZTILE2", Value::Integer(1)),
+            card("ZNAME1", Value::Str("BLOCKSIZE".to_string())),
+            card("ZVAL1", Value::Integer(32)),
+            card("ZNAME2", Value::Str("BYTEPIX".to_string())),
+            card("ZVAL2", Value::Integer(2)),
+            card("OBJECT", Value::Str("M31".to_string())),
+        ])
+        .unwrap();
+        rows.extend(heap);
+        (header, rows)
+    }
+
+    #[test]
+    fn rice_decode_test() {
+        // First value 5, then a block with fs = 0: differences 0, 1 and -2 mapped onto 0, 2
+        // and 3, each stored as that many zeros followed by a one
+        let bytes = [0x00, 0x05, 0b0001_1001, 0b0001_0000];
+        assert_eq!(rice_decode(&bytes, 3, 2, 32).unwrap(), [5, 6, 4]);
+        // The same with fs = 1, so the lowest bit of each difference is stored as is
+        let bytes = [0x00, 0x05, 0b0010_1001, 0b0011_0000];
+        assert_eq!(rice_decode(&bytes, 3, 2, 32).unwrap(), [5, 6, 4]);
+        // fs = -1: all differences are zero
+        assert_eq!(rice_decode(&[0xFF, 0xF9, 0x00], 3, 2, 32).unwrap(), [-7; 3]);
+        // Bytes are unsigned
+        assert_eq!(rice_decode(&[0xFF, 0x00], 2, 1, 32).unwrap(), [255; 2]);
+
+        assert!(matches!(
+            rice_decode(&[0x00, 0x05, 0b0001_1001], 3, 2, 32),
+            Err(FitsError::UnexpectedEof)
+        ));
+        assert!(matches!(
+            rice_decode(&[0x00], 1, 3, 32),
+            Err(FitsError::UnsupportedCompression(_))
+        ));
+    }
+
+    #[test]
+    fn unshuffle_test() {
+        assert_eq!(unshuffle(&[1, 2, 3, 4, 5, 6], 2), [1, 4, 2, 5, 3, 6]);
+        assert_eq!(unshuffle(&[1, 2, 3], 1), [1, 2, 3]);
+    }
+
+    #[test]
+    fn decompress_test() {
+        let expected = DataArray::I16(arr1(&[5, 6, 4, -7, -7, -7]).into_dyn());
+        let raw =
+            |values: &[i16]| -> Vec<u8> { values.iter().flat_map(|v| v.to_be_bytes()).collect() };
+
+        let rice = [vec![0x00, 0x05, 0x19, 0x10], vec![0xFF, 0xF9, 0x00]];
+        let gzip_1 = [gzip(&raw(&[5, 6, 4])), gzip(&raw(&[-7, -7, -7]))];
+        let gzip_2 = [
+            gzip(&[0x00, 0x00, 0x00, 0x05, 0x06, 0x04]),
+            gzip(&[0xFF, 0xFF, 0xFF, 0xF9, 0xF9, 0xF9]),
+        ];
+        for (algorithm, tiles) in [
+            ("RICE_1", &rice),
+            ("GZIP_1", &gzip_1),
+            ("GZIP_2", &gzip_2),
+            ("NOCOMPRESS", &[raw(&[5, 6, 4]), raw(&[-7, -7, -7])]),
+        ] {
+            let (header, data) = compressed_table(algorithm, tiles);
+            assert!(is_compressed_image(&header));
+            let (image_header, image) = decompress(&header, &data).unwrap();
+            assert_eq!(image, expected, "{}", algorithm);
+            assert_eq!(image_header.bitpix, Bitpix::Int16);
+            assert_eq!(image_header.axes, [3, 2]);
+            assert_eq!(image_header.get_str("XTENSION"), Some("IMAGE   "));
+            assert_eq!(image_header.get_str("OBJECT"), Some("M31"));
+            assert!(!image_header.contains("ZCMPTYPE"));
+            assert!(!image_header.contains("TFORM1"));
+        }
+
+        let (header, data) = compressed_table("HCOMPRESS_1", &rice);
+        assert!(matches!(
+            decompress(&header, &data),
+            Err(FitsError::UnsupportedCompression(_))
+        ));
+        let (header, data) = compressed_table("RICE_1", &rice);
+        assert!(matches!(
+            decompress(&header, &data[..data.len() - 1]),
+            Err(FitsError::UnexpectedEof)
+        ));
+
+        // Descriptors with a negative count, or a count that overflows the heap offset
+        for (count, offset) in [(-1i32, 0i32), (i32::MAX, i32::MAX), (4, -8)] {
+            let (header, mut data) = compressed_table("RICE_1", &rice);
+            data[..4].copy_from_slice(&count.to_be_bytes());
+            data[4..8].copy_from_slice(&offset.to_be_bytes());
+            assert!(matches!(
+                decompress(&header, &data),
+                Err(FitsError::UnexpectedEof)
+            ));
+        }
+    }
+
+    #[test]
+    fn place_tile_test() {
+        // A 4 x 3 image with 2 x 2 tiles: the second tile is cut off by the image border
+        let mut image = vec![0u8; 12];
+        place_tile(&mut image, &[4, 3], &[2, 2], &[2, 1], &[1, 2], 1);
+        place_tile(&mut image, &[4, 3], &[2, 0], &[2, 2], &[3, 4, 5, 6], 1);
+        assert_eq!(image, [0, 0, 3, 4, 0, 0, 5, 6, 0, 0, 1, 2]);
+    }
+}
@@ -0,0 +1,219 @@
+// The data of an HDU, in the element type given by its BITPIX.
+use std::borrow::Cow;
+
+use crate::header::Bitpix;
+use crate::GenericData;
+
+/// Data array holding the values as stored in the file, i.e. without BSCALE and BZERO
+/// applied. Like [`GenericData`] in general, the values are flattened into one dimension,
+/// with NAXIS1 varying fastest.
+///
+/// The exception are `U16` and `U32`: unsigned integers, which FITS stores as signed
+/// integers with BZERO = 2^15 or 2^31 (and BSCALE = 1). These hold the values with BZERO
+/// applied, see [`DataArray::to_unsigned`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum DataArray {
+    /// BITPIX 8; FITS bytes are unsigned
+    U8(GenericData<u8>),
+    /// BITPIX 16
+    I16(GenericData<i16>),
+    /// BITPIX 16 with BZERO = 32768
+    U16(GenericData<u16>),
+    /// BITPIX 32
+    I32(GenericData<i32>),
+    /// BITPIX 32 with BZERO = 2147483648
+    U32(GenericData<u32>),
+    /// BITPIX 64
+    I64(GenericData<i64>),
+    /// BITPIX -32
+    F32(GenericData<f32>),
+    /// BITPIX -64
+    F64(GenericData<f64>),
+}
+
+impl DataArray {
+    /// Data without any values, e.g. of a header-only HDU (NAXIS = 0)
+    pub fn empty(bitpix: &Bitpix) -> Self {
+        let shape = vec![0];
+        match bitpix {
+            Bitpix::Int8 => DataArray::U8(GenericData::zeros(shape)),
+            Bitpix::Int16 => DataArray::I16(GenericData::zeros(shape)),
+            Bitpix::Int32 => DataArray::I32(GenericData::zeros(shape)),
+            Bitpix::Int64 => DataArray::I64(GenericData::zeros(shape)),
+            Bitpix::Float32 => DataArray::F32(GenericData::zeros(shape)),
+            Bitpix::Float64 => DataArray::F64(GenericData::zeros(shape)),
+        }
+    }
+
+    /// The BITPIX matching the element type
+    pub fn bitpix(&self) -> Bitpix {
+        match self {
+            DataArray::U8(_) => Bitpix::Int8,
+            DataArray::I16(_) | DataArray::U16(_) => Bitpix::Int16,
+            DataArray::I32(_) | DataArray::U32(_) => Bitpix::Int32,
+            DataArray::I64(_) => Bitpix::Int64,
+            DataArray::F32(_) => Bitpix::Float32,
+            DataArray::F64(_) => Bitpix::Float64,
+        }
+    }
+
+    /// Number of values
+    pub fn len(&self) -> usize {
+        match self {
+            DataArray::U8(d) => d.len(),
+            DataArray::I16(d) => d.len(),
+            DataArray::U16(d) => d.len(),
+            DataArray::I32(d) => d.len(),
+            DataArray::U32(d) => d.len(),
+            DataArray::I64(d) => d.len(),
+            DataArray::F32(d) => d.len(),
+            DataArray::F64(d) => d.len(),
+        }
+    }
+
+    pub fn shape(&self) -> &[usize] {
+        match self {
+            DataArray::U8(d) => d.shape(),
+            DataArray::I16(d) => d.shape(),
+            DataArray::U16(d) => d.shape(),
+            DataArray::I32(d) => d.shape(),
+            DataArray::U32(d) => d.shape(),
+            DataArray::I64(d) => d.shape(),
+            DataArray::F32(d) => d.shape(),
+            DataArray::F64(d) => d.shape(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The data as `f64`, regardless of the element type. 64-bit integers beyond 2^53
+    /// lose precision.
+    pub fn to_f64(&self) -> GenericData<f64> {
+        match self {
+            DataArray::U8(d) => d.mapv(f64::from),
+            DataArray::I16(d) => d.mapv(f64::from),
+            DataArray::U16(d) => d.mapv(f64::from),
+            DataArray::I32(d) => d.mapv(f64::from),
+            DataArray::U32(d) => d.mapv(f64::from),
+            DataArray::I64(d) => d.mapv(|x| x as f64),
+            DataArray::F32(d) => d.mapv(f64::from),
+            DataArray::F64(d) => d.clone(),
+        }
+    }
+
+    /// The data as unsigned integers, if BSCALE and BZERO follow the convention for storing
+    /// them: BSCALE = 1 and BZERO = 2^15 for BITPIX 16 or 2^31 for BITPIX 32. Adding BZERO
+    /// to a signed integer is the same as flipping its sign bit.
+    pub fn to_unsigned(&self, bscale: f64, bzero: f64) -> Option<DataArray> {
+        if bscale != 1. {
+            return None;
+        }
+        match self {
+            DataArray::I16(d) if bzero == 32768. => {
+                Some(DataArray::U16(d.mapv(|x| x as u16 ^ 0x8000)))
+            }
+            DataArray::I32(d) if bzero == 2147483648. => {
+                Some(DataArray::U32(d.mapv(|x| x as u32 ^ 0x8000_0000)))
+            }
+            _ => None,
+        }
+    }
+
+    /// Whether the values already have BZERO applied, see [`DataArray::to_unsigned`].
+    pub fn is_unsigned(&self) -> bool {
+        matches!(self, DataArray::U16(_) | DataArray::U32(_))
+    }
+
+    /// The data, if it is stored as `f64` (BITPIX -64)
+    pub fn as_f64(&self) -> Option<&GenericData<f64>> {
+        match self {
+            DataArray::F64(d) => Some(d),
+            _ => None,
+        }
+    }
+
+    /// The data as `f64`, like [`DataArray::to_f64`], but borrowed instead of copied if it is
+    /// already stored as `f64`.
+    pub fn as_f64_cow(&self) -> Cow<'_, GenericData<f64>> {
+        match self {
+            DataArray::F64(d) => Cow::Borrowed(d),
+            _ => Cow::Owned(self.to_f64()),
+        }
+    }
+}
+
+macro_rules! impl_from_data {
+    ($($t:ty => $variant:ident),*) => {
+        $(
+            impl From<GenericData<$t>> for DataArray {
+                fn from(data: GenericData<$t>) -> Self {
+                    DataArray::$variant(data)
+                }
+            }
+        )*
+    };
+}
+
+impl_from_data!(
+    u8 => U8, i16 => I16, u16 => U16, i32 => I32, u32 => U32, i64 => I64, f32 => F32, f64 => F64
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::arr1;
+
+    #[test]
+    fn data_array_test() {
+        let data = DataArray::from(arr1(&[1i16, -2, 300]).into_dyn());
+        assert_eq!(data.bitpix(), Bitpix::Int16);
+        assert_eq!(data.len(), 3);
+        assert_eq!(data.shape(), [3]);
+        assert_eq!(data.to_f64(), arr1(&[1., -2., 300.]).into_dyn());
+        assert_eq!(data.as_f64(), None);
+
+        let data = DataArray::from(arr1(&[0.5f64]).into_dyn());
+        assert_eq!(data.bitpix(), Bitpix::Float64);
+        assert_eq!(data.as_f64(), Some(&arr1(&[0.5]).into_dyn()));
+        assert!(DataArray::U8(GenericData::zeros(vec![0])).is_empty());
+
+        let data = DataArray::from(arr1(&[3u16]).into_dyn());
+        assert_eq!(data.bitpix(), Bitpix::Int16);
+        assert_eq!(data.to_f64(), arr1(&[3.]).into_dyn());
+
+        let data = DataArray::empty(&Bitpix::Int32);
+        assert_eq!(data.bitpix(), Bitpix::Int32);
+        assert!(data.is_empty());
+    }
+
+    #[test]
+    fn to_unsigned_test() {
+        let data = DataArray::from(arr1(&[-32768i16, -1, 0, 32767]).into_dyn());
+        let unsigned = data.to_unsigned(1., 32768.).unwrap();
+        assert_eq!(
+            unsigned,
+            DataArray::U16(arr1(&[0, 32767, 32768, 65535]).into_dyn())
+        );
+        assert!(unsigned.is_unsigned());
+        assert_eq!(data.to_unsigned(1., 0.), None);
+        assert_eq!(data.to_unsigned(2., 32768.), None);
+
+        let data = DataArray::from(arr1(&[i32::MIN, 0, i32::MAX]).into_dyn());
+        let unsigned = data.to_unsigned(1., 2147483648.).unwrap();
+        let expected = arr1(&[0, 2147483648, u32::MAX]).into_dyn();
+        assert_eq!(unsigned, DataArray::U32(expected));
+        assert_eq!(data.to_unsigned(1., 32768.), None);
+    }
+
+    #[test]
+    fn as_f64_cow_test() {
+        let data = DataArray::F64(arr1(&[1.5, -2.]).into_dyn());
+        assert!(matches!(data.as_f64_cow(), Cow::Borrowed(_)));
+        let data = DataArray::I16(arr1(&[3i16, -4]).into_dyn());
+        let values = data.as_f64_cow();
+        assert!(matches!(values, Cow::Owned(_)));
+        assert_eq!(values.into_owned(), arr1(&[3., -4.]).into_dyn());
+    }
+}
@@ -0,0 +1,137 @@
+use std::{fmt, io, str};
+
+use crate::parsing::header::ParseError;
+
+#[derive(Debug)]
+pub enum FitsError {
+    /// Two arrays (or an array and a header) that should agree in shape do not.
+    ShapeMismatch {
+        expected: Vec<usize>,
+        found: Vec<usize>,
+    },
+    /// Two images that should have the same data type have a different BITPIX.
+    BitpixMismatch { expected: i64, found: i64 },
+    /// An operation combining several images was given none.
+    NoImages,
+    /// The operation does not support data with this number of axes.
+    InvalidNaxis(usize),
+    /// Too few valid data points for a fit, e.g. after excluding NaN values.
+    InsufficientData { found: usize, required: usize },
+    /// A requested pixel region lies (partly) outside the image.
+    OutOfBounds,
+    /// A parameter of an operation is outside its valid range, e.g. zero bins. Contains
+    /// the name of the parameter.
+    InvalidArgument(&'static str),
+    /// An image section (e.g. `[1:512,*]`) could not be parsed.
+    InvalidSection(String),
+    /// The keywords do not make up a valid header, e.g. a mandatory keyword is missing.
+    InvalidHeader,
+    /// Not a valid FITS keyword name (at most 8 characters from A-Z, 0-9, '-' and '_').
+    InvalidKeywordName(String),
+    /// The value of this keyword does not fit in a single 80 character record.
+    ValueTooLong(String),
+    /// The header has no keyword with this name.
+    KeywordNotFound(String),
+    /// The operation is not allowed on a mandatory keyword (SIMPLE, BITPIX, NAXIS, NAXISn).
+    MandatoryKeyword(String),
+    /// A mandatory keyword (e.g. BITPIX, NAXIS, or PCOUNT of an extension) is missing from
+    /// the header.
+    MissingKeyword(&'static str),
+    /// BITPIX is not one of the values allowed by the standard (8, 16, 32, 64, -32, -64).
+    InvalidBitpix(i64),
+    /// An extension HDU has an XTENSION other than IMAGE, BINTABLE or TABLE.
+    UnknownExtension(String),
+    /// A tile compressed image uses a compression algorithm or option that is not supported.
+    UnsupportedCompression(String),
+    /// A table has no column with this name.
+    ColumnNotFound(String),
+    /// A table column cannot be read as the requested type, e.g. a string column as numbers.
+    ColumnTypeMismatch(String),
+    /// The file ended before the end of the header or data.
+    UnexpectedEof,
+    /// A header record could not be parsed.
+    ParseError(String),
+    /// Text that should be UTF-8 is not.
+    Utf8(str::Utf8Error),
+    /// Reading or writing the underlying file failed.
+    Io(io::Error),
+}
+
+impl fmt::Display for FitsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FitsError::ShapeMismatch { expected, found } => {
+                write!(
+                    f,
+                    "shape mismatch: expected {:?}, found {:?}",
+                    expected, found
+                )
+            }
+            FitsError::BitpixMismatch { expected, found } => {
+                write!(f, "BITPIX mismatch: expected {}, found {}", expected, found)
+            }
+            FitsError::NoImages => write!(f, "no images given"),
+            FitsError::InvalidNaxis(n) => write!(f, "unsupported number of axes: {}", n),
+            FitsError::InsufficientData { found, required } => write!(
+                f,
+                "insufficient data: {} valid points, at least {} required",
+                found, required
+            ),
+            FitsError::OutOfBounds => write!(f, "region lies outside the image"),
+            FitsError::InvalidArgument(name) => write!(f, "invalid value of '{}'", name),
+            FitsError::InvalidSection(s) => write!(f, "invalid image section '{}'", s),
+            FitsError::InvalidHeader => write!(f, "invalid header"),
+            FitsError::InvalidKeywordName(name) => write!(f, "invalid keyword name '{}'", name),
+            FitsError::ValueTooLong(name) => {
+                write!(f, "value of keyword '{}' does not fit in one record", name)
+            }
+            FitsError::KeywordNotFound(name) => write!(f, "keyword '{}' not found", name),
+            FitsError::MandatoryKeyword(name) => {
+                write!(f, "keyword '{}' is mandatory and cannot be changed", name)
+            }
+            FitsError::MissingKeyword(name) => write!(f, "mandatory keyword '{}' missing", name),
+            FitsError::InvalidBitpix(n) => write!(f, "invalid BITPIX: {}", n),
+            FitsError::UnknownExtension(x) => write!(f, "unknown extension type '{}'", x),
+            FitsError::UnsupportedCompression(x) => write!(f, "unsupported compression: {}", x),
+            FitsError::ColumnNotFound(name) => write!(f, "column '{}' not found", name),
+            FitsError::ColumnTypeMismatch(name) => {
+                write!(f, "column '{}' cannot be read as this type", name)
+            }
+            FitsError::UnexpectedEof => write!(f, "unexpected end of file"),
+            FitsError::ParseError(e) => write!(f, "parse error: {}", e),
+            FitsError::Utf8(e) => write!(f, "invalid UTF-8: {}", e),
+            FitsError::Io(e) => write!(f, "I/O error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for FitsError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            FitsError::Io(e) => Some(e),
+            FitsError::Utf8(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for FitsError {
+    fn from(e: io::Error) -> Self {
+        FitsError::Io(e)
+    }
+}
+
+impl From<str::Utf8Error> for FitsError {
+    fn from(e: str::Utf8Error) -> Self {
+        FitsError::Utf8(e)
+    }
+}
+
+impl From<ParseError> for FitsError {
+    fn from(e: ParseError) -> Self {
+        match e {
+            ParseError::MissingEnd => FitsError::UnexpectedEof,
+            e => FitsError::ParseError(e.to_string()),
+        }
+    }
+}
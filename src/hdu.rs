@@ -0,0 +1,1043 @@
+// Multi-Extension FITS (MEF) files: a primary HDU followed by any number of extensions.
+// See section 7 of the FITS standard (2018).
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::ops::Index;
+use std::path::Path;
+use std::slice::{Chunks, Iter};
+use std::str;
+use std::sync::Arc;
+
+use crate::definitions::BLOCK_SIZE;
+use crate::header::Header;
+use crate::io::{eof_error, read_header_bytes};
+use crate::parsing::header::{parse_header, ParseError, Value};
+use crate::parsing::read_data;
+use crate::table::{AsciiTForm, ColumnDescriptor, TForm, TFormType};
+use crate::writing::{check_keyword_names, data_to_bytes, header_to_bytes};
+use crate::{BasicFits, DataArray, FitsError};
+
+/// An IMAGE extension: an array like that of the primary HDU.
+pub struct ImageExtension {
+    pub header: Header,
+    pub data: DataArray,
+}
+
+impl ImageExtension {
+    /// Combine a header and data into an extension, checking that they match as
+    /// [`BasicFits::create`] does. The header must have XTENSION = 'IMAGE', PCOUNT = 0 and
+    /// GCOUNT = 1, e.g. one from [`Header::for_image_extension`].
+    pub fn create(header: Header, data: DataArray) -> Result<Self, FitsError> {
+        match header.get_str("XTENSION") {
+            Some(xtension) if xtension.trim_end() == "IMAGE" => (),
+            Some(_) => return Err(FitsError::InvalidHeader),
+            None => return Err(FitsError::MissingKeyword("XTENSION")),
+        }
+        if integer_keyword(&header, "PCOUNT")? != 0 || integer_keyword(&header, "GCOUNT")? != 1 {
+            return Err(FitsError::InvalidHeader);
+        }
+        let BasicFits { header, data } = BasicFits::create(header, data)?;
+        Ok(ImageExtension {
+            header,
+            data: Arc::unwrap_or_clone(data),
+        })
+    }
+}
+
+/// A BINTABLE extension. The rows and the heap following them are kept as raw bytes.
+pub struct BinTableExtension {
+    pub header: Header,
+    /// NAXIS2
+    pub nrows: usize,
+    /// Number of (valid) columns, see [`Header::column_descriptors`]
+    pub ncols: usize,
+    pub columns: Vec<ColumnDescriptor>,
+    /// NAXIS2 rows of NAXIS1 bytes each, followed by the PCOUNT bytes of the heap
+    pub data: Vec<u8>,
+}
+
+impl BinTableExtension {
+    /// All values of the numeric column `name` (B, I, J, K, E or D), row by row. Integer
+    /// values equal to TNULLn become NaN, and TSCALn and TZEROn are applied.
+    pub fn read_column_f64(&self, name: &str) -> Result<Vec<f64>, FitsError> {
+        let (column, cells) = self.cells(name)?;
+        let TForm::Fixed(_, kind) = column.format else {
+            return Err(FitsError::ColumnTypeMismatch(name.to_string()));
+        };
+        if !matches!(
+            kind,
+            TFormType::B | TFormType::I | TFormType::J | TFormType::K | TFormType::E | TFormType::D
+        ) {
+            return Err(FitsError::ColumnTypeMismatch(name.to_string()));
+        }
+
+        let scale = column.scale.unwrap_or(1.);
+        let zero = column.zero.unwrap_or(0.);
+        let values = cells
+            .flat_map(|cell| cell.chunks_exact(kind.size()))
+            .map(|b| match decode_number(kind, b) {
+                (_, Some(n)) if column.null_value == Some(n) => f64::NAN,
+                (x, _) => zero + scale * x,
+            });
+        Ok(values.collect())
+    }
+
+    /// The strings of the character column `name` (A), one per row, without trailing spaces.
+    /// A NUL character ends the string early.
+    pub fn read_column_str(&self, name: &str) -> Result<Vec<String>, FitsError> {
+        let (column, cells) = self.cells(name)?;
+        if !matches!(column.format, TForm::Fixed(_, TFormType::A)) {
+            return Err(FitsError::ColumnTypeMismatch(name.to_string()));
+        }
+        cells
+            .map(|cell| {
+                let end = cell.iter().position(|&b| b == 0).unwrap_or(cell.len());
+                Ok(str::from_utf8(&cell[..end])?.trim_end().to_string())
+            })
+            .collect()
+    }
+
+    /// All values of the logical column `name` (L), row by row. Only 'T' is true, both
+    /// 'F' and the undefined value (NUL) are false.
+    pub fn read_column_bool(&self, name: &str) -> Result<Vec<bool>, FitsError> {
+        let (column, cells) = self.cells(name)?;
+        if !matches!(column.format, TForm::Fixed(_, TFormType::L)) {
+            return Err(FitsError::ColumnTypeMismatch(name.to_string()));
+        }
+        Ok(cells.flatten().map(|&b| b == b'T').collect())
+    }
+
+    // The column called `name` (compared like `Header::column_index_by_name`) and the bytes
+    // of that column in every row
+    fn cells(
+        &self,
+        name: &str,
+    ) -> Result<(&ColumnDescriptor, impl Iterator<Item = &[u8]>), FitsError> {
+        let mut offset = 0;
+        for (i, column) in self.columns.iter().enumerate() {
+            // Offsets cannot be computed past a column without a valid TFORMn
+            if column.index != i + 1 {
+                return Err(FitsError::InvalidHeader);
+            }
+            let width = self.column_width(column);
+            if !column.name.trim().eq_ignore_ascii_case(name) {
+                offset += width;
+                continue;
+            }
+
+            let row_size = self.header.axes.first().copied().unwrap_or(0);
+            if offset + width > row_size {
+                return Err(FitsError::InvalidHeader);
+            }
+            if self.data.len() < row_size * self.nrows {
+                return Err(FitsError::UnexpectedEof);
+            }
+            let rows = self.data.chunks_exact(row_size.max(1)).take(self.nrows);
+            return Ok((column, rows.map(move |row| &row[offset..offset + width])));
+        }
+        Err(FitsError::ColumnNotFound(name.to_string()))
+    }
+
+    // Width of a column in bytes. The format of TFORMn does not tell a 32-bit (P) from a
+    // 64-bit (Q) array descriptor, so that is looked up in the header.
+    fn column_width(&self, column: &ColumnDescriptor) -> usize {
+        match column.format {
+            TForm::Variable(_) => {
+                let tform = self.header.get_str(&format!("TFORM{}", column.index));
+                if tform.is_some_and(|tform| tform.contains('Q')) {
+                    16
+                } else {
+                    8
+                }
+            }
+            TForm::Fixed(repeat, TFormType::X) => repeat.div_ceil(8),
+            TForm::Fixed(repeat, kind) => repeat * kind.size(),
+        }
+    }
+}
+
+/// A TABLE (ASCII table) extension. The rows are kept as raw bytes.
+pub struct AsciiTableExtension {
+    pub header: Header,
+    /// NAXIS2
+    pub nrows: usize,
+    /// TFIELDS
+    pub ncols: usize,
+    /// NAXIS2 rows of NAXIS1 characters each
+    pub data: Vec<u8>,
+}
+
+impl AsciiTableExtension {
+    /// The values of column `name`, one per row: strings (without trailing spaces) for `A`
+    /// columns, integers for `I` and floats for `F`, `E` and `D`. Fields that are blank or
+    /// equal to TNULLn are undefined.
+    pub fn read_column(&self, name: &str) -> Result<Vec<Value>, FitsError> {
+        let n = self
+            .header
+            .column_index_by_name(name)
+            .ok_or_else(|| FitsError::ColumnNotFound(name.to_string()))?;
+        let format = self
+            .header
+            .get_str(&format!("TFORM{}", n))
+            .and_then(AsciiTForm::from_tform)
+            .ok_or(FitsError::InvalidHeader)?;
+        let start = match self.header.get_int(&format!("TBCOL{}", n)) {
+            Some(column) if column >= 1 => column as usize - 1,
+            _ => return Err(FitsError::InvalidHeader),
+        };
+        let null = self.header.get_str(&format!("TNULL{}", n));
+
+        let row_size = self.header.axes.first().copied().unwrap_or(0);
+        let end = start + format.width();
+        if end > row_size {
+            return Err(FitsError::InvalidHeader);
+        }
+        if self.data.len() < row_size * self.nrows {
+            return Err(FitsError::UnexpectedEof);
+        }
+        self.data
+            .chunks_exact(row_size.max(1))
+            .take(self.nrows)
+            .map(|row| {
+                let field = str::from_utf8(&row[start..end])?;
+                if field.trim().is_empty() || null.is_some_and(|null| null.trim() == field.trim()) {
+                    return Ok(Value::Undefined);
+                }
+                parse_ascii_field(field, format)
+            })
+            .collect()
+    }
+}
+
+/// A Header Data Unit: the primary HDU or one of the extensions following it.
+pub enum Hdu {
+    Primary(BasicFits),
+    ImageExt(ImageExtension),
+    BinTable(BinTableExtension),
+    AsciiTable(AsciiTableExtension),
+}
+
+impl Hdu {
+    pub fn header(&self) -> &Header {
+        match self {
+            Hdu::Primary(fits) => &fits.header,
+            Hdu::ImageExt(ext) => &ext.header,
+            Hdu::BinTable(ext) => &ext.header,
+            Hdu::AsciiTable(ext) => &ext.header,
+        }
+    }
+
+    /// EXTNAME, without trailing spaces
+    pub fn name(&self) -> Option<&str> {
+        self.header().get_str("EXTNAME").map(str::trim_end)
+    }
+
+    /// EXTVER, which defaults to 1
+    pub fn version(&self) -> i64 {
+        match self.header().get_value("EXTVER") {
+            Some(Value::Integer(n)) => *n,
+            _ => 1,
+        }
+    }
+
+    /// The header and data blocks of the HDU. The data is padded to whole blocks with
+    /// zeros, or spaces for an ASCII table.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = header_to_bytes(&self.header().keywords);
+        let (data, padding) = match self {
+            Hdu::Primary(fits) => (data_to_bytes(&fits.data), 0),
+            Hdu::ImageExt(ext) => (data_to_bytes(&ext.data), 0),
+            Hdu::BinTable(ext) => (ext.data.clone(), 0),
+            Hdu::AsciiTable(ext) => (ext.data.clone(), b' '),
+        };
+        bytes.extend(&data);
+        bytes.resize(bytes.len().next_multiple_of(BLOCK_SIZE), padding);
+        bytes
+    }
+}
+
+/// All HDUs of a FITS file, in order. The first one is always [`Hdu::Primary`].
+pub struct HduList {
+    hdus: Vec<Hdu>,
+}
+
+impl HduList {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, FitsError> {
+        let mut f = File::open(path)?;
+        let mut buffer = Vec::new();
+        f.read_to_end(&mut buffer)?;
+        Self::from_bytes(buffer)
+    }
+
+    /// A list with only `primary`, see [`HduList::push`] to add extensions.
+    pub fn new(primary: BasicFits) -> Self {
+        HduList {
+            hdus: vec![Hdu::Primary(primary)],
+        }
+    }
+
+    /// Read HDUs until the bytes run out.
+    pub fn from_bytes(bytes: Vec<u8>) -> Result<Self, FitsError> {
+        let mut blocks = bytes.chunks(BLOCK_SIZE);
+
+        let header = Header::from_keyword_list(parse_header(&mut blocks)?)?;
+        let mut hdus = vec![primary_data(header, &mut blocks)?];
+
+        while blocks.len() > 0 {
+            hdus.push(read_extension(&mut blocks)?);
+        }
+        Ok(HduList { hdus })
+    }
+
+    /// Read the HDUs one at a time, see [`HduIterator`].
+    pub fn iter_from_reader<R: Read + Seek>(reader: R) -> HduIterator<R> {
+        HduIterator {
+            reader,
+            position: None,
+            end: 0,
+            done: false,
+        }
+    }
+
+    /// Append an extension.
+    ///
+    /// Panics if `extension` is an [`Hdu::Primary`], only the first HDU can be one.
+    pub fn push(&mut self, extension: Hdu) {
+        assert!(
+            !matches!(extension, Hdu::Primary(_)),
+            "only the first HDU can be a primary HDU"
+        );
+        self.hdus.push(extension);
+    }
+
+    /// Write all HDUs to `writer`, in order, see [`Hdu::to_bytes`].
+    ///
+    /// Fails with [`FitsError::InvalidKeywordName`] if a keyword has a name that can not be
+    /// written, before anything is written.
+    pub fn write<W: Write>(&self, writer: &mut W) -> Result<(), FitsError> {
+        for hdu in self.hdus.iter() {
+            check_keyword_names(&hdu.header().keywords)?;
+        }
+        for hdu in self.hdus.iter() {
+            writer.write_all(&hdu.to_bytes())?;
+        }
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.hdus.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.hdus.is_empty()
+    }
+
+    pub fn iter(&self) -> Iter<'_, Hdu> {
+        self.hdus.iter()
+    }
+
+    pub fn get(&self, index: usize) -> Option<&Hdu> {
+        self.hdus.get(index)
+    }
+
+    /// The first HDU with this EXTNAME and, if given, EXTVER.
+    pub fn get_by_name(&self, name: &str, version: Option<i64>) -> Option<&Hdu> {
+        self.hdus.iter().find(|hdu| {
+            hdu.name() == Some(name) && (version.is_none() || version == Some(hdu.version()))
+        })
+    }
+}
+
+impl Index<usize> for HduList {
+    type Output = Hdu;
+
+    fn index(&self, index: usize) -> &Hdu {
+        &self.hdus[index]
+    }
+}
+
+/// Look up an HDU by EXTNAME. Panics if there is none, see [`HduList::get_by_name`].
+impl Index<&str> for HduList {
+    type Output = Hdu;
+
+    fn index(&self, name: &str) -> &Hdu {
+        self.get_by_name(name, None)
+            .unwrap_or_else(|| panic!("no HDU with EXTNAME '{}'", name))
+    }
+}
+
+/// Look up an HDU by EXTNAME and EXTVER. Panics if there is none.
+impl Index<(&str, i64)> for HduList {
+    type Output = Hdu;
+
+    fn index(&self, (name, version): (&str, i64)) -> &Hdu {
+        self.get_by_name(name, Some(version))
+            .unwrap_or_else(|| panic!("no HDU with EXTNAME '{}' and EXTVER {}", name, version))
+    }
+}
+
+impl<'a> IntoIterator for &'a HduList {
+    type Item = &'a Hdu;
+    type IntoIter = Iter<'a, Hdu>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.hdus.iter()
+    }
+}
+
+/// Reads the HDUs of a file one by one, so only a single HDU is in memory at a time. Created
+/// by [`HduList::iter_from_reader`].
+///
+/// The iterator stops after the first error.
+pub struct HduIterator<R> {
+    reader: R,
+    /// Byte offset of the next header, `None` before the primary HDU is read
+    position: Option<u64>,
+    /// Length of the file
+    end: u64,
+    done: bool,
+}
+
+impl<R: Read + Seek> HduIterator<R> {
+    fn read_next(&mut self) -> Result<Option<Hdu>, FitsError> {
+        let primary = self.position.is_none();
+        let position = match self.position {
+            Some(position) => position,
+            None => {
+                self.end = self.reader.seek(SeekFrom::End(0))?;
+                self.reader.seek(SeekFrom::Start(0))?
+            }
+        };
+        if !primary && position >= self.end {
+            return Ok(None);
+        }
+
+        self.reader.seek(SeekFrom::Start(position))?;
+        let header_bytes = read_header_bytes(&mut self.reader)?;
+        let header =
+            Header::from_keyword_list(parse_header(&mut header_bytes.chunks(BLOCK_SIZE))?)?;
+        let size = hdu_data_size(&header, primary)?;
+
+        // The size comes from the header, so check that the file has that much data left
+        // before allocating it
+        let data_end = (position + header_bytes.len() as u64).checked_add(size as u64);
+        if data_end.is_none_or(|end| end > self.end) {
+            return Err(FitsError::UnexpectedEof);
+        }
+
+        // Only the data itself is read, the padding after it is skipped by the next seek
+        let mut data = vec![0u8; size.next_multiple_of(BLOCK_SIZE)];
+        self.reader
+            .read_exact(&mut data[..size])
+            .map_err(eof_error)?;
+        self.position = Some(position + (header_bytes.len() + data.len()) as u64);
+
+        let mut blocks = data.chunks(BLOCK_SIZE);
+        Ok(Some(if primary {
+            primary_data(header, &mut blocks)?
+        } else {
+            extension_data(header, &mut blocks)?
+        }))
+    }
+}
+
+impl<R: Read + Seek> Iterator for HduIterator<R> {
+    type Item = Result<Hdu, FitsError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let result = self.read_next();
+        if !matches!(result, Ok(Some(_))) {
+            self.done = true;
+        }
+        result.transpose()
+    }
+}
+
+fn primary_data(header: Header, blocks: &mut Chunks<u8>) -> Result<Hdu, FitsError> {
+    let data = read_data(&header, blocks)?;
+    Ok(Hdu::Primary(BasicFits {
+        header,
+        data: Arc::new(data),
+    }))
+}
+
+fn read_extension(blocks: &mut Chunks<u8>) -> Result<Hdu, FitsError> {
+    let header = Header::from_keyword_list(parse_header(blocks)?)?;
+    extension_data(header, blocks)
+}
+
+fn extension_data(header: Header, blocks: &mut Chunks<u8>) -> Result<Hdu, FitsError> {
+    let xtension = header
+        .get_str("XTENSION")
+        .ok_or(FitsError::MissingKeyword("XTENSION"))?
+        .trim_end()
+        .to_string();
+    let pcount = integer_keyword(&header, "PCOUNT")?;
+    let gcount = integer_keyword(&header, "GCOUNT")?;
+
+    Ok(match xtension.as_str() {
+        "IMAGE" => {
+            let data = read_data(&header, blocks)?;
+            Hdu::ImageExt(ImageExtension { header, data })
+        }
+        "BINTABLE" => {
+            let data = read_raw(&header, pcount, gcount, blocks)?;
+            #[cfg(feature = "compression")]
+            if crate::compression::is_compressed_image(&header) {
+                let (header, data) = crate::compression::decompress(&header, &data)?;
+                return Ok(Hdu::ImageExt(ImageExtension { header, data }));
+            }
+            let columns = header.column_descriptors();
+            Hdu::BinTable(BinTableExtension {
+                nrows: header.axes.get(1).copied().unwrap_or(0),
+                ncols: columns.len(),
+                header,
+                columns,
+                data,
+            })
+        }
+        "TABLE" => {
+            let data = read_raw(&header, pcount, gcount, blocks)?;
+            Hdu::AsciiTable(AsciiTableExtension {
+                nrows: header.axes.get(1).copied().unwrap_or(0),
+                ncols: integer_keyword(&header, "TFIELDS")?,
+                header,
+                data,
+            })
+        }
+        _ => return Err(FitsError::UnknownExtension(xtension)),
+    })
+}
+
+fn integer_keyword(header: &Header, name: &'static str) -> Result<usize, FitsError> {
+    match header.get_value(name) {
+        Some(Value::Integer(n)) if *n >= 0 => Ok(*n as usize),
+        Some(_) => Err(FitsError::InvalidHeader),
+        None => Err(FitsError::MissingKeyword(name)),
+    }
+}
+
+// A (non-blank) field of an ASCII table. Floats without a decimal point have `d` implied
+// decimals, and may use D as exponent letter.
+fn parse_ascii_field(field: &str, format: AsciiTForm) -> Result<Value, FitsError> {
+    let invalid = || FitsError::from(ParseError::InvalidValue(field.to_string()));
+    let text = field.trim();
+    match format {
+        AsciiTForm::A(_) => Ok(Value::Str(field.trim_end().to_string())),
+        AsciiTForm::I(_) => text.parse().map(Value::Integer).map_err(|_| invalid()),
+        AsciiTForm::F(_, d) | AsciiTForm::E(_, d) | AsciiTForm::D(_, d) => {
+            let x: f64 = text.replace('D', "E").parse().map_err(|_| invalid())?;
+            if text.contains('.') {
+                Ok(Value::Float(x))
+            } else {
+                Ok(Value::Float(x / 10f64.powi(d as i32)))
+            }
+        }
+    }
+}
+
+// A big endian table value of a numeric type, with the integer itself for integer types
+// (to compare with TNULLn)
+fn decode_number(kind: TFormType, b: &[u8]) -> (f64, Option<i64>) {
+    let integer = |n: i64| (n as f64, Some(n));
+    match kind {
+        TFormType::B => integer(i64::from(b[0])),
+        TFormType::I => integer(i64::from(i16::from_be_bytes(b.try_into().unwrap()))),
+        TFormType::J => integer(i64::from(i32::from_be_bytes(b.try_into().unwrap()))),
+        TFormType::K => integer(i64::from_be_bytes(b.try_into().unwrap())),
+        TFormType::E => (f64::from(f32::from_be_bytes(b.try_into().unwrap())), None),
+        TFormType::D => (f64::from_be_bytes(b.try_into().unwrap()), None),
+        _ => (f64::NAN, None),
+    }
+}
+
+// Number of bytes in the data of an extension: |BITPIX| * GCOUNT * (PCOUNT + NAXIS1 * ... *
+// NAXISn) bits, without padding. Fails if that does not fit in a usize.
+fn raw_size(header: &Header, pcount: usize, gcount: usize) -> Result<usize, FitsError> {
+    let values = if header.axes.is_empty() {
+        Some(0)
+    } else {
+        header
+            .axes
+            .iter()
+            .try_fold(1usize, |size, &n| size.checked_mul(n))
+    };
+    values
+        .and_then(|values| values.checked_add(pcount))
+        .and_then(|n| n.checked_mul(gcount))
+        .and_then(|n| n.checked_mul(header.bitpix.bytes_per_pixel()))
+        .ok_or(FitsError::InvalidHeader)
+}
+
+// Number of bytes in the data of an HDU, see `raw_size`. Only extensions have PCOUNT and
+// GCOUNT.
+pub(crate) fn hdu_data_size(header: &Header, primary: bool) -> Result<usize, FitsError> {
+    if primary {
+        raw_size(header, 0, 1)
+    } else {
+        raw_size(
+            header,
+            integer_keyword(header, "PCOUNT")?,
+            integer_keyword(header, "GCOUNT")?,
+        )
+    }
+}
+
+// The data of an extension as bytes, see `raw_size`, padded to whole blocks
+fn read_raw(
+    header: &Header,
+    pcount: usize,
+    gcount: usize,
+    blocks: &mut Chunks<u8>,
+) -> Result<Vec<u8>, FitsError> {
+    let size = raw_size(header, pcount, gcount)?;
+    if size > blocks.len() * BLOCK_SIZE {
+        return Err(FitsError::UnexpectedEof);
+    }
+
+    let mut data = Vec::with_capacity(size);
+    while data.len() < size {
+        let block = blocks.next().ok_or(FitsError::UnexpectedEof)?;
+        let read = (size - data.len()).min(BLOCK_SIZE);
+        if block.len() < read {
+            return Err(FitsError::UnexpectedEof);
+        }
+        data.extend_from_slice(&block[..read]);
+    }
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::header::Bitpix;
+    use crate::parsing::header::Keyword;
+    use ndarray::arr1;
+    use std::io::Cursor;
+
+    fn card(name: &str, value: Value) -> Keyword {
+        Keyword::Value(name.to_string(), value, String::new())
+    }
+
+    fn extension(xtension: &str, bitpix: i64, axes: &[usize], extra: Vec<Keyword>) -> Vec<u8> {
+        let mut keywords = vec![
+            card("XTENSION", Value::Str(xtension.to_string())),
+            card("BITPIX", Value::Integer(bitpix)),
+            card("NAXIS", Value::Integer(axes.len() as i64)),
+        ];
+        for (i, n) in axes.iter().enumerate() {
+            keywords.push(card(&format!("NAXIS{}", i + 1), Value::Integer(*n as i64)));
+        }
+        keywords.extend(extra);
+        header_to_bytes(&keywords)
+    }
+
+    fn mef() -> Vec<u8> {
+        let primary = vec![
+            card("SIMPLE", Value::Boolean(true)),
+            card("BITPIX", Value::Integer(8)),
+            card("NAXIS", Value::Integer(0)),
+            card("EXTEND", Value::Boolean(true)),
+        ];
+        let mut bytes = header_to_bytes(&primary);
+
+        for version in 1..=2 {
+            let counts = vec![
+                card("PCOUNT", Value::Integer(0)),
+                card("GCOUNT", Value::Integer(1)),
+                card("EXTNAME", Value::Str("SCI".to_string())),
+                card("EXTVER", Value::Integer(version)),
+            ];
+            bytes.extend(extension("IMAGE", 16, &[3], counts));
+            let data = DataArray::I16(arr1(&[1, 2, 3 * version as i16]).into_dyn());
+            bytes.extend(data_to_bytes(&data));
+        }
+
+        // Two rows of a 4 byte integer and a 2 character string, plus a 4 byte heap
+        let table = vec![
+            card("PCOUNT", Value::Integer(4)),
+            card("GCOUNT", Value::Integer(1)),
+            card("TFIELDS", Value::Integer(2)),
+            card("TTYPE1", Value::Str("ID".to_string())),
+            card("TFORM1", Value::Str("1J".to_string())),
+            card("TFORM2", Value::Str("2A".to_string())),
+            card("EXTNAME", Value::Str("CATALOG ".to_string())),
+        ];
+        bytes.extend(extension("BINTABLE", 8, &[6, 2], table));
+        let rows: &[u8] = b"\0\0\0\x01ab\0\0\0\x02cdHEAP";
+        let mut data = rows.to_vec();
+        data.resize(BLOCK_SIZE, 0);
+        bytes.extend(data);
+        bytes
+    }
+
+    #[test]
+    fn hdu_list_test() {
+        let hdus = HduList::from_bytes(mef()).unwrap();
+        assert_eq!(hdus.len(), 4);
+        assert!(matches!(hdus[0], Hdu::Primary(_)));
+        assert_eq!(
+            hdus.iter()
+                .filter(|hdu| matches!(hdu, Hdu::ImageExt(_)))
+                .count(),
+            2
+        );
+
+        match &hdus[("SCI", 2)] {
+            Hdu::ImageExt(ext) => {
+                assert_eq!(ext.data, DataArray::I16(arr1(&[1, 2, 6]).into_dyn()))
+            }
+            _ => panic!("SCI 2 is not an image extension"),
+        }
+        assert_eq!(hdus["SCI"].version(), 1);
+        assert!(hdus.get_by_name("SCI", Some(3)).is_none());
+
+        match &hdus["CATALOG"] {
+            Hdu::BinTable(table) => {
+                assert_eq!(table.data, b"\0\0\0\x01ab\0\0\0\x02cdHEAP");
+                assert_eq!(table.columns.len(), 2);
+                assert_eq!(table.columns[0].name, "ID");
+                assert_eq!((table.nrows, table.ncols), (2, 2));
+                assert_eq!(table.read_column_f64("ID").unwrap(), [1., 2.]);
+                assert_eq!(
+                    table.read_column_str("COL2").unwrap_err().to_string(),
+                    "column 'COL2' not found"
+                );
+            }
+            _ => panic!("CATALOG is not a binary table"),
+        }
+    }
+
+    #[test]
+    fn read_column_test() {
+        let keywords = vec![
+            card("XTENSION", Value::Str("BINTABLE".to_string())),
+            card("BITPIX", Value::Integer(8)),
+            card("NAXIS", Value::Integer(2)),
+            card("NAXIS1", Value::Integer(20)),
+            card("NAXIS2", Value::Integer(2)),
+            card("PCOUNT", Value::Integer(0)),
+            card("GCOUNT", Value::Integer(1)),
+            card("TFIELDS", Value::Integer(6)),
+            card("TTYPE1", Value::Str("FLUX".to_string())),
+            card("TFORM1", Value::Str("1D".to_string())),
+            card("TTYPE2", Value::Str("MAG".to_string())),
+            card("TFORM2", Value::Str("E".to_string())),
+            card("TTYPE3", Value::Str("ID".to_string())),
+            card("TFORM3", Value::Str("1I".to_string())),
+            card("TNULL3", Value::Integer(-1)),
+            card("TSCAL3", Value::Float(2.)),
+            card("TTYPE4", Value::Str("NAME".to_string())),
+            card("TFORM4", Value::Str("4A".to_string())),
+            card("TTYPE5", Value::Str("FLAG".to_string())),
+            card("TFORM5", Value::Str("1L".to_string())),
+            card("TTYPE6", Value::Str("PIX".to_string())),
+            card("TFORM6", Value::Str("1B".to_string())),
+        ];
+        let header = Header::from_keyword_list(keywords).unwrap();
+        let mut data = Vec::new();
+        for (flux, mag, id, name, flag, pix) in [
+            (1.5f64, 20.25f32, 7i16, b"M31 ", b'T', 255u8),
+            (-2., 0.5, -1, b"M1\0x", b'F', 0),
+        ] {
+            data.extend(flux.to_be_bytes());
+            data.extend(mag.to_be_bytes());
+            data.extend(id.to_be_bytes());
+            data.extend(name);
+            data.push(flag);
+            data.push(pix);
+        }
+        let table = BinTableExtension {
+            nrows: 2,
+            ncols: 6,
+            columns: header.column_descriptors(),
+            header,
+            data,
+        };
+
+        assert_eq!(table.read_column_f64("FLUX").unwrap(), [1.5, -2.]);
+        assert_eq!(table.read_column_f64("mag").unwrap(), [20.25, 0.5]);
+        let ids = table.read_column_f64("ID").unwrap();
+        assert_eq!(ids[0], 14.);
+        assert!(ids[1].is_nan());
+        assert_eq!(table.read_column_f64("PIX").unwrap(), [255., 0.]);
+        assert_eq!(table.read_column_str("NAME").unwrap(), ["M31", "M1"]);
+        assert_eq!(table.read_column_bool("FLAG").unwrap(), [true, false]);
+
+        assert!(matches!(
+            table.read_column_f64("RA"),
+            Err(FitsError::ColumnNotFound(name)) if name == "RA"
+        ));
+        assert!(matches!(
+            table.read_column_f64("NAME"),
+            Err(FitsError::ColumnTypeMismatch(_))
+        ));
+        assert!(matches!(
+            table.read_column_str("FLUX"),
+            Err(FitsError::ColumnTypeMismatch(_))
+        ));
+    }
+
+    #[test]
+    fn ascii_table_test() {
+        let primary = vec![
+            card("SIMPLE", Value::Boolean(true)),
+            card("BITPIX", Value::Integer(8)),
+            card("NAXIS", Value::Integer(0)),
+        ];
+        let mut bytes = header_to_bytes(&primary);
+        let columns = [
+            ("NAME", "A6", 1),
+            ("COUNT", "I4", 8),
+            ("FLUX", "F6.2", 13),
+            ("DIST", "D10.2", 20),
+        ];
+        let mut table = vec![
+            card("PCOUNT", Value::Integer(0)),
+            card("GCOUNT", Value::Integer(1)),
+            card("TFIELDS", Value::Integer(columns.len() as i64)),
+            card("TNULL2", Value::Str("-999".to_string())),
+        ];
+        for (i, (name, tform, tbcol)) in columns.iter().enumerate() {
+            let n = i + 1;
+            table.push(card(&format!("TTYPE{}", n), Value::Str(name.to_string())));
+            table.push(card(&format!("TFORM{}", n), Value::Str(tform.to_string())));
+            table.push(card(&format!("TBCOL{}", n), Value::Integer(*tbcol)));
+        }
+        bytes.extend(extension("TABLE", 8, &[30, 3], table));
+        let rows = [
+            "M31    1234 12.50  7.8D+05  ",
+            "  M1   -999  1250  1.2E1    ",
+            "NGC 1          -1.       1 ",
+        ];
+        let mut data: Vec<u8> = rows
+            .iter()
+            .flat_map(|r| format!("{:30}", r).into_bytes())
+            .collect();
+        data.resize(BLOCK_SIZE, b' ');
+        bytes.extend(data);
+
+        let hdus = HduList::from_bytes(bytes).unwrap();
+        let Hdu::AsciiTable(table) = &hdus[1] else {
+            panic!("not an ASCII table");
+        };
+        assert_eq!((table.nrows, table.ncols), (3, 4));
+        let text = |s: &str| Value::Str(s.to_string());
+        assert_eq!(
+            table.read_column("name").unwrap(),
+            [text("M31"), text("  M1"), text("NGC 1")]
+        );
+        assert_eq!(
+            table.read_column("COUNT").unwrap(),
+            [Value::Integer(1234), Value::Undefined, Value::Undefined]
+        );
+        assert_eq!(
+            table.read_column("FLUX").unwrap(),
+            [Value::Float(12.5), Value::Float(12.5), Value::Float(-1.)]
+        );
+        assert_eq!(
+            table.read_column("DIST").unwrap(),
+            [Value::Float(7.8e5), Value::Float(12.), Value::Float(0.01)]
+        );
+        assert!(matches!(
+            table.read_column("RA"),
+            Err(FitsError::ColumnNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn primary_data_padding_test() {
+        // Data of 6 bytes (padded to a block) and of exactly one block (no padding)
+        for n in [3, BLOCK_SIZE / 2] {
+            let primary = vec![
+                card("SIMPLE", Value::Boolean(true)),
+                card("BITPIX", Value::Integer(16)),
+                card("NAXIS", Value::Integer(1)),
+                card("NAXIS1", Value::Integer(n as i64)),
+                card("EXTEND", Value::Boolean(true)),
+            ];
+            let mut bytes = header_to_bytes(&primary);
+            bytes.extend(data_to_bytes(&DataArray::I16(
+                crate::GenericData::from_elem(vec![n], 7),
+            )));
+            let counts = vec![
+                card("PCOUNT", Value::Integer(0)),
+                card("GCOUNT", Value::Integer(1)),
+                card("EXTNAME", Value::Str("SCI".to_string())),
+            ];
+            bytes.extend(extension("IMAGE", 8, &[2], counts));
+            bytes.extend(data_to_bytes(&DataArray::U8(arr1(&[4, 5]).into_dyn())));
+
+            let hdus = HduList::from_bytes(bytes).unwrap();
+            assert_eq!(hdus.len(), 2);
+            assert!(hdus[0].header().extend());
+            assert!(!hdus["SCI"].header().extend());
+            match &hdus["SCI"] {
+                Hdu::ImageExt(ext) => assert_eq!(ext.data, DataArray::U8(arr1(&[4, 5]).into_dyn())),
+                _ => panic!("SCI is not an image extension"),
+            }
+        }
+    }
+
+    #[test]
+    fn hdu_list_error_test() {
+        // A single HDU is a valid list
+        let hdus = HduList::open("data/simple.fits").unwrap();
+        assert_eq!(hdus.len(), 1);
+
+        let mut bytes = mef();
+        bytes.truncate(bytes.len() - BLOCK_SIZE);
+        assert!(matches!(
+            HduList::from_bytes(bytes),
+            Err(FitsError::UnexpectedEof)
+        ));
+
+        let primary = header_to_bytes(&vec![
+            card("SIMPLE", Value::Boolean(true)),
+            card("BITPIX", Value::Integer(8)),
+            card("NAXIS", Value::Integer(0)),
+        ]);
+        let mut bytes = primary.clone();
+        bytes.extend(extension("IMAGE", 8, &[], vec![]));
+        assert!(matches!(
+            HduList::from_bytes(bytes),
+            Err(FitsError::MissingKeyword("PCOUNT"))
+        ));
+
+        let mut bytes = primary;
+        let counts = vec![
+            card("PCOUNT", Value::Integer(0)),
+            card("GCOUNT", Value::Integer(1)),
+        ];
+        bytes.extend(extension("IUEIMAGE", 8, &[], counts));
+        assert!(matches!(
+            HduList::from_bytes(bytes),
+            Err(FitsError::UnknownExtension(x)) if x == "IUEIMAGE"
+        ));
+    }
+
+    #[test]
+    fn write_test() {
+        let header = Header::for_image(Bitpix::Float32, &[3, 2]);
+        let image = DataArray::F32(arr1(&[1., 2., 3., 4., 5., 6.]).into_dyn());
+        let mut hdus = HduList::new(BasicFits::create(header, image.clone()).unwrap());
+
+        let mut header = Header::for_image_extension(Bitpix::Float32, &[3, 2]);
+        header
+            .set_value("EXTNAME", Value::Str("ERR".to_string()), "")
+            .unwrap();
+        let uncertainty = DataArray::F32(arr1(&[0.5; 6]).into_dyn());
+        let ext = ImageExtension::create(header, uncertainty.clone()).unwrap();
+        hdus.push(Hdu::ImageExt(ext));
+
+        let mut header = Header::for_image_extension(Bitpix::Int8, &[3, 2]);
+        header
+            .set_value("EXTNAME", Value::Str("MASK".to_string()), "")
+            .unwrap();
+        let mask = DataArray::U8(arr1(&[0, 0, 1, 0, 0, 0]).into_dyn());
+        hdus.push(Hdu::ImageExt(
+            ImageExtension::create(header, mask.clone()).unwrap(),
+        ));
+
+        let mut bytes = Vec::new();
+        hdus.write(&mut bytes).unwrap();
+        assert_eq!(bytes.len(), 6 * BLOCK_SIZE);
+        let read = HduList::from_bytes(bytes).unwrap();
+        assert_eq!(read.len(), 3);
+        match (&read[0], &read["ERR"], &read["MASK"]) {
+            (Hdu::Primary(fits), Hdu::ImageExt(err), Hdu::ImageExt(bad)) => {
+                assert_eq!(*fits.data, image);
+                assert_eq!(err.data, uncertainty);
+                assert_eq!(bad.data, mask);
+                assert_eq!(err.header.get_str("XTENSION"), Some("IMAGE"));
+            }
+            _ => panic!("wrong HDU types"),
+        }
+
+        // Tables are written back as they were read
+        let mut bytes = Vec::new();
+        HduList::from_bytes(mef())
+            .unwrap()
+            .write(&mut bytes)
+            .unwrap();
+        let read = HduList::from_bytes(bytes).unwrap();
+        for (hdu, expected) in read.iter().zip(HduList::from_bytes(mef()).unwrap().iter()) {
+            assert_eq!(hdu.to_bytes(), expected.to_bytes());
+        }
+    }
+
+    #[test]
+    fn write_error_test() {
+        let data = DataArray::U8(arr1(&[1, 2]).into_dyn());
+        assert!(matches!(
+            ImageExtension::create(Header::for_image(Bitpix::Int8, &[2]), data.clone()),
+            Err(FitsError::MissingKeyword("XTENSION"))
+        ));
+        assert!(matches!(
+            ImageExtension::create(Header::for_image_extension(Bitpix::Int16, &[2]), data),
+            Err(FitsError::BitpixMismatch { .. })
+        ));
+
+        let mut header = Header::for_image(Bitpix::Int8, &[]);
+        header.keywords.push(card("BAD NAME", Value::Integer(1)));
+        let primary = BasicFits::create(header, DataArray::empty(&Bitpix::Int8)).unwrap();
+        let mut bytes = Vec::new();
+        assert!(matches!(
+            HduList::new(primary).write(&mut bytes),
+            Err(FitsError::InvalidKeywordName(_))
+        ));
+        assert!(bytes.is_empty());
+    }
+
+    #[test]
+    fn hdu_iterator_test() {
+        let hdus: Vec<Hdu> = HduList::iter_from_reader(Cursor::new(mef()))
+            .collect::<Result<_, _>>()
+            .unwrap();
+        let list = HduList::from_bytes(mef()).unwrap();
+        assert_eq!(hdus.len(), list.len());
+        assert!(matches!(hdus[0], Hdu::Primary(_)));
+        for (hdu, expected) in hdus.iter().zip(list.iter()) {
+            assert_eq!(hdu.header().keywords, expected.header().keywords);
+        }
+        match (&hdus[2], &list[2]) {
+            (Hdu::ImageExt(ext), Hdu::ImageExt(expected)) => assert_eq!(ext.data, expected.data),
+            _ => panic!("HDU 2 is not an image extension"),
+        }
+
+        // A truncated file gives an error, after which the iterator stops
+        let mut bytes = mef();
+        bytes.truncate(bytes.len() - BLOCK_SIZE);
+        let mut hdus = HduList::iter_from_reader(Cursor::new(bytes));
+        assert_eq!(hdus.by_ref().take_while(Result::is_ok).count(), 3);
+        assert!(hdus.next().is_none());
+
+        let results: Vec<_> = HduList::iter_from_reader(Cursor::new(Vec::new())).collect();
+        assert!(matches!(results[..], [Err(FitsError::InvalidHeader)]));
+
+        // Data sizes from the header are checked against the file before reading the data
+        let counts = || {
+            vec![
+                card("PCOUNT", Value::Integer(0)),
+                card("GCOUNT", Value::Integer(1)),
+            ]
+        };
+        let mut bytes = mef();
+        bytes.extend(extension("IMAGE", -64, &[1 << 20, 1 << 20], counts()));
+        let results: Vec<_> = HduList::iter_from_reader(Cursor::new(bytes)).collect();
+        assert!(matches!(
+            results.last(),
+            Some(Err(FitsError::UnexpectedEof))
+        ));
+        let mut bytes = mef();
+        bytes.extend(extension("IMAGE", -64, &[1 << 40, 1 << 40], counts()));
+        let results: Vec<_> = HduList::iter_from_reader(Cursor::new(bytes)).collect();
+        assert!(matches!(
+            results.last(),
+            Some(Err(FitsError::InvalidHeader))
+        ));
+    }
+}
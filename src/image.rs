@@ -0,0 +1,2646 @@
+// Image processing routines operating on 2D image data.
+//
+// Arrays are indexed as `[row, column]`, i.e. `[y, x]`, which matches the FITS
+// storage order where NAXIS1 is the fastest varying axis.
+
+use std::sync::Arc;
+
+use ndarray::{s, Array2, ArrayViewD, Zip};
+
+use crate::builder::FitsBuilder;
+use crate::header::Header;
+use crate::parsing::header::{set_keyword, Keyword, Value};
+use crate::{BasicFits, FitsError, GenericData, KeywordList};
+
+/// Reshape the (flat) data of a 2D FITS image with the given axes (NAXIS1, NAXIS2) into an
+/// `Array2` of shape (NAXIS2, NAXIS1).
+pub(crate) fn to_array2<T: Clone>(data: &GenericData<T>, axes: &[usize]) -> Option<Array2<T>> {
+    if axes.len() != 2 {
+        return None;
+    }
+    let values: Vec<T> = data.iter().cloned().collect();
+    Array2::from_shape_vec((axes[1], axes[0]), values).ok()
+}
+
+/// Flatten a 2D array back into the layout used for `BasicFits::data`.
+pub(crate) fn from_array2<T: Clone>(data: Array2<T>) -> GenericData<T> {
+    let values: Vec<T> = data.iter().cloned().collect();
+    GenericData::from_shape_vec(vec![values.len()], values).unwrap()
+}
+
+// Value at the fractional position `(y, x)`, interpolated bilinearly between the four
+// surrounding pixels. NaN outside the image.
+pub(crate) fn bilinear(data: &Array2<f64>, y: f64, x: f64) -> f64 {
+    let (rows, cols) = data.dim();
+    if !(y >= 0. && x >= 0. && y <= rows as f64 - 1. && x <= cols as f64 - 1.) {
+        return f64::NAN;
+    }
+    let (y0, x0) = (y as usize, x as usize);
+    let (y1, x1) = ((y0 + 1).min(rows - 1), (x0 + 1).min(cols - 1));
+    let (wy, wx) = (y - y0 as f64, x - x0 as f64);
+    let top = data[[y0, x0]] * (1. - wx) + data[[y0, x1]] * wx;
+    let bottom = data[[y1, x0]] * (1. - wx) + data[[y1, x1]] * wx;
+    top * (1. - wy) + bottom * wy
+}
+
+/// Shift an image by `(dy, dx)` pixels, filling the uncovered area with zeros.
+pub fn shift(data: &Array2<f64>, dy: i64, dx: i64) -> Array2<f64> {
+    let (rows, cols) = data.dim();
+    let mut out = Array2::zeros((rows, cols));
+    for ((y, x), value) in out.indexed_iter_mut() {
+        let sy = y as i64 - dy;
+        let sx = x as i64 - dx;
+        if sy >= 0 && sx >= 0 && (sy as usize) < rows && (sx as usize) < cols {
+            *value = data[[sy as usize, sx as usize]];
+        }
+    }
+    out
+}
+
+// Median of the values, sorting them in place. Even lengths average the two middle values.
+pub(crate) fn median(values: &mut [f64]) -> f64 {
+    if values.is_empty() {
+        return f64::NAN;
+    }
+    values.sort_by(|a, b| a.total_cmp(b));
+    let mid = values.len() / 2;
+    if values.len() % 2 == 1 {
+        values[mid]
+    } else {
+        (values[mid - 1] + values[mid]) / 2.
+    }
+}
+
+// Map a possibly out of range index back into [0, n) by mirroring at the edges,
+// repeating the edge pixel (i.e. `c b a | a b c | c b a`).
+fn reflect_index(idx: i64, n: usize) -> usize {
+    let n = n as i64;
+    let period = 2 * n;
+    let idx = idx.rem_euclid(period);
+    if idx < n {
+        idx as usize
+    } else {
+        (period - 1 - idx) as usize
+    }
+}
+
+// Median of the values after iteratively discarding those more than `nsigma` standard
+// deviations from the median. The values are reordered and truncated.
+pub(crate) fn sigma_clipped_median(values: &mut Vec<f64>, nsigma: f64) -> f64 {
+    loop {
+        let center = median(values);
+        let n = values.len() as f64;
+        let mean = values.iter().sum::<f64>() / n;
+        let std = (values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / n).sqrt();
+
+        let before = values.len();
+        values.retain(|v| (v - center).abs() <= nsigma * std);
+        if values.len() == before || values.is_empty() {
+            return center;
+        }
+    }
+}
+
+/// Cut a `size` x `size` postage stamp centred on pixel `(cx, cy)` out of a 2D image. The
+/// header is copied with updated NAXISn and CRPIXn, so pixel `(size / 2, size / 2)` of the
+/// cutout has the same sky position as `(cx, cy)` in the original.
+///
+/// Returns `FitsError::OutOfBounds` if the stamp does not fit within the image.
+pub fn cutout(fits: &BasicFits, cx: usize, cy: usize, size: usize) -> Result<BasicFits, FitsError> {
+    let half = size / 2;
+    let x0 = cx.checked_sub(half).ok_or(FitsError::OutOfBounds)?;
+    let y0 = cy.checked_sub(half).ok_or(FitsError::OutOfBounds)?;
+    FitsBuilder::from_existing(fits)
+        .crop(y0..y0 + size, x0..x0 + size)?
+        .build()
+}
+
+/// Replace each pixel by the median of its `kernel_size` x `kernel_size` neighbourhood.
+///
+/// Pixels beyond the edges are taken from the image mirrored at its border. Panics if
+/// `kernel_size` is even.
+pub fn median_filter(data: &Array2<f64>, kernel_size: usize) -> Array2<f64> {
+    assert!(kernel_size % 2 == 1, "median_filter requires an odd kernel size");
+    let (rows, cols) = data.dim();
+    let half = (kernel_size / 2) as i64;
+    let mut window = Vec::with_capacity(kernel_size * kernel_size);
+
+    Array2::from_shape_fn((rows, cols), |(y, x)| {
+        window.clear();
+        for dy in -half..=half {
+            let sy = reflect_index(y as i64 + dy, rows);
+            for dx in -half..=half {
+                let sx = reflect_index(x as i64 + dx, cols);
+                window.push(data[[sy, sx]]);
+            }
+        }
+        median(&mut window)
+    })
+}
+
+/// Direction of the derivative taken by a Sobel kernel.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SobelDirection {
+    /// Along the columns, i.e. the gradient in increasing `x`
+    X,
+    /// Along the rows, i.e. the gradient in increasing `y`
+    Y,
+}
+
+/// The kernels made by [`convolution_kernel`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum KernelType {
+    Gaussian {
+        sigma: f64,
+    },
+    /// The 4-neighbour Laplacian `[[0, 1, 0], [1, -4, 1], [0, 1, 0]]`
+    Laplacian,
+    Sobel {
+        direction: SobelDirection,
+    },
+    /// A disc with the given radius in pixels
+    TopHat {
+        radius: usize,
+    },
+    /// A box over the whole kernel
+    Uniform,
+}
+
+/// A `size` x `size` kernel for [`convolve`]. The smoothing kernels (Gaussian, top hat and
+/// uniform) are normalized to sum to 1, so they preserve the flux. The derivative kernels
+/// (Laplacian and Sobel) sum to 0; they are 3x3 and padded with zeros for larger sizes.
+///
+/// Panics if `size` is even, or smaller than 3 for a derivative kernel.
+pub fn convolution_kernel(kernel_type: KernelType, size: usize) -> Array2<f64> {
+    assert!(size % 2 == 1, "convolution kernels must have an odd size");
+    let half = (size / 2) as f64;
+    let radius2 = |y: usize, x: usize| (y as f64 - half).powi(2) + (x as f64 - half).powi(2);
+
+    let derivative = |core: [[f64; 3]; 3]| {
+        assert!(size >= 3, "derivative kernels are at least 3x3");
+        let mut kernel = Array2::zeros((size, size));
+        let c = size / 2;
+        for (y, row) in core.iter().enumerate() {
+            for (x, value) in row.iter().enumerate() {
+                kernel[[c + y - 1, c + x - 1]] = *value;
+            }
+        }
+        kernel
+    };
+
+    match kernel_type {
+        KernelType::Laplacian => derivative([[0., 1., 0.], [1., -4., 1.], [0., 1., 0.]]),
+        // Flipped, as convolution mirrors the kernel
+        KernelType::Sobel { direction } => {
+            let kernel = derivative([[1., 0., -1.], [2., 0., -2.], [1., 0., -1.]]);
+            match direction {
+                SobelDirection::X => kernel,
+                SobelDirection::Y => kernel.reversed_axes(),
+            }
+        }
+        smoothing => {
+            let kernel = Array2::from_shape_fn((size, size), |(y, x)| match smoothing {
+                KernelType::Gaussian { sigma } => (-radius2(y, x) / (2. * sigma * sigma)).exp(),
+                KernelType::TopHat { radius } if radius2(y, x) <= (radius * radius) as f64 => 1.,
+                KernelType::TopHat { .. } => 0.,
+                _ => 1.,
+            });
+            let total = kernel.sum();
+            kernel / total
+        }
+    }
+}
+
+/// Convolve `data` with `kernel`, e.g. one made by [`convolution_kernel`]. Pixels beyond
+/// the edges are taken from the image mirrored at its border.
+///
+/// This is a direct sum over the kernel for each pixel, which is fast enough for small
+/// kernels. Panics if a dimension of the kernel is even.
+pub fn convolve(data: &Array2<f64>, kernel: &Array2<f64>) -> Array2<f64> {
+    let (krows, kcols) = kernel.dim();
+    assert!(
+        krows % 2 == 1 && kcols % 2 == 1,
+        "convolve requires a kernel of odd size"
+    );
+    let (rows, cols) = data.dim();
+    let (hy, hx) = ((krows / 2) as i64, (kcols / 2) as i64);
+
+    Array2::from_shape_fn((rows, cols), |(y, x)| {
+        let mut total = 0.;
+        for ((ky, kx), k) in kernel.indexed_iter() {
+            if *k != 0. {
+                let sy = reflect_index(y as i64 + hy - ky as i64, rows);
+                let sx = reflect_index(x as i64 + hx - kx as i64, cols);
+                total += k * data[[sy, sx]];
+            }
+        }
+        total
+    })
+}
+
+/// Remove noise while keeping compact sources, by hard thresholding the à trous wavelet
+/// transform of the image (Starck & Murtagh, 2006).
+///
+/// The image is split into `n_scales` wavelet planes, each the difference of two
+/// successive smoothings with the B3-spline kernel `[1, 4, 6, 4, 1] / 16` dilated by
+/// `2^k`, plus the final smoothed image. Coefficients in a plane below `threshold` times
+/// its noise, estimated from the median absolute deviation of the plane, are set to zero
+/// before adding the planes back together.
+pub fn wavelet_denoise(data: &Array2<f64>, n_scales: usize, threshold: f64) -> Array2<f64> {
+    let mut smooth = data.clone();
+    let mut denoised = Array2::zeros(data.dim());
+    for k in 0..n_scales {
+        let next = atrous_smooth(&smooth, 1 << k);
+        let mut plane = &smooth - &next;
+
+        let mut deviations: Vec<f64> = plane.iter().map(|w| w.abs()).collect();
+        let noise = 1.4826 * median(&mut deviations);
+        plane.mapv_inplace(|w| if w.abs() > threshold * noise { w } else { 0. });
+
+        denoised += &plane;
+        smooth = next;
+    }
+    denoised + smooth
+}
+
+// Smooth with the B3-spline kernel, with `step - 1` holes between its coefficients, along
+// both axes. Edges are mirrored.
+fn atrous_smooth(data: &Array2<f64>, step: usize) -> Array2<f64> {
+    const B3: [f64; 5] = [1. / 16., 4. / 16., 6. / 16., 4. / 16., 1. / 16.];
+    let (rows, cols) = data.dim();
+    let step = step as i64;
+    let taps = |i: usize| {
+        B3.iter()
+            .zip(-2..=2i64)
+            .map(move |(w, k)| (w, i as i64 + k * step))
+    };
+
+    let along_x = Array2::from_shape_fn((rows, cols), |(y, x)| {
+        taps(x)
+            .map(|(w, sx)| w * data[[y, reflect_index(sx, cols)]])
+            .sum::<f64>()
+    });
+    Array2::from_shape_fn((rows, cols), |(y, x)| {
+        taps(y)
+            .map(|(w, sy)| w * along_x[[reflect_index(sy, rows), x]])
+            .sum::<f64>()
+    })
+}
+
+/// Flag cosmic ray hits with a simplified version of the LA Cosmic algorithm (van Dokkum,
+/// 2001). Cosmic rays are much sharper than anything passing through the optics, so they
+/// stand out in the Laplacian of the image.
+///
+/// The Laplacian is divided by the noise, estimated from the scatter of the image around
+/// its 5x5 median filtered version, and thresholded at `sigma_threshold`. With
+/// `neighbor_check`, a pixel is only flagged if at least one of its 8 neighbours also lies
+/// above `0.3 * sigma_threshold`, which rejects single pixel noise spikes (and thus also
+/// single pixel hits).
+pub fn detect_cosmic_rays(
+    data: &Array2<f64>,
+    sigma_threshold: f64,
+    neighbor_check: bool,
+) -> Array2<bool> {
+    let (rows, cols) = data.dim();
+    let smooth = median_filter(data, 5);
+    let mut residuals: Vec<f64> = Zip::from(data)
+        .and(&smooth)
+        .map_collect(|&value, &median| (value - median).abs())
+        .into_iter()
+        .collect();
+    // Gaussian sigma from the median absolute deviation
+    let noise = 1.4826 * median(&mut residuals);
+
+    // The pixel minus the mean of its 4 neighbours, which has a standard deviation of
+    // sqrt(1 + 4 / 16) times the noise. Only the positive part is of interest.
+    let laplacian_noise = noise * 1.25f64.sqrt();
+    let laplacian = convolve(data, &convolution_kernel(KernelType::Laplacian, 3));
+    let significance = laplacian.mapv(|l| (-l / 4.).max(0.) / laplacian_noise);
+
+    let low_threshold = 0.3 * sigma_threshold;
+    Array2::from_shape_fn((rows, cols), |(y, x)| {
+        let candidate = significance[[y, x]] > sigma_threshold;
+        if !candidate || !neighbor_check {
+            return candidate;
+        }
+        (-1..=1i64).any(|dy| {
+            (-1..=1i64).any(|dx| {
+                let (ny, nx) = (y as i64 + dy, x as i64 + dx);
+                (dy, dx) != (0, 0)
+                    && ny >= 0
+                    && nx >= 0
+                    && (ny as usize) < rows
+                    && (nx as usize) < cols
+                    && significance[[ny as usize, nx as usize]] > low_threshold
+            })
+        })
+    })
+}
+
+/// Mark the pixels that lie above their local background, i.e. where the value exceeds
+/// `mean - c`, with `mean` the average of the surrounding `block_size` x `block_size`
+/// window. A negative `c` thus demands that pixels stand out by at least `-c`.
+///
+/// Near the edges the window only covers the pixels inside the image. Panics if
+/// `block_size` is even.
+pub fn adaptive_threshold(data: &Array2<f64>, block_size: usize, c: f64) -> Array2<bool> {
+    assert!(
+        block_size % 2 == 1,
+        "adaptive_threshold requires an odd block size"
+    );
+    let (rows, cols) = data.dim();
+    let half = block_size / 2;
+
+    // Integral image: sums[[y, x]] is the sum of all pixels above and left of (y, x)
+    let mut sums = Array2::<f64>::zeros((rows + 1, cols + 1));
+    for y in 0..rows {
+        for x in 0..cols {
+            sums[[y + 1, x + 1]] =
+                data[[y, x]] + sums[[y, x + 1]] + sums[[y + 1, x]] - sums[[y, x]];
+        }
+    }
+
+    Array2::from_shape_fn((rows, cols), |(y, x)| {
+        let (y0, y1) = (y.saturating_sub(half), (y + half + 1).min(rows));
+        let (x0, x1) = (x.saturating_sub(half), (x + half + 1).min(cols));
+        let sum = sums[[y1, x1]] - sums[[y0, x1]] - sums[[y1, x0]] + sums[[y0, x0]];
+        let mean = sum / ((y1 - y0) * (x1 - x0)) as f64;
+        data[[y, x]] > mean - c
+    })
+}
+
+// Apply `stat` to the values of each `mesh_size` x `mesh_size` block of the image, for a
+// grid of `shape` blocks. Blocks at the edges are cut off at the image border.
+fn mesh_statistic(
+    data: &Array2<f64>,
+    mesh_size: usize,
+    shape: (usize, usize),
+    mut stat: impl FnMut(&mut Vec<f64>) -> f64,
+) -> Array2<f64> {
+    let (rows, cols) = data.dim();
+    let mut block = Vec::with_capacity(mesh_size * mesh_size);
+    Array2::from_shape_fn(shape, |(j, i)| {
+        let y = j * mesh_size..((j + 1) * mesh_size).min(rows);
+        let x = i * mesh_size..((i + 1) * mesh_size).min(cols);
+        block.clear();
+        block.extend(data.slice(s![y, x]).iter());
+        stat(&mut block)
+    })
+}
+
+/// The 3 sigma clipped median of each `mesh_size` x `mesh_size` block, i.e. the coarse
+/// background map from which [`local_background`] is interpolated. Only whole blocks are
+/// used, so the shape is `(NAXIS2 / mesh_size, NAXIS1 / mesh_size)`.
+///
+/// # Panics
+///
+/// If `mesh_size` is 0.
+pub fn background_mesh(data: &Array2<f64>, mesh_size: usize) -> Array2<f64> {
+    assert!(
+        mesh_size > 0,
+        "background_mesh requires a non-zero mesh size"
+    );
+    let (rows, cols) = data.dim();
+    let shape = (rows / mesh_size, cols / mesh_size);
+    mesh_statistic(data, mesh_size, shape, |block| {
+        sigma_clipped_median(block, 3.)
+    })
+}
+
+/// The 3 sigma clipped standard deviation of each block of [`background_mesh`], a map of
+/// the background noise.
+///
+/// # Panics
+///
+/// If `mesh_size` is 0.
+pub fn background_mesh_rms(data: &Array2<f64>, mesh_size: usize) -> Array2<f64> {
+    assert!(
+        mesh_size > 0,
+        "background_mesh_rms requires a non-zero mesh size"
+    );
+    let (rows, cols) = data.dim();
+    let shape = (rows / mesh_size, cols / mesh_size);
+    mesh_statistic(data, mesh_size, shape, |block| {
+        // Leaves only the unclipped values in the block
+        sigma_clipped_median(block, 3.);
+        let n = block.len() as f64;
+        let mean = block.iter().sum::<f64>() / n;
+        (block.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / n).sqrt()
+    })
+}
+
+/// Estimate a smoothly varying background, as done by e.g. SExtractor. The image is
+/// divided into `mesh_size` x `mesh_size` blocks, of which the 3 sigma clipped median is
+/// taken. This grid of medians is median filtered with a `filter_size` kernel (use 1 to
+/// skip this) to suppress blocks dominated by bright sources, and then bilinearly
+/// interpolated between the block centres to the full image.
+pub fn local_background(data: &Array2<f64>, mesh_size: usize, filter_size: usize) -> Array2<f64> {
+    assert!(
+        mesh_size > 0,
+        "local_background requires a non-zero mesh size"
+    );
+    let (rows, cols) = data.dim();
+    let (mesh_rows, mesh_cols) = (rows.div_ceil(mesh_size), cols.div_ceil(mesh_size));
+
+    let grid = mesh_statistic(data, mesh_size, (mesh_rows, mesh_cols), |block| {
+        sigma_clipped_median(block, 3.)
+    });
+    let grid = median_filter(&grid, filter_size);
+
+    // Block centres along an axis; the last block may be smaller than the others
+    let centres = |n: usize, len: usize| -> Vec<f64> {
+        (0..n)
+            .map(|j| (j * mesh_size + ((j + 1) * mesh_size).min(len) - 1) as f64 / 2.)
+            .collect()
+    };
+    let row_centres = centres(mesh_rows, rows);
+    let col_centres = centres(mesh_cols, cols);
+
+    Array2::from_shape_fn((rows, cols), |(y, x)| {
+        let (j0, j1, wy) = interpolation_weight(&row_centres, y as f64);
+        let (i0, i1, wx) = interpolation_weight(&col_centres, x as f64);
+        let top = (1. - wx) * grid[[j0, i0]] + wx * grid[[j0, i1]];
+        let bottom = (1. - wx) * grid[[j1, i0]] + wx * grid[[j1, i1]];
+        (1. - wy) * top + wy * bottom
+    })
+}
+
+// Linear interpolation between the two points of `centres` surrounding `t`, as the indices
+// of those points and the weight of the second one. Beyond the outer points the line through
+// the two outermost ones is extended.
+fn interpolation_weight(centres: &[f64], t: f64) -> (usize, usize, f64) {
+    if centres.len() == 1 {
+        return (0, 0, 0.);
+    }
+    let i = centres
+        .iter()
+        .rposition(|&c| c <= t)
+        .unwrap_or(0)
+        .min(centres.len() - 2);
+    let w = (t - centres[i]) / (centres[i + 1] - centres[i]);
+    (i, i + 1, w)
+}
+
+/// Raw image moments `M[[p, q]] = sum(x^p * y^q * data[[y, x]])` for `p + q <= order`,
+/// with `x` the column and `y` the row index. Entries with `p + q > order` are zero.
+/// NaN pixels are ignored.
+pub fn image_moments(data: &Array2<f64>, order: usize) -> Array2<f64> {
+    moments_around(data, order, 0., 0.)
+}
+
+/// Central moments `mu[[p, q]]`: the moments around the centroid `(M10 / M00, M01 / M00)`,
+/// which do not change when the image is shifted.
+pub fn central_moments(data: &Array2<f64>, order: usize) -> Array2<f64> {
+    let raw = image_moments(data, order.max(1));
+    let (cx, cy) = (raw[[1, 0]] / raw[[0, 0]], raw[[0, 1]] / raw[[0, 0]]);
+    moments_around(data, order, cx, cy)
+}
+
+/// Normalized central moments `nu[[p, q]] = mu[[p, q]] / mu00^(1 + (p + q) / 2)`, which
+/// also do not change when the image is scaled in size.
+pub fn normalized_central_moments(data: &Array2<f64>, order: usize) -> Array2<f64> {
+    let mu = central_moments(data, order);
+    let mu00 = mu[[0, 0]];
+    Array2::from_shape_fn(mu.dim(), |(p, q)| {
+        mu[[p, q]] / mu00.powf(1. + (p + q) as f64 / 2.)
+    })
+}
+
+fn moments_around(data: &Array2<f64>, order: usize, cx: f64, cy: f64) -> Array2<f64> {
+    let mut moments = Array2::zeros((order + 1, order + 1));
+    for ((y, x), &value) in data.indexed_iter() {
+        if value.is_nan() {
+            continue;
+        }
+        let (dx, dy) = (x as f64 - cx, y as f64 - cy);
+        for p in 0..=order {
+            for q in 0..=order - p {
+                moments[[p, q]] += dx.powi(p as i32) * dy.powi(q as i32) * value;
+            }
+        }
+    }
+    moments
+}
+
+/// The 7 moment invariants of Hu (1962), computed from the normalized central moments
+/// `nu` of at least order 3. They do not change under translation, scaling and rotation,
+/// which makes them useful for classifying shapes. The last one changes sign under
+/// reflection.
+///
+/// Panics if `nu` is smaller than 4x4.
+pub fn hu_invariants(nu: &Array2<f64>) -> [f64; 7] {
+    assert!(
+        nu.nrows() > 3 && nu.ncols() > 3,
+        "Hu invariants need moments up to order 3"
+    );
+    let (n20, n02, n11) = (nu[[2, 0]], nu[[0, 2]], nu[[1, 1]]);
+    let (n30, n03, n21, n12) = (nu[[3, 0]], nu[[0, 3]], nu[[2, 1]], nu[[1, 2]]);
+    let (a, b) = (n30 + n12, n21 + n03);
+    [
+        n20 + n02,
+        (n20 - n02).powi(2) + 4. * n11 * n11,
+        (n30 - 3. * n12).powi(2) + (3. * n21 - n03).powi(2),
+        a * a + b * b,
+        (n30 - 3. * n12) * a * (a * a - 3. * b * b) + (3. * n21 - n03) * b * (3. * a * a - b * b),
+        (n20 - n02) * (a * a - b * b) + 4. * n11 * a * b,
+        (3. * n21 - n03) * a * (a * a - 3. * b * b) - (n30 - 3. * n12) * b * (3. * a * a - b * b),
+    ]
+}
+
+/// Azimuthally averaged profile around `(cx, cy)`: the mean pixel value in `nbins` rings of
+/// equal width out to `max_radius`, as `(radius, mean)` pairs with the radius at the middle
+/// of the ring. NaN pixels are ignored; rings without any pixels have a NaN mean. Fails if
+/// `nbins` is 0.
+pub fn radial_profile(
+    data: &Array2<f64>,
+    cx: f64,
+    cy: f64,
+    max_radius: f64,
+    nbins: usize,
+) -> Result<Vec<(f64, f64)>, FitsError> {
+    if nbins == 0 {
+        return Err(FitsError::InvalidArgument("nbins"));
+    }
+    let width = max_radius / nbins as f64;
+    let mut sums = vec![0.; nbins];
+    let mut counts = vec![0usize; nbins];
+    for ((y, x), &value) in data.indexed_iter() {
+        let r = (x as f64 - cx).hypot(y as f64 - cy);
+        if value.is_nan() || r > max_radius {
+            continue;
+        }
+        // Pixels at exactly `max_radius` belong to the outer ring
+        let bin = ((r / width) as usize).min(nbins - 1);
+        sums[bin] += value;
+        counts[bin] += 1;
+    }
+
+    Ok((0..nbins)
+        .map(|i| {
+            let radius = (i as f64 + 0.5) * width;
+            let mean = if counts[i] > 0 {
+                sums[i] / counts[i] as f64
+            } else {
+                f64::NAN
+            };
+            (radius, mean)
+        })
+        .collect())
+}
+
+/// How scaled pixel values (in [0, 1]) are mapped to display brightness.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StretchMode {
+    Linear,
+    /// `log(1 + a x) / log(1 + a)` with `a = 1000`, as in DS9
+    Log,
+    Sqrt,
+}
+
+impl StretchMode {
+    fn apply(self, x: f64) -> f64 {
+        const A: f64 = 1000.;
+        match self {
+            StretchMode::Linear => x,
+            StretchMode::Log => (A * x).ln_1p() / A.ln_1p(),
+            StretchMode::Sqrt => x.sqrt(),
+        }
+    }
+}
+
+/// Combine three images taken through different filters into a colour image.
+///
+/// Each channel is scaled linearly from `[vmin, vmax]` to `[0, 1]` (clipping values
+/// outside), stretched and converted to 8 bits. NaN pixels become black. Row 0 of the
+/// data ends up at the top of the image.
+pub fn display_rgb(
+    r: &BasicFits,
+    g: &BasicFits,
+    b: &BasicFits,
+    vmin: f64,
+    vmax: f64,
+    stretch: StretchMode,
+) -> Result<::image::RgbImage, FitsError> {
+    let mut channels = Vec::with_capacity(3);
+    for fits in [r, g, b] {
+        let axes = &fits.header.axes;
+        if axes != &r.header.axes {
+            return Err(FitsError::ShapeMismatch {
+                expected: r.header.axes.clone(),
+                found: axes.clone(),
+            });
+        }
+        channels
+            .push(to_array2(&fits.data.to_f64(), axes).ok_or(FitsError::InvalidNaxis(axes.len()))?);
+    }
+
+    let to_u8 = |value: f64| {
+        let scaled = ((value - vmin) / (vmax - vmin)).clamp(0., 1.);
+        if scaled.is_nan() {
+            0
+        } else {
+            (stretch.apply(scaled) * 255.).round() as u8
+        }
+    };
+    let (rows, cols) = channels[0].dim();
+    Ok(::image::RgbImage::from_fn(
+        cols as u32,
+        rows as u32,
+        |x, y| {
+            let idx = [y as usize, x as usize];
+            ::image::Rgb([
+                to_u8(channels[0][idx]),
+                to_u8(channels[1][idx]),
+                to_u8(channels[2][idx]),
+            ])
+        },
+    ))
+}
+
+/// Map an image scaled to `[0, 255]` through a lookup table, e.g. one made by
+/// [`compute_log_lut`]. Values are rounded to the nearest entry and clipped to the table;
+/// NaN uses entry 0.
+///
+/// Computing the stretch once per grey level instead of once per pixel makes this a fast
+/// way to display large images.
+pub fn apply_stretch_lut(data: &Array2<f64>, lut: &[u8; 256]) -> Array2<u8> {
+    data.mapv(|x| lut[x.round().clamp(0., 255.) as usize])
+}
+
+/// Lookup table for the logarithmic stretch `log(1 + a x) / log(1 + a)`, with `x` the
+/// grey level scaled to `[0, 1]`. Larger `a` brings out more of the faint end.
+pub fn compute_log_lut(a: f64) -> [u8; 256] {
+    stretch_lut(|x| (a * x).ln_1p() / a.ln_1p())
+}
+
+/// Lookup table for the square root stretch.
+pub fn compute_sqrt_lut() -> [u8; 256] {
+    stretch_lut(f64::sqrt)
+}
+
+fn stretch_lut(stretch: impl Fn(f64) -> f64) -> [u8; 256] {
+    let mut lut = [0; 256];
+    for (i, entry) in lut.iter_mut().enumerate() {
+        *entry = (stretch(i as f64 / 255.) * 255.).round() as u8;
+    }
+    lut
+}
+
+/// Rule to choose the number of histogram bins from the data.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BinningRule {
+    /// Bin width `3.5 std / n^(1/3)`, optimal for normally distributed data
+    Scott,
+    /// Bin width `2 IQR / n^(1/3)`, less sensitive to outliers
+    FreedmanDiaconis,
+    /// `sqrt(n)` bins
+    Sqrt,
+}
+
+/// Number of histogram bins for the data according to Scott's rule.
+pub fn optimal_bin_size(data: &ArrayViewD<f64>) -> usize {
+    optimal_bin_size_with_rule(data, BinningRule::Scott)
+}
+
+/// Number of histogram bins for the data according to `rule`, ignoring NaN values. At least
+/// one bin is returned, also for empty or constant data.
+pub fn optimal_bin_size_with_rule(data: &ArrayViewD<f64>, rule: BinningRule) -> usize {
+    let mut values: Vec<f64> = data.iter().cloned().filter(|v| !v.is_nan()).collect();
+    if values.len() < 2 {
+        return 1;
+    }
+    values.sort_by(|a, b| a.total_cmp(b));
+    let n = values.len() as f64;
+    let range = values[values.len() - 1] - values[0];
+
+    let width = match rule {
+        BinningRule::Sqrt => return n.sqrt().ceil() as usize,
+        BinningRule::Scott => {
+            let mean = values.iter().sum::<f64>() / n;
+            let var = values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / (n - 1.);
+            3.5 * var.sqrt() / n.cbrt()
+        }
+        BinningRule::FreedmanDiaconis => {
+            let iqr = quantile(&values, 0.75) - quantile(&values, 0.25);
+            2. * iqr / n.cbrt()
+        }
+    };
+    if width > 0. {
+        ((range / width).ceil() as usize).max(1)
+    } else {
+        1
+    }
+}
+
+// Quantile `q` of sorted values, interpolating linearly between the closest ranks.
+fn quantile(sorted: &[f64], q: f64) -> f64 {
+    let pos = q * (sorted.len() - 1) as f64;
+    let (lower, upper) = (pos.floor() as usize, pos.ceil() as usize);
+    let frac = pos - lower as f64;
+    sorted[lower] + frac * (sorted[upper] - sorted[lower])
+}
+
+/// Region of an image to measure, in pixel coordinates. Angles `theta` are in radians,
+/// counter-clockwise from the x axis.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Aperture {
+    Circle {
+        cx: f64,
+        cy: f64,
+        radius: f64,
+    },
+    /// Ellipse with semi-major axis `a` along `theta` and semi-minor axis `b`
+    Ellipse {
+        cx: f64,
+        cy: f64,
+        a: f64,
+        b: f64,
+        theta: f64,
+    },
+    Rectangle {
+        cx: f64,
+        cy: f64,
+        width: f64,
+        height: f64,
+        theta: f64,
+    },
+    Annulus {
+        cx: f64,
+        cy: f64,
+        r_inner: f64,
+        r_outer: f64,
+    },
+}
+
+impl Aperture {
+    fn center(&self) -> (f64, f64) {
+        match *self {
+            Aperture::Circle { cx, cy, .. }
+            | Aperture::Ellipse { cx, cy, .. }
+            | Aperture::Rectangle { cx, cy, .. }
+            | Aperture::Annulus { cx, cy, .. } => (cx, cy),
+        }
+    }
+
+    // Radius of a circle around the centre enclosing the whole aperture
+    fn extent(&self) -> f64 {
+        match *self {
+            Aperture::Circle { radius, .. } => radius,
+            Aperture::Ellipse { a, b, .. } => a.max(b),
+            Aperture::Rectangle { width, height, .. } => width.hypot(height) / 2.,
+            Aperture::Annulus { r_outer, .. } => r_outer,
+        }
+    }
+
+    fn contains(&self, x: f64, y: f64) -> bool {
+        let (cx, cy) = self.center();
+        let (dx, dy) = (x - cx, y - cy);
+        // Offsets in a frame rotated along with the aperture
+        let rotated = |theta: f64| {
+            let (sin, cos) = theta.sin_cos();
+            (dx * cos + dy * sin, -dx * sin + dy * cos)
+        };
+        match *self {
+            Aperture::Circle { radius, .. } => dx.hypot(dy) <= radius,
+            Aperture::Ellipse { a, b, theta, .. } => {
+                let (u, v) = rotated(theta);
+                (u / a).powi(2) + (v / b).powi(2) <= 1.
+            }
+            Aperture::Rectangle {
+                width,
+                height,
+                theta,
+                ..
+            } => {
+                let (u, v) = rotated(theta);
+                u.abs() <= width / 2. && v.abs() <= height / 2.
+            }
+            Aperture::Annulus {
+                r_inner, r_outer, ..
+            } => {
+                let r = dx.hypot(dy);
+                r_inner <= r && r <= r_outer
+            }
+        }
+    }
+}
+
+/// Photometry within an [`Aperture`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ApertureResult {
+    pub sum: f64,
+    pub mean: f64,
+    /// The (fractional) number of pixels within the aperture
+    pub n_pixels: f64,
+    /// Poisson error of the sum, `sqrt(sum / gain)`
+    pub error: f64,
+}
+
+/// Sum the pixel values within `aperture`. NaN pixels are skipped.
+///
+/// Each pixel is divided into `subpixel_sampling` x `subpixel_sampling` sub-pixels, and
+/// weighted by the fraction of them that lies within the aperture. With a value of 1, a
+/// pixel is either fully in or out depending on its centre. The `gain` (e-/ADU) is used
+/// for the error estimate.
+pub fn measure_aperture(
+    data: &Array2<f64>,
+    aperture: &Aperture,
+    gain: f64,
+    subpixel_sampling: usize,
+) -> ApertureResult {
+    assert!(
+        subpixel_sampling > 0,
+        "subpixel_sampling must be at least 1"
+    );
+    let (rows, cols) = data.dim();
+    let (cx, cy) = aperture.center();
+    let extent = aperture.extent() + 1.;
+    let range = |c: f64, n: usize| {
+        let start = (c - extent).floor().max(0.) as usize;
+        let end = ((c + extent).ceil().max(0.) as usize).min(n);
+        start..end
+    };
+
+    let step = 1. / subpixel_sampling as f64;
+    let offsets: Vec<f64> = (0..subpixel_sampling)
+        .map(|i| -0.5 + (i as f64 + 0.5) * step)
+        .collect();
+
+    let (mut sum, mut n_pixels) = (0., 0.);
+    for y in range(cy, rows) {
+        for x in range(cx, cols) {
+            let value = data[[y, x]];
+            if value.is_nan() {
+                continue;
+            }
+            let inside = offsets
+                .iter()
+                .flat_map(|&oy| offsets.iter().map(move |&ox| (ox, oy)))
+                .filter(|&(ox, oy)| aperture.contains(x as f64 + ox, y as f64 + oy))
+                .count();
+            let fraction = inside as f64 * step * step;
+            sum += fraction * value;
+            n_pixels += fraction;
+        }
+    }
+
+    ApertureResult {
+        sum,
+        mean: sum / n_pixels,
+        n_pixels,
+        error: (sum.max(0.) / gain).sqrt(),
+    }
+}
+
+/// How [`interpolate_bad_pixels`] estimates the value of a bad pixel.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum InterpolationMethod {
+    /// The value of the closest good pixel
+    NearestNeighbor,
+    /// Linear interpolation between the closest good pixels left and right, and above and
+    /// below, averaging the two directions
+    BilinearNeighbors,
+    /// The median of the good pixels in the surrounding 5x5 box
+    Median5x5,
+}
+
+/// Replace the pixels where `mask` is true by a value interpolated from the good pixels
+/// around them. Where a method finds no good pixels (e.g. inside a large cluster of bad
+/// pixels), the nearest good pixel is used instead. If there are no good pixels at all,
+/// the bad pixels become NaN.
+pub fn interpolate_bad_pixels(
+    data: &Array2<f64>,
+    mask: &Array2<bool>,
+    method: InterpolationMethod,
+) -> Array2<f64> {
+    let (rows, cols) = data.dim();
+    let good = |y: i64, x: i64| {
+        y >= 0
+            && x >= 0
+            && (y as usize) < rows
+            && (x as usize) < cols
+            && !mask[[y as usize, x as usize]]
+    };
+
+    // Closest good pixel, searching square rings of growing size
+    let nearest = |y: i64, x: i64| -> f64 {
+        let mut best: Option<(i64, f64)> = None;
+        for r in 1..=rows.max(cols) as i64 {
+            if best.is_some_and(|(dist2, _v)| dist2 <= r * r) {
+                break;
+            }
+            for dy in -r..=r {
+                let step = if dy.abs() == r { 1 } else { 2 * r };
+                for dx in (-r..=r).step_by(step as usize) {
+                    let dist2 = dy * dy + dx * dx;
+                    if good(y + dy, x + dx) && best.filter(|&(d, _v)| d <= dist2).is_none() {
+                        best = Some((dist2, data[[(y + dy) as usize, (x + dx) as usize]]));
+                    }
+                }
+            }
+        }
+        best.map_or(f64::NAN, |(_d, value)| value)
+    };
+
+    // Interpolate along the line through (y, x) in direction (dy, dx)
+    let along = |y: i64, x: i64, dy: i64, dx: i64| -> Option<f64> {
+        let find = |sign: i64| {
+            (1..)
+                .map(|d| (d, y + sign * d * dy, x + sign * d * dx))
+                .take_while(|&(_d, py, px)| {
+                    py >= 0 && px >= 0 && (py as usize) < rows && (px as usize) < cols
+                })
+                .find(|&(_d, py, px)| good(py, px))
+                .map(|(d, py, px)| (d as f64, data[[py as usize, px as usize]]))
+        };
+        match (find(-1), find(1)) {
+            (Some((d0, v0)), Some((d1, v1))) => Some((v0 * d1 + v1 * d0) / (d0 + d1)),
+            (Some((_d, v)), None) | (None, Some((_d, v))) => Some(v),
+            (None, None) => None,
+        }
+    };
+
+    let mut window = Vec::with_capacity(25);
+    let mut out = data.clone();
+    for ((y, x), value) in out.indexed_iter_mut() {
+        if !mask[[y, x]] {
+            continue;
+        }
+        let (y, x) = (y as i64, x as i64);
+        let estimate = match method {
+            InterpolationMethod::NearestNeighbor => None,
+            InterpolationMethod::BilinearNeighbors => {
+                match (along(y, x, 0, 1), along(y, x, 1, 0)) {
+                    (Some(h), Some(v)) => Some((h + v) / 2.),
+                    (h, v) => h.or(v),
+                }
+            }
+            InterpolationMethod::Median5x5 => {
+                window.clear();
+                for py in y - 2..=y + 2 {
+                    for px in x - 2..=x + 2 {
+                        if good(py, px) {
+                            window.push(data[[py as usize, px as usize]]);
+                        }
+                    }
+                }
+                (!window.is_empty()).then(|| median(&mut window))
+            }
+        };
+        *value = estimate.unwrap_or_else(|| nearest(y, x));
+    }
+    out
+}
+
+/// Inverse-variance weights `1 / (readnoise^2 + max(0, data / gain))` for a Poisson plus
+/// read noise model. Pixels where `mask` is true get a weight of zero.
+pub fn create_weight_map(
+    data: &Array2<f64>,
+    mask: &Array2<bool>,
+    gain: f64,
+    readnoise: f64,
+) -> Array2<f64> {
+    Zip::from(data).and(mask).map_collect(|&value, &masked| {
+        if masked {
+            0.
+        } else {
+            1. / (readnoise * readnoise + (value / gain).max(0.))
+        }
+    })
+}
+
+/// Combine images into their (weighted) mean `sum(w_i * x_i) / sum(w_i)`.
+///
+/// Without weights every image counts equally. Weights are given per pixel, with shape
+/// (NAXIS2, NAXIS1) for 2D images. The output header is a copy of the first header with the
+/// HISTORY of the other inputs appended and EXPTIME set to the total exposure time.
+pub fn coadd(
+    images: &[&BasicFits],
+    weights: Option<&[&Array2<f64>]>,
+) -> Result<BasicFits, FitsError> {
+    let first = check_same_shape(images)?;
+    let axes = &first.header.axes;
+
+    let mut sum = GenericData::<f64>::zeros(first.data.shape());
+    let data = match weights {
+        None => {
+            for fits in images.iter() {
+                sum += &fits.physical_data();
+            }
+            sum / images.len() as f64
+        }
+        Some(weights) => {
+            if weights.len() != images.len() {
+                return Err(FitsError::ShapeMismatch {
+                    expected: vec![images.len()],
+                    found: vec![weights.len()],
+                });
+            }
+            // Weights are indexed [y, x], i.e. with the FITS axes reversed
+            let weight_shape: Vec<usize> = axes.iter().rev().cloned().collect();
+            let mut weight_sum = GenericData::<f64>::zeros(sum.raw_dim());
+            for (fits, w) in images.iter().zip(weights.iter()) {
+                if w.shape() != weight_shape.as_slice() {
+                    return Err(FitsError::ShapeMismatch {
+                        expected: weight_shape,
+                        found: w.shape().to_vec(),
+                    });
+                }
+                for ((s, ws), (x, wi)) in sum
+                    .iter_mut()
+                    .zip(weight_sum.iter_mut())
+                    .zip(fits.physical_data().iter().zip(w.iter()))
+                {
+                    *s += wi * x;
+                    *ws += wi;
+                }
+            }
+            sum / weight_sum
+        }
+    };
+
+    // Only non-mandatory keywords were touched, so this parses like the first header did
+    let header = Header::from_keyword_list(stacked_keywords(images))
+        .expect("mandatory keywords are unchanged");
+    Ok(BasicFits {
+        header,
+        data: Arc::new(data.into()),
+    })
+}
+
+// The first image, if all images have the same shape
+fn check_same_shape<'a>(images: &[&'a BasicFits]) -> Result<&'a BasicFits, FitsError> {
+    let first = images.first().ok_or(FitsError::NoImages)?;
+    for fits in images.iter() {
+        if fits.header.axes != first.header.axes {
+            return Err(FitsError::ShapeMismatch {
+                expected: first.header.axes.clone(),
+                found: fits.header.axes.clone(),
+            });
+        }
+    }
+    Ok(first)
+}
+
+// Keywords for a stack of images: those of the first image, the HISTORY of the others,
+// the total EXPTIME and the keywords of f64 data
+fn stacked_keywords(images: &[&BasicFits]) -> KeywordList {
+    let mut keywords = images[0].header.keywords.clone();
+    for fits in images[1..].iter() {
+        for kw in fits.header.keywords.iter() {
+            if let Keyword::History(_) = kw {
+                keywords.push(kw.clone());
+            }
+        }
+    }
+    let exptimes: Vec<f64> = images
+        .iter()
+        .filter_map(|fits| fits.header.get_value("EXPTIME"))
+        .map(Value::as_f64_lossy)
+        .collect();
+    if !exptimes.is_empty() {
+        let exptime = Value::Float(exptimes.iter().sum());
+        set_keyword(&mut keywords, "EXPTIME", exptime, None);
+    }
+    set_float_keywords(&mut keywords);
+    keywords
+}
+
+// Results are physical values stored as f64: BITPIX -64, without BSCALE, BZERO and BLANK,
+// which would otherwise be applied to them again when reading
+pub(crate) fn set_float_keywords(keywords: &mut KeywordList) {
+    keywords.retain(|kw| !matches!(kw.keyword_name(), "BSCALE" | "BZERO" | "BLANK"));
+    set_keyword(keywords, "BITPIX", Value::Integer(-64), None);
+}
+
+/// Average images pixel by pixel, leaving out values more than `sigma` robust standard
+/// deviations (1.4826 times the median absolute deviation) from the median of that pixel.
+/// This removes e.g. cosmic rays and satellite trails, which only appear in one image.
+/// NaN values are ignored.
+///
+/// The header is that of the first image, with the HISTORY of the others, the total
+/// EXPTIME and the number of images in NIMAGES.
+pub fn sigma_clip_stack(images: &[&BasicFits], sigma: f64) -> Result<BasicFits, FitsError> {
+    let first = check_same_shape(images)?;
+
+    let datas: Vec<GenericData<f64>> = images.iter().map(|fits| fits.physical_data()).collect();
+    let mut values = Vec::with_capacity(images.len());
+    let mut deviations = Vec::with_capacity(images.len());
+    let data = GenericData::from_shape_fn(first.data.shape(), |idx| {
+        values.clear();
+        values.extend(datas.iter().map(|data| data[&idx]).filter(|v| !v.is_nan()));
+        let center = median(&mut values);
+        deviations.clear();
+        deviations.extend(values.iter().map(|v| (v - center).abs()));
+        let limit = sigma * 1.4826 * median(&mut deviations);
+
+        let kept: Vec<f64> = values
+            .iter()
+            .cloned()
+            .filter(|v| (v - center).abs() <= limit)
+            .collect();
+        if kept.is_empty() {
+            center
+        } else {
+            kept.iter().sum::<f64>() / kept.len() as f64
+        }
+    });
+
+    let mut keywords = stacked_keywords(images);
+    let count = Value::Integer(images.len() as i64);
+    set_keyword(
+        &mut keywords,
+        "NIMAGES",
+        count,
+        Some("number of stacked images"),
+    );
+    keywords.push(Keyword::History(format!(
+        "Sigma clipped ({}) mean of {} images",
+        sigma,
+        images.len()
+    )));
+    let header = Header::from_keyword_list(keywords).expect("mandatory keywords are unchanged");
+    Ok(BasicFits {
+        header,
+        data: Arc::new(data.into()),
+    })
+}
+
+/// Median of images pixel by pixel, which is robust against e.g. cosmic rays and satellite
+/// trails in a few of them. The images must have the same shape and BITPIX. NaN values are
+/// ignored.
+///
+/// The header is that of the first image, with the HISTORY of the others and the number of
+/// images in NIMAGES. EXPTIME is that of the first image, as the median has the exposure
+/// time of a single frame.
+pub fn stacked_median(images: &[&BasicFits]) -> Result<BasicFits, FitsError> {
+    let first = images.first().ok_or(FitsError::NoImages)?;
+    for fits in images.iter() {
+        check_same_format(first, fits)?;
+    }
+
+    let datas: Vec<GenericData<f64>> = images.iter().map(|fits| fits.physical_data()).collect();
+    let mut values = Vec::with_capacity(images.len());
+    let data = GenericData::from_shape_fn(first.data.shape(), |idx| {
+        values.clear();
+        values.extend(datas.iter().map(|data| data[&idx]).filter(|v| !v.is_nan()));
+        median(&mut values)
+    });
+
+    let mut keywords = stacked_keywords(images);
+    if let Some(exptime) = first.header.get_value("EXPTIME") {
+        set_keyword(&mut keywords, "EXPTIME", exptime.clone(), None);
+    }
+    let count = Value::Integer(images.len() as i64);
+    set_keyword(
+        &mut keywords,
+        "NIMAGES",
+        count,
+        Some("number of stacked images"),
+    );
+    keywords.push(Keyword::History(format!(
+        "Median of {} images",
+        images.len()
+    )));
+    let header = Header::from_keyword_list(keywords).expect("mandatory keywords are unchanged");
+    Ok(BasicFits {
+        header,
+        data: Arc::new(data.into()),
+    })
+}
+
+// Images that are combined pixel by pixel have to match in shape and data type
+fn check_same_format(reference: &BasicFits, other: &BasicFits) -> Result<(), FitsError> {
+    if other.header.axes != reference.header.axes {
+        return Err(FitsError::ShapeMismatch {
+            expected: reference.header.axes.clone(),
+            found: other.header.axes.clone(),
+        });
+    }
+    if other.header.bitpix != reference.header.bitpix {
+        return Err(FitsError::BitpixMismatch {
+            expected: reference.header.bitpix.to_int(),
+            found: other.header.bitpix.to_int(),
+        });
+    }
+    Ok(())
+}
+
+/// Correct `science` for pixel-to-pixel sensitivity variations by dividing it by `flat`,
+/// normalized by its median. Pixels where the normalized flat is below 0.1 are set to
+/// NaN, as dividing by them would mostly amplify noise.
+///
+/// The header is that of the science image, with a HISTORY card added.
+pub fn flat_field_correct(science: &BasicFits, flat: &BasicFits) -> Result<BasicFits, FitsError> {
+    check_same_format(science, flat)?;
+
+    let flat = flat.physical_data();
+    let mut values: Vec<f64> = flat.iter().cloned().filter(|v| !v.is_nan()).collect();
+    let norm = median(&mut values);
+    let mut data = science.physical_data();
+    Zip::from(&mut data).and(&flat).for_each(|s, &f| {
+        let f = f / norm;
+        *s = if f >= 0.1 { *s / f } else { f64::NAN };
+    });
+
+    let history = format!("Flat field corrected (flat median {})", norm);
+    Ok(calibrated(science, data, history))
+}
+
+/// Subtract a master bias frame (the electronic offset) from `science`, pixel by pixel.
+///
+/// The header is that of the science image, with a HISTORY card added.
+pub fn bias_subtract(science: &BasicFits, bias: &BasicFits) -> Result<BasicFits, FitsError> {
+    check_same_shape(&[science, bias])?;
+    let data = science.physical_data() - bias.physical_data();
+    Ok(calibrated(science, data, "Bias subtracted".to_string()))
+}
+
+/// Subtract a master dark frame (the thermal signal) from `science`. The dark is first
+/// multiplied by `exposure_ratio`, the science exposure time divided by that of the dark,
+/// so a dark taken with a different exposure time can be used.
+///
+/// The header is that of the science image, with a HISTORY card added.
+pub fn dark_subtract(
+    science: &BasicFits,
+    dark: &BasicFits,
+    exposure_ratio: f64,
+) -> Result<BasicFits, FitsError> {
+    check_same_shape(&[science, dark])?;
+    let data = science.physical_data() - dark.physical_data() * exposure_ratio;
+    let history = format!("Dark subtracted (scaled by {})", exposure_ratio);
+    Ok(calibrated(science, data, history))
+}
+
+/// Remove the bias level measured in the overscan columns `x_start..x_end` of a CCD
+/// image. The median of the overscan pixels of each row is subtracted from that whole row
+/// (overscan included), which also corrects drifts of the bias during readout.
+///
+/// With `smooth_overscan`, the per row levels are median filtered over 5 rows first to
+/// reduce their noise. Panics if the overscan region is empty or outside the image.
+pub fn overscan_subtract(
+    data: &Array2<f64>,
+    overscan_region: (usize, usize),
+    smooth_overscan: bool,
+) -> Array2<f64> {
+    let (x_start, x_end) = overscan_region;
+    assert!(
+        x_start < x_end && x_end <= data.ncols(),
+        "overscan region must be a non-empty range of columns"
+    );
+    let mut row_values = Vec::with_capacity(x_end - x_start);
+    let mut levels: Vec<f64> = data
+        .slice(s![.., x_start..x_end])
+        .rows()
+        .into_iter()
+        .map(|row| {
+            row_values.clear();
+            row_values.extend(row.iter().cloned());
+            median(&mut row_values)
+        })
+        .collect();
+
+    if smooth_overscan {
+        let n = levels.len();
+        let mut window = Vec::with_capacity(5);
+        levels = (0..n as i64)
+            .map(|y| {
+                window.clear();
+                window.extend((y - 2..=y + 2).map(|i| levels[reflect_index(i, n)]));
+                median(&mut window)
+            })
+            .collect();
+    }
+
+    let mut corrected = data.clone();
+    for (mut row, level) in corrected.rows_mut().into_iter().zip(levels) {
+        row -= level;
+    }
+    corrected
+}
+
+/// How [`defringe`] determines the amplitude of the fringes in the image.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FringeMethod {
+    /// Least squares scale of the template, `sum(data * template) / sum(template^2)`.
+    ScaleAndSubtract,
+    /// Scale from the correlation of the data and template around their means, i.e. the
+    /// least squares scale after removing the mean of both. Unlike `ScaleAndSubtract`,
+    /// this is not biased by a sky background.
+    CorrelationScale,
+}
+
+/// Remove an interference fringe pattern by subtracting `fringe_template`, scaled to the
+/// amplitude of the fringes in `data`. NaN pixels are ignored when determining the scale.
+///
+/// Panics if the data and template differ in shape.
+pub fn defringe(
+    data: &Array2<f64>,
+    fringe_template: &Array2<f64>,
+    method: FringeMethod,
+) -> Array2<f64> {
+    assert_eq!(
+        data.dim(),
+        fringe_template.dim(),
+        "fringe template must have the shape of the image"
+    );
+    let pairs: Vec<(f64, f64)> = data
+        .iter()
+        .zip(fringe_template.iter())
+        .filter(|(d, t)| !d.is_nan() && !t.is_nan())
+        .map(|(&d, &t)| (d, t))
+        .collect();
+    let (mean_d, mean_t) = match method {
+        FringeMethod::ScaleAndSubtract => (0., 0.),
+        FringeMethod::CorrelationScale => {
+            let n = pairs.len() as f64;
+            (
+                pairs.iter().map(|(d, _)| d).sum::<f64>() / n,
+                pairs.iter().map(|(_, t)| t).sum::<f64>() / n,
+            )
+        }
+    };
+    let covariance: f64 = pairs.iter().map(|(d, t)| (d - mean_d) * (t - mean_t)).sum();
+    let variance: f64 = pairs.iter().map(|(_, t)| (t - mean_t).powi(2)).sum();
+    let scale = if variance > 0. {
+        covariance / variance
+    } else {
+        0.
+    };
+    data - &(fringe_template * scale)
+}
+
+/// Isolate the fringe pattern of a single image, for use as template in [`defringe`], by
+/// subtracting the background on scales larger than the fringes. The background is
+/// [`local_background`] with meshes of `fringe_scale` pixels, so this should be a few
+/// times the spacing of the fringes.
+///
+/// `fringe_scale` is in whole pixels, as the meshes are made of whole pixels.
+/// Panics if `fringe_scale` is 0.
+pub fn fringe_map(data: &Array2<f64>, fringe_scale: usize) -> Array2<f64> {
+    data - &local_background(data, fringe_scale, 1)
+}
+
+// The calibrated version of `science`, recording the step in its HISTORY
+fn calibrated(science: &BasicFits, data: GenericData<f64>, history: String) -> BasicFits {
+    let mut keywords = science.header.keywords.clone();
+    set_float_keywords(&mut keywords);
+    keywords.push(Keyword::History(history));
+    let header = Header::from_keyword_list(keywords).expect("mandatory keywords are unchanged");
+    BasicFits {
+        header,
+        data: Arc::new(data.into()),
+    }
+}
+
+#[cfg(feature = "fft")]
+mod fourier {
+    use ndarray::{Array2, Axis};
+    use rustfft::num_complex::Complex;
+    use rustfft::FftPlanner;
+
+    // 2D FFT done as 1D transforms over the rows followed by the columns.
+    // The inverse transform is not normalized, like rustfft itself.
+    pub fn fft2(data: &mut Array2<Complex<f64>>, inverse: bool) {
+        let mut planner = FftPlanner::new();
+        for axis in [Axis(1), Axis(0)] {
+            let len = data.len_of(axis);
+            let fft = if inverse {
+                planner.plan_fft_inverse(len)
+            } else {
+                planner.plan_fft_forward(len)
+            };
+            let mut buffer = vec![Complex::new(0., 0.); len];
+            for mut lane in data.lanes_mut(axis) {
+                for (b, v) in buffer.iter_mut().zip(lane.iter()) {
+                    *b = *v;
+                }
+                fft.process(&mut buffer);
+                for (v, b) in lane.iter_mut().zip(buffer.iter()) {
+                    *v = *b;
+                }
+            }
+        }
+    }
+
+    pub fn to_complex(data: &Array2<f64>) -> Array2<Complex<f64>> {
+        data.mapv(|x| Complex::new(x, 0.))
+    }
+}
+
+/// Cross-correlation of two equally shaped images, computed as IFFT(FFT(a) * conj(FFT(b))).
+///
+/// If `a` is `b` shifted by `(dy, dx)`, the output peaks at `(dy, dx)` (modulo the image size).
+/// Panics if the shapes of `a` and `b` differ.
+#[cfg(feature = "fft")]
+pub fn cross_correlate(a: &Array2<f64>, b: &Array2<f64>) -> Array2<f64> {
+    assert_eq!(a.dim(), b.dim(), "cross_correlate requires equally shaped images");
+    let mut fa = fourier::to_complex(a);
+    let mut fb = fourier::to_complex(b);
+    fourier::fft2(&mut fa, false);
+    fourier::fft2(&mut fb, false);
+
+    let mut product = fa * fb.mapv(|x| x.conj());
+    fourier::fft2(&mut product, true);
+
+    let n = a.len() as f64;
+    product.mapv(|x| x.re / n)
+}
+
+/// Convolve an image with a point spread function, using FFTs.
+///
+/// The centre of the PSF is taken to be at `(rows / 2, cols / 2)`, so the output is aligned
+/// with the input image. Both are zero padded to avoid wrapping around the edges, so the
+/// image is taken to be zero outside its borders.
+#[cfg(feature = "fft")]
+pub fn fft_convolve_with_psf(image: &Array2<f64>, psf: &Array2<f64>) -> Array2<f64> {
+    let (rows, cols) = image.dim();
+    let (psf_rows, psf_cols) = psf.dim();
+    let padded = (
+        (rows + psf_rows - 1).next_power_of_two(),
+        (cols + psf_cols - 1).next_power_of_two(),
+    );
+
+    let mut fi = Array2::zeros(padded);
+    fi.slice_mut(s![..rows, ..cols])
+        .assign(&fourier::to_complex(image));
+
+    // Put the centre of the PSF at the origin, wrapping the rest around the edges
+    let mut fp = fourier::to_complex(&Array2::zeros(padded));
+    for ((y, x), &value) in psf.indexed_iter() {
+        let py = (y as i64 - (psf_rows / 2) as i64).rem_euclid(padded.0 as i64);
+        let px = (x as i64 - (psf_cols / 2) as i64).rem_euclid(padded.1 as i64);
+        fp[[py as usize, px as usize]].re = value;
+    }
+
+    fourier::fft2(&mut fi, false);
+    fourier::fft2(&mut fp, false);
+    let mut product = fi * fp;
+    fourier::fft2(&mut product, true);
+
+    let n = (padded.0 * padded.1) as f64;
+    product.slice(s![..rows, ..cols]).mapv(|x| x.re / n)
+}
+
+/// How [`align_and_stack`] lines up the images.
+#[cfg(feature = "fft")]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AlignMethod {
+    /// Shift by the offset found with [`cross_correlate`], see [`BasicFits::align_to`]
+    CrossCorrelation,
+    /// Resample with the WCS of the headers, see [`BasicFits::reproject_to`]
+    Wcs,
+}
+
+/// Align all images onto the first one and combine them with [`sigma_clip_stack`],
+/// rejecting values beyond 3 sigma.
+#[cfg(feature = "fft")]
+pub fn align_and_stack(images: &[&BasicFits], method: AlignMethod) -> Result<BasicFits, FitsError> {
+    let first = check_same_shape(images)?;
+    let aligned = match method {
+        AlignMethod::CrossCorrelation => images[1..]
+            .iter()
+            .map(|fits| fits.align_to(first))
+            .collect::<Result<Vec<_>, _>>()?,
+        AlignMethod::Wcs => images[1..]
+            .iter()
+            .map(|fits| fits.reproject_to(first))
+            .collect::<Result<Vec<_>, _>>()?,
+    };
+
+    let stack: Vec<&BasicFits> = std::iter::once(first).chain(aligned.iter()).collect();
+    sigma_clip_stack(&stack, 3.)
+}
+
+/// Location of the maximum of a cross-correlation, as a signed `(dy, dx)` offset.
+///
+/// Peaks beyond half the image size are wrapped around to negative offsets.
+pub fn find_correlation_peak(corr: &Array2<f64>) -> (i64, i64) {
+    let (rows, cols) = corr.dim();
+    let mut best = (0, 0);
+    let mut best_value = f64::NEG_INFINITY;
+    for ((y, x), value) in corr.indexed_iter() {
+        if *value > best_value {
+            best_value = *value;
+            best = (y, x);
+        }
+    }
+
+    let wrap = |idx: usize, n: usize| {
+        if idx > n / 2 {
+            idx as i64 - n as i64
+        } else {
+            idx as i64
+        }
+    };
+    (wrap(best.0, rows), wrap(best.1, cols))
+}
+
+/// Find where `template` matches `data` best, as `(row, col, ncc)` with `(row, col)` the
+/// top left corner of the best matching patch and `ncc` its normalized cross-correlation
+/// `cov(template, patch) / (std(template) * std(patch))`, between -1 and 1.
+///
+/// Every patch is compared in turn, so this is meant for small templates. Constant
+/// patches have an NCC of 0. Panics if the template is larger than the data.
+pub fn sub_image_correlation(data: &Array2<f64>, template: &Array2<f64>) -> (usize, usize, f64) {
+    let (rows, cols) = data.dim();
+    let (trows, tcols) = template.dim();
+    assert!(
+        trows <= rows && tcols <= cols,
+        "template must not be larger than the data"
+    );
+    let n = template.len() as f64;
+    let t = template - template.sum() / n;
+    let t_norm = t.iter().map(|v| v * v).sum::<f64>().sqrt();
+
+    let mut best = (0, 0, f64::NEG_INFINITY);
+    for y in 0..=rows - trows {
+        for x in 0..=cols - tcols {
+            let patch = data.slice(s![y..y + trows, x..x + tcols]);
+            let mean = patch.sum() / n;
+            let (mut cov, mut var) = (0., 0.);
+            Zip::from(&patch).and(&t).for_each(|&p, &t| {
+                cov += (p - mean) * t;
+                var += (p - mean) * (p - mean);
+            });
+            let ncc = if var > 0. && t_norm > 0. {
+                cov / (var.sqrt() * t_norm)
+            } else {
+                0.
+            };
+            if ncc > best.2 {
+                best = (y, x, ncc);
+            }
+        }
+    }
+    best
+}
+
+/// Least squares fit of the line `y = slope * x + intercept`, returned as
+/// `(slope, intercept, r)` with `r` the Pearson correlation coefficient. Points where `x`
+/// or `y` is NaN are left out.
+///
+/// Fails with `FitsError::InsufficientData` if fewer than 2 points (with different `x`)
+/// remain.
+pub fn linear_fit(x: &[f64], y: &[f64]) -> Result<(f64, f64, f64), FitsError> {
+    if x.len() != y.len() {
+        return Err(FitsError::ShapeMismatch {
+            expected: vec![x.len()],
+            found: vec![y.len()],
+        });
+    }
+    let points: Vec<(f64, f64)> = x
+        .iter()
+        .zip(y.iter())
+        .filter(|(x, y)| !x.is_nan() && !y.is_nan())
+        .map(|(&x, &y)| (x, y))
+        .collect();
+    let n = points.len() as f64;
+    let mean_x = points.iter().map(|p| p.0).sum::<f64>() / n;
+    let mean_y = points.iter().map(|p| p.1).sum::<f64>() / n;
+    let (mut sxx, mut syy, mut sxy) = (0., 0., 0.);
+    for (x, y) in points.iter() {
+        sxx += (x - mean_x) * (x - mean_x);
+        syy += (y - mean_y) * (y - mean_y);
+        sxy += (x - mean_x) * (y - mean_y);
+    }
+    if points.len() < 2 || sxx == 0. {
+        return Err(FitsError::InsufficientData {
+            found: points.len(),
+            required: 2,
+        });
+    }
+
+    let slope = sxy / sxx;
+    let r = sxy / (sxx * syy).sqrt();
+    Ok((slope, mean_y - slope * mean_x, r))
+}
+
+/// Fit the power law `y = a * x^alpha`, e.g. to source counts, as a straight line in
+/// log-log space. Returns `(alpha, a)`. Points that are NaN or not positive are left out.
+pub fn power_law_fit(x: &[f64], y: &[f64]) -> Result<(f64, f64), FitsError> {
+    let log = |v: &f64| if *v > 0. { v.log10() } else { f64::NAN };
+    let log_x: Vec<f64> = x.iter().map(log).collect();
+    let log_y: Vec<f64> = y.iter().map(log).collect();
+    let (alpha, log_a, _r) = linear_fit(&log_x, &log_y)?;
+    Ok((alpha, 10f64.powf(log_a)))
+}
+
+/// Angular separation in degrees between two sky positions, given in degrees. Uses the
+/// Vincenty formula, which is accurate for both very small and nearly antipodal
+/// separations.
+pub fn celestial_distance(ra1_deg: f64, dec1_deg: f64, ra2_deg: f64, dec2_deg: f64) -> f64 {
+    let (dec1, dec2) = (dec1_deg.to_radians(), dec2_deg.to_radians());
+    let dra = (ra2_deg - ra1_deg).to_radians();
+    let (sin_dra, cos_dra) = dra.sin_cos();
+
+    let y = ((dec2.cos() * sin_dra).powi(2)
+        + (dec1.cos() * dec2.sin() - dec1.sin() * dec2.cos() * cos_dra).powi(2))
+    .sqrt();
+    let x = dec1.sin() * dec2.sin() + dec1.cos() * dec2.cos() * cos_dra;
+    y.atan2(x).to_degrees()
+}
+
+/// Flat sky approximation of [`celestial_distance`],
+/// `sqrt((dRA * cos(dec))^2 + dDec^2)` with `dec` the mean declination. Faster, and
+/// accurate for separations up to about a degree away from the poles.
+pub fn small_angle_distance_approx(
+    ra1_deg: f64,
+    dec1_deg: f64,
+    ra2_deg: f64,
+    dec2_deg: f64,
+) -> f64 {
+    // Take the short way around when crossing RA = 0
+    let dra = (ra2_deg - ra1_deg + 180.).rem_euclid(360.) - 180.;
+    let ddec = dec2_deg - dec1_deg;
+    let cos_dec = ((dec1_deg + dec2_deg) / 2.).to_radians().cos();
+    ((dra * cos_dec).powi(2) + ddec.powi(2)).sqrt()
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use ndarray::{arr1, Array1};
+
+    pub(crate) fn test_fits(data: &Array2<f64>) -> BasicFits {
+        let (rows, cols) = data.dim();
+        let card = |kw: &str, value: Value| Keyword::Value(kw.to_string(), value, String::new());
+        let keywords = vec![
+            card("SIMPLE", Value::Boolean(true)),
+            card("BITPIX", Value::Integer(-64)),
+            card("NAXIS", Value::Integer(2)),
+            card("NAXIS1", Value::Integer(cols as i64)),
+            card("NAXIS2", Value::Integer(rows as i64)),
+        ];
+        BasicFits {
+            header: Header::from_keyword_list(keywords).unwrap(),
+            data: Arc::new(from_array2(data.clone()).into()),
+        }
+    }
+
+    fn blob(rows: usize, cols: usize, cy: f64, cx: f64) -> Array2<f64> {
+        Array2::from_shape_fn((rows, cols), |(y, x)| {
+            let r2 = (y as f64 - cy).powi(2) + (x as f64 - cx).powi(2);
+            (-r2 / 8.).exp()
+        })
+    }
+
+    #[test]
+    fn shift_test() {
+        let data = Array2::from_shape_vec((2, 3), vec![1., 2., 3., 4., 5., 6.]).unwrap();
+        let shifted = shift(&data, 1, -1);
+        assert_eq!(
+            shifted,
+            Array2::from_shape_vec((2, 3), vec![0., 0., 0., 2., 3., 0.]).unwrap()
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "fft")]
+    fn cross_correlate_test() {
+        let reference = blob(32, 24, 12., 10.);
+        let moved = blob(32, 24, 15., 8.);
+
+        let corr = cross_correlate(&moved, &reference);
+        assert_eq!(find_correlation_peak(&corr), (3, -2));
+
+        let corr = cross_correlate(&reference, &moved);
+        assert_eq!(find_correlation_peak(&corr), (-3, 2));
+    }
+
+    #[test]
+    #[cfg(feature = "fft")]
+    fn align_to_test() {
+        let reference = test_fits(&blob(32, 32, 16., 16.));
+        let moved = test_fits(&blob(32, 32, 19., 14.));
+
+        let aligned = moved.align_to(&reference).unwrap();
+        let aligned = to_array2(&aligned.data.to_f64(), &aligned.header.axes).unwrap();
+        let expected = to_array2(&reference.data.to_f64(), &reference.header.axes).unwrap();
+        // Compare away from the edges, where zeros were shifted in
+        for y in 4..28 {
+            for x in 4..28 {
+                assert!((aligned[[y, x]] - expected[[y, x]]).abs() < 1e-12);
+            }
+        }
+    }
+
+    #[cfg(feature = "fft")]
+    #[test]
+    fn fft_convolve_with_psf_test() {
+        let image = blob(20, 30, 8., 13.) + Array2::from_elem((20, 30), 1.);
+        let mut delta = Array2::zeros((5, 5));
+        delta[[2, 2]] = 1.;
+        let convolved = fft_convolve_with_psf(&image, &delta);
+        Zip::from(&convolved)
+            .and(&image)
+            .for_each(|&c, &i| assert!((c - i).abs() < 1e-12));
+
+        // A point source becomes the PSF itself
+        let mut point = Array2::zeros((32, 32));
+        point[[10, 20]] = 1.;
+        let psf = blob(15, 15, 7., 7.);
+        let convolved = fft_convolve_with_psf(&point, &psf);
+        let mut expected = Array2::zeros((32, 32));
+        expected.slice_mut(s![3..18, 13..28]).assign(&psf);
+        Zip::from(&convolved)
+            .and(&expected)
+            .for_each(|&c, &e| assert!((c - e).abs() < 1e-9));
+        assert!((convolved.sum() - psf.sum()).abs() < 1e-9);
+    }
+
+    #[test]
+    fn cutout_test() {
+        let data = Array2::from_shape_fn((10, 12), |(y, x)| (100 * y + x) as f64);
+        let mut fits = test_fits(&data);
+        fits.header.keywords.extend([
+            Keyword::Value("CRPIX1".to_string(), Value::Float(10.5), String::new()),
+            Keyword::Value("CRPIX2".to_string(), Value::Integer(2), String::new()),
+        ]);
+
+        let stamp = cutout(&fits, 6, 5, 5).unwrap();
+        assert_eq!(stamp.header.axes, vec![5, 5]);
+        let pixels = to_array2(&stamp.data.to_f64(), &stamp.header.axes).unwrap();
+        assert_eq!(pixels[[2, 2]], data[[5, 6]]);
+
+        // The centre keeps its position relative to the reference pixel
+        let crpix1 = stamp.header.get_float("CRPIX1").unwrap();
+        let crpix2 = stamp.header.get_float("CRPIX2").unwrap();
+        assert_eq!(2. - crpix1, 6. - 10.5);
+        assert_eq!(2. - crpix2, 5. - 2.);
+
+        assert!(matches!(
+            cutout(&fits, 1, 5, 5),
+            Err(FitsError::OutOfBounds)
+        ));
+        assert!(matches!(
+            cutout(&fits, 6, 8, 5),
+            Err(FitsError::OutOfBounds)
+        ));
+        assert!(cutout(&fits, 9, 7, 5).is_ok());
+    }
+
+    #[test]
+    fn median_filter_test() {
+        let mut data = Array2::from_elem((7, 7), 10.);
+        data[[3, 4]] = 5000.; // cosmic ray
+        data[[0, 0]] = 12.;
+
+        let filtered = median_filter(&data, 3);
+        assert_eq!(filtered[[3, 4]], 10.);
+        assert!(filtered.iter().all(|&v| v == 10.));
+
+        // The filter should leave a linear gradient untouched (away from the corners)
+        let ramp = Array2::from_shape_fn((5, 6), |(y, x)| (y + x) as f64);
+        let filtered = median_filter(&ramp, 3);
+        for y in 1..4 {
+            for x in 1..5 {
+                assert_eq!(filtered[[y, x]], ramp[[y, x]]);
+            }
+        }
+    }
+
+    #[test]
+    fn adaptive_threshold_test() {
+        let mut data = Array2::<f64>::zeros((20, 20));
+        data[[10, 10]] = 100.;
+        let mask = adaptive_threshold(&data, 5, -10.);
+        assert!(mask[[10, 10]]);
+        assert_eq!(mask.iter().filter(|&&m| m).count(), 1);
+
+        // Spanning a range close to the source brightness, a global threshold would fail
+        let gradient = Array2::from_shape_fn((20, 20), |(y, x)| 2. * (x + y) as f64);
+        let mask = adaptive_threshold(&(data + gradient), 5, -10.);
+        assert!(mask[[10, 10]]);
+        assert_eq!(mask.iter().filter(|&&m| m).count(), 1);
+    }
+
+    #[test]
+    fn detect_cosmic_rays_test() {
+        // A noisy, but deterministic, background
+        let mut data =
+            Array2::from_shape_fn((20, 20), |(y, x)| 100. + ((y * 31 + x * 17) as f64).sin());
+        data[[10, 10]] = 200.;
+
+        let mask = detect_cosmic_rays(&data, 5., false);
+        assert!(mask[[10, 10]]);
+        assert_eq!(mask.iter().filter(|&&m| m).count(), 1);
+
+        // A single pixel is rejected by the neighbour check, a track is not
+        assert!(!detect_cosmic_rays(&data, 5., true)[[10, 10]]);
+        data[[10, 11]] = 180.;
+        let mask = detect_cosmic_rays(&data, 5., true);
+        assert!(mask[[10, 10]] && mask[[10, 11]]);
+        assert_eq!(mask.iter().filter(|&&m| m).count(), 2);
+    }
+
+    #[test]
+    fn local_background_test() {
+        let flat = Array2::from_elem((50, 40), 7.);
+        let background = local_background(&flat, 8, 3);
+        assert!(background.iter().all(|&b| (b - 7.).abs() < 1e-12));
+
+        // Meshes that do not fit the image exactly, and a bright source that should
+        // be clipped away
+        let mut data = Array2::from_shape_fn((50, 40), |(y, x)| 1000. + 3. * y as f64 + x as f64);
+        let gradient = data.clone();
+        data[[20, 20]] = 1e6;
+        let background = local_background(&data, 8, 1);
+        Zip::from(&background).and(&gradient).for_each(|&b, &g| {
+            assert!((b - g).abs() / g < 0.01, "{} != {}", b, g);
+        });
+
+        // A block full of source is removed by the filter
+        data.slice_mut(s![16..24, 16..24]).fill(1e6);
+        let background = local_background(&data, 8, 3);
+        assert!(background[[20, 20]] < 2000.);
+    }
+
+    #[test]
+    fn background_mesh_test() {
+        let flat = Array2::from_elem((50, 34), 7.);
+        let mesh = background_mesh(&flat, 8);
+        assert_eq!(mesh.dim(), (6, 4));
+        assert!(mesh.iter().all(|&m| m == 7.));
+        assert!(background_mesh_rms(&flat, 8).iter().all(|&r| r == 0.));
+
+        // Alternating 6 and 8 has an RMS of 1, also with an outlier present
+        let mut noisy = Array2::from_shape_fn((16, 16), |(y, x)| 6. + 2. * ((x + y) % 2) as f64);
+        noisy[[3, 3]] = 1000.;
+        let rms = background_mesh_rms(&noisy, 8);
+        assert_eq!(rms.dim(), (2, 2));
+        assert!(rms.iter().all(|&r| (r - 1.).abs() < 0.01));
+        let mesh = background_mesh(&noisy, 8);
+        assert_eq!(mesh[[1, 1]], 7.);
+        // The outlier replaced a 6, leaving more 8s
+        assert_eq!(mesh[[0, 0]], 8.);
+    }
+
+    #[test]
+    #[should_panic(expected = "non-zero mesh size")]
+    fn background_mesh_zero_test() {
+        background_mesh(&Array2::zeros((4, 4)), 0);
+    }
+
+    #[test]
+    fn interpolation_weight_test() {
+        let centres = [3.5, 11.5, 17.];
+        assert_eq!(interpolation_weight(&centres, 7.5), (0, 1, 0.5));
+        assert_eq!(interpolation_weight(&centres, 0.), (0, 1, -3.5 / 8.));
+        assert_eq!(interpolation_weight(&centres, 19.75), (1, 2, 1.5));
+        assert_eq!(interpolation_weight(&[2.], 10.), (0, 0, 0.));
+    }
+
+    #[test]
+    fn radial_profile_test() {
+        let sigma: f64 = 6.;
+        let gaussian = Array2::from_shape_fn((61, 61), |(y, x)| {
+            let r2 = (y as f64 - 30.).powi(2) + (x as f64 - 30.).powi(2);
+            (-r2 / (2. * sigma * sigma)).exp()
+        });
+        let profile = radial_profile(&gaussian, 30., 30., 18., 18).unwrap();
+        assert_eq!(profile.len(), 18);
+        // The mean of the (decreasing) Gaussian lies between its values at the ring edges
+        let g = |r: f64| (-r * r / (2. * sigma * sigma)).exp();
+        for (r, mean) in profile {
+            assert!(g(r + 0.5) <= mean && mean <= g(r - 0.5), "{}: {}", r, mean);
+        }
+
+        let mut disk = Array2::from_shape_fn((21, 21), |(y, x)| {
+            let r = (y as f64 - 10.).hypot(x as f64 - 10.);
+            if r < 5. {
+                2.
+            } else {
+                0.
+            }
+        });
+        disk[[10, 12]] = f64::NAN;
+        let profile = radial_profile(&disk, 10., 10., 10., 10).unwrap();
+        assert_eq!(profile[0], (0.5, 2.));
+        assert!(profile[..5].iter().all(|&(_r, mean)| mean == 2.));
+        assert!(profile[5..].iter().all(|&(_r, mean)| mean == 0.));
+
+        // Rings too thin to contain a pixel
+        let profile = radial_profile(&disk, 10., 10., 1., 4).unwrap();
+        assert!(profile[1].1.is_nan());
+
+        // The pixels at exactly the maximum radius are in the last ring
+        let profile = radial_profile(&disk, 10., 10., 2., 4).unwrap();
+        assert_eq!(profile[3], (1.75, 2.));
+        assert!(matches!(
+            radial_profile(&disk, 10., 10., 10., 0),
+            Err(FitsError::InvalidArgument("nbins"))
+        ));
+    }
+
+    #[test]
+    fn display_rgb_test() {
+        let channel = |value: f64| test_fits(&Array2::from_elem((3, 4), value));
+        let (r, g, b) = (channel(1.), channel(0.5), channel(0.25));
+
+        let rgb = display_rgb(&r, &g, &b, 0., 1., StretchMode::Linear).unwrap();
+        assert_eq!(rgb.dimensions(), (4, 3));
+        assert!(rgb.pixels().all(|p| p.0 == [255, 128, 64]));
+
+        let rgb = display_rgb(&r, &g, &b, 0., 1., StretchMode::Sqrt).unwrap();
+        assert_eq!(rgb.get_pixel(0, 0).0, [255, 180, 128]);
+        let rgb = display_rgb(&g, &r, &b, 0., 0.5, StretchMode::Log).unwrap();
+        assert_eq!(rgb.get_pixel(0, 0).0, [255, 255, 229]);
+
+        let small = test_fits(&Array2::zeros((2, 4)));
+        assert!(matches!(
+            display_rgb(&r, &small, &b, 0., 1., StretchMode::Linear),
+            Err(FitsError::ShapeMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn optimal_bin_size_test() {
+        // 1000 evenly spread values: std = 1 / sqrt(12), IQR = 0.5
+        let data = GenericData::from_shape_fn(vec![1000], |i| i[0] as f64 / 999.);
+        assert_eq!(optimal_bin_size(&data.view()), 10);
+        let rule = |rule| optimal_bin_size_with_rule(&data.view(), rule);
+        assert_eq!(rule(BinningRule::FreedmanDiaconis), 10);
+        assert_eq!(rule(BinningRule::Sqrt), 32);
+
+        // Independent of the shape, and NaN values are skipped
+        let mut image = data.into_shape(vec![20, 50]).unwrap();
+        image[[0, 0]] = f64::NAN;
+        assert_eq!(optimal_bin_size(&image.view()), 10);
+
+        let constant = GenericData::from_elem(vec![5, 5], 3.);
+        assert_eq!(optimal_bin_size(&constant.view()), 1);
+        assert_eq!(optimal_bin_size(&GenericData::zeros(vec![0]).view()), 1);
+    }
+
+    #[test]
+    fn quantile_test() {
+        let sorted = [1., 2., 3., 4., 5.];
+        assert_eq!(quantile(&sorted, 0.), 1.);
+        assert_eq!(quantile(&sorted, 0.5), 3.);
+        assert_eq!(quantile(&sorted, 0.625), 3.5);
+        assert_eq!(quantile(&sorted, 1.), 5.);
+    }
+
+    #[test]
+    fn measure_aperture_test() {
+        use std::f64::consts::PI;
+
+        let (sigma, amplitude) = (2., 50.);
+        let (cx, cy) = (25.3, 24.6);
+        let gaussian = Array2::from_shape_fn((50, 50), |(y, x)| {
+            let r2 = (x as f64 - cx).powi(2) + (y as f64 - cy).powi(2);
+            amplitude * (-r2 / (2. * sigma * sigma)).exp()
+        });
+        let radius = 4. * sigma;
+        let circle = Aperture::Circle { cx, cy, radius };
+        let result = measure_aperture(&gaussian, &circle, 2., 10);
+        // Integral of a Gaussian within a radius of 4 sigma
+        let expected = 2. * PI * sigma * sigma * amplitude * (1. - (-8f64).exp());
+        assert!((result.sum / expected - 1.).abs() < 1e-3);
+        assert_eq!(result.error, (result.sum / 2.).sqrt());
+
+        // The sampled areas of the different shapes on a flat image
+        let flat = Array2::from_elem((40, 40), 2.);
+        let area = |aperture: Aperture| {
+            let result = measure_aperture(&flat, &aperture, 1., 8);
+            assert_eq!(result.mean, 2.);
+            result.n_pixels
+        };
+        let (cx, cy) = (20., 19.5);
+        let ellipse = Aperture::Ellipse {
+            cx,
+            cy,
+            a: 8.,
+            b: 3.,
+            theta: 0.6,
+        };
+        assert!((area(ellipse) / (PI * 24.) - 1.).abs() < 0.01);
+        let rectangle = Aperture::Rectangle {
+            cx,
+            cy,
+            width: 10.,
+            height: 4.,
+            theta: PI / 4.,
+        };
+        assert!((area(rectangle) / 40. - 1.).abs() < 0.01);
+        let annulus = Aperture::Annulus {
+            cx,
+            cy,
+            r_inner: 5.,
+            r_outer: 10.,
+        };
+        assert!((area(annulus) / (PI * 75.) - 1.).abs() < 0.01);
+
+        // Apertures partly outside the image only count the pixels inside
+        let corner = Aperture::Circle {
+            cx: 0.,
+            cy: 0.,
+            radius: 0.6,
+        };
+        assert_eq!(measure_aperture(&flat, &corner, 1., 1).n_pixels, 1.);
+    }
+
+    #[test]
+    fn interpolate_bad_pixels_test() {
+        use InterpolationMethod::*;
+
+        let mut data = Array2::from_elem((12, 10), 5.);
+        let mut mask = Array2::from_elem((12, 10), false);
+        data[[4, 4]] = 1e5;
+        mask[[4, 4]] = true;
+        for method in [NearestNeighbor, BilinearNeighbors, Median5x5] {
+            let fixed = interpolate_bad_pixels(&data, &mask, method);
+            assert!(fixed.iter().all(|&v| v == 5.), "{:?}", method);
+        }
+
+        // A cluster larger than the 5x5 box, touching the edge
+        mask.slice_mut(s![0..8, 0..7]).fill(true);
+        for method in [NearestNeighbor, BilinearNeighbors, Median5x5] {
+            let fixed = interpolate_bad_pixels(&data, &mask, method);
+            assert!(fixed.iter().all(|&v| v == 5.), "{:?}", method);
+        }
+
+        // Bilinear interpolation recovers a gradient exactly
+        let ramp = Array2::from_shape_fn((12, 10), |(y, x)| (3 * y + x) as f64);
+        let mut inner = Array2::from_elem((12, 10), false);
+        inner.slice_mut(s![3..7, 3..6]).fill(true);
+        let fixed = interpolate_bad_pixels(&ramp, &inner, BilinearNeighbors);
+        assert_eq!(fixed, ramp);
+
+        let nearest = interpolate_bad_pixels(&ramp, &mask, NearestNeighbor);
+        assert_eq!(nearest[[0, 0]], ramp[[0, 7]]);
+        assert_eq!(nearest[[7, 3]], ramp[[8, 3]]);
+
+        let all_bad = Array2::from_elem((3, 3), true);
+        let fixed =
+            interpolate_bad_pixels(&ramp.slice(s![..3, ..3]).to_owned(), &all_bad, Median5x5);
+        assert!(fixed.iter().all(|v| v.is_nan()));
+    }
+
+    #[test]
+    fn create_weight_map_test() {
+        let data = Array2::from_shape_vec((2, 2), vec![100., 200., -50., 75.]).unwrap();
+        let mut mask = Array2::from_elem((2, 2), false);
+        mask[[1, 1]] = true;
+
+        let weights = create_weight_map(&data, &mask, 1., 5.);
+        assert_eq!(weights[[0, 0]], 1. / 125.);
+        assert_eq!(weights[[0, 1]], 1. / 225.);
+        // Negative values do not add Poisson noise
+        assert_eq!(weights[[1, 0]], 1. / 25.);
+        assert_eq!(weights[[1, 1]], 0.);
+    }
+
+    #[test]
+    fn coadd_test() {
+        let data = Array2::from_shape_fn((4, 5), |(y, x)| (y * 5 + x) as f64);
+        let mut a = test_fits(&data);
+        let mut b = test_fits(&data);
+        let exptime = |t| Keyword::Value("EXPTIME".to_string(), Value::Float(t), String::new());
+        a.header.keywords.push(exptime(300.));
+        b.header.keywords.push(exptime(200.));
+        b.header.keywords.push(Keyword::History("dark subtracted".to_string()));
+
+        let combined = coadd(&[&a, &b], None).unwrap();
+        assert_eq!(combined.data, a.data);
+        assert_eq!(combined.header.get_value("EXPTIME"), Some(&Value::Float(500.)));
+        assert_eq!(
+            combined
+                .header
+                .keywords
+                .iter()
+                .filter(|kw| matches!(kw, Keyword::History(_)))
+                .count(),
+            1
+        );
+
+        let weight = Array2::from_elem((4, 5), 2.);
+        let combined = coadd(&[&a, &b], Some(&[&weight, &weight])).unwrap();
+        assert_eq!(combined.data, a.data);
+
+        // Weights favour the second image
+        let c = test_fits(&(&data + 3.));
+        let low = Array2::from_elem((4, 5), 1.);
+        let combined = coadd(&[&a, &c], Some(&[&low, &weight])).unwrap();
+        assert_eq!(combined.data.to_f64(), a.data.to_f64() + 2.);
+    }
+
+    #[test]
+    fn sigma_clip_stack_test() {
+        let background = Array2::from_shape_fn((6, 8), |(y, x)| 100. + (y + x) as f64);
+        let mut images = Vec::new();
+        for (i, noise) in [0.5, -0.5, 0.].iter().enumerate() {
+            let mut data = &background + *noise;
+            data[[i, 2 * i]] = 5e4; // cosmic ray
+            let mut fits = test_fits(&data);
+            fits.header.keywords.push(Keyword::Value(
+                "EXPTIME".to_string(),
+                Value::Float(60.),
+                String::new(),
+            ));
+            images.push(fits);
+        }
+        let images: Vec<&BasicFits> = images.iter().collect();
+
+        let stacked = sigma_clip_stack(&images, 3.).unwrap();
+        let stacked_data = to_array2(&stacked.data.to_f64(), &stacked.header.axes).unwrap();
+        Zip::from(&stacked_data)
+            .and(&background)
+            .for_each(|&s, &b| assert!((s - b).abs() <= 0.5));
+        assert_eq!(stacked.header.get_float("EXPTIME"), Some(180.));
+        assert_eq!(
+            stacked.header.get_value("NIMAGES"),
+            Some(&Value::Integer(3))
+        );
+
+        // A plain average keeps the cosmic rays
+        let mean = coadd(&images, None).unwrap();
+        assert!(mean.data.to_f64().iter().any(|&v| v > 1e4));
+    }
+
+    #[test]
+    fn stacked_median_test() {
+        let background = Array2::from_shape_fn((6, 8), |(y, x)| 100. + (y * x) as f64);
+        let mut images = Vec::new();
+        for i in 0..5 {
+            let mut data = &background + (i as f64 - 2.);
+            if i == 3 {
+                data[[4, 5]] = 5e4; // cosmic ray
+            }
+            let mut fits = test_fits(&data);
+            fits.header.keywords.push(Keyword::Value(
+                "EXPTIME".to_string(),
+                Value::Float(30.),
+                String::new(),
+            ));
+            images.push(fits);
+        }
+        let images: Vec<&BasicFits> = images.iter().collect();
+
+        let stacked = stacked_median(&images).unwrap();
+        let stacked_data = to_array2(&stacked.data.to_f64(), &stacked.header.axes).unwrap();
+        assert_eq!(stacked_data, background);
+        assert_eq!(stacked.header.get_float("EXPTIME"), Some(30.));
+        assert_eq!(
+            stacked.header.get_value("NIMAGES"),
+            Some(&Value::Integer(5))
+        );
+
+        let other = test_fits(&Array2::zeros((8, 6)));
+        assert!(matches!(
+            stacked_median(&[images[0], &other]),
+            Err(FitsError::ShapeMismatch { .. })
+        ));
+        assert!(matches!(stacked_median(&[]), Err(FitsError::NoImages)));
+    }
+
+    #[test]
+    fn linear_fit_test() {
+        let x = [0., 1., 2., f64::NAN, 3.];
+        let y = [1., 3., 5., 100., 7.];
+        let (slope, intercept, r) = linear_fit(&x, &y).unwrap();
+        assert!((slope - 2.).abs() < 1e-12);
+        assert!((intercept - 1.).abs() < 1e-12);
+        assert!((r - 1.).abs() < 1e-12);
+
+        let (_, _, r) = linear_fit(&[0., 1., 2., 3.], &[3., 1., 2., 0.]).unwrap();
+        assert!(r < -0.5 && r > -1.);
+
+        assert!(matches!(
+            linear_fit(&[1., f64::NAN], &[1., 2.]),
+            Err(FitsError::InsufficientData {
+                found: 1,
+                required: 2
+            })
+        ));
+        assert!(matches!(
+            linear_fit(&[1., 1.], &[1., 2.]),
+            Err(FitsError::InsufficientData { .. })
+        ));
+        assert!(matches!(
+            linear_fit(&[1., 2.], &[1.]),
+            Err(FitsError::ShapeMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn power_law_fit_test() {
+        // N(>S) = 500 S^-1.5, with a non-positive point that can not be used
+        let s = [1., 2., 5., 10., 0., 50.];
+        let n: Vec<f64> = s.iter().map(|s: &f64| 500. * s.powf(-1.5)).collect();
+        let (alpha, a) = power_law_fit(&s, &n).unwrap();
+        assert!((alpha + 1.5).abs() < 1e-12);
+        assert!((a - 500.).abs() < 1e-9);
+    }
+
+    #[test]
+    fn celestial_distance_test() {
+        let close = |a: f64, b: f64| (a - b).abs() < 1e-9;
+        assert!(close(celestial_distance(0., 0., 0., 90.), 90.));
+        assert!(close(celestial_distance(0., 0., 180., 0.), 180.));
+        assert!(close(celestial_distance(10., 20., 190., -20.), 180.));
+        assert_eq!(celestial_distance(83.6, 22.01, 83.6, 22.01), 0.);
+        assert!(close(celestial_distance(359.5, 0., 0.5, 0.), 1.));
+        // One arcsecond near the pole
+        assert!(close(
+            celestial_distance(0., 89.9, 0., 89.9 + 1. / 3600.),
+            1. / 3600.
+        ));
+
+        let exact = celestial_distance(150., 2.2, 150.3, 2.5);
+        let approx = small_angle_distance_approx(150., 2.2, 150.3, 2.5);
+        assert!((exact - approx).abs() < 1e-5);
+        assert!(close(small_angle_distance_approx(359.5, 0., 0.5, 0.), 1.));
+    }
+
+    #[test]
+    fn apply_stretch_lut_test() {
+        let mut identity = [0u8; 256];
+        for (i, entry) in identity.iter_mut().enumerate() {
+            *entry = i as u8;
+        }
+        let data = Array2::from_shape_vec((2, 3), vec![0.4, 0.6, 127.5, 254.7, 300., -3.]).unwrap();
+        let stretched = apply_stretch_lut(&data, &identity);
+        assert_eq!(stretched.into_raw_vec(), vec![0, 1, 128, 255, 255, 0]);
+
+        let log = compute_log_lut(1000.);
+        assert_eq!((log[0], log[255]), (0, 255));
+        assert!(log.windows(2).all(|w| w[0] <= w[1]));
+        // Same as the Log stretch of display_rgb, which uses a = 1000
+        assert_eq!(
+            log[26],
+            (StretchMode::Log.apply(26. / 255.) * 255.).round() as u8
+        );
+        let sqrt = compute_sqrt_lut();
+        assert_eq!((sqrt[0], sqrt[64], sqrt[255]), (0, 128, 255));
+    }
+
+    #[test]
+    fn convolution_kernel_test() {
+        let gaussian = convolution_kernel(KernelType::Gaussian { sigma: 1.5 }, 9);
+        assert_eq!(gaussian.dim(), (9, 9));
+        assert!((gaussian.sum() - 1.).abs() < 1e-12);
+        assert!(gaussian.iter().all(|&k| k <= gaussian[[4, 4]]));
+
+        let laplacian = convolution_kernel(KernelType::Laplacian, 5);
+        assert_eq!(laplacian.sum(), 0.);
+        assert_eq!(laplacian[[2, 2]], -4.);
+
+        let tophat = convolution_kernel(KernelType::TopHat { radius: 1 }, 5);
+        assert_eq!(tophat.iter().filter(|&&k| k > 0.).count(), 5);
+        assert!((tophat.sum() - 1.).abs() < 1e-12);
+        let uniform = convolution_kernel(KernelType::Uniform, 3);
+        assert_eq!(uniform, Array2::from_elem((3, 3), 1. / 9.));
+
+        // Sobel kernels measure 8 times the gradient
+        let ramp = Array2::from_shape_fn((6, 6), |(y, x)| 2. * x as f64 + 3. * y as f64);
+        let dx = SobelDirection::X;
+        let gx = convolve(
+            &ramp,
+            &convolution_kernel(KernelType::Sobel { direction: dx }, 3),
+        );
+        let dy = SobelDirection::Y;
+        let gy = convolve(
+            &ramp,
+            &convolution_kernel(KernelType::Sobel { direction: dy }, 3),
+        );
+        assert_eq!(gx[[3, 2]], 16.);
+        assert_eq!(gy[[3, 2]], 24.);
+    }
+
+    #[test]
+    fn convolve_test() {
+        let data = Array2::from_shape_fn((5, 7), |(y, x)| (y * x) as f64);
+        let mut delta = Array2::zeros((3, 3));
+        delta[[1, 1]] = 1.;
+        assert_eq!(convolve(&data, &delta), data);
+
+        // An off-centre delta shifts the image, with mirrored edges
+        let mut delta = Array2::zeros((1, 3));
+        delta[[0, 2]] = 1.;
+        let shifted = convolve(&data, &delta);
+        assert_eq!(shifted.slice(s![.., 1..]), data.slice(s![.., ..6]));
+        assert_eq!(shifted.column(0), data.column(0));
+    }
+
+    #[test]
+    fn image_moments_test() {
+        let data = Array2::from_shape_vec((2, 3), vec![1., 2., 0., 0., 3., f64::NAN]).unwrap();
+        let m = image_moments(&data, 2);
+        assert_eq!(m.dim(), (3, 3));
+        assert_eq!(m[[0, 0]], 6.);
+        assert_eq!(m[[1, 0]], 2. + 3.);
+        assert_eq!(m[[0, 1]], 3.);
+        assert_eq!(m[[1, 1]], 3.);
+        assert_eq!(m[[2, 0]], 2. + 3.);
+        assert_eq!(m[[0, 2]], 3.);
+        assert_eq!(m[[2, 1]], 0.);
+
+        let blob = blob(25, 25, 11., 9.);
+        let mu = central_moments(&blob, 2);
+        assert!(mu[[1, 0]].abs() < 1e-9 && mu[[0, 1]].abs() < 1e-9);
+        assert!(mu[[1, 1]].abs() < 1e-9);
+        assert!((mu[[2, 0]] / mu[[0, 2]] - 1.).abs() < 1e-4);
+    }
+
+    #[test]
+    fn hu_invariants_test() {
+        // A shape without any symmetry: an ellipse with a bump off its axes
+        let shape = Array2::from_shape_fn((31, 31), |(y, x)| {
+            let (dx, dy) = (x as f64 - 14., y as f64 - 16.);
+            (-(dx * dx / 18. + dy * dy / 6.)).exp()
+                + 0.5 * (-((dx - 4.).powi(2) + (dy - 2.).powi(2))).exp()
+        });
+        let hu = hu_invariants(&normalized_central_moments(&shape, 3));
+
+        // Rotate by 90 degrees
+        let rotated = shape.t().slice(s![.., ..;-1]).to_owned();
+        let hu_rotated = hu_invariants(&normalized_central_moments(&rotated, 3));
+        for (a, b) in hu.iter().zip(hu_rotated.iter()) {
+            assert!((a - b).abs() <= 1e-9 * a.abs().max(1e-12));
+        }
+
+        // The mirrored shape only flips the sign of the last invariant
+        let mirrored = shape.t().to_owned();
+        let hu_mirrored = hu_invariants(&normalized_central_moments(&mirrored, 3));
+        assert!((hu[0] - hu_mirrored[0]).abs() < 1e-12);
+        assert!((hu[6] + hu_mirrored[6]).abs() <= 1e-9 * hu[6].abs());
+    }
+
+    #[test]
+    fn wavelet_denoise_test() {
+        // Pseudo random uniform noise with a standard deviation of 1
+        let noise = Array2::from_shape_fn((64, 64), |(y, x)| {
+            let h = ((y * 64 + x) as f64 * 12.9898).sin() * 43758.5453;
+            (h - h.floor() - 0.5) * 12f64.sqrt()
+        });
+        let std = |a: &Array2<f64>| a.std(0.);
+        assert!((std(&noise) - 1.).abs() < 0.1);
+
+        let denoised = wavelet_denoise(&noise, 4, 3.);
+        assert!(std(&denoised) < 0.5 * std(&noise));
+        // The mean level is kept in the smooth plane
+        assert!((denoised.mean().unwrap() - noise.mean().unwrap()).abs() < 0.05);
+
+        let mut data = noise.clone();
+        data[[20, 20]] += 50.;
+        data[[40, 45]] += 30.;
+        let denoised = wavelet_denoise(&data, 4, 3.);
+        assert!(denoised[[20, 20]] > 40.);
+        assert!(denoised[[40, 45]] > 20.);
+    }
+
+    #[test]
+    fn sub_image_correlation_test() {
+        // Not periodic, so the template only matches in one place
+        let data = Array2::from_shape_fn((30, 40), |(y, x)| ((y * y + 3 * x) as f64).sin());
+        let template = data.slice(s![12..19, 25..30]).to_owned();
+        let (row, col, ncc) = sub_image_correlation(&data, &template);
+        assert_eq!((row, col), (12, 25));
+        assert!((ncc - 1.).abs() < 1e-12);
+
+        // A brighter copy with an offset matches just as well
+        let (row, col, ncc) = sub_image_correlation(&data, &(&template * 3. + 10.));
+        assert_eq!((row, col), (12, 25));
+        assert!((ncc - 1.).abs() < 1e-12);
+
+        let (_, _, ncc) = sub_image_correlation(&data, &(-&template));
+        assert!(ncc < 1.);
+    }
+
+    #[test]
+    fn fringe_map_test() {
+        let uniform = Array2::from_elem((40, 40), 250.);
+        assert!(fringe_map(&uniform, 16).iter().all(|f| f.abs() < 1e-9));
+
+        // Fringes with a period of 4 pixels on top of a gradient
+        let fringes = Array2::from_shape_fn((64, 64), |(y, x)| {
+            (std::f64::consts::FRAC_PI_2 * (x + y) as f64).sin()
+        });
+        let gradient = Array2::from_shape_fn((64, 64), |(y, x)| 100. + 0.5 * x as f64 + y as f64);
+        let map = fringe_map(&(&gradient + &fringes), 16);
+        let inner = s![16..48, 16..48];
+        let residual = &map.slice(inner) - &fringes.slice(inner);
+        assert!(residual.iter().all(|r| r.abs() < 1e-9));
+        let cleaned = defringe(
+            &(&gradient + &fringes),
+            &map,
+            FringeMethod::ScaleAndSubtract,
+        );
+        let left = &cleaned.slice(inner) - &gradient.slice(inner);
+        assert!(left.iter().all(|r| r.abs() < 1e-9));
+    }
+
+    #[test]
+    fn flat_field_correct_test() {
+        // The flat has median 1, so the corrected image is the inverse of the flat
+        let science = test_fits(&Array2::ones((2, 3)));
+        let flat = Array2::from_shape_vec((2, 3), vec![0.5, 1., 1., 2., 4., 0.01]).unwrap();
+        let corrected = flat_field_correct(&science, &test_fits(&flat)).unwrap();
+        let corrected = to_array2(&corrected.data.to_f64(), &corrected.header.axes).unwrap();
+
+        for (c, f) in corrected.iter().zip(flat.iter()).take(5) {
+            assert!((c - 1. / f).abs() < 1e-12);
+        }
+        assert!(corrected[[1, 2]].is_nan());
+
+        let other = test_fits(&Array2::ones((3, 2)));
+        assert!(matches!(
+            flat_field_correct(&science, &other),
+            Err(FitsError::ShapeMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn bias_dark_subtract_test() {
+        let data = Array2::from_shape_fn((3, 4), |(y, x)| 1500. + (10 * y + x) as f64);
+        let science = test_fits(&data);
+        let bias = test_fits(&Array2::from_elem((3, 4), 1000.));
+        let result = bias_subtract(&science, &bias).unwrap();
+        assert_eq!(result.data.to_f64(), science.data.to_f64() - 1000.);
+        assert!(matches!(
+            result.header.keywords.last(),
+            Some(Keyword::History(h)) if h == "Bias subtracted"
+        ));
+
+        let dark = test_fits(&Array2::from_elem((3, 4), 20.));
+        let result = dark_subtract(&science, &dark, 2.5).unwrap();
+        assert_eq!(result.data.to_f64(), science.data.to_f64() - 50.);
+
+        let other = test_fits(&Array2::zeros((4, 3)));
+        assert!(matches!(
+            bias_subtract(&science, &other),
+            Err(FitsError::ShapeMismatch { .. })
+        ));
+
+        // The frames are subtracted as f64, so their BITPIX does not have to match
+        let mut bias = test_fits(&Array2::from_elem((3, 4), 1000.));
+        bias.data = Arc::new(crate::DataArray::I16(bias.data.to_f64().mapv(|x| x as i16)));
+        bias.header
+            .set_value("BITPIX", Value::Integer(16), "")
+            .unwrap();
+        let result = bias_subtract(&science, &bias).unwrap();
+        assert_eq!(result.data.to_f64(), science.data.to_f64() - 1000.);
+    }
+
+    #[test]
+    fn calibration_scaled_input_test() {
+        // Stored 4 with BZERO 10 is a physical 14, the bias a physical 6
+        let mut science = test_fits(&Array2::from_elem((2, 2), 4.));
+        science
+            .header
+            .set_value("BZERO", Value::Float(10.), "")
+            .unwrap();
+        let mut bias = test_fits(&Array2::from_elem((2, 2), 3.));
+        bias.header
+            .set_value("BSCALE", Value::Float(2.), "")
+            .unwrap();
+        let result = bias_subtract(&science, &bias).unwrap();
+        assert_eq!(result.header.get_value("BZERO"), None);
+
+        let read = BasicFits::from_bytes(result.to_bytes()).unwrap();
+        assert!(read.physical_data().iter().all(|&x| x == 8.));
+
+        let stacked = stacked_median(&[&science, &science]).unwrap();
+        assert!(stacked.physical_data().iter().all(|&x| x == 14.));
+    }
+
+    #[test]
+    fn overscan_subtract_test() {
+        // Science pixels in columns 0..4, a constant overscan of 200 in columns 4..6
+        let data = Array2::from_shape_fn(
+            (4, 6),
+            |(y, x)| {
+                if x < 4 {
+                    1000. + (y * x) as f64
+                } else {
+                    200.
+                }
+            },
+        );
+        let corrected = overscan_subtract(&data, (4, 6), false);
+        assert_eq!(corrected, &data - 200.);
+        assert_eq!(overscan_subtract(&data, (4, 6), true), corrected);
+
+        // A bias drifting from row to row is followed, unless smoothed away
+        let mut data = Array2::from_elem((5, 3), 10.);
+        data.column_mut(2).assign(&arr1(&[1., 2., 3., 4., 50.]));
+        let corrected = overscan_subtract(&data, (2, 3), false);
+        assert_eq!(corrected.column(0), arr1(&[9., 8., 7., 6., -40.]));
+        assert_eq!(corrected.column(2), Array1::<f64>::zeros(5));
+        let smoothed = overscan_subtract(&data, (2, 3), true);
+        assert_eq!(smoothed.column(0), arr1(&[8., 8., 7., 6., 6.]));
+    }
+
+    #[test]
+    fn defringe_test() {
+        let fringes = Array2::from_shape_fn((16, 16), |(y, x)| ((x + 2 * y) as f64 / 3.).sin());
+        let stars = blob(16, 16, 8., 5.);
+
+        let data = &stars + &(&fringes * 2.);
+        let cleaned = defringe(&data, &fringes, FringeMethod::ScaleAndSubtract);
+        let residual = &cleaned - &stars;
+        // Not exact, as the star also correlates a little with the template
+        assert!(residual.iter().all(|r| r.abs() < 0.05));
+
+        let data = &data + 100.;
+        let cleaned = defringe(&data, &fringes, FringeMethod::CorrelationScale);
+        let residual = &cleaned - &stars - 100.;
+        assert!(residual.iter().all(|r| r.abs() < 0.05));
+
+        let cleaned = defringe(&(&fringes + 100.), &fringes, FringeMethod::CorrelationScale);
+        assert!(cleaned.iter().all(|v| (v - 100.).abs() < 1e-9));
+    }
+
+    #[cfg(feature = "fft")]
+    #[test]
+    fn align_and_stack_test() {
+        let mut images = Vec::new();
+        for (i, (cy, cx)) in [(16., 16.), (18., 15.), (14., 17.)].iter().enumerate() {
+            let mut data = 100. * blob(32, 32, *cy, *cx);
+            data[[10 + i, 20]] = 50.;
+            images.push(test_fits(&data));
+        }
+        let images: Vec<&BasicFits> = images.iter().collect();
+
+        let stacked = align_and_stack(&images, AlignMethod::CrossCorrelation).unwrap();
+        let stacked = to_array2(&stacked.data.to_f64(), &stacked.header.axes).unwrap();
+        let expected = 100. * blob(32, 32, 16., 16.);
+        for y in 4..28 {
+            for x in 4..28 {
+                assert!((stacked[[y, x]] - expected[[y, x]]).abs() < 1e-9);
+            }
+        }
+
+        // Without a WCS the images can not be aligned with it
+        assert!(matches!(
+            align_and_stack(&images, AlignMethod::Wcs),
+            Err(FitsError::MissingKeyword(_))
+        ));
+    }
+
+    #[cfg(feature = "fft")]
+    #[test]
+    fn align_and_stack_wcs_test() {
+        // The same star, at the reference pixel of every image
+        let mut images = Vec::new();
+        for (i, (cy, cx)) in [(16., 16.), (18., 15.), (14., 17.)].iter().enumerate() {
+            let mut data = 100. * blob(32, 32, *cy, *cx);
+            data[[10 + i, 20]] = 50.;
+            let mut fits = test_fits(&data);
+            for (key, value) in [
+                ("CRPIX1", cx + 1.),
+                ("CRPIX2", cy + 1.),
+                ("CRVAL1", 150.),
+                ("CRVAL2", 2.),
+                ("CDELT1", -0.001),
+                ("CDELT2", 0.001),
+            ] {
+                fits.header.set_value(key, Value::Float(value), "").unwrap();
+            }
+            images.push(fits);
+        }
+        let images: Vec<&BasicFits> = images.iter().collect();
+
+        let stacked = align_and_stack(&images, AlignMethod::Wcs).unwrap();
+        assert_eq!(stacked.header.get_float("CRPIX1"), Some(17.));
+        let stacked = to_array2(&stacked.data.to_f64(), &stacked.header.axes).unwrap();
+        let expected = 100. * blob(32, 32, 16., 16.);
+        for y in 4..28 {
+            for x in 4..28 {
+                assert!((stacked[[y, x]] - expected[[y, x]]).abs() < 1e-9);
+            }
+        }
+    }
+
+    #[test]
+    fn coadd_error_test() {
+        let a = test_fits(&Array2::zeros((4, 5)));
+        let b = test_fits(&Array2::zeros((5, 4)));
+        assert!(matches!(
+            coadd(&[&a, &b], None),
+            Err(FitsError::ShapeMismatch { .. })
+        ));
+        assert!(matches!(coadd(&[], None), Err(FitsError::NoImages)));
+
+        let wrong = Array2::zeros((5, 4));
+        assert!(matches!(
+            coadd(&[&a], Some(&[&wrong])),
+            Err(FitsError::ShapeMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn reflect_index_test() {
+        let indices: Vec<usize> = (-3..7).map(|i| reflect_index(i, 4)).collect();
+        assert_eq!(indices, vec![2, 1, 0, 0, 1, 2, 3, 3, 2, 1]);
+    }
+
+    #[test]
+    fn find_correlation_peak_test() {
+        let mut corr = Array2::zeros((10, 10));
+        corr[[9, 2]] = 1.;
+        assert_eq!(find_correlation_peak(&corr), (-1, 2));
+    }
+}
@@ -0,0 +1,598 @@
+// Streaming access to FITS files, for files too large to hold in memory at once.
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::ops::Range;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use ndarray::Array2;
+
+use crate::definitions::{BLOCK_SIZE, HEADER_END_KEYWORD_FULL, HEADER_KEYWORD_SIZE};
+use crate::hdu::hdu_data_size;
+use crate::header::{Bitpix, Header};
+use crate::parsing::header::parse_header;
+use crate::parsing::read_data;
+use crate::writing::header_to_bytes;
+use crate::{DataArray, FitsError, KeywordList};
+
+/// Throughput statistics of a finished [`FitsPipeline`].
+#[derive(Debug, Clone, Copy)]
+pub struct Stats {
+    pub header_blocks: usize,
+    /// Blocks passed through the transform
+    pub data_blocks: usize,
+    /// Total number of bytes written, including headers and trailing HDUs
+    pub bytes: u64,
+    pub elapsed: Duration,
+}
+
+impl Stats {
+    /// Throughput in bytes per second
+    pub fn throughput(&self) -> f64 {
+        self.bytes as f64 / self.elapsed.as_secs_f64()
+    }
+}
+
+/// Copies a FITS file from `R` to `W` one 2880 byte block at a time, applying `transform`
+/// to each data block of the primary HDU. Header blocks, and any extensions after the
+/// primary data, are written unchanged, unless a header transform is set with
+/// [`FitsPipeline::with_header_transform`].
+///
+/// The data is passed in its raw (big endian) form, including the zero padding of the
+/// last block.
+pub struct FitsPipeline<R, W, F, H = fn(&mut KeywordList)> {
+    reader: R,
+    writer: W,
+    transform: F,
+    header_transform: Option<H>,
+}
+
+impl<R: Read, W: Write, F: FnMut(&mut [u8])> FitsPipeline<R, W, F> {
+    pub fn new(reader: R, writer: W, transform: F) -> Self {
+        FitsPipeline {
+            reader,
+            writer,
+            transform,
+            header_transform: None,
+        }
+    }
+
+    /// Apply `header_transform` to the keywords of the primary header before it is written.
+    /// The size of the data is still taken from the original header, so the transform
+    /// should not change BITPIX or NAXISn.
+    pub fn with_header_transform<H: FnMut(&mut KeywordList)>(
+        self,
+        header_transform: H,
+    ) -> FitsPipeline<R, W, F, H> {
+        FitsPipeline {
+            reader: self.reader,
+            writer: self.writer,
+            transform: self.transform,
+            header_transform: Some(header_transform),
+        }
+    }
+}
+
+impl<R, W, F, H> FitsPipeline<R, W, F, H>
+where
+    R: Read,
+    W: Write,
+    F: FnMut(&mut [u8]),
+    H: FnMut(&mut KeywordList),
+{
+    pub fn run(mut self) -> Result<Stats, FitsError> {
+        let start = Instant::now();
+        let mut block = [0u8; BLOCK_SIZE];
+        let mut stats = Stats {
+            header_blocks: 0,
+            data_blocks: 0,
+            bytes: 0,
+            elapsed: Duration::ZERO,
+        };
+
+        // The header is needed to know the size of the data, so keep it around
+        let mut header_bytes = read_header_bytes(&mut self.reader)?;
+        let mut keywords = parse_header(&mut header_bytes.chunks(BLOCK_SIZE))?;
+        let header = Header::from_keyword_list(keywords.clone())?;
+        if let Some(header_transform) = self.header_transform.as_mut() {
+            header_transform(&mut keywords);
+            header_bytes = header_to_bytes(&keywords);
+        }
+        self.writer.write_all(&header_bytes)?;
+        stats.header_blocks = header_bytes.len() / BLOCK_SIZE;
+
+        let data_blocks = data_size(&header)?.div_ceil(BLOCK_SIZE);
+        for _ in 0..data_blocks {
+            if !read_block(&mut self.reader, &mut block).map_err(eof_error)? {
+                return Err(FitsError::UnexpectedEof);
+            }
+            (self.transform)(&mut block);
+            self.writer.write_all(&block)?;
+            stats.data_blocks += 1;
+        }
+
+        // Anything after the primary HDU is copied as is
+        let trailing = io::copy(&mut self.reader, &mut self.writer)?;
+        self.writer.flush()?;
+
+        stats.bytes = ((stats.header_blocks + stats.data_blocks) * BLOCK_SIZE) as u64 + trailing;
+        stats.elapsed = start.elapsed();
+        Ok(stats)
+    }
+}
+
+/// A FITS file opened for the header of one HDU only. Its data is read when asked for with
+/// [`FitsFile::read_data`], so the metadata of large images can be inspected without
+/// loading them.
+pub struct FitsFile {
+    file: File,
+    header: Header,
+    /// Byte offset of the header in the file
+    header_offset: u64,
+    /// Bytes of the data in the file, without padding
+    data_range: Range<u64>,
+}
+
+impl FitsFile {
+    /// Open `path` and read the header of the primary HDU.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, FitsError> {
+        Self::open_hdu(path, 0)
+    }
+
+    /// Open `path` and read the header of HDU `index`, where 0 is the primary HDU. Of the
+    /// HDUs before it only the headers are read, their data is skipped.
+    ///
+    /// Fails with [`FitsError::InvalidArgument`] if the file has no HDU `index`.
+    pub fn open_hdu(path: impl AsRef<Path>, index: usize) -> Result<Self, FitsError> {
+        let mut file = File::open(path)?;
+        let end = file.metadata()?.len();
+        let mut hdu = 0;
+        loop {
+            let header_offset = file.stream_position()?;
+            if hdu > 0 && header_offset >= end {
+                return Err(FitsError::InvalidArgument("index"));
+            }
+            let header_bytes = read_header_bytes(&mut file)?;
+            let keywords = parse_header(&mut header_bytes.chunks(BLOCK_SIZE))?;
+            let header = Header::from_keyword_list(keywords)?;
+
+            let data_start = header_offset + header_bytes.len() as u64;
+            let size = hdu_data_size(&header, hdu == 0)? as u64;
+            if hdu == index {
+                return Ok(FitsFile {
+                    file,
+                    header,
+                    header_offset,
+                    data_range: data_start..data_start + size,
+                });
+            }
+            let next = data_start + size.next_multiple_of(BLOCK_SIZE as u64);
+            file.seek(SeekFrom::Start(next))?;
+            hdu += 1;
+        }
+    }
+
+    pub fn header(&self) -> &Header {
+        &self.header
+    }
+
+    /// Byte offset of the header in the file
+    pub fn header_offset(&self) -> u64 {
+        self.header_offset
+    }
+
+    /// Bytes of the data in the file, without padding
+    pub fn data_range(&self) -> Range<u64> {
+        self.data_range.clone()
+    }
+
+    /// Read the data from the file. Each call reads it again.
+    pub fn read_data(&mut self) -> Result<DataArray, FitsError> {
+        self.file.seek(SeekFrom::Start(self.data_range.start))?;
+        let mut data = vec![0u8; (self.data_range.end - self.data_range.start) as usize];
+        self.file.read_exact(&mut data).map_err(eof_error)?;
+        read_data(&self.header, &mut data.chunks(BLOCK_SIZE))
+    }
+
+    /// Read the columns `x` and rows `y` (starting at 0) of a 2D image, as an array of shape
+    /// (rows, columns). Only the cutout is read: the file is read row by row, skipping the
+    /// pixels in between. The values are physical, i.e. with BSCALE and BZERO applied and
+    /// BLANK pixels as NaN.
+    pub fn read_cutout(
+        &mut self,
+        x: Range<usize>,
+        y: Range<usize>,
+    ) -> Result<Array2<f64>, FitsError> {
+        let &[nx, ny] = &self.header.axes[..] else {
+            return Err(FitsError::InvalidNaxis(self.header.axes.len()));
+        };
+        if x.start > x.end || x.end > nx || y.start > y.end || y.end > ny {
+            return Err(FitsError::OutOfBounds);
+        }
+
+        let bitpix = &self.header.bitpix;
+        let pixel_size = bitpix.bytes_per_pixel();
+        let scale = self.header.get_float("BSCALE").unwrap_or(1.);
+        let zero = self.header.get_float("BZERO").unwrap_or(0.);
+        let blank = self.header.blank();
+
+        let first = self.data_range.start + ((y.start * nx + x.start) * pixel_size) as u64;
+        let skip = ((nx - x.len()) * pixel_size) as i64;
+        self.file.seek(SeekFrom::Start(first))?;
+
+        let mut row = vec![0u8; x.len() * pixel_size];
+        let mut values = Vec::with_capacity(x.len() * y.len());
+        for i in 0..y.len() {
+            if i > 0 {
+                self.file.seek(SeekFrom::Current(skip))?;
+            }
+            self.file.read_exact(&mut row).map_err(eof_error)?;
+            values.extend(
+                row.chunks_exact(pixel_size)
+                    .map(|b| match decode_pixel(bitpix, b) {
+                        (_x, Some(n)) if Some(n) == blank => f64::NAN,
+                        (x, _n) => zero + scale * x,
+                    }),
+            );
+        }
+        Ok(Array2::from_shape_vec((y.len(), x.len()), values).unwrap())
+    }
+}
+
+// A big endian pixel value, and the stored integer for comparing with BLANK
+fn decode_pixel(bitpix: &Bitpix, b: &[u8]) -> (f64, Option<i64>) {
+    let integer = |n: i64| (n as f64, Some(n));
+    match bitpix {
+        Bitpix::Int8 => integer(i64::from(b[0])),
+        Bitpix::Int16 => integer(i64::from(i16::from_be_bytes(b.try_into().unwrap()))),
+        Bitpix::Int32 => integer(i64::from(i32::from_be_bytes(b.try_into().unwrap()))),
+        Bitpix::Int64 => integer(i64::from_be_bytes(b.try_into().unwrap())),
+        Bitpix::Float32 => (f64::from(f32::from_be_bytes(b.try_into().unwrap())), None),
+        Bitpix::Float64 => (f64::from_be_bytes(b.try_into().unwrap()), None),
+    }
+}
+
+/// A memory mapped FITS file, for images too large to copy into memory. The header of the
+/// primary HDU is parsed on opening, the data is only read when accessed.
+#[cfg(feature = "mmap")]
+pub struct MmapFits {
+    mmap: memmap2::Mmap,
+    header: Header,
+    /// Bytes of the data in the file, without padding
+    data_range: Range<usize>,
+}
+
+#[cfg(feature = "mmap")]
+impl MmapFits {
+    /// Map `path` into memory and parse the header of the primary HDU.
+    ///
+    /// The file should not be changed while it is mapped, as the mapped bytes change along
+    /// with it.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, FitsError> {
+        let file = File::open(path)?;
+        // Safety: only sound as long as no one truncates or writes to the file meanwhile,
+        // which is documented above
+        let mmap = unsafe { memmap2::Mmap::map(&file)? };
+        let (keywords, header_size) = crate::parsing::header::parse_header_slice(&mmap)?;
+        let header = Header::from_keyword_list(keywords)?;
+
+        let data_range = header_size..header_size.saturating_add(data_size(&header)?);
+        if data_range.end > mmap.len() {
+            return Err(FitsError::UnexpectedEof);
+        }
+        Ok(MmapFits {
+            mmap,
+            header,
+            data_range,
+        })
+    }
+
+    pub fn header(&self) -> &Header {
+        &self.header
+    }
+
+    /// The raw bytes of the data, straight from the mapped file. The values are big endian,
+    /// as stored in the file.
+    pub fn data_slice(&self) -> &[u8] {
+        &self.mmap[self.data_range.clone()]
+    }
+
+    /// Copy the data out of the mapped file into an array.
+    pub fn read_data(&self) -> Result<DataArray, FitsError> {
+        read_data(&self.header, &mut self.data_slice().chunks(BLOCK_SIZE))
+    }
+}
+
+// The first bytes of a gzip stream
+#[cfg(feature = "gzip")]
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+// A FITS stream, decompressed on the fly if it is gzip compressed (with the gzip feature).
+// The first two bytes are read ahead to recognise gzip, and then put back in front.
+pub(crate) enum FitsStream<R> {
+    Plain(io::Chain<io::Cursor<[u8; 2]>, R>),
+    #[cfg(feature = "gzip")]
+    Gzip(flate2::read::MultiGzDecoder<io::Chain<io::Cursor<[u8; 2]>, R>>),
+}
+
+impl<R: Read> FitsStream<R> {
+    // Decompress when the stream starts with the gzip magic bytes, or when `gzip` is set,
+    // e.g. for a file name ending in .gz
+    #[cfg_attr(not(feature = "gzip"), allow(unused_variables))]
+    pub(crate) fn new(mut reader: R, gzip: bool) -> Result<Self, FitsError> {
+        let mut magic = [0u8; 2];
+        reader.read_exact(&mut magic).map_err(eof_error)?;
+        let stream = io::Cursor::new(magic).chain(reader);
+        #[cfg(feature = "gzip")]
+        if gzip || magic == GZIP_MAGIC {
+            return Ok(FitsStream::Gzip(flate2::read::MultiGzDecoder::new(stream)));
+        }
+        Ok(FitsStream::Plain(stream))
+    }
+}
+
+impl<R: Read> Read for FitsStream<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            FitsStream::Plain(reader) => reader.read(buf),
+            #[cfg(feature = "gzip")]
+            FitsStream::Gzip(reader) => reader.read(buf),
+        }
+    }
+}
+
+// A file that ends too early is reported as such, instead of as a general I/O error
+pub(crate) fn eof_error(e: io::Error) -> FitsError {
+    match e.kind() {
+        io::ErrorKind::UnexpectedEof => FitsError::UnexpectedEof,
+        _ => FitsError::Io(e),
+    }
+}
+
+/// Read the raw bytes of the primary header of a FITS file, without parsing them: all
+/// 2880 byte blocks up to and including the one with the END record.
+pub fn read_fits_header_block(path: impl AsRef<Path>) -> Result<Vec<u8>, FitsError> {
+    let mut f = File::open(path)?;
+    read_header_bytes(&mut f)
+}
+
+// Number of bytes in the data of the HDU described by `header`, without padding. Fails if
+// that does not fit in a usize.
+pub(crate) fn data_size(header: &Header) -> Result<usize, FitsError> {
+    if header.axes.is_empty() {
+        return Ok(0);
+    }
+    header
+        .axes
+        .iter()
+        .try_fold(header.bitpix.bytes_per_pixel(), |size, &n| {
+            size.checked_mul(n)
+        })
+        .ok_or(FitsError::InvalidHeader)
+}
+
+// Read header blocks until the END record
+pub(crate) fn read_header_bytes<R: Read>(reader: &mut R) -> Result<Vec<u8>, FitsError> {
+    let mut block = [0u8; BLOCK_SIZE];
+    let mut header_bytes = Vec::new();
+    loop {
+        if !read_block(reader, &mut block).map_err(eof_error)? {
+            return Err(FitsError::InvalidHeader);
+        }
+        header_bytes.extend_from_slice(&block);
+
+        let mut cards = block.chunks(HEADER_KEYWORD_SIZE);
+        if cards.any(|card| card == HEADER_END_KEYWORD_FULL) {
+            return Ok(header_bytes);
+        }
+    }
+}
+
+// Fill `block` completely. Returns false if the reader was already exhausted, and an
+// error if it ran out halfway through the block.
+fn read_block<R: Read>(reader: &mut R, block: &mut [u8]) -> io::Result<bool> {
+    let mut filled = 0;
+    while filled < block.len() {
+        match reader.read(&mut block[filled..]) {
+            Ok(0) if filled == 0 => return Ok(false),
+            Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(true)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::hdu::{Hdu, HduList, ImageExtension};
+    use crate::parsing::header::{set_keyword, Value};
+    use crate::BasicFits;
+    use std::path::PathBuf;
+    use std::sync::Arc;
+
+    const SIMPLE: &[u8] = include_bytes!("../data/simple.fits");
+
+    // A file in the temporary directory for the test `name`, unique to this test run
+    pub(crate) fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("fits_{}_{}.fits", name, std::process::id()))
+    }
+
+    // Negate every f64 value in a block
+    fn negate(block: &mut [u8]) {
+        for x in block.chunks_exact_mut(8) {
+            let n = f64::from_be_bytes(x.try_into().unwrap());
+            x.copy_from_slice(&(-n).to_be_bytes());
+        }
+    }
+
+    #[test]
+    fn pipeline_test() {
+        let mut out = Vec::new();
+        let stats = FitsPipeline::new(SIMPLE, &mut out, negate).run().unwrap();
+        assert_eq!(stats.header_blocks, 1);
+        assert_eq!(stats.data_blocks, 1);
+        assert_eq!(stats.bytes, SIMPLE.len() as u64);
+
+        assert_eq!(out[..BLOCK_SIZE], SIMPLE[..BLOCK_SIZE]);
+        let original = BasicFits::from_bytes(SIMPLE.to_vec()).unwrap();
+        let negated = BasicFits::from_bytes(out).unwrap();
+        assert_eq!(negated.data.to_f64(), -original.data.to_f64());
+    }
+
+    #[test]
+    fn pipeline_header_transform_test() {
+        let mut out = Vec::new();
+        let set_object = |keywords: &mut KeywordList| {
+            set_keyword(keywords, "OBJECT", Value::Str("M31".to_string()), None)
+        };
+        let stats = FitsPipeline::new(SIMPLE, &mut out, negate)
+            .with_header_transform(set_object)
+            .run()
+            .unwrap();
+        assert_eq!(stats.bytes, out.len() as u64);
+
+        let original = BasicFits::from_bytes(SIMPLE.to_vec()).unwrap();
+        let transformed = BasicFits::from_bytes(out).unwrap();
+        assert_eq!(transformed.header.get_str("OBJECT"), Some("M31"));
+        assert_eq!(transformed.data.to_f64(), -original.data.to_f64());
+    }
+
+    #[test]
+    fn pipeline_truncated_test() {
+        let mut out = Vec::new();
+        let truncated = &SIMPLE[..SIMPLE.len() - 100];
+        let result = FitsPipeline::new(truncated, &mut out, negate).run();
+        assert!(matches!(result, Err(FitsError::UnexpectedEof)));
+
+        let result = FitsPipeline::new(&SIMPLE[..0], &mut out, negate).run();
+        assert!(matches!(result, Err(FitsError::InvalidHeader)));
+    }
+
+    #[test]
+    fn fits_file_test() {
+        let mut file = FitsFile::open("data/twodim.fits").unwrap();
+        let fits = BasicFits::open("data/twodim.fits").unwrap();
+        assert_eq!(file.header().axes, fits.header.axes);
+        assert_eq!(file.header_offset(), 0);
+        // One header block, followed by 4 x 4 values of 8 bytes
+        let start = BLOCK_SIZE as u64;
+        assert_eq!(file.data_range(), start..start + 128);
+
+        assert_eq!(file.read_data().unwrap(), *fits.data);
+        // Reading again seeks back to the data
+        assert_eq!(file.read_data().unwrap(), *fits.data);
+
+        assert!(matches!(
+            FitsFile::open("data/missing.fits"),
+            Err(FitsError::Io(_))
+        ));
+    }
+
+    #[test]
+    fn read_cutout_test() {
+        let mut file = FitsFile::open("data/twodim.fits").unwrap();
+        let full = BasicFits::open("data/twodim.fits")
+            .unwrap()
+            .reshape_to_2d()
+            .unwrap();
+        let cutout = file.read_cutout(1..3, 2..4).unwrap();
+        assert_eq!(cutout, full.slice(ndarray::s![2..4, 1..3]));
+        assert_eq!(file.read_cutout(0..4, 0..4).unwrap(), full);
+        assert_eq!(file.read_cutout(2..2, 0..4).unwrap().dim(), (4, 0));
+
+        assert!(matches!(
+            file.read_cutout(0..5, 0..1),
+            Err(FitsError::OutOfBounds)
+        ));
+        assert!(matches!(
+            file.read_cutout(0..1, 3..5),
+            Err(FitsError::OutOfBounds)
+        ));
+    }
+
+    #[test]
+    fn read_cutout_scaled_test() {
+        let data = ndarray::Array2::from_shape_vec((2, 3), vec![1i16, 2, 3, 4, -1, 6]).unwrap();
+        let mut fits = crate::image::tests::test_fits(&data.mapv(f64::from));
+        fits.data = Arc::new(DataArray::I16(crate::image::from_array2(data)));
+        for (key, value) in [
+            ("BITPIX", Value::Integer(16)),
+            ("BSCALE", Value::Float(2.)),
+            ("BZERO", Value::Float(10.)),
+            ("BLANK", Value::Integer(-1)),
+        ] {
+            fits.header.set_value(key, value, "").unwrap();
+        }
+
+        let path = temp_path("read_cutout_scaled_test");
+        fits.write(&mut File::create(&path).unwrap()).unwrap();
+        let cutout = FitsFile::open(&path).unwrap().read_cutout(1..3, 0..2);
+        std::fs::remove_file(&path).unwrap();
+
+        let cutout = cutout.unwrap();
+        assert_eq!(cutout.row(0).to_vec(), [14., 16.]);
+        assert!(cutout[[1, 0]].is_nan());
+        assert_eq!(cutout[[1, 1]], 22.);
+    }
+
+    #[test]
+    fn open_hdu_test() {
+        let primary = BasicFits::from_bytes(SIMPLE.to_vec()).unwrap();
+        let data = DataArray::I16(ndarray::arr1(&[1i16, 2, 3, 4, 5, 6]).into_dyn());
+        let header = Header::for_image_extension(Bitpix::Int16, &[3, 2]);
+        let extension = ImageExtension::create(header, data.clone()).unwrap();
+        let mut hdus = HduList::new(primary.clone());
+        hdus.push(Hdu::ImageExt(extension));
+
+        let path = temp_path("open_hdu_test");
+        hdus.write(&mut File::create(&path).unwrap()).unwrap();
+        let first = FitsFile::open_hdu(&path, 0);
+        let second = FitsFile::open_hdu(&path, 1);
+        let missing = FitsFile::open_hdu(&path, 2);
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(first.unwrap().header_offset(), 0);
+        let mut second = second.unwrap();
+        let primary_size = primary.to_bytes().len() as u64;
+        assert_eq!(second.header_offset(), primary_size);
+        let data_start = primary_size + BLOCK_SIZE as u64;
+        assert_eq!(second.data_range(), data_start..data_start + 12);
+        assert_eq!(second.header().get_str("XTENSION"), Some("IMAGE"));
+        assert_eq!(second.read_data().unwrap(), data);
+        let cutout = second.read_cutout(1..3, 1..2).unwrap();
+        assert_eq!(cutout.row(0).to_vec(), [5., 6.]);
+        assert!(matches!(missing, Err(FitsError::InvalidArgument("index"))));
+    }
+
+    #[cfg(feature = "mmap")]
+    #[test]
+    fn mmap_fits_test() {
+        let fits = MmapFits::open("data/twodim.fits").unwrap();
+        let expected = BasicFits::open("data/twodim.fits").unwrap();
+        assert_eq!(fits.header().axes, expected.header.axes);
+        assert_eq!(fits.data_slice().len(), 4 * 4 * 8);
+        assert_eq!(fits.read_data().unwrap(), *expected.data);
+
+        let first = f64::from_be_bytes(fits.data_slice()[..8].try_into().unwrap());
+        assert_eq!(first, expected.data.to_f64()[[0]]);
+    }
+
+    #[test]
+    fn read_fits_header_block_test() {
+        let header = read_fits_header_block("data/simple.fits").unwrap();
+        assert_eq!(header.len() % BLOCK_SIZE, 0);
+        assert_eq!(header, SIMPLE[..header.len()]);
+        let mut cards = header.chunks(HEADER_KEYWORD_SIZE);
+        assert!(cards.any(|card| card == HEADER_END_KEYWORD_FULL));
+
+        let long = read_fits_header_block("data/long.fits").unwrap();
+        assert_eq!(long.len() % BLOCK_SIZE, 0);
+        assert!(matches!(
+            read_fits_header_block("data/missing.fits"),
+            Err(FitsError::Io(_))
+        ));
+    }
+}
@@ -23,6 +23,7 @@ mod definitions {
     pub const HEADER_HISTORY_KEYWORD: &str = "HISTORY";
     pub const HEADER_COMMENT_KEYWORD: &str = "COMMENT";
     pub const HEADER_CONTINUE_KEYWORD: &str = "CONTINUE";
+    pub const HEADER_HIERARCH_KEYWORD: &str = "HIERARCH";
 
     // FITS with only a primary HDU is a 'Basic FITS File' or a 'Single Image FITS (SIF) File'
     // FITS with one or more extensions is a Multi-Extension FITS (MEF) file .
@@ -31,21 +32,74 @@ mod definitions {
 #[allow(dead_code)]
 pub mod parsing;
 
+pub mod builder;
+pub mod checksum;
+#[cfg(feature = "compression")]
+pub mod compression;
+pub mod data;
+pub mod error;
+pub mod hdu;
+pub mod image;
+pub mod io;
+pub mod stats;
+pub mod table;
+pub mod wcs;
+pub mod writing;
+
+pub use data::DataArray;
+pub use error::FitsError;
+pub use hdu::{Hdu, HduList};
+
 use std::fs::File;
-use std::io::Read;
+use std::io::{BufReader, Read, Write};
+use std::path::Path;
+use std::sync::Arc;
 
 use header::Header;
-use ndarray::{Array, IxDyn};
+use ndarray::{Array, Array2, ArrayView2, ArrayView3, ArrayViewD, IxDyn};
+use parsing::header::{HeaderParser, HeaderParserState, ParseError};
 
 type KeywordList = Vec<parsing::header::Keyword>;
-type RawHeaderList<'a> = Vec<parsing::header::HeaderChunk<'a>>;
 pub type GenericData<T> = Array<T, IxDyn>;
 
 pub mod header {
+    use std::cmp::Ordering;
+    use std::collections::HashMap;
+    use std::fmt;
+
     use tightness::bound;
 
-    use crate::parsing::header::extract_values;
-    use crate::KeywordList;
+    use crate::builder::HeaderBuilder;
+    use crate::definitions::{
+        BLOCK_SIZE, HEADER_END_KEYWORD, HEADER_END_KEYWORD_FULL, HEADER_KEYWORD_NAME_SIZE,
+        HEADER_KEYWORD_SIZE,
+    };
+    use crate::parsing::header::{
+        extract_values, is_valid_keyword_name, parse_header_lenient, set_keyword,
+        validate_keyword_name, ExtractedValues, FitsDateTime, Keyword, Value,
+    };
+    use crate::table::{ColumnDescriptor, TForm};
+    use crate::{writing, FitsError, KeywordList};
+
+    // The keywords describing the data layout: SIMPLE, BITPIX, NAXIS and NAXISn
+    pub(crate) fn is_mandatory_keyword(name: &str) -> bool {
+        mandatory_rank(name).is_some()
+    }
+
+    // Position of a mandatory keyword in the header, as prescribed by the standard
+    fn mandatory_rank(name: &str) -> Option<usize> {
+        match name {
+            "SIMPLE" => Some(0),
+            "BITPIX" => Some(1),
+            "NAXIS" => Some(2),
+            _ => Some(2 + name.strip_prefix("NAXIS")?.parse::<usize>().ok()?),
+        }
+    }
+
+    // The keywords in Header::summary, after the type and shape of the data
+    const SUMMARY_KEYWORDS: [&str; 8] = [
+        "OBJECT", "INSTRUME", "TELESCOP", "DATE-OBS", "EXPTIME", "FILTER", "RA", "DEC",
+    ];
 
     pub struct Header {
         pub simple: bool,
@@ -55,12 +109,97 @@ pub mod header {
         pub keywords: KeywordList,
     }
 
+    // Implemented by hand, as the bounded Naxis does not implement Clone and Debug itself
+    impl Clone for Header {
+        fn clone(&self) -> Self {
+            Header {
+                simple: self.simple,
+                bitpix: self.bitpix.clone(),
+                naxis: Naxis::new(*self.naxis.get()).expect("NAXIS was already checked"),
+                axes: self.axes.clone(),
+                keywords: self.keywords.clone(),
+            }
+        }
+    }
+
+    impl PartialEq for Header {
+        fn eq(&self, other: &Self) -> bool {
+            self.simple == other.simple
+                && self.bitpix == other.bitpix
+                && self.naxis.get() == other.naxis.get()
+                && self.axes == other.axes
+                && self.keywords == other.keywords
+        }
+    }
+
+    impl fmt::Debug for Header {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.debug_struct("Header")
+                .field("simple", &self.simple)
+                .field("bitpix", &self.bitpix)
+                .field("naxis", self.naxis.get())
+                .field("axes", &self.axes)
+                .field("keywords", &self.keywords)
+                .finish()
+        }
+    }
+
+    /// The header as FITS text: every keyword as 80 character records, followed by END. With
+    /// the alternate flag (`{:#}`) the records are separated by newlines.
+    impl fmt::Display for Header {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            let records = self.keywords.iter().flat_map(writing::keyword_to_records);
+            for record in records.chain(std::iter::once(HEADER_END_KEYWORD.to_string())) {
+                write!(f, "{:1$}", record, HEADER_KEYWORD_SIZE)?;
+                if f.alternate() {
+                    writeln!(f)?;
+                }
+            }
+            Ok(())
+        }
+    }
+
+    /// The value of the first keyword named `key`, see [`Header::get_value`].
+    ///
+    /// # Panics
+    /// When the header has no keyword named `key`.
+    impl std::ops::Index<&str> for Header {
+        type Output = Value;
+
+        fn index(&self, key: &str) -> &Value {
+            self.get_value(key)
+                .unwrap_or_else(|| panic!("keyword {} not found in header", key))
+        }
+    }
+
+    // With the serde feature, a header is the list of its keywords in order, as keywords
+    // (e.g. COMMENT) can occur more than once.
+    #[cfg(feature = "serde")]
+    impl serde::Serialize for Header {
+        fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            serde::Serialize::serialize(&self.keywords, serializer)
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    impl<'de> serde::Deserialize<'de> for Header {
+        fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            let keywords: KeywordList = serde::Deserialize::deserialize(deserializer)?;
+            Header::from_keyword_list(keywords).map_err(serde::de::Error::custom)
+        }
+    }
+
     impl Header {
-        pub fn from_keyword_list(keywords: KeywordList) -> Option<Self> {
-            let (simple, naxis, axes, bitpix) = extract_values(&keywords)?;
-            let naxis = Naxis::new(naxis).ok()?;
-            let bitpix = Bitpix::from_int(bitpix)?;
-            Some(Header {
+        pub fn from_keyword_list(keywords: KeywordList) -> Result<Self, FitsError> {
+            let ExtractedValues {
+                simple,
+                naxis,
+                axes,
+                bitpix,
+            } = extract_values(&keywords)?;
+            let naxis = Naxis::new(naxis).map_err(|_| FitsError::InvalidNaxis(naxis))?;
+            let bitpix = Bitpix::from_int(bitpix).ok_or(FitsError::InvalidBitpix(bitpix))?;
+            Ok(Header {
                 simple,
                 bitpix,
                 naxis,
@@ -69,11 +208,755 @@ pub mod header {
             })
         }
 
+        /// Parse a header from its text form, as 80 character records either separated by
+        /// newlines or concatenated. The END record is optional.
+        pub fn from_fits_text(text: &str) -> Result<Self, FitsError> {
+            let mut bytes: Vec<u8> = Vec::with_capacity(text.len());
+            let mut add_record = |record: &[u8]| {
+                if record.len() > HEADER_KEYWORD_SIZE {
+                    return Err(FitsError::InvalidHeader);
+                }
+                bytes.extend_from_slice(record);
+                bytes.resize(bytes.len() + HEADER_KEYWORD_SIZE - record.len(), b' ');
+                Ok(())
+            };
+            if text.contains('\n') {
+                for line in text.lines().filter(|line| !line.is_empty()) {
+                    add_record(line.as_bytes())?;
+                }
+            } else {
+                for record in text.as_bytes().chunks(HEADER_KEYWORD_SIZE) {
+                    add_record(record)?;
+                }
+            }
+            add_record(HEADER_END_KEYWORD_FULL)?;
+            bytes.resize(bytes.len().div_ceil(BLOCK_SIZE) * BLOCK_SIZE, b' ');
+
+            let (keywords, errors) = parse_header_lenient(&mut bytes.as_slice());
+            if !errors.is_empty() {
+                return Err(FitsError::InvalidHeader);
+            }
+            Header::from_keyword_list(keywords)
+        }
+
+        /// A minimal primary header for an image: SIMPLE = T, BITPIX, NAXIS and NAXISn for
+        /// the length of each axis in `axes`, starting with NAXIS1.
+        ///
+        /// Panics if there are more than 999 axes.
+        pub fn for_image(bitpix: Bitpix, axes: &[usize]) -> Header {
+            Self::for_image_with_keywords(bitpix, axes, Vec::new())
+        }
+
+        /// Like [`Header::for_image`], with the `(name, value, comment)` keywords of `extra`
+        /// after the mandatory ones. A keyword that occurs twice keeps the last value.
+        ///
+        /// Panics if there are more than 999 axes, or a keyword of `extra` has an invalid
+        /// name or is mandatory.
+        pub fn for_image_with_keywords(
+            bitpix: Bitpix,
+            axes: &[usize],
+            extra: Vec<(String, Value, String)>,
+        ) -> Header {
+            let builder = HeaderBuilder::new()
+                .simple(true)
+                .bitpix(bitpix)
+                .naxis(axes.to_vec());
+            let builder = extra
+                .into_iter()
+                .fold(builder, |builder, (name, value, comment)| {
+                    builder.keyword(&name, value, &comment)
+                });
+            builder
+                .build()
+                .unwrap_or_else(|e| panic!("invalid image header: {}", e))
+        }
+
+        /// A minimal header for an IMAGE extension: XTENSION = 'IMAGE', BITPIX, NAXIS,
+        /// NAXISn for the length of each axis in `axes`, PCOUNT = 0 and GCOUNT = 1.
+        ///
+        /// Panics if there are more than 999 axes.
+        pub fn for_image_extension(bitpix: Bitpix, axes: &[usize]) -> Header {
+            let card = |name: &str, value| Keyword::Value(name.to_string(), value, String::new());
+            let mut keywords = vec![
+                // Padded to 8 characters, as the standard asks for XTENSION
+                card("XTENSION", Value::Str("IMAGE   ".to_string())),
+                card("BITPIX", Value::Integer(bitpix.to_int())),
+                card("NAXIS", Value::Integer(axes.len() as i64)),
+            ];
+            for (i, n) in axes.iter().enumerate() {
+                keywords.push(card(&format!("NAXIS{}", i + 1), Value::Integer(*n as i64)));
+            }
+            keywords.push(card("PCOUNT", Value::Integer(0)));
+            keywords.push(card("GCOUNT", Value::Integer(1)));
+            Header::from_keyword_list(keywords)
+                .unwrap_or_else(|e| panic!("invalid image extension header: {}", e))
+        }
+
+        #[deprecated(note = "Print each of `header.keywords` with the Display trait instead")]
         pub fn print_keywords(&self) {
             for keyword in self.keywords.iter() {
-                keyword.print()
+                println!("{}", keyword)
+            }
+        }
+
+        /// The value of the first keyword named `key`. HIERARCH keywords can be found by
+        /// their long name, with or without the "HIERARCH " prefix, e.g. "ESO DET WIN NX".
+        pub fn get_value(&self, key: &str) -> Option<&Value> {
+            let long_key = key.strip_prefix("HIERARCH ").unwrap_or(key);
+            self.keywords.iter().find_map(|kw| match kw {
+                Keyword::Value(k, v, _c) if k == key => Some(v),
+                Keyword::Hierarch(k, v, _c) if k == long_key => Some(v),
+                _ => None,
+            })
+        }
+
+        /// Shorthand for [`Header::get_value`].
+        pub fn get(&self, key: &str) -> Option<&Value> {
+            self.get_value(key)
+        }
+
+        /// The number of axes (NAXIS).
+        pub fn naxis(&self) -> usize {
+            *self.naxis.get()
+        }
+
+        /// The number of dimensions of the data, the same as [`Header::naxis`].
+        pub fn ndim(&self) -> usize {
+            *self.naxis.get()
+        }
+
+        /// The length of axis `n` (NAXISn), counting from 1 as in the keyword names. `None`
+        /// for 0 or an axis beyond NAXIS.
+        pub fn get_naxisn(&self, n: usize) -> Option<usize> {
+            self.axes.get(n.checked_sub(1)?).copied()
+        }
+
+        /// Whether the header has a keyword named `key` with a value (possibly undefined).
+        pub fn contains(&self, key: &str) -> bool {
+            self.get_value(key).is_some()
+        }
+
+        /// All keywords, in the order of the header.
+        pub fn keywords_iter(&self) -> impl Iterator<Item = &Keyword> {
+            self.keywords.iter()
+        }
+
+        /// All keywords, in the order of the header. Same as [`Header::keywords_iter`].
+        pub fn iter(&self) -> impl Iterator<Item = &Keyword> {
+            self.keywords.iter()
+        }
+
+        /// The `(name, value, comment)` of every keyword with a value, including HIERARCH
+        /// keywords. COMMENT and HISTORY records are skipped.
+        pub fn value_keywords(&self) -> impl Iterator<Item = (&str, &Value, &str)> {
+            self.keywords.iter().filter_map(|kw| match kw {
+                Keyword::Value(name, value, comment) | Keyword::Hierarch(name, value, comment) => {
+                    Some((name.as_str(), value, comment.as_str()))
+                }
+                _ => None,
+            })
+        }
+
+        /// Like [`Header::value_keywords`], but only the keywords whose name starts with
+        /// `prefix`, ignoring case. E.g. "NAXIS" gives NAXIS, NAXIS1, NAXIS2, ...
+        pub fn keywords_with_prefix<'a>(
+            &'a self,
+            prefix: &'a str,
+        ) -> impl Iterator<Item = (&'a str, &'a Value, &'a str)> {
+            self.value_keywords()
+                .filter(move |(name, _value, _comment)| {
+                    name.get(..prefix.len())
+                        .is_some_and(|start| start.eq_ignore_ascii_case(prefix))
+                })
+        }
+
+        /// The text of all COMMENT records, in the order of the header.
+        pub fn comments(&self) -> impl Iterator<Item = &str> {
+            self.keywords.iter().filter_map(|kw| match kw {
+                Keyword::Comment(text) => Some(text.as_str()),
+                _ => None,
+            })
+        }
+
+        /// The text of all HISTORY records, in the order of the header.
+        pub fn history(&self) -> impl Iterator<Item = &str> {
+            self.keywords.iter().filter_map(|kw| match kw {
+                Keyword::History(text) => Some(text.as_str()),
+                _ => None,
+            })
+        }
+
+        /// Append `text` as COMMENT records, wrapped over as many records as needed.
+        pub fn add_comment(&mut self, text: &str) {
+            let records = wrap_text(text, TEXT_SIZE).into_iter().map(Keyword::Comment);
+            self.keywords.extend(records);
+        }
+
+        /// Append `text` as HISTORY records, wrapped over as many records as needed.
+        pub fn add_history(&mut self, text: &str) {
+            let records = wrap_text(text, TEXT_SIZE).into_iter().map(Keyword::History);
+            self.keywords.extend(records);
+        }
+
+        /// The value of keyword `key`, if it is an integer.
+        pub fn get_int(&self, key: &str) -> Option<i64> {
+            match self.get_value(key)? {
+                Value::Integer(n) => Some(*n),
+                _ => None,
+            }
+        }
+
+        /// The value of keyword `key` as a float, also accepting integer values.
+        pub fn get_float(&self, key: &str) -> Option<f64> {
+            match self.get_value(key)? {
+                Value::Float(x) => Some(*x),
+                Value::Integer(n) => Some(*n as f64),
+                _ => None,
+            }
+        }
+
+        /// The value of keyword `key`, if it is a string.
+        pub fn get_str(&self, key: &str) -> Option<&str> {
+            match self.get_value(key)? {
+                Value::Str(s) => Some(s.as_str()),
+                _ => None,
+            }
+        }
+
+        /// The value of keyword `key`, if it is a logical (T or F).
+        pub fn get_bool(&self, key: &str) -> Option<bool> {
+            match self.get_value(key)? {
+                Value::Boolean(b) => Some(*b),
+                _ => None,
+            }
+        }
+
+        /// The BLANK value marking undefined pixels. Only integer data can have one, floating
+        /// point data uses NaN instead.
+        pub fn blank(&self) -> Option<i64> {
+            if self.bitpix.is_float() {
+                return None;
+            }
+            self.get_int("BLANK")
+        }
+
+        /// Whether EXTEND = T, i.e. extensions may follow the primary HDU. Each of them
+        /// starts at the next 2880 byte block after the (padded) data.
+        pub fn extend(&self) -> bool {
+            self.get_bool("EXTEND").unwrap_or(false)
+        }
+
+        /// The equinox of the celestial coordinate system in years, e.g. 2000.0 for J2000.
+        pub fn wcs_equinox(&self) -> Option<f64> {
+            self.get_float("EQUINOX")
+        }
+
+        /// The reference frame of the celestial coordinates, e.g. "ICRS", "FK5" or "FK4".
+        pub fn wcs_radesys(&self) -> Option<String> {
+            self.get_str("RADESYS").map(|s| s.trim().to_string())
+        }
+
+        /// The epoch of the observation as a (Julian) year. Uses the old EPOCH keyword if
+        /// present (common for FK4 data), otherwise the date in DATE-OBS.
+        pub fn epoch_of_observation(&self) -> Option<f64> {
+            self.get_float("EPOCH")
+                .or_else(|| julian_year(self.get_str("DATE-OBS")?.trim()))
+        }
+
+        /// The equinox of the coordinates as a Julian year, from EQUINOX or the older EPOCH
+        /// keyword. Defaults to 2000.0 (J2000) if neither is present.
+        pub fn equinox_year(&self) -> f64 {
+            self.get_float("EQUINOX")
+                .or_else(|| self.get_float("EPOCH"))
+                .unwrap_or(2000.)
+        }
+
+        /// The celestial reference frame: RADESYS if present. Otherwise it follows from
+        /// EQUINOX, or the older EPOCH, as in the FITS standard: "FK4" before 1984.0, "FK5"
+        /// from 1984.0 on and "ICRS" without an equinox.
+        pub fn radesys_str(&self) -> &str {
+            if let Some(radesys) = self.get_str("RADESYS") {
+                return radesys.trim();
+            }
+            match self
+                .get_float("EQUINOX")
+                .or_else(|| self.get_float("EPOCH"))
+            {
+                Some(equinox) if equinox < 1984. => "FK4",
+                Some(_) => "FK5",
+                None => "ICRS",
+            }
+        }
+
+        /// The pointing of the telescope as `(ra, dec)` in degrees, from the sexagesimal
+        /// OBJCTRA ('hh mm ss.s') and OBJCTDEC ('+dd mm ss.s') written by many camera control
+        /// programs. Falls back to CRVAL1 and CRVAL2 if those are missing or invalid.
+        pub fn equatorial_coords(&self) -> Option<(f64, f64)> {
+            let ra = self.get_str("OBJCTRA").and_then(parse_sexagesimal);
+            let dec = self.get_str("OBJCTDEC").and_then(parse_sexagesimal);
+            match (ra, dec) {
+                (Some(ra), Some(dec)) => Some((15. * ra, dec)),
+                _ => Some((self.get_float("CRVAL1")?, self.get_float("CRVAL2")?)),
+            }
+        }
+
+        /// A short, human readable summary of the header: the kind of HDU, BITPIX, the shape
+        /// of the data and the most common observation keywords, one per line. Keywords that
+        /// are missing or undefined are left out.
+        pub fn summary(&self) -> String {
+            let kind = match (self.get_str("XTENSION"), self.simple) {
+                (Some(xtension), _) => format!("{} extension", xtension.trim()),
+                (None, true) => "standard FITS".to_string(),
+                (None, false) => "non-standard FITS (SIMPLE = F)".to_string(),
+            };
+            let shape = if self.axes.is_empty() {
+                "no data".to_string()
+            } else {
+                let axes: Vec<String> = self.axes.iter().map(usize::to_string).collect();
+                axes.join(" × ")
+            };
+            let mut lines = vec![
+                ("Type", kind),
+                (
+                    "BITPIX",
+                    format!("{} ({})", self.bitpix.to_int(), self.bitpix.description()),
+                ),
+                ("Shape", shape),
+            ];
+
+            for key in SUMMARY_KEYWORDS {
+                let value = match self.get_value(key) {
+                    None | Some(Value::Undefined) => continue,
+                    Some(Value::Str(s)) => s.trim().to_string(),
+                    Some(value) => value.to_string().trim().to_string(),
+                };
+                lines.push((key, value));
+            }
+            lines
+                .iter()
+                .map(|(label, value)| format!("{:<8} : {}\n", label, value))
+                .collect()
+        }
+
+        /// The inline comment of the first keyword named `key`, e.g. "[s] exposure time".
+        /// Empty if the keyword has no comment. Like [`Header::get`], HIERARCH keywords are
+        /// found by their long name.
+        pub fn get_comment(&self, key: &str) -> Option<&str> {
+            let long_key = key.strip_prefix("HIERARCH ").unwrap_or(key);
+            self.keywords.iter().find_map(|kw| match kw {
+                Keyword::Value(k, _v, c) if k == key => Some(c.as_str()),
+                Keyword::Hierarch(k, _v, c) if k == long_key => Some(c.as_str()),
+                _ => None,
+            })
+        }
+
+        /// The inline comment of the first keyword named `name`, see [`Header::get_comment`].
+        pub fn get_comment_at_keyword(&self, name: &str) -> Option<&str> {
+            self.get_comment(name)
+        }
+
+        /// Rename the first keyword called `old_name`, e.g. AIRMASS to SECZ.
+        ///
+        /// Mandatory keywords can not be renamed, nor can a keyword be renamed to one.
+        pub fn rename_keyword(&mut self, old_name: &str, new_name: &str) -> Result<(), FitsError> {
+            if !is_valid_keyword_name(new_name) {
+                return Err(FitsError::InvalidKeywordName(new_name.to_string()));
+            }
+            for name in [old_name, new_name] {
+                if is_mandatory_keyword(name) {
+                    return Err(FitsError::MandatoryKeyword(name.to_string()));
+                }
+            }
+
+            for kw in self.keywords.iter_mut() {
+                if let Keyword::Value(k, _v, _c) = kw {
+                    if k == old_name {
+                        *k = new_name.to_string();
+                        return Ok(());
+                    }
+                }
+            }
+            Err(FitsError::KeywordNotFound(old_name.to_string()))
+        }
+
+        /// Append a keyword to the end of the header.
+        ///
+        /// Mandatory keywords can not be inserted, as the header already has them; use
+        /// [`Header::set_value`] to change their value.
+        pub fn insert(&mut self, keyword: Keyword) -> Result<(), FitsError> {
+            if let Keyword::Value(name, _v, _c) = &keyword {
+                if !name.is_empty() {
+                    validate_keyword_name(name, false)?;
+                }
+                if is_mandatory_keyword(name) {
+                    return Err(FitsError::MandatoryKeyword(name.to_string()));
+                }
+            }
+            self.keywords.push(keyword);
+            Ok(())
+        }
+
+        /// Set the value and comment of the first keyword named `key`, appending a new
+        /// keyword if there is none.
+        ///
+        /// Mandatory keywords must keep a legal value: SIMPLE a logical, BITPIX one of the
+        /// allowed integers and NAXISn (up to NAXIS) a non-negative integer. The `simple`,
+        /// `bitpix` and `axes` fields are updated to match. NAXIS itself can only be set to
+        /// its current value, as changing it leaves the NAXISn keywords inconsistent.
+        pub fn set_value(
+            &mut self,
+            key: &str,
+            value: Value,
+            comment: &str,
+        ) -> Result<(), FitsError> {
+            validate_keyword_name(key, false)?;
+            let illegal = || FitsError::MandatoryKeyword(key.to_string());
+            match (key, &value) {
+                ("SIMPLE", Value::Boolean(b)) => self.simple = *b,
+                ("BITPIX", Value::Integer(n)) => {
+                    self.bitpix = Bitpix::from_int(*n).ok_or(FitsError::InvalidBitpix(*n))?
+                }
+                ("NAXIS", Value::Integer(n)) if *n == self.axes.len() as i64 => (),
+                (key, value) if is_mandatory_keyword(key) => {
+                    let axis = key
+                        .strip_prefix("NAXIS")
+                        .and_then(|n| n.parse::<usize>().ok());
+                    match (axis, value) {
+                        (Some(n), Value::Integer(size)) if (1..=self.axes.len()).contains(&n) => {
+                            self.axes[n - 1] = usize::try_from(*size).map_err(|_| illegal())?
+                        }
+                        _ => return Err(illegal()),
+                    }
+                }
+                _ => (),
+            }
+            set_keyword(&mut self.keywords, key, value, Some(comment));
+            Ok(())
+        }
+
+        /// Remove the first keyword named `key` and return it. Mandatory keywords can not be
+        /// removed.
+        pub fn remove(&mut self, key: &str) -> Result<Option<Keyword>, FitsError> {
+            if is_mandatory_keyword(key) {
+                return Err(FitsError::MandatoryKeyword(key.to_string()));
+            }
+            let index = self
+                .keywords
+                .iter()
+                .position(|kw| matches!(kw, Keyword::Value(k, _v, _c) if k == key));
+            Ok(index.map(|i| self.keywords.remove(i)))
+        }
+
+        /// Set the value and comment of several keywords in a single pass over the header,
+        /// appending the ones that do not exist yet. Returns the number of existing keywords
+        /// that were updated.
+        ///
+        /// The result is the same as setting each keyword in turn, so if a name occurs more
+        /// than once in `updates` the last one wins. The `bitpix`, `naxis` and `axes` fields
+        /// are not updated, so this should not be used for mandatory keywords.
+        pub fn set_batch(&mut self, updates: &[(&str, Value, &str)]) -> usize {
+            let mut latest: HashMap<&str, usize> = HashMap::new();
+            for (i, (name, _value, _comment)) in updates.iter().enumerate() {
+                latest.insert(name, i);
+            }
+
+            let mut updated = 0;
+            for kw in self.keywords.iter_mut() {
+                if let Keyword::Value(k, v, c) = kw {
+                    if let Some(i) = latest.remove(k.as_str()) {
+                        let (_name, value, comment) = &updates[i];
+                        *v = value.clone();
+                        *c = comment.to_string();
+                        updated += 1;
+                    }
+                }
+            }
+
+            // The remaining ones are new, keep them in the order they first appear
+            for (name, _value, _comment) in updates {
+                if let Some(i) = latest.remove(name) {
+                    let (_name, value, comment) = &updates[i];
+                    self.keywords.push(Keyword::Value(
+                        name.to_string(),
+                        value.clone(),
+                        comment.to_string(),
+                    ));
+                }
+            }
+            updated
+        }
+
+        /// Remove all keywords with an undefined value, returning how many were removed.
+        /// COMMENT and HISTORY records are kept.
+        pub fn drop_undefined(&mut self) -> usize {
+            let before = self.keywords.len();
+            self.keywords
+                .retain(|kw| !matches!(kw, Keyword::Value(_, Value::Undefined, _)));
+            before - self.keywords.len()
+        }
+
+        /// Names of the keywords holding an undefined value. Many of these can point to
+        /// values the parser did not understand.
+        pub fn undefined_keywords(&self) -> Vec<&str> {
+            self.keywords
+                .iter()
+                .filter_map(|kw| match kw {
+                    Keyword::Value(k, Value::Undefined, _c) => Some(k.as_str()),
+                    _ => None,
+                })
+                .collect()
+        }
+
+        /// Sort the keywords alphabetically by name. Keywords with the same name (e.g.
+        /// HISTORY) keep their relative order.
+        ///
+        /// With `preserve_mandatory_order`, SIMPLE, BITPIX, NAXIS and NAXIS1..n are kept at
+        /// the top in the order required by the standard.
+        pub fn sort_keywords(&mut self, preserve_mandatory_order: bool) {
+            if !preserve_mandatory_order {
+                self.keywords
+                    .sort_by(|a, b| a.keyword_name().cmp(b.keyword_name()));
+                return;
+            }
+            self.keywords.sort_by(|a, b| {
+                match (
+                    mandatory_rank(a.keyword_name()),
+                    mandatory_rank(b.keyword_name()),
+                ) {
+                    (Some(ra), Some(rb)) => ra.cmp(&rb),
+                    (Some(_), None) => Ordering::Less,
+                    (None, Some(_)) => Ordering::Greater,
+                    (None, None) => a.keyword_name().cmp(b.keyword_name()),
+                }
+            });
+        }
+
+        /// The (1-based) number of the table column whose TTYPEn equals `col_name`.
+        ///
+        /// Column names are compared case-insensitively, as recommended by the standard.
+        pub fn column_index_by_name(&self, col_name: &str) -> Option<usize> {
+            self.keywords.iter().find_map(|kw| match kw {
+                Keyword::Value(k, Value::Str(name), _c)
+                    if k.starts_with("TTYPE") && name.trim().eq_ignore_ascii_case(col_name) =>
+                {
+                    k["TTYPE".len()..].parse().ok()
+                }
+                _ => None,
+            })
+        }
+
+        /// Convert a stored table value of column `name` to its physical value,
+        /// i.e. `TZEROn + TSCALn * stored`. Returns None if there is no such column.
+        pub fn get_physical_value(&self, name: &str, stored: f64) -> Option<f64> {
+            let n = self.column_index_by_name(name)?;
+            let scale = self
+                .get_value(&format!("TSCAL{}", n))
+                .map_or(1., Value::as_f64_lossy);
+            let zero = self
+                .get_value(&format!("TZERO{}", n))
+                .map_or(0., Value::as_f64_lossy);
+            Some(zero + scale * stored)
+        }
+
+        /// Descriptions of the columns of a binary table, numbered 1 up to TFIELDS.
+        /// Columns without a (valid) TFORMn are skipped.
+        pub fn column_descriptors(&self) -> Vec<ColumnDescriptor> {
+            let n_fields = match self.get_value("TFIELDS") {
+                Some(Value::Integer(n)) => *n as usize,
+                _ => return Vec::new(),
+            };
+            (1..=n_fields)
+                .filter_map(|n| {
+                    let format = TForm::from_tform(self.get_str(&format!("TFORM{}", n))?)?;
+                    let text = |key: &str| self.get_str(&format!("{}{}", key, n));
+                    Some(ColumnDescriptor {
+                        index: n,
+                        name: text("TTYPE").unwrap_or_default().trim_end().to_string(),
+                        format,
+                        unit: text("TUNIT").map(|unit| unit.trim_end().to_string()),
+                        null_value: match self.get_value(&format!("TNULL{}", n)) {
+                            Some(Value::Integer(null)) => Some(*null),
+                            _ => None,
+                        },
+                        scale: self.get_float(&format!("TSCAL{}", n)),
+                        zero: self.get_float(&format!("TZERO{}", n)),
+                    })
+                })
+                .collect()
+        }
+
+        /// Check the keywords against the FITS standard. Returns every violation found, so
+        /// the caller can decide which ones are fatal. A header read from a file already
+        /// has a valid BITPIX and NAXIS, but the keywords may have been changed since.
+        pub fn validate(&self) -> Vec<FitsValidationWarning> {
+            let mut warnings = Vec::new();
+            let primary = self.get_value("XTENSION").is_none();
+            if primary && self.keywords.first().map(Keyword::keyword_name) != Some("SIMPLE") {
+                warnings.push(FitsValidationWarning::MissingSimple);
+            }
+
+            match self.get_value("BITPIX") {
+                Some(Value::Integer(n)) if Bitpix::from_int(*n).is_some() => (),
+                Some(value) => warnings.push(FitsValidationWarning::InvalidBitpix(
+                    value.to_string().trim().to_string(),
+                )),
+                None => warnings.push(FitsValidationWarning::MissingKeyword("BITPIX")),
+            }
+
+            let naxis = match self.get_value("NAXIS") {
+                Some(Value::Integer(n)) if (0..=999).contains(n) => Some(*n as usize),
+                Some(value) => {
+                    warnings.push(FitsValidationWarning::InvalidNaxis(
+                        value.to_string().trim().to_string(),
+                    ));
+                    None
+                }
+                None => {
+                    warnings.push(FitsValidationWarning::MissingKeyword("NAXIS"));
+                    None
+                }
+            };
+            if let Some(naxis) = naxis {
+                for n in 1..=naxis {
+                    match self.get_value(&format!("NAXIS{}", n)) {
+                        Some(Value::Integer(size)) if *size >= 0 => (),
+                        _ => warnings.push(FitsValidationWarning::MissingAxis(n)),
+                    }
+                }
+                let axis = |kw: &Keyword| kw.keyword_name().strip_prefix("NAXIS")?.parse().ok();
+                for n in self.keywords.iter().filter_map(axis) {
+                    if n > naxis {
+                        warnings.push(FitsValidationWarning::UnexpectedAxis(n));
+                    }
+                }
+            }
+
+            let long_strings = self.get_value("LONGSTRN").is_some();
+            for (position, kw) in self.keywords.iter().enumerate() {
+                let Keyword::Value(name, value, _c) = kw else {
+                    continue;
+                };
+                if name == HEADER_END_KEYWORD {
+                    warnings.push(FitsValidationWarning::MisplacedEnd(position));
+                } else if !name.is_empty() && !is_valid_keyword_name(name) {
+                    warnings.push(FitsValidationWarning::InvalidKeywordName(name.clone()));
+                }
+                if let Value::Str(s) = value {
+                    if !long_strings && s.len() > MAX_STRING_LENGTH {
+                        warnings.push(FitsValidationWarning::LongString {
+                            keyword: name.clone(),
+                            length: s.len(),
+                        });
+                    }
+                }
+            }
+            warnings
+        }
+    }
+
+    // Longest string value that fits in a single record, without the quotes
+    const MAX_STRING_LENGTH: usize = 68;
+
+    /// A way in which a header does not conform to the FITS standard, see
+    /// [`Header::validate`].
+    #[derive(Debug, Clone, PartialEq)]
+    pub enum FitsValidationWarning {
+        /// A primary header does not start with SIMPLE.
+        MissingSimple,
+        /// A mandatory keyword (BITPIX or NAXIS) is missing.
+        MissingKeyword(&'static str),
+        /// BITPIX is not one of 8, 16, 32, 64, -32 or -64. Contains the value.
+        InvalidBitpix(String),
+        /// NAXIS is not an integer from 0 up to 999. Contains the value.
+        InvalidNaxis(String),
+        /// NAXISn is missing (or not a non-negative integer) for an n up to NAXIS.
+        MissingAxis(usize),
+        /// NAXISn is present for an n larger than NAXIS.
+        UnexpectedAxis(usize),
+        /// A string value is longer than fits in one record, while there is no LONGSTRN
+        /// keyword announcing the use of CONTINUE.
+        LongString { keyword: String, length: usize },
+        /// A keyword name with characters other than A-Z, 0-9, '-' and '_', or longer than 8.
+        InvalidKeywordName(String),
+        /// An END keyword that is not the last one, at this position in the keywords.
+        MisplacedEnd(usize),
+    }
+
+    impl fmt::Display for FitsValidationWarning {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            match self {
+                Self::MissingSimple => write!(f, "primary header does not start with SIMPLE"),
+                Self::MissingKeyword(name) => write!(f, "mandatory keyword {} is missing", name),
+                Self::InvalidBitpix(value) => write!(f, "invalid BITPIX: {}", value),
+                Self::InvalidNaxis(value) => write!(f, "invalid NAXIS: {}", value),
+                Self::MissingAxis(n) => write!(f, "NAXIS{} is missing or invalid", n),
+                Self::UnexpectedAxis(n) => write!(f, "NAXIS{} is present, but NAXIS < {}", n, n),
+                Self::LongString { keyword, length } => write!(
+                    f,
+                    "string value of {} has {} characters (at most {} without LONGSTRN)",
+                    keyword, length, MAX_STRING_LENGTH
+                ),
+                Self::InvalidKeywordName(name) => write!(f, "invalid keyword name '{}'", name),
+                Self::MisplacedEnd(position) => {
+                    write!(f, "END is keyword {}, but not the last one", position + 1)
+                }
+            }
+        }
+    }
+
+    // Value of a sexagesimal '[+-]dd mm ss.s' (or 'dd:mm:ss.s') string, in the unit of `dd`
+    fn parse_sexagesimal(text: &str) -> Option<f64> {
+        let text = text.trim();
+        let (sign, text) = match text.strip_prefix('-') {
+            Some(rest) => (-1., rest),
+            None => (1., text.strip_prefix('+').unwrap_or(text)),
+        };
+        let mut parts = text
+            .split([' ', ':'])
+            .filter(|p| !p.is_empty())
+            .map(|p| p.parse::<f64>().ok());
+        let (d, m, s) = (parts.next()??, parts.next()??, parts.next()??);
+        if parts.next().is_some() || !(0. ..60.).contains(&m) || !(0. ..60.).contains(&s) {
+            return None;
+        }
+        Some(sign * (d + m / 60. + s / 3600.))
+    }
+
+    // Room for the text of a COMMENT or HISTORY record, after the keyword name
+    pub(crate) const TEXT_SIZE: usize = HEADER_KEYWORD_SIZE - HEADER_KEYWORD_NAME_SIZE;
+
+    // Split `text` into lines of at most `width` characters, breaking between words where
+    // possible. Empty text gives a single empty line.
+    pub(crate) fn wrap_text(text: &str, width: usize) -> Vec<String> {
+        let mut lines = Vec::new();
+        let mut line = String::new();
+        for word in text.split_whitespace() {
+            let mut word: Vec<char> = word.chars().collect();
+            let line_len = line.chars().count();
+            if line_len > 0 && line_len + 1 + word.len() <= width {
+                line.push(' ');
+                line.extend(word);
+                continue;
+            }
+            if line_len > 0 {
+                lines.push(std::mem::take(&mut line));
             }
+            // Words that are too long by themselves are split anywhere
+            while word.len() > width {
+                lines.push(word.drain(..width).collect());
+            }
+            line = word.into_iter().collect();
+        }
+        if !line.is_empty() || lines.is_empty() {
+            lines.push(line);
         }
+        lines
+    }
+
+    // Julian year of an ISO-8601 date as used in DATE-OBS: 'YYYY-MM-DD[Thh:mm:ss[.sss]]'
+    fn julian_year(date: &str) -> Option<f64> {
+        let jd = FitsDateTime::parse_iso(date)?.as_jd();
+        Some(2000. + (jd - 2451545.) / 365.25)
     }
 
     // usize already guarentees that it is >= 0
@@ -113,11 +996,37 @@ pub mod header {
                 Self::Float64 => -64,
             }
         }
+
+        /// Size of a single value in bytes: |BITPIX| / 8
+        pub fn bytes_per_pixel(&self) -> usize {
+            self.to_int().unsigned_abs() as usize / 8
+        }
+
+        pub fn is_integer(&self) -> bool {
+            self.to_int() > 0
+        }
+
+        pub fn is_float(&self) -> bool {
+            !self.is_integer()
+        }
+
+        /// The data type, as described in Table 8 of the FITS standard.
+        pub fn description(&self) -> &'static str {
+            match self {
+                Self::Int8 => "8-bit unsigned integer",
+                Self::Int16 => "16-bit two's complement integer",
+                Self::Int32 => "32-bit two's complement integer",
+                Self::Int64 => "64-bit two's complement integer",
+                Self::Float32 => "32-bit IEEE floating point",
+                Self::Float64 => "64-bit IEEE floating point",
+            }
+        }
     }
 
     #[cfg(test)]
     mod tests {
         use super::*;
+        use crate::table::TFormType;
 
         #[test]
         fn bitpix_test() {
@@ -128,36 +1037,1848 @@ pub mod header {
             assert!(Bitpix::from_int(0).is_none());
             assert!(Bitpix::from_int(-63).is_none());
             assert!(Bitpix::from_int(-8).is_none());
+
+            assert_eq!(Bitpix::Int8.bytes_per_pixel(), 1);
+            assert_eq!(Bitpix::Int16.bytes_per_pixel(), 2);
+            assert_eq!(Bitpix::Float32.bytes_per_pixel(), 4);
+            assert_eq!(Bitpix::Float64.bytes_per_pixel(), 8);
+            assert!(Bitpix::Int64.is_integer() && !Bitpix::Int64.is_float());
+            assert!(Bitpix::Float32.is_float() && !Bitpix::Float32.is_integer());
         }
-    }
-}
 
-// Only basic FITS file for now, i.e. with one HDU
-pub struct BasicFits {
-    pub header: Header,
-    pub data: GenericData<f64>,
-}
+        fn card(kw: &str, value: Value) -> Keyword {
+            Keyword::Value(kw.to_string(), value, String::new())
+        }
 
-impl BasicFits {
-    pub fn from_bytes<'a>(bytes: Vec<u8>) -> Option<Self> {
-        let (header, data) = parsing::read_fits_buffer(&bytes)?;
-        let data = data.unwrap_or(GenericData::zeros(Vec::new()));
-        let fits = BasicFits { header, data };
-        Some(fits)
-    }
+        fn table_header() -> Header {
+            Header::from_keyword_list(vec![
+                card("SIMPLE", Value::Boolean(true)),
+                card("BITPIX", Value::Integer(8)),
+                card("NAXIS", Value::Integer(0)),
+                card("TTYPE1", Value::Str("ID".to_string())),
+                card("TTYPE2", Value::Str("FLUX    ".to_string())),
+                card("TSCAL2", Value::Float(0.5)),
+                card("TZERO2", Value::Integer(100)),
+            ])
+            .unwrap()
+        }
 
-    pub fn open<'a>(filename: &String) -> Option<Self> {
-        let mut f = File::open(filename).ok()?;
-        let mut buffer = Vec::new();
+        #[test]
+        fn from_fits_text_test() {
+            let records = [
+                "SIMPLE  =                    T / conforms to FITS standard",
+                "BITPIX  =                  -64 / array data type",
+                "NAXIS   =                    2",
+                "NAXIS1  =                   10",
+                "NAXIS2  =                   20",
+                "OBJECT  = 'M31     '",
+            ];
+            let header = Header::from_fits_text(&records.join("\n")).unwrap();
+            assert_eq!(header.axes, vec![10, 20]);
+            assert_eq!(header.get_str("OBJECT"), Some("M31"));
+            assert_eq!(
+                header.get_comment_at_keyword("SIMPLE"),
+                Some("conforms to FITS standard")
+            );
 
-        if let Ok(_) = f.read_to_end(&mut buffer) {
-            Self::from_bytes(buffer) // TODO: Check if this is good?
-            // let (header, data) = parsing::read_fits_buffer(&buffer)?;
-            // let data = data.unwrap_or(Tensor::new());
-            // let fits = BasicFits { header, data };
-            // Some(fits)
-        } else {
-            None
+            let padded: Vec<String> = records.iter().map(|r| format!("{:80}", r)).collect();
+            let concatenated = padded.concat() + "END";
+            let header = Header::from_fits_text(&concatenated).unwrap();
+            assert_eq!(header.axes, vec![10, 20]);
+            assert_eq!(header.keywords.len(), 6);
+
+            let too_long = format!("{}\n{:81}", records[0], "COMMENT");
+            assert!(Header::from_fits_text(&too_long).is_err());
+            assert!(Header::from_fits_text("NAXIS   =                  1.2.3\n").is_err());
+            assert!(Header::from_fits_text("").is_err());
+        }
+
+        #[test]
+        fn display_test() {
+            let records = [
+                "SIMPLE  =                    T / conforms to FITS standard",
+                "BITPIX  =                  -64 / array data type",
+                "NAXIS   =                    1",
+                "NAXIS1  =                   10",
+                "OBJECT  = 'M31'",
+                "EXPTIME =                 30.0",
+                "HISTORY reduced",
+            ];
+            let header = Header::from_fits_text(&records.join("\n")).unwrap();
+
+            let text = header.to_string();
+            let expected: Vec<String> = records
+                .iter()
+                .chain(["END"].iter())
+                .map(|r| format!("{:80}", r))
+                .collect();
+            assert_eq!(text, expected.concat());
+            assert_eq!(format!("{:#}", header), expected.join("\n") + "\n");
+
+            assert_eq!(Header::from_fits_text(&text).unwrap(), header);
+        }
+
+        #[test]
+        fn get_comment_at_keyword_test() {
+            let mut header = table_header();
+            header.keywords.push(Keyword::Value(
+                "EXPTIME".to_string(),
+                Value::Float(300.),
+                "[s] exposure time".to_string(),
+            ));
+            header.keywords.push(Keyword::Comment("EXPTIME is in seconds".to_string()));
+
+            assert_eq!(header.get_comment_at_keyword("EXPTIME"), Some("[s] exposure time"));
+            assert_eq!(header.get_comment_at_keyword("SIMPLE"), Some(""));
+            assert_eq!(header.get_comment_at_keyword("OBJECT"), None);
+        }
+
+        #[test]
+        fn wcs_frame_test() {
+            let mut header = table_header();
+            assert_eq!(header.wcs_equinox(), None);
+            assert_eq!(header.wcs_radesys(), None);
+            assert_eq!(header.epoch_of_observation(), None);
+
+            let date = "2000-01-01T12:00:00".to_string();
+            header.keywords.extend([
+                card("EQUINOX", Value::Integer(2000)),
+                card("RADESYS", Value::Str("FK5     ".to_string())),
+                card("DATE-OBS", Value::Str(date)),
+            ]);
+            assert_eq!(header.wcs_equinox(), Some(2000.));
+            assert_eq!(header.wcs_radesys(), Some("FK5".to_string()));
+            assert_eq!(header.epoch_of_observation(), Some(2000.));
+
+            header.keywords.push(card("EPOCH", Value::Float(1950.)));
+            assert_eq!(header.epoch_of_observation(), Some(1950.));
+        }
+
+        #[test]
+        fn equinox_year_test() {
+            let mut header = table_header();
+            assert_eq!(header.equinox_year(), 2000.);
+            assert_eq!(header.radesys_str(), "ICRS");
+
+            header.keywords.push(card("EPOCH", Value::Integer(1950)));
+            assert_eq!(header.equinox_year(), 1950.);
+            assert_eq!(header.radesys_str(), "FK4");
+
+            header.keywords.push(card("EQUINOX", Value::Float(1990.)));
+            assert_eq!(header.equinox_year(), 1990.);
+            assert_eq!(header.radesys_str(), "FK5");
+            header.set_value("EQUINOX", Value::Float(1984.), "").unwrap();
+            assert_eq!(header.radesys_str(), "FK5");
+            header.set_value("EQUINOX", Value::Float(2000.), "").unwrap();
+            assert_eq!(header.radesys_str(), "FK5");
+
+            header
+                .keywords
+                .push(card("RADESYS", Value::Str("FK4 ".to_string())));
+            assert_eq!(header.radesys_str(), "FK4");
+        }
+
+        #[test]
+        fn equatorial_coords_test() {
+            let mut header = table_header();
+            assert_eq!(header.equatorial_coords(), None);
+
+            header.keywords.extend([
+                card("CRVAL1", Value::Float(222.3)),
+                card("CRVAL2", Value::Integer(40)),
+            ]);
+            assert_eq!(header.equatorial_coords(), Some((222.3, 40.)));
+
+            header.keywords.extend([
+                card("OBJCTRA", Value::Str("14 49 09.474        ".to_string())),
+                card("OBJCTDEC", Value::Str("+40 42 04.35        ".to_string())),
+            ]);
+            let (ra, dec) = header.equatorial_coords().unwrap();
+            assert!((ra - 222.289475).abs() < 1e-9);
+            assert!((dec - 40.701208333).abs() < 1e-9);
+        }
+
+        #[test]
+        fn parse_sexagesimal_test() {
+            assert_eq!(parse_sexagesimal("-00 30 00"), Some(-0.5));
+            assert_eq!(parse_sexagesimal("12:30:36"), Some(12.51));
+            assert_eq!(parse_sexagesimal("12 30"), None);
+            assert_eq!(parse_sexagesimal("12 60 00"), None);
+            assert_eq!(parse_sexagesimal("12 30 00 1"), None);
+        }
+
+        #[test]
+        fn julian_year_test() {
+            assert_eq!(julian_year("2000-01-01T12:00:00"), Some(2000.));
+            assert_eq!(julian_year("2000-01-01T12"), None);
+            assert_eq!(julian_year("2000-13-01"), None);
+            assert_eq!(julian_year("01/01/00"), None);
+
+            let year = julian_year("2024-07-02").unwrap();
+            assert!((year - 2024.5).abs() < 0.01);
+            let noon = julian_year("2024-07-02T12:00").unwrap();
+            assert!((noon - year - 0.5 / 365.25).abs() < 1e-9);
         }
+
+        #[test]
+        fn set_batch_test() {
+            let updates = [
+                ("TSCAL2", Value::Float(2.), "scale"),
+                ("EXPTIME", Value::Float(30.), "[s]"),
+                ("TTYPE1", Value::Str("SOURCE_ID".to_string()), ""),
+                ("AIRMASS", Value::Float(1.2), ""),
+                ("EXPTIME", Value::Float(60.), "[s] total"),
+            ];
+            let mut batch = table_header();
+            assert_eq!(batch.set_batch(&updates), 2);
+
+            let mut sequential = table_header();
+            for (name, value, comment) in updates.iter() {
+                set_keyword(&mut sequential.keywords, name, value.clone(), Some(comment));
+            }
+
+            let cards = |header: &Header| -> Vec<(String, Value, String)> {
+                let cards = header.keywords.iter().filter_map(|kw| match kw {
+                    Keyword::Value(k, v, c) => Some((k.clone(), v.clone(), c.clone())),
+                    _ => None,
+                });
+                cards.collect()
+            };
+            assert_eq!(cards(&batch), cards(&sequential));
+            assert_eq!(batch.keywords.len(), 9);
+            assert_eq!(batch.get_value("EXPTIME"), Some(&Value::Float(60.)));
+        }
+
+        #[test]
+        fn drop_undefined_test() {
+            let mut header = table_header();
+            header.keywords.extend([
+                card("OBSERVER", Value::Undefined),
+                Keyword::Comment(String::new()),
+                card("FILTER", Value::Undefined),
+            ]);
+            assert_eq!(header.undefined_keywords(), ["OBSERVER", "FILTER"]);
+
+            assert_eq!(header.drop_undefined(), 2);
+            assert!(header.undefined_keywords().is_empty());
+            assert_eq!(header.keywords.len(), 8);
+            assert_eq!(header.drop_undefined(), 0);
+        }
+
+        #[test]
+        fn sort_keywords_test() {
+            let mut header = table_header();
+            header.keywords.extend([
+                Keyword::History("first".to_string()),
+                card("NAXIS1", Value::Integer(0)),
+                Keyword::History("second".to_string()),
+                card("AIRMASS", Value::Float(1.02)),
+            ]);
+            header.keywords.insert(0, card("NAXIS2", Value::Integer(0)));
+
+            header.sort_keywords(true);
+            let names: Vec<&str> = header.keywords.iter().map(Keyword::keyword_name).collect();
+            assert_eq!(
+                names,
+                [
+                    "SIMPLE", "BITPIX", "NAXIS", "NAXIS1", "NAXIS2", "AIRMASS", "HISTORY",
+                    "HISTORY", "TSCAL2", "TTYPE1", "TTYPE2", "TZERO2"
+                ]
+            );
+            // Stable: the HISTORY cards are in their original order
+            assert!(matches!(&header.keywords[6], Keyword::History(h) if h == "first"));
+            assert!(matches!(&header.keywords[7], Keyword::History(h) if h == "second"));
+
+            header.sort_keywords(false);
+            assert_eq!(header.keywords[0].keyword_name(), "AIRMASS");
+            assert_eq!(header.keywords[1].keyword_name(), "BITPIX");
+        }
+
+        #[test]
+        fn rename_keyword_test() {
+            let mut header = table_header();
+            header.keywords.push(card("AIRMASS", Value::Float(1.02)));
+
+            header.rename_keyword("AIRMASS", "SECZ").unwrap();
+            assert_eq!(header.get_value("SECZ"), Some(&Value::Float(1.02)));
+            assert_eq!(header.get_value("AIRMASS"), None);
+
+            assert!(matches!(
+                header.rename_keyword("AIRMASS", "SECZ"),
+                Err(FitsError::KeywordNotFound(_))
+            ));
+            for name in ["secz", "TOOLONGNAME", "SEC Z", "SEC.Z", ""] {
+                assert!(matches!(
+                    header.rename_keyword("SECZ", name),
+                    Err(FitsError::InvalidKeywordName(_))
+                ));
+            }
+            assert!(matches!(
+                header.rename_keyword("NAXIS", "AXES"),
+                Err(FitsError::MandatoryKeyword(_))
+            ));
+            assert!(matches!(
+                header.rename_keyword("SECZ", "NAXIS1"),
+                Err(FitsError::MandatoryKeyword(_))
+            ));
+            header.rename_keyword("SECZ", "SEC-Z_1").unwrap();
+        }
+
+        #[test]
+        fn modify_keywords_test() {
+            let mut header = Header::from_fits_text(
+                "SIMPLE  =                    T\n\
+                 BITPIX  =                  -64\n\
+                 NAXIS   =                    2\n\
+                 NAXIS1  =                   10\n\
+                 NAXIS2  =                   20\n\
+                 OBJECT  = 'M31'\n",
+            )
+            .unwrap();
+
+            header
+                .insert(card("FILTER", Value::Str("V".into())))
+                .unwrap();
+            assert_eq!(header.keywords.last().unwrap().keyword_name(), "FILTER");
+            assert!(matches!(
+                header.insert(card("NAXIS3", Value::Integer(1))),
+                Err(FitsError::MandatoryKeyword(_))
+            ));
+            assert!(matches!(
+                header.insert(card("filter", Value::Str("V".into()))),
+                Err(FitsError::InvalidKeywordName(_))
+            ));
+
+            header
+                .set_value("OBJECT", Value::Str("M33".into()), "target")
+                .unwrap();
+            assert_eq!(header.get_str("OBJECT"), Some("M33"));
+            assert_eq!(header.get_comment("OBJECT"), Some("target"));
+            header.set_value("EXPTIME", Value::Float(30.), "").unwrap();
+            assert_eq!(header.keywords.len(), 8);
+
+            header.set_value("BITPIX", Value::Integer(16), "").unwrap();
+            assert_eq!(header.bitpix, Bitpix::Int16);
+            assert_eq!(header.get_int("BITPIX"), Some(16));
+            header.set_value("NAXIS2", Value::Integer(5), "").unwrap();
+            assert_eq!(header.axes, [10, 5]);
+            header
+                .set_value("SIMPLE", Value::Boolean(false), "")
+                .unwrap();
+            assert!(!header.simple);
+
+            assert!(matches!(
+                header.set_value("BITPIX", Value::Integer(12), ""),
+                Err(FitsError::InvalidBitpix(12))
+            ));
+            for (key, value) in [
+                ("BITPIX", Value::Float(16.)),
+                ("SIMPLE", Value::Integer(1)),
+                ("NAXIS", Value::Integer(3)),
+                ("NAXIS1", Value::Integer(-1)),
+                ("NAXIS3", Value::Integer(1)),
+            ] {
+                assert!(matches!(
+                    header.set_value(key, value, ""),
+                    Err(FitsError::MandatoryKeyword(_))
+                ));
+            }
+            assert_eq!(header.bitpix, Bitpix::Int16);
+            assert_eq!(header.axes, [10, 5]);
+
+            let removed = header.remove("OBJECT").unwrap().unwrap();
+            assert_eq!(removed.keyword_name(), "OBJECT");
+            assert!(!header.contains("OBJECT"));
+            assert!(header.remove("OBJECT").unwrap().is_none());
+            assert!(matches!(
+                header.remove("NAXIS1"),
+                Err(FitsError::MandatoryKeyword(_))
+            ));
+        }
+
+        #[test]
+        fn physical_value_test() {
+            let header = table_header();
+            assert_eq!(header.column_index_by_name("ID"), Some(1));
+            assert_eq!(header.column_index_by_name("flux"), Some(2));
+            assert_eq!(header.column_index_by_name("MAG"), None);
+
+            assert_eq!(header.get_physical_value("FLUX", 10.), Some(105.));
+            // No TSCAL/TZERO means the identity transform
+            assert_eq!(header.get_physical_value("ID", 10.), Some(10.));
+            assert_eq!(header.get_physical_value("MAG", 10.), None);
+        }
+
+        #[test]
+        fn get_comment_test() {
+            let header = Header::from_fits_text(
+                "SIMPLE  =                    T / conforms to FITS standard\n\
+                 BITPIX  =                  -64\n\
+                 NAXIS   =                    0\n\
+                 DATE-OBS= '2020/04/18'         / date / time\n\
+                 HIERARCH ESO TEL AIRM START = 1.25 / airmass\n",
+            )
+            .unwrap();
+            assert_eq!(
+                header.get_comment("SIMPLE"),
+                Some("conforms to FITS standard")
+            );
+            assert_eq!(header.get_comment("BITPIX"), Some(""));
+            assert_eq!(header.get_str("DATE-OBS"), Some("2020/04/18"));
+            assert_eq!(header.get_comment("DATE-OBS"), Some("date / time"));
+            assert_eq!(
+                header.get_comment("HIERARCH ESO TEL AIRM START"),
+                Some("airmass")
+            );
+            assert_eq!(header.get_comment("EXPTIME"), None);
+        }
+
+        #[test]
+        fn get_hierarch_test() {
+            let header = Header::from_fits_text(
+                "SIMPLE  =                    T\n\
+                 BITPIX  =                  -64\n\
+                 NAXIS   =                    0\n\
+                 HIERARCH ESO TEL AIRM START = 1.25\n",
+            )
+            .unwrap();
+            assert_eq!(header.get("NAXIS"), Some(&Value::Integer(0)));
+            assert_eq!(header.get("ESO TEL AIRM START"), Some(&Value::Float(1.25)));
+            assert_eq!(
+                header.get("HIERARCH ESO TEL AIRM START"),
+                Some(&Value::Float(1.25))
+            );
+            assert_eq!(header.get_float("ESO TEL AIRM START"), Some(1.25));
+            assert_eq!(header.get("ESO TEL AIRM END"), None);
+        }
+
+        #[test]
+        fn typed_getters_test() {
+            let header = Header::from_fits_text(
+                "SIMPLE  =                    T\n\
+                 BITPIX  =                  -64\n\
+                 NAXIS   =                    0\n\
+                 OBJECT  = 'M31'\n\
+                 EXPTIME =                 30.5\n",
+            )
+            .unwrap();
+            assert_eq!(header.get_int("NAXIS"), Some(0));
+            assert_eq!(header.get_int("EXPTIME"), None);
+            assert_eq!(header.get_float("EXPTIME"), Some(30.5));
+            assert_eq!(header.get_float("NAXIS"), Some(0.));
+            assert_eq!(header.get_str("OBJECT"), Some("M31"));
+            assert_eq!(header.get_bool("SIMPLE"), Some(true));
+            assert_eq!(header.get_bool("OBJECT"), None);
+
+            assert!(header.contains("OBJECT"));
+            assert!(!header.contains("FILTER"));
+            assert_eq!(header.keywords_iter().count(), 5);
+        }
+
+        #[cfg(feature = "serde")]
+        #[test]
+        fn serde_test() {
+            let mut header = Header::from_fits_text(
+                "SIMPLE  =                    T\n\
+                 BITPIX  =                  -64\n\
+                 NAXIS   =                    1\n\
+                 NAXIS1  =                   10 / length\n\
+                 OBJECT  = 'M31'\n\
+                 GAIN    =                 1.5E-10\n\
+                 CGAIN   =          (1.5, -2.0)\n\
+                 CNOISE  =               (1, 2)\n\
+                 OBSERVER=\n\
+                 COMMENT first\n\
+                 COMMENT second\n\
+                 HIERARCH ESO DET WIN NX = 512\n",
+            )
+            .unwrap();
+            header.add_history("reduced");
+
+            let json = serde_json::to_string(&header).unwrap();
+            assert!(json.starts_with(r#"[{"name":"SIMPLE","value":true,"comment":""}"#));
+            assert!(json.contains(r#"{"name":"NAXIS1","value":10,"comment":"length"}"#));
+            assert!(json.contains(r#"{"name":"OBSERVER","value":null,"comment":""}"#));
+            assert!(json.contains(r#"{"name":"CGAIN","value":[1.5,-2.0],"comment":""}"#));
+            assert!(json.contains(r#"{"name":"COMMENT","value":null,"comment":"second"}"#));
+            assert!(json.contains(r#"{"name":"HIERARCH ESO DET WIN NX","value":512"#));
+            assert_eq!(serde_json::from_str::<Header>(&json).unwrap(), header);
+
+            let missing = r#"[{"name": "SIMPLE", "value": true}, {"name": "NAXIS", "value": 0}]"#;
+            assert!(serde_json::from_str::<Header>(missing).is_err());
+            let comment = r#"{"name": "COMMENT", "comment": "text"}"#;
+            assert_eq!(
+                serde_json::from_str::<Keyword>(comment).unwrap(),
+                Keyword::Comment("text".to_string())
+            );
+        }
+
+        #[test]
+        fn for_image_test() {
+            let header = Header::for_image(Bitpix::Int16, &[10, 20]);
+            let names: Vec<&str> = header.keywords.iter().map(Keyword::keyword_name).collect();
+            assert_eq!(names, ["SIMPLE", "BITPIX", "NAXIS", "NAXIS1", "NAXIS2"]);
+            assert_eq!(header.axes, [10, 20]);
+            assert_eq!(header.get_int("BITPIX"), Some(16));
+            assert!(header.validate().is_empty());
+
+            let extra = vec![
+                ("EXPTIME".to_string(), Value::Float(30.), "s".to_string()),
+                (
+                    "OBJECT".to_string(),
+                    Value::Str("M31".to_string()),
+                    String::new(),
+                ),
+            ];
+            let header = Header::for_image_with_keywords(Bitpix::Float32, &[], extra);
+            let names: Vec<&str> = header.keywords.iter().map(Keyword::keyword_name).collect();
+            assert_eq!(names, ["SIMPLE", "BITPIX", "NAXIS", "EXPTIME", "OBJECT"]);
+            assert_eq!(header.naxis(), 0);
+            assert_eq!(header.get_comment("EXPTIME"), Some("s"));
+        }
+
+        #[test]
+        #[should_panic]
+        fn for_image_invalid_test() {
+            let extra = vec![("NAXIS".to_string(), Value::Integer(3), String::new())];
+            Header::for_image_with_keywords(Bitpix::Int8, &[2], extra);
+        }
+
+        #[test]
+        fn summary_test() {
+            let header = Header::from_fits_text(
+                "SIMPLE  =                    T\n\
+                 BITPIX  =                  -64\n\
+                 NAXIS   =                    2\n\
+                 NAXIS1  =                  100\n\
+                 NAXIS2  =                  200\n\
+                 OBJECT  = 'M31     '\n\
+                 FILTER  =\n\
+                 EXPTIME =                  300\n\
+                 DEC     =                41.27\n",
+            )
+            .unwrap();
+            assert_eq!(
+                header.summary(),
+                "Type     : standard FITS\n\
+                 BITPIX   : -64 (64-bit IEEE floating point)\n\
+                 Shape    : 100 × 200\n\
+                 OBJECT   : M31\n\
+                 EXPTIME  : 300\n\
+                 DEC      : 41.27\n"
+            );
+
+            let mut extension = Header::for_image(Bitpix::Int16, &[]);
+            extension.keywords[0] = Keyword::Value(
+                "XTENSION".to_string(),
+                Value::Str("IMAGE   ".to_string()),
+                String::new(),
+            );
+            let summary = extension.summary();
+            assert!(summary.starts_with("Type     : IMAGE extension\nBITPIX   : 16 (16-bit"));
+            assert!(summary.ends_with("Shape    : no data\n"));
+        }
+
+        #[test]
+        fn naxis_test() {
+            let header = Header::from_fits_text(
+                "SIMPLE  =                    T\n\
+                 BITPIX  =                    8\n\
+                 NAXIS   =                    2\n\
+                 NAXIS1  =                   10\n\
+                 NAXIS2  =                   20\n",
+            )
+            .unwrap();
+            assert_eq!(header.naxis(), 2);
+            assert_eq!(header.ndim(), 2);
+
+            assert_eq!(header.get_naxisn(1), Some(10));
+            assert_eq!(header.get_naxisn(2), Some(20));
+            assert_eq!(header.get_naxisn(0), None);
+            assert_eq!(header.get_naxisn(3), None);
+
+            // NAXIS promises an axis that is missing
+            let truncated = Header::from_fits_text(
+                "SIMPLE  =                    T\n\
+                 BITPIX  =                    8\n\
+                 NAXIS   =                    3\n\
+                 NAXIS1  =                   10\n\
+                 NAXIS2  =                   20\n",
+            );
+            assert!(matches!(
+                truncated,
+                Err(FitsError::MissingKeyword("NAXIS3"))
+            ));
+        }
+
+        #[test]
+        fn value_keywords_test() {
+            let header = Header::from_fits_text(
+                "SIMPLE  =                    T\n\
+                 BITPIX  =                    8\n\
+                 NAXIS   =                    2 / dimensions\n\
+                 NAXIS1  =                   10\n\
+                 NAXIS2  =                   20\n\
+                 COMMENT naxis\n\
+                 HIERARCH ESO DET NAXIS = 3\n",
+            )
+            .unwrap();
+            assert_eq!(header.iter().count(), 7);
+            assert_eq!(header.value_keywords().count(), 6);
+            assert_eq!(
+                header.value_keywords().nth(2),
+                Some(("NAXIS", &Value::Integer(2), "dimensions"))
+            );
+
+            let names: Vec<&str> = header
+                .keywords_with_prefix("naxis")
+                .map(|kw| kw.0)
+                .collect();
+            assert_eq!(names, ["NAXIS", "NAXIS1", "NAXIS2"]);
+            assert_eq!(header.keywords_with_prefix("ESO DET").count(), 1);
+            assert_eq!(header.keywords_with_prefix("").count(), 6);
+            assert_eq!(header.keywords_with_prefix("NAXIS12").count(), 0);
+        }
+
+        #[test]
+        fn comments_history_test() {
+            let mut header = Header::from_fits_text(
+                "SIMPLE  =                    T\n\
+                 BITPIX  =                    8\n\
+                 NAXIS   =                    0\n\
+                 COMMENT first comment\n\
+                 HISTORY created\n\
+                 COMMENT second comment\n",
+            )
+            .unwrap();
+            let comments: Vec<&str> = header.comments().collect();
+            assert_eq!(comments, ["first comment", "second comment"]);
+            assert_eq!(header.history().collect::<Vec<_>>(), ["created"]);
+
+            let long = "lorem ipsum ".repeat(10);
+            header.add_comment(&long);
+            header.add_history("reduced");
+            header.add_history("");
+            let comments: Vec<&str> = header.comments().skip(2).collect();
+            assert_eq!(comments.len(), 2);
+            assert!(comments.iter().all(|c| c.len() <= 72 && !c.ends_with(' ')));
+            assert_eq!(comments.join(" "), long.trim());
+            assert_eq!(
+                header.history().collect::<Vec<_>>(),
+                ["created", "reduced", ""]
+            );
+
+            assert_eq!(wrap_text(&"x".repeat(150), 72).len(), 3);
+            assert_eq!(wrap_text("a b  c", 3), ["a b", "c"]);
+        }
+
+        #[test]
+        fn index_test() {
+            let header = Header::from_fits_text(
+                "SIMPLE  =                    T\n\
+                 BITPIX  =                   16\n\
+                 NAXIS   =                    1\n\
+                 NAXIS1  =                   10\n\
+                 OBJECT  = 'M31'\n\
+                 OBJECT  = 'M32'\n",
+            )
+            .unwrap();
+            assert_eq!(header["NAXIS1"], Value::Integer(10));
+            assert_eq!(header["OBJECT"], Value::Str("M31".to_string()));
+            assert_eq!(header.get("OBJECT"), Some(&header["OBJECT"]));
+        }
+
+        #[test]
+        #[should_panic(expected = "keyword EXPTIME not found")]
+        fn index_missing_test() {
+            let header = Header::from_fits_text("SIMPLE  = T\nBITPIX  = 8\nNAXIS   = 0\n").unwrap();
+            let _ = &header["EXPTIME"];
+        }
+
+        #[test]
+        fn validate_test() {
+            let mut header = Header::from_fits_text(
+                "SIMPLE  =                    T\n\
+                 BITPIX  =                  -64\n\
+                 NAXIS   =                    1\n\
+                 NAXIS1  =                   10\n\
+                 OBJECT  = 'M31'\n",
+            )
+            .unwrap();
+            assert!(header.validate().is_empty());
+
+            let long = "x".repeat(70);
+            header.keywords.extend([
+                card("NAXIS2", Value::Integer(5)),
+                card("LONGVAL", Value::Str(long.clone())),
+                card("obs-date", Value::Str("2020".into())),
+                card("END", Value::Undefined),
+                card("", Value::Undefined),
+            ]);
+            set_keyword(&mut header.keywords, "BITPIX", Value::Integer(12), None);
+            header.keywords.remove(0);
+            assert_eq!(
+                header.validate(),
+                [
+                    FitsValidationWarning::MissingSimple,
+                    FitsValidationWarning::InvalidBitpix("12".to_string()),
+                    FitsValidationWarning::UnexpectedAxis(2),
+                    FitsValidationWarning::LongString {
+                        keyword: "LONGVAL".to_string(),
+                        length: 70
+                    },
+                    FitsValidationWarning::InvalidKeywordName("obs-date".to_string()),
+                    FitsValidationWarning::MisplacedEnd(7),
+                ]
+            );
+
+            header
+                .keywords
+                .push(card("LONGSTRN", Value::Str("OGIP 1.0".into())));
+            set_keyword(&mut header.keywords, "NAXIS", Value::Integer(3), None);
+            let warnings = header.validate();
+            assert!(warnings.contains(&FitsValidationWarning::MissingAxis(3)));
+            assert!(!warnings
+                .iter()
+                .any(|w| matches!(w, FitsValidationWarning::LongString { .. })));
+            assert_eq!(
+                FitsValidationWarning::MissingAxis(3).to_string(),
+                "NAXIS3 is missing or invalid"
+            );
+
+            header.keywords.retain(|kw| kw.keyword_name() != "NAXIS");
+            assert!(header
+                .validate()
+                .contains(&FitsValidationWarning::MissingKeyword("NAXIS")));
+        }
+
+        #[test]
+        fn column_descriptors_test() {
+            let mut header = table_header();
+            assert!(header.column_descriptors().is_empty());
+
+            header.keywords.extend([
+                card("TFIELDS", Value::Integer(3)),
+                card("TFORM1", Value::Str("1J      ".to_string())),
+                card("TNULL1", Value::Integer(-1)),
+                card("TFORM2", Value::Str("E".to_string())),
+                card("TUNIT2", Value::Str("Jy".to_string())),
+                card("TFORM3", Value::Str("1Z".to_string())),
+            ]);
+            let columns = header.column_descriptors();
+            assert_eq!(
+                columns,
+                vec![
+                    ColumnDescriptor {
+                        index: 1,
+                        name: "ID".to_string(),
+                        format: TForm::Fixed(1, TFormType::J),
+                        unit: None,
+                        null_value: Some(-1),
+                        scale: None,
+                        zero: None,
+                    },
+                    ColumnDescriptor {
+                        index: 2,
+                        name: "FLUX".to_string(),
+                        format: TForm::Fixed(1, TFormType::E),
+                        unit: Some("Jy".to_string()),
+                        null_value: None,
+                        scale: Some(0.5),
+                        zero: Some(100.),
+                    },
+                ]
+            );
+        }
+    }
+}
+
+// Only basic FITS file for now, i.e. with one HDU
+#[derive(Debug, Clone, PartialEq)]
+pub struct BasicFits {
+    pub header: Header,
+    /// The data, shared between clones of this file, see [`BasicFits::data_arc`].
+    pub data: Arc<DataArray>,
+}
+
+impl BasicFits {
+    /// Combine a header and data into a file, checking that they match: the type of the
+    /// data must be the one of BITPIX, and there must be a value for every pixel of the
+    /// NAXISn axes. Data with more than one dimension must have the shape of the axes in
+    /// reverse, e.g. (NAXIS2, NAXIS1), as with [`BasicFits::reshape_to_2d`].
+    pub fn create(header: Header, data: DataArray) -> Result<Self, FitsError> {
+        if data.bitpix() != header.bitpix {
+            return Err(FitsError::BitpixMismatch {
+                expected: header.bitpix.to_int(),
+                found: data.bitpix().to_int(),
+            });
+        }
+
+        let size = if header.axes.is_empty() {
+            0
+        } else {
+            header.axes.iter().product()
+        };
+        let reversed: Vec<usize> = header.axes.iter().rev().copied().collect();
+        let shape = data.shape();
+        if data.len() != size || (shape.len() > 1 && shape != reversed) {
+            return Err(FitsError::ShapeMismatch {
+                expected: reversed,
+                found: shape.to_vec(),
+            });
+        }
+        Ok(BasicFits {
+            header,
+            data: Arc::new(data),
+        })
+    }
+
+    pub fn from_bytes<'a>(bytes: Vec<u8>) -> Result<Self, FitsError> {
+        Self::from_slice(&bytes)
+    }
+
+    /// Read the primary HDU from bytes that are already in memory, e.g. a memory mapped or
+    /// shared buffer, without copying them first.
+    pub fn from_slice(bytes: &[u8]) -> Result<Self, FitsError> {
+        let (header, data) = parsing::read_fits_buffer(bytes)?;
+        Ok(BasicFits {
+            header,
+            data: Arc::new(data),
+        })
+    }
+
+    /// Open a FITS file. With the gzip feature, gzip compressed files (e.g. `.fits.gz`) are
+    /// decompressed while reading.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, FitsError> {
+        let path = path.as_ref();
+        let gzip = path.extension().is_some_and(|ext| ext == "gz");
+        let file = BufReader::new(File::open(path)?);
+        Self::read_stream(io::FitsStream::new(file, gzip)?)
+    }
+
+    /// Read the primary HDU from `reader`, e.g. stdin or a network stream. The header is
+    /// parsed one block at a time, after which only the data of the primary HDU is read.
+    /// Like [`BasicFits::from_bytes`], a missing padding after the data is accepted.
+    ///
+    /// With the gzip feature, a gzip compressed stream is decompressed while reading.
+    pub fn from_reader<R: Read>(reader: R) -> Result<Self, FitsError> {
+        Self::read_stream(io::FitsStream::new(reader, false)?)
+    }
+
+    fn read_stream<R: Read>(mut reader: R) -> Result<Self, FitsError> {
+        let mut parser = HeaderParser::new();
+        let mut block = [0u8; definitions::BLOCK_SIZE];
+        while parser.state() == HeaderParserState::ReadingBlocks {
+            reader.read_exact(&mut block).map_err(io::eof_error)?;
+            parser.push_block(&block);
+        }
+        let header = Header::from_keyword_list(parser.finish()?)?;
+
+        // The buffer grows with what is actually read, rather than trusting the header size
+        let size = io::data_size(&header)?;
+        let mut data = Vec::new();
+        let read = (&mut reader)
+            .take(size as u64)
+            .read_to_end(&mut data)
+            .map_err(io::eof_error)?;
+        if read < size {
+            return Err(FitsError::UnexpectedEof);
+        }
+        let padding = size.next_multiple_of(definitions::BLOCK_SIZE) - size;
+        std::io::copy(&mut reader.take(padding as u64), &mut std::io::sink())?;
+
+        let data = parsing::read_data(&header, &mut data.chunks(definitions::BLOCK_SIZE))?;
+        Ok(BasicFits {
+            header,
+            data: Arc::new(data),
+        })
+    }
+
+    /// Serialize to the FITS format: the header records followed by the data in big endian
+    /// order, both padded to a multiple of 2880 bytes.
+    ///
+    /// The header is written as is, so its BITPIX and NAXISn should match the data.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = writing::header_to_bytes(&self.header.keywords);
+        bytes.extend(writing::data_to_bytes(&self.data));
+        bytes
+    }
+
+    /// Write the file to `writer`, see [`BasicFits::to_bytes`].
+    ///
+    /// Fails with [`FitsError::InvalidKeywordName`] if a keyword has a name that can not be
+    /// written, before anything is written.
+    pub fn write<W: Write>(&self, writer: &mut W) -> Result<(), FitsError> {
+        writing::check_keyword_names(&self.header.keywords)?;
+        writer.write_all(&self.to_bytes())?;
+        Ok(())
+    }
+
+    /// Open a file with a header that does not fully follow the standard. Records that can
+    /// not be parsed are kept as COMMENT records, and returned as errors alongside the file.
+    pub fn open_lenient(path: impl AsRef<Path>) -> Result<(Self, Vec<ParseError>), FitsError> {
+        let mut f = File::open(path)?;
+        let (keywords, errors) = parsing::header::parse_header_lenient(&mut f);
+        let header = Header::from_keyword_list(keywords)?;
+
+        let mut buffer = Vec::new();
+        f.read_to_end(&mut buffer)?;
+        let data = parsing::read_data(&header, &mut buffer.chunks(definitions::BLOCK_SIZE))?;
+        let fits = BasicFits {
+            header,
+            data: Arc::new(data),
+        };
+        Ok((fits, errors))
+    }
+
+    /// Open part of a 2D image, given in image section notation, e.g. `[1:512,1:512]` or
+    /// `[*,100:200:2]` (see [`parsing::section::parse_section`]). Only the section is read
+    /// from the file. The data is the physical values as f64, so BITPIX becomes -64 and
+    /// BSCALE, BZERO and BLANK are dropped. NAXISn and CRPIXn are updated to the section,
+    /// and for a step other than 1 the pixel scale: the CDi_j and PCi_j of that axis, or
+    /// CDELTn when there is no PC matrix.
+    pub fn open_section(path: impl AsRef<Path>, section: &str) -> Result<Self, FitsError> {
+        let sections = parsing::section::parse_section(section)?;
+        let mut file = io::FitsFile::open(path)?;
+        let axes = &file.header().axes;
+        let &[nx, ny] = &axes[..] else {
+            return Err(FitsError::InvalidNaxis(axes.len()));
+        };
+        let [sx, sy] = sections[..] else {
+            return Err(FitsError::InvalidSection(section.to_string()));
+        };
+        let (x, step_x) = sx.range(nx)?;
+        let (y, step_y) = sy.range(ny)?;
+
+        let cutout = file.read_cutout(x.clone(), y.clone())?;
+        let cutout = cutout.slice(ndarray::s![..;step_y, ..;step_x]).to_owned();
+
+        use parsing::header::{set_keyword, Keyword, Value};
+        let mut keywords = file.header().keywords.clone();
+        keywords.retain(|kw| !matches!(kw.keyword_name(), "BSCALE" | "BZERO" | "BLANK"));
+        set_keyword(&mut keywords, "BITPIX", Value::Integer(-64), None);
+        for (key, n) in [("NAXIS1", cutout.ncols()), ("NAXIS2", cutout.nrows())] {
+            set_keyword(&mut keywords, key, Value::Integer(n as i64), None);
+        }
+        let mut update = |key: &str, f: &dyn Fn(f64) -> f64| {
+            let value = keywords.iter_mut().find_map(|kw| match kw {
+                Keyword::Value(k, v, _c) if k == key => Some(v),
+                _ => None,
+            });
+            if let Some(v) = value {
+                *v = Value::Float(f(v.as_f64_lossy()));
+            }
+        };
+        let has_pc = file.header().keywords.iter().any(|kw| {
+            let name = kw.keyword_name();
+            name.starts_with("PC") && name.contains('_')
+        });
+        for (j, start, step) in [(1, x.start, step_x), (2, y.start, step_y)] {
+            // The first pixel of the section becomes pixel 1
+            let crpix = |v: f64| (v - start as f64 - 1.) / step as f64 + 1.;
+            update(&format!("CRPIX{}", j), &crpix);
+            // Pixel j of the section spans `step` pixels of the image along axis j
+            let scale = |v: f64| v * step as f64;
+            for i in 1..=2 {
+                update(&format!("CD{}_{}", i, j), &scale);
+                update(&format!("PC{}_{}", i, j), &scale);
+            }
+            if !has_pc {
+                update(&format!("CDELT{}", j), &scale);
+            }
+        }
+        let header = Header::from_keyword_list(keywords)?;
+        Ok(BasicFits {
+            header,
+            data: Arc::new(image::from_array2(cutout).into()),
+        })
+    }
+
+    /// The minimum and maximum of the data, found in a single pass. Both are NaN if the data
+    /// contains a NaN; see [`BasicFits::data_range_nan_aware`] to skip those. Without any data
+    /// this is `(f64::MAX, f64::MIN)`.
+    pub fn data_range(&self) -> (f64, f64) {
+        self.data
+            .as_f64_cow()
+            .fold((f64::MAX, f64::MIN), |(min, max), &x| {
+                if x.is_nan() || min.is_nan() {
+                    (f64::NAN, f64::NAN)
+                } else {
+                    (min.min(x), max.max(x))
+                }
+            })
+    }
+
+    /// Like [`BasicFits::data_range`], but ignoring NaN values.
+    pub fn data_range_nan_aware(&self) -> (f64, f64) {
+        self.data
+            .as_f64_cow()
+            .iter()
+            .filter(|x| !x.is_nan())
+            .fold((f64::MAX, f64::MIN), |(min, max), &x| {
+                (min.min(x), max.max(x))
+            })
+    }
+
+    pub fn data_sum(&self) -> f64 {
+        self.data.as_f64_cow().sum()
+    }
+
+    /// The mean of the data, NaN if there is none.
+    pub fn data_mean(&self) -> f64 {
+        self.data_sum() / self.data.len() as f64
+    }
+
+    /// The data of a 2D image as an array of shape (NAXIS2, NAXIS1), i.e. indexed as `[y, x]`.
+    /// The values are converted to f64, see [`BasicFits::as_array2`] for a view that does
+    /// not copy.
+    pub fn reshape_to_2d(&self) -> Result<Array2<f64>, FitsError> {
+        image::to_array2(&self.data.to_f64(), &self.header.axes)
+            .ok_or(FitsError::InvalidNaxis(self.header.axes.len()))
+    }
+
+    /// A view of the data of a 2D image with shape (NAXIS2, NAXIS1). Only possible without
+    /// copying when the data is stored as f64 (BITPIX -64).
+    pub fn as_array2(&self) -> Result<ArrayView2<'_, f64>, FitsError> {
+        let &[nx, ny] = &self.header.axes[..] else {
+            return Err(FitsError::InvalidNaxis(self.header.axes.len()));
+        };
+        let view = self.f64_view()?;
+        let found = view.shape().to_vec();
+        view.into_shape((ny, nx))
+            .map_err(|_| FitsError::ShapeMismatch {
+                expected: vec![ny, nx],
+                found,
+            })
+    }
+
+    /// A view of the data of a 3D cube with shape (NAXIS3, NAXIS2, NAXIS1), like
+    /// [`BasicFits::as_array2`].
+    pub fn as_array3(&self) -> Result<ArrayView3<'_, f64>, FitsError> {
+        let &[nx, ny, nz] = &self.header.axes[..] else {
+            return Err(FitsError::InvalidNaxis(self.header.axes.len()));
+        };
+        let view = self.f64_view()?;
+        let found = view.shape().to_vec();
+        view.into_shape((nz, ny, nx))
+            .map_err(|_| FitsError::ShapeMismatch {
+                expected: vec![nz, ny, nx],
+                found,
+            })
+    }
+
+    /// Like [`BasicFits::reshape_to_2d`], but consuming the file so f64 data is not copied.
+    ///
+    /// Panics if the data is not a 2D image.
+    pub fn into_array2(self) -> Array2<f64> {
+        let &[nx, ny] = &self.header.axes[..] else {
+            panic!(
+                "expected a 2D image, found NAXIS = {}",
+                self.header.axes.len()
+            );
+        };
+        let data = match Arc::unwrap_or_clone(self.data) {
+            DataArray::F64(d) => d,
+            other => other.to_f64(),
+        };
+        data.into_shape((ny, nx))
+            .expect("data does not match NAXIS1 and NAXIS2")
+    }
+
+    /// The data, only copied if it is still shared with another file or `Arc`.
+    pub fn into_data(self) -> DataArray {
+        Arc::unwrap_or_clone(self.data)
+    }
+
+    /// The data behind an `Arc`, shared instead of copied, so several stages of a pipeline
+    /// can hold the pixels of one file.
+    pub fn data_arc(&self) -> Arc<DataArray> {
+        Arc::clone(&self.data)
+    }
+
+    /// Split into the header and the shared data, without copying either.
+    pub fn into_shared_data(self) -> (Header, Arc<DataArray>) {
+        (self.header, self.data)
+    }
+
+    fn f64_view(&self) -> Result<ArrayViewD<'_, f64>, FitsError> {
+        match self.data.as_f64() {
+            Some(data) => Ok(data.view()),
+            None => Err(FitsError::BitpixMismatch {
+                expected: header::Bitpix::Float64.to_int(),
+                found: self.data.bitpix().to_int(),
+            }),
+        }
+    }
+
+    /// The physical values `BZERO + BSCALE * stored` of the data. Pixels equal to BLANK
+    /// become NaN. `data` itself keeps the stored values, unless
+    /// [`BasicFits::apply_scaling`] was called.
+    pub fn physical_data(&self) -> GenericData<f64> {
+        let mut data = self.data.to_f64();
+        if let Some((scale, zero)) = self.scaling() {
+            data.mapv_inplace(|x| zero + scale * x);
+        }
+        if let Some(mask) = self.pixel_mask() {
+            data.zip_mut_with(&mask, |x, &blank| {
+                if blank {
+                    *x = f64::NAN
+                }
+            });
+        }
+        data
+    }
+
+    /// Replace the stored values of the data by their physical values, see
+    /// [`BasicFits::physical_data`]. The header is updated to match: BSCALE, BZERO and
+    /// BLANK are removed and BITPIX becomes -64. Nothing changes when BLANK is absent and
+    /// so are BSCALE and BZERO (or BSCALE = 1 and BZERO = 0).
+    pub fn apply_scaling(&mut self) {
+        if self.scaling().is_none() && self.header.blank().is_none() {
+            return;
+        }
+        self.data = Arc::new(self.physical_data().into());
+        self.header
+            .keywords
+            .retain(|kw| !matches!(kw.keyword_name(), "BSCALE" | "BZERO" | "BLANK"));
+        let bitpix = parsing::header::Value::Integer(-64);
+        parsing::header::set_keyword(&mut self.header.keywords, "BITPIX", bitpix, None);
+        self.header.bitpix = header::Bitpix::Float64;
+    }
+
+    /// Which pixels are undefined, i.e. equal to the BLANK value of an integer image. `None`
+    /// when there is no BLANK keyword, which includes all floating point images.
+    pub fn pixel_mask(&self) -> Option<GenericData<bool>> {
+        let blank = self.header.blank()?;
+        let mask = match self.data.as_ref() {
+            DataArray::U8(d) => d.mapv(|x| i64::from(x) == blank),
+            DataArray::I16(d) => d.mapv(|x| i64::from(x) == blank),
+            // BLANK is a stored value, i.e. without BZERO
+            DataArray::U16(d) => d.mapv(|x| i64::from(x) - 32768 == blank),
+            DataArray::I32(d) => d.mapv(|x| i64::from(x) == blank),
+            DataArray::U32(d) => d.mapv(|x| i64::from(x) - 2147483648 == blank),
+            DataArray::I64(d) => d.mapv(|x| x == blank),
+            DataArray::F32(_) | DataArray::F64(_) => return None,
+        };
+        Some(mask)
+    }
+
+    /// The data as unsigned integers (`DataArray::U16` or `DataArray::U32`), if the header
+    /// follows the convention for storing them, see [`DataArray::to_unsigned`]. Assigning
+    /// them to `data` keeps the file consistent, as the header still describes the storage.
+    pub fn unsigned_data(&self) -> Option<DataArray> {
+        let scale = self.header.get_float("BSCALE").unwrap_or(1.);
+        let zero = self.header.get_float("BZERO").unwrap_or(0.);
+        self.data.to_unsigned(scale, zero)
+    }
+
+    // BSCALE and BZERO, if they change the data. Unsigned data already has them applied.
+    fn scaling(&self) -> Option<(f64, f64)> {
+        let scale = self.header.get_float("BSCALE").unwrap_or(1.);
+        let zero = self.header.get_float("BZERO").unwrap_or(0.);
+        if (scale == 1. && zero == 0.) || self.data.is_unsigned() {
+            None
+        } else {
+            Some((scale, zero))
+        }
+    }
+
+    // Shift the image onto the pixel grid of `reference`, using the offset found by
+    // cross-correlating the two images. Both must be 2D images of the same shape.
+    #[cfg(feature = "fft")]
+    pub fn align_to(&self, reference: &BasicFits) -> Result<Self, FitsError> {
+        let data = image::to_array2(&self.physical_data(), &self.header.axes)
+            .ok_or(FitsError::InvalidNaxis(self.header.axes.len()))?;
+        let reference_data = image::to_array2(&reference.physical_data(), &reference.header.axes)
+            .ok_or(FitsError::InvalidNaxis(reference.header.axes.len()))?;
+        if data.dim() != reference_data.dim() {
+            return Err(FitsError::ShapeMismatch {
+                expected: reference.header.axes.clone(),
+                found: self.header.axes.clone(),
+            });
+        }
+
+        let corr = image::cross_correlate(&data, &reference_data);
+        let (dy, dx) = image::find_correlation_peak(&corr);
+        let aligned = image::shift(&data, -dy, -dx);
+
+        let mut keywords = self.header.keywords.clone();
+        image::set_float_keywords(&mut keywords);
+        let header = Header::from_keyword_list(keywords)?;
+        Ok(BasicFits {
+            header,
+            data: Arc::new(image::from_array2(aligned).into()),
+        })
+    }
+
+    /// Resample the image onto the pixel grid of `reference`: every pixel of `reference` is
+    /// mapped through the WCS of both headers (see [`wcs::WcsLinear`]) to a position in this
+    /// image, where the value is interpolated bilinearly. Pixels that fall outside this image
+    /// become NaN. Both must be 2D images of the same shape with a WCS, which the result
+    /// takes from `reference`.
+    pub fn reproject_to(&self, reference: &BasicFits) -> Result<Self, FitsError> {
+        let data = image::to_array2(&self.physical_data(), &self.header.axes)
+            .ok_or(FitsError::InvalidNaxis(self.header.axes.len()))?;
+        if self.header.axes != reference.header.axes {
+            return Err(FitsError::ShapeMismatch {
+                expected: reference.header.axes.clone(),
+                found: self.header.axes.clone(),
+            });
+        }
+        let wcs = |header: &Header| {
+            wcs::WcsLinear::from_header(header).ok_or(FitsError::MissingKeyword("CRPIX1"))
+        };
+        let (from, to) = (wcs(&reference.header)?, wcs(&self.header)?);
+
+        // FITS pixel coordinates start at 1, array indices at 0
+        let aligned = Array2::from_shape_fn(data.dim(), |(y, x)| {
+            let world = from.pixel_to_world(&[x as f64 + 1., y as f64 + 1.]);
+            let pixel = to.world_to_pixel(&world);
+            image::bilinear(&data, pixel[1] - 1., pixel[0] - 1.)
+        });
+
+        let is_wcs = |kw: &&parsing::header::Keyword| {
+            let name = kw.keyword_name();
+            ["CRPIX", "CRVAL", "CDELT", "CTYPE", "CROTA", "CD1_", "CD2_"]
+                .iter()
+                .any(|prefix| name.starts_with(prefix))
+        };
+        let mut keywords: KeywordList = self
+            .header
+            .keywords
+            .iter()
+            .filter(|kw| !is_wcs(kw))
+            .cloned()
+            .collect();
+        keywords.extend(reference.header.keywords.iter().filter(is_wcs).cloned());
+        image::set_float_keywords(&mut keywords);
+        let header = Header::from_keyword_list(keywords)?;
+        Ok(BasicFits {
+            header,
+            data: Arc::new(image::from_array2(aligned).into()),
+        })
+    }
+}
+
+impl TryFrom<Vec<u8>> for BasicFits {
+    type Error = FitsError;
+
+    fn try_from(bytes: Vec<u8>) -> Result<Self, FitsError> {
+        Self::from_bytes(bytes)
+    }
+}
+
+impl TryFrom<&[u8]> for BasicFits {
+    type Error = FitsError;
+
+    fn try_from(bytes: &[u8]) -> Result<Self, FitsError> {
+        Self::from_slice(bytes)
+    }
+}
+
+/// Open the file, see [`BasicFits::open`].
+impl TryFrom<std::path::PathBuf> for BasicFits {
+    type Error = FitsError;
+
+    fn try_from(path: std::path::PathBuf) -> Result<Self, FitsError> {
+        Self::open(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::definitions::BLOCK_SIZE;
+    use crate::definitions::HEADER_KEYWORD_SIZE;
+    use crate::header::{Bitpix, Header};
+    use crate::image::tests::test_fits;
+    use crate::parsing::header::{parse_header, Value};
+    use crate::{parsing, writing, BasicFits, DataArray, FitsError};
+    use ndarray::{arr1, Array2};
+    use std::io::Cursor;
+    use std::sync::Arc;
+
+    const SIMPLE: &[u8] = include_bytes!("../data/simple.fits");
+
+    // A file with a 1D array of `values` in the given BITPIX
+    fn fits_bytes(bitpix: i64, values: usize, data: &[u8]) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        for record in [
+            "SIMPLE  =                    T".to_string(),
+            format!("BITPIX  = {:>20}", bitpix),
+            "NAXIS   =                    1".to_string(),
+            format!("NAXIS1  = {:>20}", values),
+            "END".to_string(),
+        ] {
+            bytes.extend_from_slice(record.as_bytes());
+            bytes.resize(bytes.len() + HEADER_KEYWORD_SIZE - record.len(), b' ');
+        }
+        bytes.resize(BLOCK_SIZE, b' ');
+        bytes.extend_from_slice(data);
+        bytes.resize(bytes.len().div_ceil(BLOCK_SIZE) * BLOCK_SIZE, 0);
+        bytes
+    }
+
+    #[test]
+    fn read_bitpix_test() {
+        let read = |bitpix, values, data: Vec<u8>| {
+            BasicFits::from_bytes(fits_bytes(bitpix, values, &data))
+                .unwrap()
+                .into_data()
+        };
+
+        let data = read(8, 3, vec![0, 7, 255]);
+        assert_eq!(data, DataArray::U8(arr1(&[0, 7, 255]).into_dyn()));
+
+        let values = [1i16, -2, 300];
+        let data = read(16, 3, values.iter().flat_map(|x| x.to_be_bytes()).collect());
+        assert_eq!(data, DataArray::I16(arr1(&values).into_dyn()));
+
+        let values = [1i32, -2, 70000];
+        let data = read(32, 3, values.iter().flat_map(|x| x.to_be_bytes()).collect());
+        assert_eq!(data, DataArray::I32(arr1(&values).into_dyn()));
+
+        let values = [1i64, -2, 1 << 40];
+        let data = read(64, 3, values.iter().flat_map(|x| x.to_be_bytes()).collect());
+        assert_eq!(data, DataArray::I64(arr1(&values).into_dyn()));
+
+        let values = [1.5f32, -2., f32::NAN];
+        let data = read(
+            -32,
+            3,
+            values.iter().flat_map(|x| x.to_be_bytes()).collect(),
+        );
+        let data = data.to_f64();
+        assert_eq!(data.as_slice().unwrap()[..2], [1.5, -2.]);
+        assert!(data[2].is_nan());
+
+        let values = [1.5f64, -2., 1e300];
+        let data = read(
+            -64,
+            3,
+            values.iter().flat_map(|x| x.to_be_bytes()).collect(),
+        );
+        assert_eq!(data, DataArray::F64(arr1(&values).into_dyn()));
+
+        // A 16-bit image spanning two blocks
+        let values: Vec<i16> = (0..2000).collect();
+        let data = read(
+            16,
+            2000,
+            values.iter().flat_map(|x| x.to_be_bytes()).collect(),
+        );
+        assert_eq!(data, DataArray::I16(arr1(&values).into_dyn()));
+    }
+
+    #[test]
+    fn header_only_test() {
+        let header = Header::from_fits_text(
+            "SIMPLE  =                    T\n\
+             BITPIX  =                   16\n\
+             NAXIS   =                    0\n\
+             EXTEND  =                    T\n",
+        )
+        .unwrap();
+        let mut bytes = writing::header_to_bytes(&header.keywords);
+        assert_eq!(bytes.len(), BLOCK_SIZE);
+
+        let fits = BasicFits::from_bytes(bytes.clone()).unwrap();
+        assert!(fits.data.is_empty());
+        assert_eq!(fits.data.bitpix(), Bitpix::Int16);
+        assert_eq!(fits.to_bytes(), bytes);
+
+        // The block after the header belongs to the next HDU, not to the data
+        bytes.resize(2 * BLOCK_SIZE, b'X');
+        let mut blocks = bytes.chunks(BLOCK_SIZE);
+        parse_header(&mut blocks).unwrap();
+        assert!(parsing::read_data(&header, &mut blocks).unwrap().is_empty());
+        assert_eq!(blocks.next(), Some(&bytes[BLOCK_SIZE..]));
+    }
+
+    #[test]
+    fn from_reader_test() {
+        let mut bytes = SIMPLE.to_vec();
+        bytes.extend_from_slice(b"next HDU");
+        let mut reader = Cursor::new(&bytes);
+        let fits = BasicFits::from_reader(&mut reader).unwrap();
+        let expected = BasicFits::from_bytes(SIMPLE.to_vec()).unwrap();
+        assert_eq!(fits.data, expected.data);
+        // Only the primary HDU is read
+        assert_eq!(reader.position(), SIMPLE.len() as u64);
+
+        // Without the padding of the data
+        let values = [1i16, -2, 300];
+        let data = values
+            .iter()
+            .flat_map(|x| x.to_be_bytes())
+            .collect::<Vec<u8>>();
+        let unpadded = &fits_bytes(16, 3, &data)[..BLOCK_SIZE + data.len()];
+        let fits = BasicFits::from_reader(unpadded).unwrap();
+        assert_eq!(*fits.data, DataArray::I16(arr1(&values).into_dyn()));
+
+        // A 320 GB data array announced by the header is not allocated up front
+        let huge = fits_bytes(-64, 40_000_000_000, &[]);
+        assert!(matches!(
+            BasicFits::from_reader(huge.as_slice()),
+            Err(FitsError::UnexpectedEof)
+        ));
+
+        for truncated in [&SIMPLE[..0], &SIMPLE[..100], &unpadded[..BLOCK_SIZE + 2]] {
+            assert!(matches!(
+                BasicFits::from_reader(truncated),
+                Err(FitsError::UnexpectedEof)
+            ));
+        }
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn gzip_test() {
+        use flate2::{write::GzEncoder, Compression};
+        use std::io::Write;
+
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(SIMPLE).unwrap();
+        let compressed = encoder.finish().unwrap();
+
+        let fits = BasicFits::from_reader(compressed.as_slice()).unwrap();
+        let expected = BasicFits::from_bytes(SIMPLE.to_vec()).unwrap();
+        assert_eq!(fits, expected);
+        assert!(matches!(
+            BasicFits::from_reader(&compressed[..100]),
+            Err(FitsError::UnexpectedEof)
+        ));
+    }
+
+    #[test]
+    fn from_bytes_error_test() {
+        assert!(BasicFits::from_bytes(SIMPLE.to_vec()).is_ok());
+
+        // The 10 values of the data end at byte 80 of the second block
+        let truncated = SIMPLE[..BLOCK_SIZE + 40].to_vec();
+        assert!(matches!(
+            BasicFits::from_bytes(truncated),
+            Err(FitsError::UnexpectedEof)
+        ));
+        assert!(matches!(
+            BasicFits::from_bytes(Vec::new()),
+            Err(FitsError::UnexpectedEof)
+        ));
+
+        let mut bitpix = SIMPLE.to_vec();
+        let record = b"BITPIX  =                   12";
+        let start = bitpix.windows(8).position(|w| w == b"BITPIX  ").unwrap();
+        bitpix[start..start + record.len()].copy_from_slice(record);
+        assert!(matches!(
+            BasicFits::from_bytes(bitpix),
+            Err(FitsError::InvalidBitpix(12))
+        ));
+
+        // Sizes from the header are checked before the data is allocated
+        let huge = fits_bytes(-64, 40_000_000_000, &[]);
+        assert!(matches!(
+            BasicFits::from_bytes(huge),
+            Err(FitsError::UnexpectedEof)
+        ));
+        let overflow = fits_bytes(-64, i64::MAX as usize, &[]);
+        assert!(matches!(
+            BasicFits::from_slice(&overflow),
+            Err(FitsError::InvalidHeader)
+        ));
+        assert!(matches!(
+            BasicFits::from_reader(overflow.as_slice()),
+            Err(FitsError::InvalidHeader)
+        ));
+
+        let missing = String::from("data/missing.fits");
+        assert!(matches!(BasicFits::open(&missing), Err(FitsError::Io(_))));
+        let path = std::path::PathBuf::from("data/simple.fits");
+        assert!(BasicFits::open(&path).is_ok());
+    }
+
+    #[test]
+    fn from_slice_test() {
+        let fits = BasicFits::from_slice(SIMPLE).unwrap();
+        assert_eq!(fits, BasicFits::from_bytes(SIMPLE.to_vec()).unwrap());
+
+        // Only the primary HDU is read, anything after it is ignored
+        let shared = std::sync::Arc::new([SIMPLE, SIMPLE].concat());
+        assert_eq!(BasicFits::from_slice(&shared).unwrap(), fits);
+        assert_eq!(
+            BasicFits::from_slice(&shared[SIMPLE.len()..]).unwrap(),
+            fits
+        );
+        assert!(matches!(
+            BasicFits::from_slice(&SIMPLE[..BLOCK_SIZE]),
+            Err(FitsError::UnexpectedEof)
+        ));
+    }
+
+    #[test]
+    fn try_from_test() {
+        let fits = BasicFits::from_slice(SIMPLE).unwrap();
+        assert_eq!(BasicFits::try_from(SIMPLE).unwrap(), fits);
+        assert_eq!(BasicFits::try_from(SIMPLE.to_vec()).unwrap(), fits);
+        let path = std::path::PathBuf::from("data/simple.fits");
+        assert_eq!(BasicFits::try_from(path).unwrap(), fits);
+
+        let truncated: Result<BasicFits, _> = SIMPLE[..BLOCK_SIZE].try_into();
+        assert!(matches!(truncated, Err(FitsError::UnexpectedEof)));
+    }
+
+    // The records as displayed
+    fn header_text(fits: &BasicFits) -> Vec<String> {
+        fits.header
+            .keywords
+            .iter()
+            .map(|kw| kw.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn write_round_trip_test() {
+        for path in ["data/simple.fits", "data/long.fits", "data/twodim.fits"] {
+            let original = BasicFits::open(path).unwrap();
+            let bytes = original.to_bytes();
+            assert_eq!(bytes.len() % BLOCK_SIZE, 0);
+
+            let read = BasicFits::from_bytes(bytes).unwrap();
+            assert_eq!(header_text(&read), header_text(&original));
+            assert_eq!(read.header.axes, original.header.axes);
+            assert_eq!(read.data, original.data);
+        }
+
+        let values = [1i16, -2, 300];
+        let data = values
+            .iter()
+            .flat_map(|x| x.to_be_bytes())
+            .collect::<Vec<u8>>();
+        let original = BasicFits::from_bytes(fits_bytes(16, 3, &data)).unwrap();
+        let mut written = Vec::new();
+        original.write(&mut written).unwrap();
+        assert_eq!(written, fits_bytes(16, 3, &data));
+
+        let mut invalid = original.clone();
+        let hierarch = parsing::header::Keyword::Hierarch(
+            "ESO DET WIN NX".to_string(),
+            Value::Integer(512),
+            String::new(),
+        );
+        invalid.header.keywords.push(hierarch);
+        invalid.write(&mut Vec::new()).unwrap();
+        invalid
+            .header
+            .keywords
+            .push(parsing::header::Keyword::Value(
+                "exptime".to_string(),
+                Value::Float(1.),
+                String::new(),
+            ));
+        let mut written = Vec::new();
+        assert!(matches!(
+            invalid.write(&mut written),
+            Err(FitsError::InvalidKeywordName(name)) if name == "exptime"
+        ));
+        assert!(written.is_empty());
+    }
+
+    #[test]
+    fn clone_test() {
+        let original = BasicFits::open("data/twodim.fits").unwrap();
+        let mut copy = original.clone();
+        copy.header
+            .set_value("OBJECT", Value::Str("copy".into()), "")
+            .unwrap();
+        assert_eq!(copy.data, original.data);
+        assert_eq!(copy.header.axes, original.header.axes);
+        assert_eq!(copy.header.naxis.get(), original.header.naxis.get());
+        assert_ne!(header_text(&copy), header_text(&original));
+
+        let debug = format!("{:?}", original.header);
+        assert!(debug.starts_with("Header { simple: true, bitpix: Float64, naxis: 2"));
+    }
+
+    #[test]
+    fn eq_test() {
+        let original = BasicFits::open("data/twodim.fits").unwrap();
+        let mut copy = original.clone();
+        assert_eq!(copy, original);
+        assert_eq!(copy.header.keywords[0], original.header.keywords[0]);
+
+        copy.header
+            .set_value("OBJECT", Value::Str("copy".into()), "")
+            .unwrap();
+        assert_ne!(copy.header, original.header);
+        assert_ne!(copy, original);
+
+        let mut copy = original.clone();
+        copy.apply_scaling();
+        copy.data = Arc::new(DataArray::empty(&copy.header.bitpix));
+        assert_eq!(copy.header, original.header);
+        assert_ne!(copy, original);
+    }
+
+    #[test]
+    fn apply_scaling_test() {
+        let values = [0i16, -32768, 32767];
+        let data = values
+            .iter()
+            .flat_map(|x| x.to_be_bytes())
+            .collect::<Vec<u8>>();
+        let mut fits = BasicFits::from_bytes(fits_bytes(16, 3, &data)).unwrap();
+
+        // Without BSCALE and BZERO the data is left alone
+        fits.apply_scaling();
+        assert_eq!(*fits.data, DataArray::I16(arr1(&values).into_dyn()));
+
+        // The unsigned 16 bit convention
+        fits.header
+            .set_batch(&[("BZERO", Value::Float(32768.), "")]);
+        let physical = arr1(&[32768., 0., 65535.]).into_dyn();
+        assert_eq!(fits.physical_data(), physical);
+        assert_eq!(*fits.data, DataArray::I16(arr1(&values).into_dyn()));
+
+        fits.apply_scaling();
+        assert_eq!(*fits.data, DataArray::F64(physical.clone()));
+        assert_eq!(fits.header.bitpix, Bitpix::Float64);
+        assert_eq!(fits.header.get_value("BZERO"), None);
+        assert_eq!(fits.physical_data(), physical);
+
+        // Still consistent after writing
+        let read = BasicFits::from_bytes(fits.to_bytes()).unwrap();
+        assert_eq!(*read.data, DataArray::F64(physical));
+
+        fits.header.set_batch(&[
+            ("BSCALE", Value::Float(0.5), ""),
+            ("BZERO", Value::Integer(1), ""),
+        ]);
+        assert_eq!(
+            fits.physical_data(),
+            arr1(&[16385., 1., 32768.5]).into_dyn()
+        );
+    }
+
+    #[test]
+    fn unsigned_data_test() {
+        let values = [0i16, -32768, 32767, 1];
+        let data = values
+            .iter()
+            .flat_map(|x| x.to_be_bytes())
+            .collect::<Vec<u8>>();
+        let mut fits = BasicFits::from_bytes(fits_bytes(16, 4, &data)).unwrap();
+        assert_eq!(fits.unsigned_data(), None);
+
+        fits.header.set_batch(&[
+            ("BSCALE", Value::Float(1.), ""),
+            ("BZERO", Value::Float(32768.), ""),
+            ("BLANK", Value::Integer(1), ""),
+        ]);
+        let physical = fits.physical_data();
+        let unsigned = arr1(&[32768u16, 0, 65535, 32769]).into_dyn();
+        fits.data = Arc::new(fits.unsigned_data().unwrap());
+        assert_eq!(*fits.data, DataArray::U16(unsigned.clone()));
+        assert_eq!(fits.data.bitpix(), Bitpix::Int16);
+
+        // Scaling and BLANK keep working on the unsigned values
+        let scaled = fits.physical_data();
+        assert_eq!(scaled[[2]], 65535.);
+        assert!(scaled[[3]].is_nan());
+        assert!((0..3).all(|i| scaled[[i]] == physical[[i]]));
+
+        // Written back as signed integers with BZERO
+        let read = BasicFits::from_bytes(fits.to_bytes()).unwrap();
+        assert_eq!(*read.data, DataArray::I16(arr1(&values).into_dyn()));
+        assert_eq!(read.unsigned_data(), Some(DataArray::U16(unsigned)));
+    }
+
+    #[test]
+    fn blank_test() {
+        let values = [7i16, -1, 3];
+        let data = values
+            .iter()
+            .flat_map(|x| x.to_be_bytes())
+            .collect::<Vec<u8>>();
+        let mut fits = BasicFits::from_bytes(fits_bytes(16, 3, &data)).unwrap();
+        assert_eq!(fits.pixel_mask(), None);
+
+        fits.header.set_batch(&[
+            ("BLANK", Value::Integer(-1), ""),
+            ("BZERO", Value::Float(10.), ""),
+        ]);
+        assert_eq!(fits.header.blank(), Some(-1));
+        assert_eq!(
+            fits.pixel_mask(),
+            Some(arr1(&[false, true, false]).into_dyn())
+        );
+        let physical = fits.physical_data();
+        assert_eq!(physical[[0]], 17.);
+        assert!(physical[[1]].is_nan());
+        assert_eq!(physical[[2]], 13.);
+
+        // Floating point data marks undefined pixels with NaN instead
+        fits.apply_scaling();
+        assert!(fits.data.as_f64().unwrap()[[1]].is_nan());
+        assert_eq!(fits.header.blank(), None);
+        assert!(!fits.header.contains("BLANK"));
+        assert_eq!(fits.pixel_mask(), None);
+    }
+
+    #[test]
+    fn data_range_test() {
+        let data = Array2::from_shape_vec((2, 3), vec![3., -1., 4., 1., 5., 9.]).unwrap();
+        let fits = test_fits(&data);
+        assert_eq!(fits.data_range(), (-1., 9.));
+        assert_eq!(fits.data_sum(), 21.);
+        assert_eq!(fits.data_mean(), 3.5);
+
+        let mut data = data;
+        data[[0, 2]] = f64::NAN;
+        let fits = test_fits(&data);
+        let (min, max) = fits.data_range();
+        assert!(min.is_nan() && max.is_nan());
+        assert_eq!(fits.data_range_nan_aware(), (-1., 9.));
+        assert!(fits.data_mean().is_nan());
+    }
+
+    #[test]
+    fn create_test() {
+        let header = Header::for_image(Bitpix::Int16, &[3, 2]);
+        let flat = DataArray::I16(arr1(&[1i16, 2, 3, 4, 5, 6]).into_dyn());
+        let fits = BasicFits::create(header.clone(), flat.clone()).unwrap();
+        assert_eq!(*fits.data, flat);
+
+        let image = Array2::<i16>::zeros((2, 3)).into_dyn();
+        assert!(BasicFits::create(header.clone(), image.into()).is_ok());
+
+        let transposed = Array2::<i16>::zeros((3, 2)).into_dyn();
+        assert!(matches!(
+            BasicFits::create(header.clone(), transposed.into()),
+            Err(FitsError::ShapeMismatch { expected, found }) if expected == [2, 3] && found == [3, 2]
+        ));
+        let short = DataArray::I16(arr1(&[1i16, 2]).into_dyn());
+        assert!(matches!(
+            BasicFits::create(header.clone(), short),
+            Err(FitsError::ShapeMismatch { .. })
+        ));
+        let floats = DataArray::F32(arr1(&[0f32; 6]).into_dyn());
+        assert!(matches!(
+            BasicFits::create(header, floats),
+            Err(FitsError::BitpixMismatch {
+                expected: 16,
+                found: -32
+            })
+        ));
+
+        let empty = Header::for_image(Bitpix::Int8, &[]);
+        assert!(BasicFits::create(empty, DataArray::empty(&Bitpix::Int8)).is_ok());
+    }
+
+    #[test]
+    fn array2_test() {
+        let data = Array2::from_shape_vec((2, 3), vec![3., -1., 4., 1., 5., 9.]).unwrap();
+        let fits = test_fits(&data);
+        assert_eq!(fits.as_array2().unwrap(), data);
+        assert_eq!(fits.reshape_to_2d().unwrap(), data);
+        assert!(matches!(fits.as_array3(), Err(FitsError::InvalidNaxis(2))));
+        assert_eq!(fits.clone().into_array2(), data);
+
+        let mut ints = fits.clone();
+        ints.data = Arc::new(DataArray::I16(ints.data.to_f64().mapv(|x| x as i16)));
+        assert_eq!(ints.reshape_to_2d().unwrap(), data);
+        assert!(matches!(
+            ints.as_array2(),
+            Err(FitsError::BitpixMismatch {
+                expected: -64,
+                found: 16
+            })
+        ));
+        assert_eq!(ints.into_array2()[[1, 2]], 9.);
+    }
+
+    #[test]
+    fn into_data_test() {
+        let fits = BasicFits::from_slice(SIMPLE).unwrap();
+        let data = fits.data.as_ref().clone();
+        assert_eq!(fits.clone().into_data(), data);
+
+        // Copies of the file and pipeline stages share the data
+        let copy = fits.clone();
+        let stage = fits.data_arc();
+        assert!(Arc::ptr_eq(&copy.data, &stage));
+        let (header, shared) = fits.into_shared_data();
+        assert_eq!(header, copy.header);
+        assert!(Arc::ptr_eq(&shared, &stage));
+        drop((copy, stage));
+        assert_eq!(Arc::try_unwrap(shared).unwrap(), data);
+    }
+
+    #[test]
+    fn open_section_test() {
+        let full = BasicFits::open("data/twodim.fits")
+            .unwrap()
+            .reshape_to_2d()
+            .unwrap();
+        let fits = BasicFits::open_section("data/twodim.fits", "[2:3, 3:4]").unwrap();
+        assert_eq!(fits.header.axes, [2, 2]);
+        assert_eq!(
+            fits.reshape_to_2d().unwrap(),
+            full.slice(ndarray::s![2..4, 1..3])
+        );
+
+        let fits = BasicFits::open_section("data/twodim.fits", "[*,1:4:2]").unwrap();
+        assert_eq!(
+            fits.reshape_to_2d().unwrap(),
+            full.slice(ndarray::s![0..4;2, ..])
+        );
+
+        assert!(matches!(
+            BasicFits::open_section("data/twodim.fits", "[1:5,*]"),
+            Err(FitsError::OutOfBounds)
+        ));
+        assert!(matches!(
+            BasicFits::open_section("data/twodim.fits", "[*]"),
+            Err(FitsError::InvalidSection(_))
+        ));
+
+        // The reference pixel moves along with the section
+        let mut fits = test_fits(&Array2::zeros((4, 4)));
+        fits.header
+            .set_value("CRPIX1", Value::Float(2.), "")
+            .unwrap();
+        fits.header
+            .set_value("CRPIX2", Value::Float(3.), "")
+            .unwrap();
+        // And the pixel scale along with the step
+        for key in ["CDELT1", "CDELT2", "CD1_1", "CD2_1", "CD2_2"] {
+            fits.header.set_value(key, Value::Float(0.5), "").unwrap();
+        }
+        let path = crate::io::tests::temp_path("open_section_test");
+        fits.write(&mut std::fs::File::create(&path).unwrap())
+            .unwrap();
+        let section = BasicFits::open_section(&path, "[2:4:2, 2:3]");
+        std::fs::remove_file(&path).unwrap();
+
+        let header = section.unwrap().header;
+        assert_eq!(header.axes, [2, 2]);
+        assert_eq!(header.get_float("CRPIX1"), Some(1.));
+        assert_eq!(header.get_float("CRPIX2"), Some(2.));
+        assert_eq!(header.get_float("CDELT1"), Some(1.));
+        assert_eq!(header.get_float("CDELT2"), Some(0.5));
+        assert_eq!(header.get_float("CD1_1"), Some(1.));
+        assert_eq!(header.get_float("CD2_1"), Some(1.));
+        assert_eq!(header.get_float("CD2_2"), Some(0.5));
+
+        assert!(matches!(
+            BasicFits::open_section("data/twodim.fits", "[1:2:0,*]"),
+            Err(FitsError::InvalidSection(_))
+        ));
+    }
+
+    #[test]
+    fn array3_test() {
+        let mut fits = test_fits(&Array2::zeros((2, 3)));
+        fits.header.axes = vec![3, 1, 2];
+        let cube = fits.as_array3().unwrap();
+        assert_eq!(cube.dim(), (2, 1, 3));
+        assert!(fits.as_array2().is_err());
     }
 }
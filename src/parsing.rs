@@ -4,26 +4,126 @@ use std::str::Utf8Error;
 use std::{fmt, str};
 use ndarray::Array1;
 
-use crate::header::Header;
-use crate::{definitions, KeywordList, RawHeaderList, GenericData};
+use crate::header::{Bitpix, Header};
+use crate::{definitions, DataArray, FitsError, KeywordList, GenericData};
 
 pub mod header {
-    use std::string::FromUtf8Error;
+    use std::io::Read;
 
-    use crate::definitions::HEADER_CONTINUE_KEYWORD;
+    use crate::definitions::{HEADER_CONTINUE_KEYWORD, HEADER_HIERARCH_KEYWORD};
 
     use super::*;
 
+    #[derive(Debug, Clone, PartialEq)]
     pub enum Keyword {
         History(String),
         Comment(String),
         Value(String, Value, String),
         Continue(String, Value, String),
+        /// A keyword following the HIERARCH convention, with a name longer than 8
+        /// characters, e.g. `HIERARCH ESO DET WIN NX = 512`. The name excludes "HIERARCH".
+        Hierarch(String, Value, String),
+    }
+
+    // With the serde feature, every keyword is a {name, value, comment} object. COMMENT and
+    // HISTORY records have their text as comment and no value, and the name of a HIERARCH
+    // keyword includes "HIERARCH".
+    #[cfg(feature = "serde")]
+    #[derive(serde::Serialize, serde::Deserialize)]
+    struct KeywordRecord {
+        name: String,
+        #[serde(default = "undefined")]
+        value: Value,
+        #[serde(default)]
+        comment: String,
+    }
+
+    #[cfg(feature = "serde")]
+    fn undefined() -> Value {
+        Value::Undefined
+    }
+
+    #[cfg(feature = "serde")]
+    impl serde::Serialize for Keyword {
+        fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            let record = |name: String, value: &Value, comment: &str| KeywordRecord {
+                name,
+                value: value.clone(),
+                comment: comment.to_string(),
+            };
+            let record = match self {
+                Keyword::Value(name, value, comment) => record(name.clone(), value, comment),
+                Keyword::Hierarch(name, value, comment) => record(
+                    format!("{} {}", HEADER_HIERARCH_KEYWORD, name),
+                    value,
+                    comment,
+                ),
+                Keyword::History(text) | Keyword::Comment(text) => {
+                    record(self.keyword_name().to_string(), &Value::Undefined, text)
+                }
+                Keyword::Continue(_kw, value, comment) => {
+                    record(HEADER_CONTINUE_KEYWORD.to_string(), value, comment)
+                }
+            };
+            serde::Serialize::serialize(&record, serializer)
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    impl<'de> serde::Deserialize<'de> for Keyword {
+        fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            let KeywordRecord {
+                name,
+                value,
+                comment,
+            } = serde::Deserialize::deserialize(deserializer)?;
+            let hierarch = name
+                .strip_prefix(HEADER_HIERARCH_KEYWORD)
+                .and_then(|rest| rest.strip_prefix(' '));
+            if let Some(hierarch) = hierarch {
+                return Ok(Keyword::Hierarch(hierarch.to_string(), value, comment));
+            }
+            Ok(match (name.as_str(), value) {
+                (definitions::HEADER_HISTORY_KEYWORD, Value::Undefined) => {
+                    Keyword::History(comment)
+                }
+                (definitions::HEADER_COMMENT_KEYWORD, Value::Undefined) => {
+                    Keyword::Comment(comment)
+                }
+                (HEADER_CONTINUE_KEYWORD, value) => {
+                    Keyword::Continue(String::new(), value, comment)
+                }
+                (_, value) => Keyword::Value(name, value, comment),
+            })
+        }
     }
 
     impl Keyword {
+        /// The keyword name, e.g. "NAXIS1", "HISTORY" or "COMMENT".
+        pub fn keyword_name(&self) -> &str {
+            match self {
+                Keyword::Value(kw, _v, _c) => kw,
+                Keyword::History(_) => definitions::HEADER_HISTORY_KEYWORD,
+                Keyword::Comment(_) => definitions::HEADER_COMMENT_KEYWORD,
+                Keyword::Continue(..) => HEADER_CONTINUE_KEYWORD,
+                Keyword::Hierarch(kw, _v, _c) => kw,
+            }
+        }
+
+        /// The 80 byte record (card) of the keyword, see [`crate::writing::keyword_to_card`].
+        pub fn to_card(&self) -> Result<[u8; definitions::HEADER_KEYWORD_SIZE], FitsError> {
+            crate::writing::keyword_to_card(self)
+        }
+
+        #[deprecated(note = "Use the Display trait instead: println!(\"{}\", kw)")]
         pub fn print(&self) {
-            // This is just a basic print function, mainly for a bit better debugging
+            println!("{}", self)
+        }
+    }
+
+    // This is just a basic format, mainly for a bit better debugging
+    impl fmt::Display for Keyword {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
             match self {
                 Keyword::Value(kw, value, comment) => {
                     let cmt = if comment.len() > 0 {
@@ -31,16 +131,23 @@ pub mod header {
                     } else {
                         "".to_string()
                     };
-                    println!("{:8} | {:>30}{}", kw, value, cmt)
+                    write!(f, "{:8} | {:>30}{}", kw, value, cmt)
                 }
                 Keyword::Continue(kw, value, comment) => {
-                    println!("{:8} | {:>30} / {}", kw, value, comment)
+                    write!(f, "{:8} | {:>30} / {}", kw, value, comment)
+                }
+                Keyword::Hierarch(kw, value, comment) => {
+                    write!(f, "{} {} | {}", HEADER_HIERARCH_KEYWORD, kw, value)?;
+                    if !comment.is_empty() {
+                        write!(f, " / {}", comment)?;
+                    }
+                    Ok(())
                 }
                 Keyword::History(v) => {
-                    println!("{:8} {:>30}", definitions::HEADER_HISTORY_KEYWORD, v)
+                    write!(f, "{:8} {:>30}", definitions::HEADER_HISTORY_KEYWORD, v)
                 }
                 Keyword::Comment(v) => {
-                    println!("{:8} {:>30}", definitions::HEADER_COMMENT_KEYWORD, v)
+                    write!(f, "{:8} {:>30}", definitions::HEADER_COMMENT_KEYWORD, v)
                 }
             }
         }
@@ -52,23 +159,34 @@ pub mod header {
         History(&'a str),
         Comment(&'a str),
         RawValue(&'a str, &'a str),
+        /// Long name and value of a HIERARCH keyword
+        Hierarch(&'a str, &'a str),
     }
 
-    impl<'a> HeaderChunk<'a> {
-        pub fn print(&self) {
-            // This is just a basic print function, mainly for a bit better debugging
+    impl<'a> fmt::Display for HeaderChunk<'a> {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
             match self {
                 // RawKeyword::ParsedValue(kw, value, comment) => println!("{:8} | {:>30} / {}", kw, value, comment),
-                HeaderChunk::RawValue(kw, value) => println!("{:8} | {:>30}", kw, value),
+                HeaderChunk::RawValue(kw, value) => write!(f, "{:8} | {:>30}", kw, value),
+                HeaderChunk::Hierarch(kw, value) => {
+                    write!(f, "{} {} | {}", HEADER_HIERARCH_KEYWORD, kw, value)
+                }
                 HeaderChunk::History(v) => {
-                    println!("{:8} {:>30}", definitions::HEADER_HISTORY_KEYWORD, v)
+                    write!(f, "{:8} {:>30}", definitions::HEADER_HISTORY_KEYWORD, v)
                 }
                 HeaderChunk::Comment(v) => {
-                    println!("{:8} {:>30}", definitions::HEADER_COMMENT_KEYWORD, v)
+                    write!(f, "{:8} {:>30}", definitions::HEADER_COMMENT_KEYWORD, v)
                 }
-                HeaderChunk::End => println!("{:8}", definitions::HEADER_END_KEYWORD),
+                HeaderChunk::End => write!(f, "{:8}", definitions::HEADER_END_KEYWORD),
             }
         }
+    }
+
+    impl<'a> HeaderChunk<'a> {
+        #[deprecated(note = "Use the Display trait instead: println!(\"{}\", chunk)")]
+        pub fn print(&self) {
+            println!("{}", self)
+        }
 
         pub fn from_bytes(hc_bytes: &'a [u8]) -> Result<HeaderChunk<'a>, Utf8Error> {
             if hc_bytes == definitions::HEADER_END_KEYWORD_FULL {
@@ -77,6 +195,14 @@ pub mod header {
             let chunk = str::from_utf8(hc_bytes.into())?;
             let (kw, _sep, value) = split_header_chunk(chunk);
 
+            // The long name of a HIERARCH keyword runs up to the '='
+            let rest = &chunk[definitions::HEADER_KEYWORD_NAME_SIZE..];
+            if kw.trim_end() == HEADER_HIERARCH_KEYWORD {
+                if let Some((long_kw, value)) = rest.split_once('=') {
+                    return Ok(HeaderChunk::Hierarch(long_kw.trim(), value.trim()));
+                }
+            }
+
             let kw = kw.trim_matches(' ');
             let value = value.trim_matches(' ');
 
@@ -87,16 +213,20 @@ pub mod header {
             })
         }
 
-        pub fn parse(&self) -> Keyword {
-            match self {
-                Self::End => panic!("Should be no end value ever."),
+        pub fn try_parse(&self) -> Result<Keyword, ParseError> {
+            Ok(match self {
+                Self::End => return Err(ParseError::EndRecord),
                 Self::History(v) => Keyword::History(v.to_string()),
                 Self::Comment(v) => Keyword::Comment(v.to_string()),
                 Self::RawValue(kw, value) => {
-                    let (val, cmt) = parse_keyword(value);
+                    let (val, cmt) = parse_keyword(value)?;
                     Keyword::Value(kw.to_string(), val, cmt)
                 }
-            }
+                Self::Hierarch(kw, value) => {
+                    let (val, cmt) = parse_keyword(value)?;
+                    Keyword::Hierarch(kw.to_string(), val, cmt)
+                }
+            })
         }
     }
 
@@ -124,28 +254,144 @@ pub mod header {
         Str(String),
         Float(f64),
         Boolean(bool),
-        // TODO: Add complex integers and complex floats
+        /// Complex integer, as (real, imaginary)
+        ComplexInteger(i64, i64),
+        /// Complex float, as (real, imaginary)
+        ComplexFloat(f64, f64),
     }
 
+    /// Formats the value the way it appears in a header record, so the output can be read
+    /// back with [`Value::from_str`](str::FromStr::from_str). Integers are right-justified in
+    /// a field of 20 characters, floats use an uppercase exponent (e.g. `1.5E-10`) and quotes
+    /// inside strings are escaped.
     impl fmt::Display for Value {
-        // TODO: Add functionality for formatting, like padding and alignment
         fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
             match self {
                 Value::Undefined => {
                     write!(f, "UNDEFINED")
                 }
                 Value::Integer(x) => {
-                    write!(f, "{}", x)
+                    write!(f, "{:>20}", x)
                 }
+                // Debug keeps the '.' and switches to exponent notation for very small and
+                // large values, so the value is read back as a float
                 Value::Float(x) => {
-                    write!(f, "{}", x)
+                    write!(f, "{}", format!("{:?}", x).to_uppercase())
                 }
                 Value::Str(x) => {
-                    write!(f, "'{}'", x)
+                    write!(f, "'{}'", x.replace('\'', "''"))
                 }
                 Value::Boolean(x) => {
                     write!(f, "{}", if *x { "T" } else { "F" })
                 }
+                Value::ComplexInteger(re, im) => {
+                    write!(f, "({}, {})", re, im)
+                }
+                Value::ComplexFloat(re, im) => {
+                    let part = |x: &f64| format!("{:?}", x).to_uppercase();
+                    write!(f, "({}, {})", part(re), part(im))
+                }
+            }
+        }
+    }
+
+    /// Parses a value as written in a header record, without the keyword name. Anything
+    /// after a '/' is a comment and is ignored.
+    impl str::FromStr for Value {
+        type Err = ParseError;
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            match s.trim() {
+                "UNDEFINED" => Ok(Value::Undefined),
+                value => parse_keyword(value).map(|(value, _comment)| value),
+            }
+        }
+    }
+
+    // With the serde feature, a value is the closest JSON type: Undefined is null, complex
+    // numbers are a [real, imaginary] pair.
+    #[cfg(feature = "serde")]
+    impl serde::Serialize for Value {
+        fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            match self {
+                Value::Undefined => serializer.serialize_unit(),
+                Value::Integer(n) => serializer.serialize_i64(*n),
+                Value::Float(x) => serializer.serialize_f64(*x),
+                Value::Str(s) => serializer.serialize_str(s),
+                Value::Boolean(b) => serializer.serialize_bool(*b),
+                Value::ComplexInteger(re, im) => serde::Serialize::serialize(&(re, im), serializer),
+                Value::ComplexFloat(re, im) => serde::Serialize::serialize(&(re, im), serializer),
+            }
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    impl<'de> serde::Deserialize<'de> for Value {
+        fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            deserializer.deserialize_any(ValueVisitor)
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    struct ValueVisitor;
+
+    #[cfg(feature = "serde")]
+    impl<'de> serde::de::Visitor<'de> for ValueVisitor {
+        type Value = Value;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(
+                f,
+                "null, a number, string, boolean or [real, imaginary] pair"
+            )
+        }
+
+        fn visit_unit<E>(self) -> Result<Value, E> {
+            Ok(Value::Undefined)
+        }
+
+        fn visit_none<E>(self) -> Result<Value, E> {
+            Ok(Value::Undefined)
+        }
+
+        fn visit_i64<E>(self, n: i64) -> Result<Value, E> {
+            Ok(Value::Integer(n))
+        }
+
+        fn visit_u64<E: serde::de::Error>(self, n: u64) -> Result<Value, E> {
+            let n = i64::try_from(n).map_err(|_| E::custom(format!("integer too large: {}", n)))?;
+            Ok(Value::Integer(n))
+        }
+
+        fn visit_f64<E>(self, x: f64) -> Result<Value, E> {
+            Ok(Value::Float(x))
+        }
+
+        fn visit_str<E>(self, s: &str) -> Result<Value, E> {
+            Ok(Value::Str(s.to_string()))
+        }
+
+        fn visit_bool<E>(self, b: bool) -> Result<Value, E> {
+            Ok(Value::Boolean(b))
+        }
+
+        fn visit_seq<A: serde::de::SeqAccess<'de>>(self, mut seq: A) -> Result<Value, A::Error> {
+            use serde::de::Error;
+
+            let (re, im): (Value, Value) = match (seq.next_element()?, seq.next_element()?) {
+                (Some(re), Some(im)) => (re, im),
+                _ => return Err(A::Error::invalid_length(0, &self)),
+            };
+            if seq.next_element::<Value>()?.is_some() {
+                return Err(A::Error::invalid_length(3, &self));
+            }
+            match (re, im) {
+                (Value::Integer(re), Value::Integer(im)) => Ok(Value::ComplexInteger(re, im)),
+                (
+                    re @ (Value::Integer(_) | Value::Float(_)),
+                    im @ (Value::Integer(_) | Value::Float(_)),
+                ) => Ok(Value::ComplexFloat(re.as_f64_lossy(), im.as_f64_lossy())),
+                _ => Err(A::Error::custom("complex parts must be numbers")),
             }
         }
     }
@@ -158,6 +404,8 @@ pub mod header {
                 Value::Float(n) => Value::Float(*n),
                 Value::Undefined => Value::Undefined,
                 Value::Str(s) => Value::Str(s.clone()),
+                Value::ComplexInteger(re, im) => Value::ComplexInteger(*re, *im),
+                Value::ComplexFloat(re, im) => Value::ComplexFloat(*re, *im),
             }
         }
 
@@ -196,17 +444,45 @@ pub mod header {
                 _ => false,
             }
         }
+
+        pub fn is_complex_integer(&self) -> bool {
+            matches!(self, Self::ComplexInteger(..))
+        }
+
+        pub fn is_complex_float(&self) -> bool {
+            matches!(self, Self::ComplexFloat(..))
+        }
+
+        /// Coerce any value to a f64, for algorithms that do not care about the storage type.
+        ///
+        /// This is lossy: integers beyond 2^53 are rounded, booleans become 1.0 or 0.0 and
+        /// non-numeric values (Undefined and Str) and complex values become NaN.
+        pub fn as_f64_lossy(&self) -> f64 {
+            match self {
+                Self::Integer(n) => *n as f64,
+                Self::Float(x) => *x,
+                Self::Boolean(b) => {
+                    if *b {
+                        1.0
+                    } else {
+                        0.0
+                    }
+                }
+                Self::Undefined | Self::Str(_) => f64::NAN,
+                Self::ComplexInteger(..) | Self::ComplexFloat(..) => f64::NAN,
+            }
+        }
     }
 
-    fn parse_keyword(line: &str) -> (Value, String) {
+    fn parse_keyword(line: &str) -> Result<(Value, String), ParseError> {
         if line.is_empty() {
-            return (Value::Undefined, String::new());
+            return Ok((Value::Undefined, String::new()));
         }
 
         // Case we have a string
         if line.starts_with("'") {
-            let (v, cmt) = parse_str_cmt(line).unwrap();
-            return (Value::Str(v), cmt);
+            let (v, cmt) = parse_str_cmt(line)?;
+            return Ok((Value::Str(v), cmt));
         }
 
         // TODO: Verify that this split is correct. (previously there was a ' ')
@@ -217,24 +493,49 @@ pub mod header {
 
         // Case of only a comment
         if value.is_empty() {
-            return (Value::Undefined, comment.to_string());
+            return Ok((Value::Undefined, comment.to_string()));
         }
 
         // Case of a boolean
         if value.starts_with(['T', 'F']) {
-            return (Value::Boolean(value.starts_with("T")), comment.to_string());
+            return Ok((Value::Boolean(value.starts_with("T")), comment.to_string()));
         }
 
-        // Case of a complex number
-        if value.starts_with('(') {
-            todo!("Implement complex numbers")
+        let invalid = || ParseError::InvalidValue(value.to_string());
+
+        // Case of a complex number: (real, imaginary)
+        if let Some(inner) = value.strip_prefix('(') {
+            let (re, im) = inner
+                .strip_suffix(')')
+                .and_then(|parts| parts.split_once(','))
+                .ok_or_else(invalid)?;
+            let complex = match (parse_number(re.trim())?, parse_number(im.trim())?) {
+                (Some(Value::Integer(re)), Some(Value::Integer(im))) => {
+                    Value::ComplexInteger(re, im)
+                }
+                (Some(re), Some(im)) => Value::ComplexFloat(re.as_f64_lossy(), im.as_f64_lossy()),
+                _ => return Err(invalid()),
+            };
+            return Ok((complex, comment.to_string()));
+        }
+
+        match parse_number(value)? {
+            Some(number) => Ok((number, comment.to_string())),
+            // No case matched
+            None => Ok((Value::Undefined, String::new())),
         }
+    }
+
+    // An integer or float value, or None if `value` does not look like a number at all
+    fn parse_number(value: &str) -> Result<Option<Value>, ParseError> {
+        let invalid = || ParseError::InvalidValue(value.to_string());
 
         // Case of a exponent
-        // Case of a float
+        // Case of a float. Fortran style double precision exponents (1.5D-10) are not
+        // understood by str::parse, so 'D' becomes 'E' first.
         if value.find(['.', 'E', 'D']).is_some() {
-            let num = value.parse().unwrap();
-            return (Value::Float(num), comment.to_string());
+            let num = value.replace('D', "E").parse().map_err(|_| invalid())?;
+            return Ok(Some(Value::Float(num)));
         }
 
         // Case of a integer
@@ -242,45 +543,63 @@ pub mod header {
             .chars()
             .all(|x| x.is_numeric() || x == '-' || x == '+')
         {
-            let num = value.parse().unwrap();
-            return (Value::Integer(num), comment.to_string());
+            let num = value.parse().map_err(|_| invalid())?;
+            return Ok(Some(Value::Integer(num)));
         }
-
-        // No case matched
-        (Value::Undefined, String::new())
+        Ok(None)
     }
 
-    // TODO: Test this function
-    fn parse_str_cmt(input: &str) -> Result<(String, String), FromUtf8Error> {
-        let input = input.as_bytes();
-        // We know input[0] == b'\''
-        let mut i: usize = 1;
-        while i < input.len() {
-            if input[i] == b'\'' {
-                if i + 1 < input.len() && input[i + 1] == b'\'' {
-                    i += 1;
-                } else {
-                    break;
-                }
-            }
-            i += 1;
-        }
-        let (extract, rest) = input.split_at(i);
-        let string_value = String::from_utf8(extract[1..].to_vec())?.replace("''", "'");
+    // Split a string value (starting with a quote) from the comment after it. A '/' inside
+    // the string does not start the comment.
+    fn parse_str_cmt(input: &str) -> Result<(String, String), ParseError> {
+        let string_value = extract_str(input.as_bytes())?;
 
         // TODO: Check that the stuff before the comment is empty
-        let rest = String::from_utf8(rest.to_vec())?;
+        let rest = &input[closing_quote(input.as_bytes())..];
         let comment = match rest.split_once(['/']) {
-            Some((_a, b)) => b,
+            Some((_a, b)) => b.trim(),
             None => "",
         };
 
         Ok((string_value, comment.to_string()))
     }
 
-    // TODO: Remove this function
-    fn extract_str(input: &str, output: &mut Vec<u8>) {
-        let input = input.as_bytes();
+    #[derive(PartialEq, Debug)]
+    pub enum ParseError {
+        /// A string value is not valid UTF-8 (FITS only allows ASCII). Contains the value
+        /// with the invalid sequences replaced, for callers that want to continue anyway.
+        InvalidUtf8(String),
+        /// A header record contains characters other than printable ASCII.
+        InvalidRecord(String),
+        /// A value could not be parsed, e.g. a malformed number.
+        InvalidValue(String),
+        /// The header stopped before its END record.
+        MissingEnd,
+        /// The END record was parsed as a keyword.
+        EndRecord,
+    }
+
+    impl fmt::Display for ParseError {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            match self {
+                ParseError::InvalidUtf8(lossy) => {
+                    write!(f, "string value is not valid UTF-8: '{}'", lossy)
+                }
+                ParseError::InvalidRecord(lossy) => {
+                    write!(f, "header record is not printable ASCII: '{}'", lossy)
+                }
+                ParseError::InvalidValue(value) => write!(f, "invalid value: '{}'", value),
+                ParseError::MissingEnd => write!(f, "header has no END record"),
+                ParseError::EndRecord => write!(f, "END record is not a keyword"),
+            }
+        }
+    }
+
+    impl std::error::Error for ParseError {}
+
+    // Index of the quote ending the string that starts at input[0], skipping escaped quotes
+    // (''). The length of the input if the string is not closed.
+    fn closing_quote(input: &[u8]) -> usize {
         // We know input[0] == b'\''
         let mut i: usize = 1;
         while i < input.len() {
@@ -293,88 +612,247 @@ pub mod header {
             }
             i += 1;
         }
-        let extract = &input[1..i];
+        i
+    }
+
+    // The string value that starts at input[0], without its quotes
+    fn extract_str(input: &[u8]) -> Result<String, ParseError> {
+        let extract = &input[1..closing_quote(input)];
 
-        let mut prev = b' ';
-        // let mut bytes: Vec<u8> = Vec::new();
+        // Every escaped quote ('') becomes a single one
+        let mut output: Vec<u8> = Vec::with_capacity(extract.len());
+        let mut escaped = false;
         for c in extract {
-            if !(*c == b'\'' && prev == b'\'') {
-                output.push(*c);
+            if *c == b'\'' && !escaped {
+                escaped = true;
+                continue;
             }
-            prev = *c;
+            escaped = false;
+            output.push(*c);
         }
+        // Trailing spaces are not significant (section 4.2.1.1), so 'AB' and 'AB   ' are
+        // the same value. Leading ones are kept.
+        while output.last() == Some(&b' ') {
+            output.pop();
+        }
+
+        String::from_utf8(output).map_err(|e| {
+            ParseError::InvalidUtf8(String::from_utf8_lossy(e.as_bytes()).into_owned())
+        })
     }
 
-    pub fn parse_header<'a>(blocks: &mut Chunks<'a, u8>) -> KeywordList {
-        let mut raw_header: RawHeaderList = Vec::new();
-        let mut reading_header = true;
+    /// Where a [`HeaderParser`] is in reading a header.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum HeaderParserState {
+        /// The END record has not been seen yet, so more blocks are expected
+        ReadingBlocks,
+        /// The END record was found, further blocks are ignored
+        Done,
+        /// A record could not be parsed (only in strict mode), further blocks are ignored
+        Error,
+    }
 
-        // First read the raw header
-        while reading_header {
-            let block = blocks.next().unwrap();
+    /// Parses a header one 2880 byte block at a time, e.g. while streaming a file.
+    ///
+    /// In strict mode ([`HeaderParser::new`]) parsing stops at the first invalid record. In
+    /// lenient mode ([`HeaderParser::lenient`]) such records are kept as a COMMENT with
+    /// their raw text, and the reasons they failed are collected in [`HeaderParser::errors`].
+    pub struct HeaderParser {
+        state: HeaderParserState,
+        keywords: KeywordList,
+        errors: Vec<ParseError>,
+        lenient: bool,
+    }
 
-            for header_chunk_bytes in block.chunks(definitions::HEADER_KEYWORD_SIZE) {
-                match HeaderChunk::from_bytes(header_chunk_bytes).unwrap() {
-                    HeaderChunk::End => {
-                        reading_header = false;
-                        break;
-                    }
-                    header_chunk => raw_header.push(header_chunk),
-                };
+    impl HeaderParser {
+        pub fn new() -> Self {
+            HeaderParser {
+                state: HeaderParserState::ReadingBlocks,
+                keywords: Vec::new(),
+                errors: Vec::new(),
+                lenient: false,
             }
         }
 
-        // Turn into a parsed header
-        let mut header: KeywordList = Vec::new();
-        let continue_kw = HEADER_CONTINUE_KEYWORD.to_string();
-        for chunk in raw_header.into_iter() {
-            let parsed = chunk.parse();
-
-            // Merge continue keywords into a single value keyword
-            match parsed {
-                Keyword::Value(kw, v0, c0) if kw == continue_kw && v0.is_str() => {
-                    match header.pop() {
-                        Some(Keyword::Value(kw, Value::Str(mut s), mut c)) if s.ends_with("&") => {
-                            let v0 = if let Value::Str(v0) = v0 {
-                                v0
-                            } else {
-                                panic!("CONTINUE Keyword did not have a string");
-                            };
-                            s.pop(); // remove the last &
-                            c.pop(); // remove the last &
-                            s.push_str(&v0);
-                            c.push_str(&c0);
-                            let new = Keyword::Value(kw, Value::Str(s), c);
-                            header.push(new);
+        pub fn lenient() -> Self {
+            HeaderParser {
+                lenient: true,
+                ..Self::new()
+            }
+        }
+
+        pub fn state(&self) -> HeaderParserState {
+            self.state
+        }
+
+        /// The errors found so far. In strict mode this is at most one.
+        pub fn errors(&self) -> &[ParseError] {
+            &self.errors
+        }
+
+        /// Parse the records of the next block, and return the resulting state.
+        pub fn push_block(&mut self, block: &[u8; definitions::BLOCK_SIZE]) -> HeaderParserState {
+            if self.state != HeaderParserState::ReadingBlocks {
+                return self.state;
+            }
+            for record in block.chunks(definitions::HEADER_KEYWORD_SIZE) {
+                let lossy = || String::from_utf8_lossy(record).trim_end().to_string();
+                let parsed = if record.iter().all(|b| (b' '..=b'~').contains(b)) {
+                    // Only ASCII, so always valid
+                    match HeaderChunk::from_bytes(record).unwrap() {
+                        HeaderChunk::End => {
+                            self.state = HeaderParserState::Done;
+                            break;
                         }
-                        Some(prev) => {
-                            // TODO: Maybe print some warning here, since we have a CONTINUE
-                            // as keyword.
-                            header.push(prev);
-                            header.push(Keyword::Continue(
-                                definitions::HEADER_CONTINUE_KEYWORD.to_string(),
-                                v0,
-                                c0,
-                            ));
+                        chunk => chunk.try_parse(),
+                    }
+                } else {
+                    Err(ParseError::InvalidRecord(lossy()))
+                };
+                match parsed {
+                    Ok(keyword) => self.keywords.push(keyword),
+                    Err(e) => {
+                        self.errors.push(e);
+                        if !self.lenient {
+                            self.state = HeaderParserState::Error;
+                            break;
                         }
-                        None => header.push(Keyword::Continue(
-                            definitions::HEADER_CONTINUE_KEYWORD.to_string(),
-                            v0,
-                            c0,
-                        )),
+                        self.keywords.push(Keyword::Comment(lossy()));
                     }
                 }
-                kw => header.push(kw),
             }
+            self.state
+        }
+
+        /// The parsed keywords, or the error that stopped a strict parser. Fails with
+        /// `ParseError::MissingEnd` if no END record was found.
+        pub fn finish(mut self) -> Result<KeywordList, ParseError> {
+            match self.state {
+                HeaderParserState::Done => Ok(merge_continued(self.keywords)),
+                HeaderParserState::Error => Err(self.errors.pop().unwrap()),
+                HeaderParserState::ReadingBlocks => Err(ParseError::MissingEnd),
+            }
+        }
+    }
+
+    impl Default for HeaderParser {
+        fn default() -> Self {
+            Self::new()
+        }
+    }
+
+    /// Parse the header from the blocks, stopping after the block containing END.
+    ///
+    /// Returns `FitsError::UnexpectedEof` if the blocks run out (or the last one is
+    /// incomplete) before the END record, and `FitsError::ParseError` for the first
+    /// record that can not be parsed.
+    pub fn parse_header<'a>(blocks: &mut Chunks<'a, u8>) -> Result<KeywordList, FitsError> {
+        let mut parser = HeaderParser::new();
+        while parser.state() == HeaderParserState::ReadingBlocks {
+            let block = blocks.next().ok_or(FitsError::UnexpectedEof)?;
+            let block = block.try_into().map_err(|_| FitsError::UnexpectedEof)?;
+            parser.push_block(block);
+        }
+        Ok(parser.finish()?)
+    }
+
+    /// Parse the header at the start of `bytes`, e.g. a memory mapped file. Also returns the
+    /// size of the header in bytes (a multiple of 2880), which is where the data starts.
+    pub fn parse_header_slice(bytes: &[u8]) -> Result<(KeywordList, usize), FitsError> {
+        let mut blocks = bytes.chunks(definitions::BLOCK_SIZE);
+        let total = blocks.len();
+        let keywords = parse_header(&mut blocks)?;
+        Ok((keywords, (total - blocks.len()) * definitions::BLOCK_SIZE))
+    }
+
+    /// The header blocks for `keywords`, the inverse of [`parse_header`]: every keyword as
+    /// 80 character records, followed by END and padded with spaces to a multiple of 2880
+    /// bytes. See [`crate::writing::header_to_bytes`].
+    pub fn serialize_header(keywords: &KeywordList) -> Vec<u8> {
+        crate::writing::header_to_bytes(keywords)
+    }
+
+    /// Parse a header from `reader`, skipping records that can not be parsed instead of
+    /// panicking. Those are kept as a COMMENT with their raw text, and the reason they
+    /// failed is collected in the returned errors.
+    ///
+    /// Reads whole blocks up to and including the one containing END, so afterwards the
+    /// reader is positioned at the start of the data.
+    pub fn parse_header_lenient(reader: &mut dyn Read) -> (KeywordList, Vec<ParseError>) {
+        let mut parser = HeaderParser::lenient();
+        let mut block = [0u8; definitions::BLOCK_SIZE];
+        while parser.state() == HeaderParserState::ReadingBlocks {
+            if reader.read_exact(&mut block).is_err() {
+                parser.errors.push(ParseError::MissingEnd);
+                break;
+            }
+            parser.push_block(&block);
+        }
+        (merge_continued(parser.keywords), parser.errors)
+    }
+
+    // Merge CONTINUE records into the long string value preceding them. A string is continued
+    // for as long as it ends with '&', which is removed before appending the next part.
+    // CONTINUE records that do not follow such a string are kept as `Keyword::Continue`.
+    //
+    // Headers with a LONGSTRN keyword may follow the older convention of continuing strings
+    // in COMMENT records instead, with the part quoted as in `COMMENT   'more text'`. In
+    // such headers these are merged as well.
+    fn merge_continued(keywords: impl IntoIterator<Item = Keyword>) -> KeywordList {
+        let keywords: Vec<Keyword> = keywords.into_iter().collect();
+        let longstrn = keywords.iter().any(|kw| kw.keyword_name() == "LONGSTRN");
+        let is_continue = |kw: &Keyword| matches!(kw, Keyword::Value(k, Value::Str(_), _) if k == HEADER_CONTINUE_KEYWORD);
+        let is_part = |kw: &Keyword| {
+            is_continue(kw)
+                || matches!(kw, Keyword::Comment(text) if longstrn && quoted_part(text).is_some())
+        };
+        let mut header: KeywordList = Vec::new();
+        let mut keywords = keywords.into_iter().peekable();
+        while let Some(parsed) = keywords.next() {
+            if is_continue(&parsed) {
+                // TODO: Maybe print some warning here, since there is no string to continue
+                if let Keyword::Value(kw, v, c) = parsed {
+                    header.push(Keyword::Continue(kw, v, c));
+                }
+                continue;
+            }
+            let Keyword::Value(kw, Value::Str(mut s), mut c) = parsed else {
+                header.push(parsed);
+                continue;
+            };
+            while s.ends_with('&') {
+                let (part, part_comment) = match keywords.next_if(is_part) {
+                    Some(Keyword::Value(_, Value::Str(part), part_comment)) => (part, part_comment),
+                    Some(Keyword::Comment(text)) => (quoted_part(&text).unwrap(), String::new()),
+                    _ => break,
+                };
+                s.pop(); // remove the last &
+                if c.ends_with('&') {
+                    c.pop(); // remove the last &
+                }
+                s.push_str(&part);
+                c.push_str(&part_comment);
+            }
+            header.push(Keyword::Value(kw, Value::Str(s), c));
         }
         header
     }
 
+    // The string of a COMMENT record continuing a string under the LONGSTRN convention, i.e.
+    // with text of the form 'part'
+    fn quoted_part(text: &str) -> Option<String> {
+        let text = text.trim().as_bytes();
+        if text.len() < 2 || text[0] != b'\'' || closing_quote(text) != text.len() - 1 {
+            return None;
+        }
+        extract_str(text).ok()
+    }
+
     // TODO: Move as method of a proper Header datatype
     fn find_value<'a, 'b>(header: &'a KeywordList, key: &'b str) -> Option<Value> {
         for kw in header.iter() {
             match kw {
-                Keyword::Value(k, v, _c) => {
+                Keyword::Value(k, v, _c) | Keyword::Hierarch(k, v, _c) => {
                     if *k == key {
                         return Some(v.clone());
                     }
@@ -387,7 +865,212 @@ pub mod header {
         None
     }
 
-    pub fn extract_values(header: &KeywordList) -> Option<(bool, usize, Vec<usize>, i64)> {
+    // A keyword name has at most 8 characters, all uppercase letters, digits, '-' or '_'
+    pub(crate) fn is_valid_keyword_name(name: &str) -> bool {
+        !name.is_empty()
+            && name.len() <= definitions::HEADER_KEYWORD_NAME_SIZE
+            && name.chars().all(is_keyword_char)
+    }
+
+    fn is_keyword_char(c: char) -> bool {
+        c.is_ascii_uppercase() || c.is_ascii_digit() || c == '-' || c == '_'
+    }
+
+    /// Check that `name` is a valid keyword name: at most 8 characters, all uppercase letters,
+    /// digits, '-' or '_'. With `allow_hierarch`, the name of a HIERARCH keyword (without
+    /// "HIERARCH") is accepted as well, which may be longer and consist of several words,
+    /// e.g. "ESO DET WIN NX".
+    pub fn validate_keyword_name(name: &str, allow_hierarch: bool) -> Result<(), FitsError> {
+        let hierarch = || {
+            name.split(' ')
+                .all(|word| !word.is_empty() && word.chars().all(is_keyword_char))
+        };
+        if is_valid_keyword_name(name) || (allow_hierarch && hierarch()) {
+            Ok(())
+        } else {
+            Err(FitsError::InvalidKeywordName(name.to_string()))
+        }
+    }
+
+    // Set the value (and comment, if given) of the first keyword named `key`, appending a new
+    // keyword if there is none.
+    pub(crate) fn set_keyword(
+        keywords: &mut KeywordList,
+        key: &str,
+        value: Value,
+        comment: Option<&str>,
+    ) {
+        for kw in keywords.iter_mut() {
+            if let Keyword::Value(k, v, c) = kw {
+                if k == key {
+                    *v = value;
+                    if let Some(comment) = comment {
+                        *c = comment.to_string();
+                    }
+                    return;
+                }
+            }
+        }
+        let comment = comment.unwrap_or("").to_string();
+        keywords.push(Keyword::Value(key.to_string(), value, comment));
+    }
+
+    /// A date and time as stored in FITS date keywords such as DATE-OBS and DATE-END. The time
+    /// scale is not part of the value, see the TIMESYS keyword.
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub struct FitsDateTime {
+        pub year: i64,
+        pub month: u32,
+        pub day: u32,
+        pub hour: u32,
+        pub minute: u32,
+        pub second: u32,
+        /// Fraction of a second, in [0, 1)
+        pub fraction: f64,
+    }
+
+    impl FitsDateTime {
+        /// Parse the ISO-8601 form `YYYY-MM-DD[Thh:mm[:ss[.sss]]]`
+        pub(crate) fn parse_iso(date: &str) -> Option<Self> {
+            let (date, time) = date.split_once('T').unwrap_or((date, ""));
+
+            let mut ymd = date.split('-');
+            let year = ymd.next()?.parse::<i64>().ok()?;
+            let month = ymd.next()?.parse::<u32>().ok()?;
+            let day = ymd.next()?.parse::<u32>().ok()?;
+            if ymd.next().is_some() {
+                return None;
+            }
+
+            let (mut hour, mut minute, mut second, mut fraction) = (0, 0, 0, 0.);
+            if !time.is_empty() {
+                let mut hms = time.split(':');
+                hour = hms.next()?.parse::<u32>().ok()?;
+                minute = hms.next()?.parse::<u32>().ok()?;
+                if let Some(s) = hms.next() {
+                    let (s, frac) = s.split_once('.').unwrap_or((s, ""));
+                    second = s.parse::<u32>().ok()?;
+                    if !frac.is_empty() {
+                        if !frac.chars().all(|c| c.is_ascii_digit()) {
+                            return None;
+                        }
+                        fraction = format!("0.{}", frac).parse::<f64>().ok()?;
+                    }
+                }
+                if hms.next().is_some() {
+                    return None;
+                }
+            }
+
+            Self::checked(year, month, day, hour, minute, second, fraction)
+        }
+
+        /// Parse the deprecated form `DD/MM/YY`, which always refers to the years 1900 to 1999
+        fn parse_old_style(date: &str) -> Option<Self> {
+            let mut dmy = date.split('/');
+            let day = dmy.next()?.parse::<u32>().ok()?;
+            let month = dmy.next()?.parse::<u32>().ok()?;
+            let year = dmy.next()?;
+            if year.len() != 2 || dmy.next().is_some() {
+                return None;
+            }
+            let year = 1900 + year.parse::<i64>().ok()?;
+            Self::checked(year, month, day, 0, 0, 0, 0.)
+        }
+
+        fn checked(
+            year: i64,
+            month: u32,
+            day: u32,
+            hour: u32,
+            minute: u32,
+            second: u32,
+            fraction: f64,
+        ) -> Option<Self> {
+            // A second of 60 is allowed for leap seconds
+            let valid = (1..=12).contains(&month)
+                && (1..=31).contains(&day)
+                && hour < 24
+                && minute < 60
+                && second <= 60;
+            valid.then_some(Self {
+                year,
+                month,
+                day,
+                hour,
+                minute,
+                second,
+                fraction,
+            })
+        }
+
+        /// Julian Date of this date and time, using the proleptic Gregorian calendar
+        pub fn as_jd(&self) -> f64 {
+            let (year, month, day) = (self.year, self.month as i64, self.day as i64);
+
+            // Julian day number (at noon) of the Gregorian calendar date
+            let a = (14 - month) / 12;
+            let y = year + 4800 - a;
+            let m = month + 12 * a - 3;
+            let jdn = day + (153 * m + 2) / 5 + 365 * y + y / 4 - y / 100 + y / 400 - 32045;
+
+            let seconds = self.second as f64 + self.fraction;
+            let day_fraction =
+                (self.hour as f64 + self.minute as f64 / 60. + seconds / 3600.) / 24.;
+            jdn as f64 - 0.5 + day_fraction
+        }
+    }
+
+    impl fmt::Display for FitsDateTime {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(
+                f,
+                "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+                self.year, self.month, self.day, self.hour, self.minute, self.second
+            )?;
+            // Clamp to milliseconds, rounding up could carry into the seconds
+            let millis = ((self.fraction * 1000.).round() as u32).min(999);
+            if millis > 0 {
+                write!(f, ".{:03}", millis)?;
+            }
+            Ok(())
+        }
+    }
+
+    /// Parse the value of a FITS date keyword (e.g. DATE-OBS), in either the ISO-8601 form
+    /// `YYYY-MM-DD[Thh:mm:ss[.sss]]` or the old `DD/MM/YY` form.
+    pub fn parse_fits_date(value: &Value) -> Option<FitsDateTime> {
+        let Value::Str(date) = value else {
+            return None;
+        };
+        let date = date.trim();
+        if date.contains('/') {
+            FitsDateTime::parse_old_style(date)
+        } else {
+            FitsDateTime::parse_iso(date)
+        }
+    }
+
+    /// The mandatory keywords of a header, see [`extract_values`].
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct ExtractedValues {
+        pub simple: bool,
+        pub naxis: usize,
+        /// NAXIS1 up to NAXISn
+        pub axes: Vec<usize>,
+        pub bitpix: i64,
+    }
+
+    // The name of the NAXISn keyword, for 1 <= n <= 999. The names are made once, so they
+    // can be given to FitsError::MissingKeyword.
+    fn naxisn_name(n: usize) -> &'static str {
+        static NAMES: std::sync::OnceLock<Vec<String>> = std::sync::OnceLock::new();
+        &NAMES.get_or_init(|| (1..=999).map(|n| format!("NAXIS{}", n)).collect())[n - 1]
+    }
+
+    /// Extract the mandatory keywords SIMPLE, NAXIS, BITPIX and NAXISn from the header.
+    /// A missing SIMPLE is taken as false.
+    pub fn extract_values(header: &KeywordList) -> Result<ExtractedValues, FitsError> {
         let simple = {
             let value_simple = find_value(header, "SIMPLE").unwrap_or(Value::Boolean(false));
             if let Value::Boolean(b) = value_simple {
@@ -398,36 +1081,42 @@ pub mod header {
         };
 
         let naxis = {
-            let value_naxis = find_value(header, "NAXIS")?;
-            if let Value::Integer(i) = value_naxis {
-                i as usize
-            } else {
-                panic!("Naxis was not an integer");
+            let value_naxis =
+                find_value(header, "NAXIS").ok_or(FitsError::MissingKeyword("NAXIS"))?;
+            // Checked here, as the axes are allocated below
+            match value_naxis {
+                Value::Integer(i) if (0..=999).contains(&i) => i as usize,
+                Value::Integer(i) if i > 999 => return Err(FitsError::InvalidNaxis(i as usize)),
+                _ => return Err(FitsError::InvalidHeader),
             }
         };
 
         let bitpix = {
-            let value_bitpix = find_value(header, "BITPIX")?;
+            let value_bitpix =
+                find_value(header, "BITPIX").ok_or(FitsError::MissingKeyword("BITPIX"))?;
             if let Value::Integer(i) = value_bitpix {
                 i
             } else {
-                panic!("BITPIX was not an integer");
+                return Err(FitsError::InvalidHeader);
             }
         };
 
         let mut axes = Vec::with_capacity(naxis);
         for i in 1..=naxis {
-            let kw = format!("NAXIS{}", i);
-            let kw = kw.as_str();
-            let value_axis = find_value(header, kw)?;
-            if let Value::Integer(i) = value_axis {
-                axes.push(i as usize);
-            } else {
-                panic!("{} was not an integer", kw);
+            let kw = naxisn_name(i);
+            let value_axis = find_value(header, kw).ok_or(FitsError::MissingKeyword(kw))?;
+            match value_axis {
+                Value::Integer(i) if i >= 0 => axes.push(i as usize),
+                _ => return Err(FitsError::InvalidHeader),
             }
         }
 
-        Some((simple, naxis, axes, bitpix))
+        Ok(ExtractedValues {
+            simple,
+            naxis,
+            axes,
+            bitpix,
+        })
     }
 
     #[cfg(test)]
@@ -487,107 +1176,857 @@ pub mod header {
             let _tmp = "SIMPLE  =                    T / conforms to FITS standard                      BITPIX  =                  -64 / array data type                                NAXIS   =                    2 / number of array dimensions                     NAXIS1  =                 1024                                                  NAXIS2  =                  682                                                  BIAS    =                  100                                                  FOCALLEN= +0.000000000000E+000                                                  APTAREA = +0.000000000000E+000                                                  APTDIA  = +0.000000000000E+000                                                  DATE-OBS= '2020-04-18T00:56:58.604'                                             TIME-OBS= '00:56:58.604        '                                                SWCREATE= 'CCDSoft Version 5.00.218'                                            SET-TEMP= -2.000000000000E+001                                                  COLORCCD=                    0                                                  DISPCOLR=                    1                                                  IMAGETYP= 'Light Frame         '                                                CCDSFPT =                    1                                                  XORGSUBF=                    0                                                  YORGSUBF=                    0                                                  CCDSUBFL=                    0                                                  CCDSUBFT=                    0                                                  XBINNING=                    3                                                  CCDXBIN =                    3                                                  YBINNING=                    3                                                  CCDYBIN =                    3                                                  EXPSTATE=                  293                                                  CCD-TEMP= -2.041762134545E+001                                                  TEMPERAT= -2.041762134545E+001                                                  OBJECT  = 'Entered_Coordinates '                                                OBJCTRA = '14 49 09.474        '                                                OBJCTDEC= '+40 42 04.35        '                                                TELTKRA = -1.000000000000E+003                                                  TELTKDEC= -1.000000000000E+003                                                  CENTAZ  = +1.966280653172E+002                                                  CENTALT = +7.695155713274E+001                                                  TELHA   = '00 20 20.742        '                                                LST     = '15 09 30.056        '                                                AIRMASS = +1.026504260005E+000                                                  SITELAT = '+53:14:24.90        '                                                SITELONG= '-006:32:11.02       '                                                INSTRUME= 'SBIG STL-6303 3 CCD Camera'                                          EGAIN   = +2.360000000000E+000                                                  E-GAIN  = +2.360000000000E+000                                                  XPIXSZ  = +2.700000000000E+001                                                  YPIXSZ  = +2.700000000000E+001                                                  SBIGIMG =                   18                                                  USER_2  = 'SBIG STL-6303 3 CCD Camera'                                          DATAMAX =                65535                                                  SBSTDVER= 'SBFITSEXT Version 1.0'                                               FILTER  = 'R                   '                                                EXPTIME = +3.000000000000E+002                                                  EXPOSURE= +3.000000000000E+002                                                  CBLACK  =                 3754                                                  CWHITE  =                 4141                                                  END                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                             ";
         }
 
+        // Pad the records to 80 characters and the header to a full block
+        fn header_block(records: &[&[u8]]) -> Vec<u8> {
+            let mut block = Vec::new();
+            for record in records {
+                block.extend_from_slice(record);
+                block.resize(
+                    block.len() + definitions::HEADER_KEYWORD_SIZE - record.len(),
+                    b' ',
+                );
+            }
+            block.resize(definitions::BLOCK_SIZE, b' ');
+            block
+        }
+
+        #[test]
+        fn parse_header_lenient_test() {
+            let block = header_block(&[
+                b"SIMPLE  =                    T",
+                b"BITPIX  =                  -64",
+                b"EXPTIME =              1.2.3 / seconds",
+                b"OBJECT  = 'caf\xe9'",
+                b"LNGVALUE= 'AA&'",
+                b"CONTINUE  'BB'",
+                b"END",
+            ]);
+            let (keywords, errors) = parse_header_lenient(&mut block.as_slice());
+
+            assert_eq!(
+                errors,
+                [
+                    ParseError::InvalidValue("1.2.3".to_string()),
+                    ParseError::InvalidRecord("OBJECT  = 'caf\u{FFFD}'".to_string()),
+                ]
+            );
+            assert_eq!(keywords.len(), 5);
+            assert!(
+                matches!(&keywords[2], Keyword::Comment(c) if c == "EXPTIME =              1.2.3 / seconds")
+            );
+            assert!(matches!(&keywords[4], Keyword::Value(_, Value::Str(v), _) if v == "AABB"));
+
+            // Without END the header is incomplete
+            let block = header_block(&[b"SIMPLE  =                    T"]);
+            let (keywords, errors) = parse_header_lenient(&mut block.as_slice());
+            assert_eq!(keywords[0].keyword_name(), "SIMPLE");
+            assert_eq!(errors, [ParseError::MissingEnd]);
+        }
+
+        #[test]
+        fn longstrn_test() {
+            let records: [&[u8]; 8] = [
+                b"SIMPLE  =                    T",
+                b"LONGSTRN= 'OGIP 1.0'",
+                b"LNGVALUE= 'one &'          / first",
+                b"COMMENT   'two &'",
+                b"COMMENT   'it''s three'",
+                b"COMMENT   'not a part'",
+                b"COMMENT   plain text",
+                b"END",
+            ];
+            let (keywords, errors) = parse_header_lenient(&mut header_block(&records).as_slice());
+            assert!(errors.is_empty());
+            assert_eq!(keywords.len(), 5);
+            assert_eq!(
+                keywords[2],
+                Keyword::Value(
+                    "LNGVALUE".to_string(),
+                    Value::Str("one two it's three".to_string()),
+                    "first".to_string()
+                )
+            );
+            assert_eq!(keywords[3], Keyword::Comment("'not a part'".to_string()));
+
+            // Without LONGSTRN, COMMENT records never continue a string
+            let mut records = records;
+            records[1] = b"OTHER   = 'OGIP 1.0'";
+            let (keywords, _errors) = parse_header_lenient(&mut header_block(&records).as_slice());
+            assert_eq!(keywords.len(), 7);
+            assert!(matches!(&keywords[2], Keyword::Value(_, Value::Str(v), _) if v == "one &"));
+        }
+
+        #[test]
+        fn merge_continued_test() {
+            let block = header_block(&[
+                b"SIMPLE  =                    T",
+                b"LNGVALUE= 'one &'          / first&",
+                b"CONTINUE  'two &'",
+                b"CONTINUE  'three &'        / second",
+                b"CONTINUE  'four'",
+                b"CONTINUE  'stray'",
+                b"SHORT   = 'no&'",
+                b"CONTINUE  'more'",
+                b"CONTINUE  5",
+                b"END",
+            ]);
+            let (keywords, errors) = parse_header_lenient(&mut block.as_slice());
+            assert!(errors.is_empty());
+            assert_eq!(keywords.len(), 5);
+            assert_eq!(
+                keywords[1],
+                Keyword::Value(
+                    "LNGVALUE".to_string(),
+                    Value::Str("one two three four".to_string()),
+                    "firstsecond".to_string()
+                )
+            );
+            assert!(matches!(&keywords[2], Keyword::Continue(_, Value::Str(v), _) if v == "stray"));
+            assert!(matches!(&keywords[3], Keyword::Value(_, Value::Str(v), _) if v == "nomore"));
+            // Not a continued string at all
+            assert!(
+                matches!(&keywords[4], Keyword::Value(k, Value::Integer(5), _) if k == "CONTINUE")
+            );
+
+            // A string ending with '&' at the end of the header is kept as is
+            let block = header_block(&[b"LNGVALUE= 'last&'", b"END"]);
+            let (keywords, _errors) = parse_header_lenient(&mut block.as_slice());
+            assert!(matches!(&keywords[0], Keyword::Value(_, Value::Str(v), _) if v == "last&"));
+        }
+
+        #[test]
+        fn extract_values_test() {
+            let block = header_block(&[
+                b"SIMPLE  =                    T",
+                b"BITPIX  =                  -64",
+                b"NAXIS   =                    2",
+                b"NAXIS1  =                   10",
+                b"NAXIS2  =                   20",
+                b"END",
+            ]);
+            let keywords = parse_header(&mut block.chunks(definitions::BLOCK_SIZE)).unwrap();
+            let values = extract_values(&keywords).unwrap();
+            assert_eq!(
+                values,
+                ExtractedValues {
+                    simple: true,
+                    naxis: 2,
+                    axes: vec![10, 20],
+                    bitpix: -64,
+                }
+            );
+
+            let missing = |name: &str| {
+                let keywords: KeywordList = keywords
+                    .iter()
+                    .filter(|kw| kw.keyword_name() != name)
+                    .cloned()
+                    .collect();
+                extract_values(&keywords)
+            };
+            assert!(matches!(
+                missing("BITPIX"),
+                Err(FitsError::MissingKeyword("BITPIX"))
+            ));
+            assert!(matches!(
+                missing("NAXIS"),
+                Err(FitsError::MissingKeyword("NAXIS"))
+            ));
+            assert!(matches!(
+                missing("NAXIS2"),
+                Err(FitsError::MissingKeyword("NAXIS2"))
+            ));
+
+            let block = header_block(&[
+                b"BITPIX  =                  -64",
+                b"NAXIS   =                  2.5",
+                b"END",
+            ]);
+            let keywords = parse_header(&mut block.chunks(definitions::BLOCK_SIZE)).unwrap();
+            assert!(matches!(
+                extract_values(&keywords),
+                Err(FitsError::InvalidHeader)
+            ));
+
+            let block = header_block(&[
+                b"BITPIX  =                  -64",
+                b"NAXIS   =  1000000000000000000",
+                b"END",
+            ]);
+            let keywords = parse_header(&mut block.chunks(definitions::BLOCK_SIZE)).unwrap();
+            assert!(matches!(
+                extract_values(&keywords),
+                Err(FitsError::InvalidNaxis(1_000_000_000_000_000_000))
+            ));
+        }
+
+        #[test]
+        fn parse_header_error_test() {
+            let block = header_block(&[b"SIMPLE  =                    T"]);
+            let result = parse_header(&mut block.chunks(definitions::BLOCK_SIZE));
+            assert!(matches!(result, Err(FitsError::UnexpectedEof)));
+
+            let block = header_block(&[b"SIMPLE  =                    T", b"END"]);
+            let result = parse_header(&mut block[..100].chunks(definitions::BLOCK_SIZE));
+            assert!(matches!(result, Err(FitsError::UnexpectedEof)));
+
+            let block = header_block(&[b"EXPTIME =              1.2.3", b"END"]);
+            let result = parse_header(&mut block.chunks(definitions::BLOCK_SIZE));
+            assert!(matches!(result, Err(FitsError::ParseError(_))));
+        }
+
+        #[test]
+        fn parse_header_slice_test() {
+            let mut bytes = header_block(&[b"SIMPLE  =                    T"]);
+            bytes.extend(header_block(&[b"NAXIS   =                    0", b"END"]));
+            bytes.extend([0; 100]);
+            let (keywords, size) = parse_header_slice(&bytes).unwrap();
+            assert_eq!(keywords.first().unwrap().keyword_name(), "SIMPLE");
+            assert_eq!(keywords.last().unwrap().keyword_name(), "NAXIS");
+            assert_eq!(size, 2 * definitions::BLOCK_SIZE);
+        }
+
+        #[test]
+        fn serialize_header_test() {
+            for file in ["data/simple.fits", "data/long.fits", "data/twodim.fits"] {
+                let bytes = std::fs::read(file).unwrap();
+                let (keywords, size) = parse_header_slice(&bytes).unwrap();
+                let serialized = serialize_header(&keywords);
+                assert_eq!(serialized.len() % definitions::BLOCK_SIZE, 0);
+                assert_eq!(serialized.len(), size);
+                assert_eq!(parse_header_slice(&serialized).unwrap(), (keywords, size));
+            }
+
+            let keywords = vec![
+                Keyword::Value(
+                    "NAXIS".to_string(),
+                    Value::Integer(0),
+                    "no data".to_string(),
+                ),
+                Keyword::Comment("a comment".to_string()),
+            ];
+            let serialized = serialize_header(&keywords);
+            let records: Vec<&[u8]> = serialized.chunks(80).take(3).collect();
+            assert_eq!(
+                records[0],
+                format!("{:80}", "NAXIS   =                    0 / no data").as_bytes()
+            );
+            assert_eq!(records[1], format!("{:80}", "COMMENT a comment").as_bytes());
+            assert_eq!(records[2], format!("{:80}", "END").as_bytes());
+        }
+
+        #[test]
+        fn header_parser_test() {
+            let first = header_block(&[
+                b"SIMPLE  =                    T",
+                b"NAXIS   =                    0",
+            ]);
+            let second = header_block(&[b"COMMENT   done", b"END"]);
+            let mut parser = HeaderParser::new();
+            assert_eq!(parser.state(), HeaderParserState::ReadingBlocks);
+            let state = parser.push_block(first.as_slice().try_into().unwrap());
+            assert_eq!(state, HeaderParserState::ReadingBlocks);
+            let state = parser.push_block(second.as_slice().try_into().unwrap());
+            assert_eq!(state, HeaderParserState::Done);
+            // Blocks after END (i.e. the data) are not looked at
+            let state = parser.push_block(first.as_slice().try_into().unwrap());
+            assert_eq!(state, HeaderParserState::Done);
+            let keywords = parser.finish().unwrap();
+            // Including the blank records padding the first block
+            assert_eq!(keywords.len(), 36 + 1);
+            assert_eq!(keywords[1].keyword_name(), "NAXIS");
+            assert!(matches!(keywords.last(), Some(Keyword::Comment(c)) if c == "done"));
+
+            let mut parser = HeaderParser::new();
+            parser.push_block(first.as_slice().try_into().unwrap());
+            assert!(matches!(parser.finish(), Err(ParseError::MissingEnd)));
+
+            let bad = header_block(&[b"EXPTIME =              1.2.3", b"END"]);
+            let mut parser = HeaderParser::new();
+            let state = parser.push_block(bad.as_slice().try_into().unwrap());
+            assert_eq!(state, HeaderParserState::Error);
+            let error = ParseError::InvalidValue("1.2.3".to_string());
+            assert_eq!(parser.errors(), [error]);
+            assert!(parser.finish().is_err());
+
+            let mut parser = HeaderParser::lenient();
+            let state = parser.push_block(bad.as_slice().try_into().unwrap());
+            assert_eq!(state, HeaderParserState::Done);
+            assert_eq!(parser.errors().len(), 1);
+            let keywords = parser.finish().unwrap();
+            assert!(
+                matches!(&keywords[0], Keyword::Comment(c) if c == "EXPTIME =              1.2.3")
+            );
+        }
+
+        #[test]
+        fn hierarch_test() {
+            let block = header_block(&[
+                b"HIERARCH ESO DET WIN NX = 512 / window size",
+                b"HIERARCH ESO INS FILT1 NAME = 'R_SPECIAL'",
+                b"END",
+            ]);
+            let keywords = parse_header(&mut block.chunks(definitions::BLOCK_SIZE)).unwrap();
+            assert!(matches!(
+                &keywords[0],
+                Keyword::Hierarch(kw, Value::Integer(512), c) if kw == "ESO DET WIN NX" && c == "window size"
+            ));
+            assert_eq!(keywords[1].keyword_name(), "ESO INS FILT1 NAME");
+            assert_eq!(
+                find_value(&keywords, "ESO INS FILT1 NAME"),
+                Some(Value::Str("R_SPECIAL".to_string()))
+            );
+            assert_eq!(
+                keywords[0].to_string(),
+                format!("HIERARCH ESO DET WIN NX | {:>20} / window size", 512)
+            );
+        }
+
+        #[test]
+        fn keyword_name_test() {
+            let value = Keyword::Value("NAXIS1".to_string(), Value::Integer(4), String::new());
+            assert_eq!(value.keyword_name(), "NAXIS1");
+            assert_eq!(Keyword::History("h".to_string()).keyword_name(), "HISTORY");
+            assert_eq!(Keyword::Comment("c".to_string()).keyword_name(), "COMMENT");
+            let cont = Keyword::Continue(String::new(), Value::Undefined, String::new());
+            assert_eq!(cont.keyword_name(), "CONTINUE");
+        }
+
+        #[test]
+        fn validate_keyword_name_test() {
+            for name in ["NAXIS1", "DATE-OBS", "TTYPE_1", "A"] {
+                assert!(validate_keyword_name(name, false).is_ok(), "{}", name);
+            }
+            for name in ["", "naxis", "EXPOSURE1", "DATE OBS", "OBJ=", "ÅNGSTRÖM"] {
+                assert!(matches!(
+                    validate_keyword_name(name, false),
+                    Err(FitsError::InvalidKeywordName(n)) if n == name
+                ));
+            }
+
+            assert!(validate_keyword_name("ESO DET WIN NX", true).is_ok());
+            assert!(validate_keyword_name("EXPOSURE1", true).is_ok());
+            assert!(validate_keyword_name("ESO DET WIN NX", false).is_err());
+            for name in ["ESO  DET", " ESO", "eso det", ""] {
+                assert!(validate_keyword_name(name, true).is_err(), "{}", name);
+            }
+        }
+
+        #[test]
+        fn display_test() {
+            let value = Keyword::Value("EXPTIME".to_string(), Value::Integer(30), "s".to_string());
+            assert_eq!(format!("{}", value), format!("EXPTIME  | {:>20} / s", 30));
+            let value = Keyword::Value("OBJECT".to_string(), Value::Undefined, String::new());
+            assert_eq!(format!("{}", value), "OBJECT   | UNDEFINED");
+            let history = Keyword::History("flat fielded".to_string());
+            let padding = " ".repeat(30 - "flat fielded".len());
+            assert_eq!(
+                format!("{}", history),
+                format!("HISTORY  {}flat fielded", padding)
+            );
+
+            assert_eq!(format!("{}", HeaderChunk::End), "END     ");
+            let chunk = HeaderChunk::RawValue("NAXIS", "2");
+            assert_eq!(format!("{}", chunk), format!("NAXIS    | {:>30}", 2));
+        }
+
+        #[test]
+        fn value_round_trip_test() {
+            let values = [
+                Value::Undefined,
+                Value::Integer(0),
+                Value::Integer(-42),
+                Value::Integer(i64::MAX),
+                Value::Float(30.0),
+                Value::Float(-2.5),
+                Value::Float(1.5e-10),
+                Value::Float(6.02e23),
+                Value::Str(String::new()),
+                Value::Str("hello world".to_string()),
+                Value::Str("M'31 / core".to_string()),
+                Value::Boolean(true),
+                Value::Boolean(false),
+                Value::ComplexInteger(1, -2),
+                Value::ComplexFloat(1.0, -0.5e-12),
+            ];
+            for v in values {
+                assert_eq!(format!("{}", v).parse::<Value>(), Ok(v.clone()), "{}", v);
+            }
+
+            assert_eq!(format!("{}", Value::Integer(5)), format!("{:>20}", 5));
+            assert_eq!(format!("{}", Value::Float(1.5e-10)), "1.5E-10");
+            assert_eq!(format!("{}", Value::Str("M'31".to_string())), "'M''31'");
+            assert_eq!("12 / count".parse(), Ok(Value::Integer(12)));
+        }
+
+        #[test]
+        fn as_f64_lossy_test() {
+            assert_eq!(Value::Integer(-3).as_f64_lossy(), -3.0);
+            assert_eq!(Value::Float(2.5).as_f64_lossy(), 2.5);
+            assert_eq!(Value::Boolean(true).as_f64_lossy(), 1.0);
+            assert_eq!(Value::Boolean(false).as_f64_lossy(), 0.0);
+            assert!(Value::Undefined.as_f64_lossy().is_nan());
+            assert!(Value::Str("1.0".to_string()).as_f64_lossy().is_nan());
+
+            // Not exactly representable, but should not panic or overflow
+            assert!(Value::Integer(i64::MAX).as_f64_lossy().is_finite());
+        }
+
+        #[test]
+        fn d_exponent_test() {
+            let chunk = HeaderChunk::from_bytes(b"FOCALLEN= +0.000000000000D+000").unwrap();
+            assert!(matches!(
+                chunk.try_parse(),
+                Ok(Keyword::Value(kw, Value::Float(x), _c)) if kw == "FOCALLEN" && x == 0.0
+            ));
+
+            let (value, comment) = parse_keyword("1.5D-10 / gain").unwrap();
+            assert_eq!(value, Value::Float(1.5e-10));
+            assert_eq!(comment, "gain");
+            assert_eq!("-2.5D3".parse(), Ok(Value::Float(-2500.0)));
+            assert!(parse_keyword("1.5DD3").is_err());
+
+            let chunk = HeaderChunk::from_bytes(b"FOCALLEN= 1.5DD3").unwrap();
+            assert!(chunk.try_parse().is_err());
+            assert_eq!(HeaderChunk::End.try_parse(), Err(ParseError::EndRecord));
+        }
+
+        #[test]
+        fn complex_test() {
+            let (value, comment) = parse_keyword("(1, -2) / gain").unwrap();
+            assert_eq!(value, Value::ComplexInteger(1, -2));
+            assert!(value.is_complex_integer());
+            assert_eq!(comment, "gain");
+
+            let (value, _) = parse_keyword("(1.5,2)").unwrap();
+            assert_eq!(value, Value::ComplexFloat(1.5, 2.));
+            assert!(value.is_complex_float());
+
+            for text in ["(1, 2", "(1 2)", "(1, x)", "(, 2)"] {
+                assert!(parse_keyword(text).is_err(), "{}", text);
+            }
+
+            // Display round trips
+            for value in [Value::ComplexInteger(3, 0), Value::ComplexFloat(1., -0.5)] {
+                let (parsed, _) = parse_keyword(&value.to_string()).unwrap();
+                assert_eq!(parsed, value);
+            }
+        }
+
+        #[test]
+        fn parse_fits_date_test() {
+            let date = |s: &str| parse_fits_date(&Value::Str(s.to_string()));
+
+            let full = date("2000-01-01T12:00:00.5").unwrap();
+            assert_eq!((full.hour, full.minute, full.second), (12, 0, 0));
+            assert_eq!(full.fraction, 0.5);
+            assert_eq!(full.to_string(), "2000-01-01T12:00:00.500");
+            assert!((full.as_jd() - 2451545. - 0.5 / 86400.).abs() < 1e-8);
+
+            let day = date("2024-07-02 ").unwrap();
+            assert_eq!(day.to_string(), "2024-07-02T00:00:00");
+            assert_eq!(day.as_jd(), 2460493.5);
+
+            let old = date("31/12/99").unwrap();
+            assert_eq!((old.year, old.month, old.day), (1999, 12, 31));
+            assert_eq!(old.to_string(), "1999-12-31T00:00:00");
+
+            assert_eq!(date("2000-13-01"), None);
+            assert_eq!(date("2000-01-01T12"), None);
+            assert_eq!(date("2000-01-01T25:00:00"), None);
+            assert_eq!(date("1/1/2000"), None);
+            assert_eq!(parse_fits_date(&Value::Integer(2000)), None);
+        }
+
         #[test]
         fn extract_str_test() {
-            // TODO: Test the new parse_str_cmt function here instead.
-            let mut out: Vec<u8> = Vec::new();
-            extract_str("'Hello'", &mut out);
-            assert_eq!(out, b"Hello");
+            let out = extract_str(b"'Hello'").unwrap();
+            assert_eq!(out, "Hello");
+
+            let out = extract_str(b"'a'").unwrap();
+            assert_eq!(out, "a");
 
-            let mut out: Vec<u8> = Vec::new();
-            extract_str("'a'", &mut out);
-            assert_eq!(out, b"a");
+            let out = extract_str(b"'something'/comment").unwrap();
+            assert_eq!(out, "something");
 
-            let mut out: Vec<u8> = Vec::new();
-            extract_str("'something'/comment", &mut out);
-            assert_eq!(out, b"something");
+            let out = extract_str(b"'something' / comment").unwrap();
+            assert_eq!(out, "something");
 
-            let mut out: Vec<u8> = Vec::new();
-            extract_str("'something' / comment", &mut out);
-            assert_eq!(out, b"something");
+            let out = extract_str(b"'something   ' / comment").unwrap();
+            assert_eq!(out, "something");
 
-            let mut out: Vec<u8> = Vec::new();
-            extract_str("'something   ' / comment", &mut out);
-            assert_eq!(out, b"something   ");
+            let out = extract_str(b"'  indented ' / comment").unwrap();
+            assert_eq!(out, "  indented");
 
-            let mut out: Vec<u8> = Vec::new();
-            extract_str("'''' / comment", &mut out);
-            assert_eq!(out, b"'", "Singular quote");
+            let out = extract_str(b"'        '").unwrap();
+            assert_eq!(out, "");
 
-            let mut out: Vec<u8> = Vec::new();
-            extract_str("'' / comment", &mut out);
-            assert_eq!(out, b"");
+            let out = extract_str(b"'''' / comment").unwrap();
+            assert_eq!(out, "'", "Singular quote");
 
-            let mut out: Vec<u8> = Vec::new();
-            extract_str("''", &mut out);
-            assert_eq!(out, b"");
+            let out = extract_str(b"'' / comment").unwrap();
+            assert_eq!(out, "");
 
-            let mut out: Vec<u8> = Vec::new();
-            extract_str("' / is string ' / and comment", &mut out);
-            assert_eq!(out, b" / is string ");
+            let out = extract_str(b"''").unwrap();
+            assert_eq!(out, "");
 
-            let mut out: Vec<u8> = Vec::new();
-            extract_str("' / is string ' / and comment ' ''' with quote", &mut out);
-            assert_eq!(out, b" / is string ");
+            let out = extract_str(b"' / is string ' / and comment").unwrap();
+            assert_eq!(out, " / is string");
+
+            let out = extract_str(b"' / is string ' / and comment ' ''' with quote").unwrap();
+            assert_eq!(out, " / is string");
+
+            let out = extract_str(b"'''''' / comment").unwrap();
+            assert_eq!(out, "''", "Two quotes");
+        }
+
+        #[test]
+        fn parse_str_cmt_test() {
+            let parsed = |input: &str| parse_str_cmt(input).unwrap();
+            assert_eq!(parsed("'Hello'"), ("Hello".to_string(), String::new()));
+            assert_eq!(
+                parsed("'a / b' / c / d"),
+                ("a / b".to_string(), "c / d".to_string())
+            );
+            assert_eq!(
+                parsed("'it''s' / quote"),
+                ("it's".to_string(), "quote".to_string())
+            );
+            assert_eq!(
+                parsed("'unclosed / x"),
+                ("unclosed / x".to_string(), String::new())
+            );
+        }
+
+        #[test]
+        fn extract_str_utf8_test() {
+            // Non-ASCII, but valid UTF-8 is accepted
+            assert_eq!(extract_str("'café'".as_bytes()), Ok("café".to_string()));
+
+            // A Latin-1 encoded 'é' is not valid UTF-8
+            assert_eq!(
+                extract_str(b"'caf\xe9' / latin-1"),
+                Err(ParseError::InvalidUtf8("caf\u{FFFD}".to_string()))
+            );
         }
     }
 }
 
-mod data {
+/// Image sections, the notation many FITS tools use for part of an image, e.g.
+/// `image.fits[1:512,1:512]`.
+pub mod section {
+    use std::ops::Range;
+
+    use super::*;
+
+    /// The part of one axis in an image section. Pixels count from 1 and ranges include
+    /// both ends, as in the section notation.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum ImageSection {
+        /// `*`: the whole axis
+        Full,
+        /// `first:last`
+        Range(usize, usize),
+        /// `first:last:step`, every `step`th pixel
+        Strided(usize, usize, usize),
+        /// `n`: a single pixel
+        Single(usize),
+    }
+
+    impl ImageSection {
+        /// The pixels (starting at 0) and the step between them, for an axis of length `n`.
+        /// Pixel 0 is outside the image, and a step of 0 or `first > last` is an invalid
+        /// section, like in [`parse_section`].
+        pub fn range(&self, n: usize) -> Result<(Range<usize>, usize), FitsError> {
+            let (first, last, step) = match *self {
+                ImageSection::Full => return Ok((0..n, 1)),
+                ImageSection::Range(first, last) => (first, last, 1),
+                ImageSection::Strided(first, last, step) => (first, last, step),
+                ImageSection::Single(i) => (i, i, 1),
+            };
+            if step == 0 || first > last {
+                return Err(FitsError::InvalidSection(format!(
+                    "{}:{}:{}",
+                    first, last, step
+                )));
+            }
+            let start = first.checked_sub(1).ok_or(FitsError::OutOfBounds)?;
+            if last > n {
+                return Err(FitsError::OutOfBounds);
+            }
+            Ok((start..last, step))
+        }
+    }
+
+    /// Parse an image section such as `[1:512, 1:512]`, with one part per axis, starting
+    /// with NAXIS1. Each part is `*`, `first:last`, `first:last:step` or a single pixel.
+    pub fn parse_section(s: &str) -> Result<Vec<ImageSection>, FitsError> {
+        let invalid = || FitsError::InvalidSection(s.to_string());
+        let inner = s
+            .trim()
+            .strip_prefix('[')
+            .and_then(|s| s.strip_suffix(']'))
+            .ok_or_else(invalid)?;
+
+        let pixel = |p: &str| match p.trim().parse::<usize>() {
+            Ok(p) if p >= 1 => Ok(p),
+            _ => Err(invalid()),
+        };
+        inner
+            .split(',')
+            .map(|part| {
+                let numbers: Vec<&str> = part.split(':').collect();
+                let section = match numbers[..] {
+                    [all] if all.trim() == "*" => ImageSection::Full,
+                    [i] => ImageSection::Single(pixel(i)?),
+                    [first, last] => ImageSection::Range(pixel(first)?, pixel(last)?),
+                    [first, last, step] => {
+                        ImageSection::Strided(pixel(first)?, pixel(last)?, pixel(step)?)
+                    }
+                    _ => return Err(invalid()),
+                };
+                match section {
+                    ImageSection::Range(first, last) | ImageSection::Strided(first, last, _)
+                        if first > last =>
+                    {
+                        Err(invalid())
+                    }
+                    section => Ok(section),
+                }
+            })
+            .collect()
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn parse_section_test() {
+            assert_eq!(
+                parse_section("[1:512, 1:512]").unwrap(),
+                [ImageSection::Range(1, 512), ImageSection::Range(1, 512)]
+            );
+            assert_eq!(
+                parse_section("[*,100]").unwrap(),
+                [ImageSection::Full, ImageSection::Single(100)]
+            );
+            assert_eq!(
+                parse_section(" [1:512:2] ").unwrap(),
+                [ImageSection::Strided(1, 512, 2)]
+            );
+
+            for invalid in [
+                "1:512",
+                "[]",
+                "[0:10]",
+                "[10:1]",
+                "[1:10:0]",
+                "[a]",
+                "[1:2:3:4]",
+            ] {
+                assert!(
+                    matches!(parse_section(invalid), Err(FitsError::InvalidSection(s)) if s == invalid),
+                    "{}",
+                    invalid
+                );
+            }
+        }
+
+        #[test]
+        fn range_test() {
+            assert_eq!(ImageSection::Full.range(10).unwrap(), (0..10, 1));
+            assert_eq!(ImageSection::Range(2, 5).range(10).unwrap(), (1..5, 1));
+            assert_eq!(ImageSection::Strided(1, 9, 4).range(10).unwrap(), (0..9, 4));
+            assert_eq!(ImageSection::Single(10).range(10).unwrap(), (9..10, 1));
+            for out_of_bounds in [
+                ImageSection::Range(5, 11),
+                ImageSection::Range(0, 5),
+                ImageSection::Strided(0, 5, 2),
+                ImageSection::Single(0),
+            ] {
+                assert!(matches!(
+                    out_of_bounds.range(10),
+                    Err(FitsError::OutOfBounds)
+                ));
+            }
+            for invalid in [ImageSection::Strided(1, 5, 0), ImageSection::Range(5, 1)] {
+                assert!(matches!(
+                    invalid.range(10),
+                    Err(FitsError::InvalidSection(_))
+                ));
+            }
+        }
+    }
+}
+
+/// Decoding of data arrays: big endian values packed in 2880 byte blocks. Only the first
+/// `size` values are read, the zero padding of the last block is skipped.
+pub mod data {
     use super::*;
     use std::slice::Chunks;
 
-    pub fn chuncks_to_data_f64<'a>(
-        blocks: &mut Chunks<'a, u8>,
+    /// Read `size` big endian values of `N` bytes each from the blocks, converting them
+    /// with e.g. `i16::from_be_bytes`.
+    pub fn chunks_to_data<T, const N: usize>(
+        blocks: &mut Chunks<u8>,
         size: usize,
-        bytes: u64,
-    ) -> Vec<f64> {
-        // NOTE: assume we are reading '64' floats:
-        let mut data: Vec<f64> = Vec::with_capacity(size);
-        let mut rem = bytes;
+        from_be_bytes: fn([u8; N]) -> T,
+    ) -> Result<Vec<T>, FitsError> {
+        // The size comes from the header, so check it against the bytes there are before
+        // allocating
+        let mut rem = size.checked_mul(N).ok_or(FitsError::InvalidHeader)?;
+        if rem > blocks.len() * definitions::BLOCK_SIZE {
+            return Err(FitsError::UnexpectedEof);
+        }
+        let mut data: Vec<T> = Vec::with_capacity(size);
         while rem > 0 {
-            let block = blocks.next().unwrap();
-            let read = rem.min(definitions::BLOCK_SIZE as u64);
-            for (x, _i) in block.chunks_exact((64 / 8) as usize).zip(0..read / 8) {
-                // make of exact size
-                let bts = [x[0], x[1], x[2], x[3], x[4], x[5], x[6], x[7]];
-                let n = f64::from_be_bytes(bts);
-                data.push(n);
+            let block = blocks.next().ok_or(FitsError::UnexpectedEof)?;
+            let read = rem.min(definitions::BLOCK_SIZE);
+            if block.len() < read {
+                return Err(FitsError::UnexpectedEof);
+            }
+            // The block size is a multiple of every N, so values never span two blocks
+            for x in block[..read].chunks_exact(N) {
+                data.push(from_be_bytes(x.try_into().unwrap()));
             }
             rem -= read;
         }
-        data
+        Ok(data)
+    }
+
+    /// BITPIX 8: unsigned bytes.
+    pub fn chunks_to_data_u8(blocks: &mut Chunks<u8>, size: usize) -> Result<Vec<u8>, FitsError> {
+        chunks_to_data(blocks, size, u8::from_be_bytes)
+    }
+
+    pub fn chunks_to_data_i16(blocks: &mut Chunks<u8>, size: usize) -> Result<Vec<i16>, FitsError> {
+        chunks_to_data(blocks, size, i16::from_be_bytes)
+    }
+
+    pub fn chunks_to_data_i32(blocks: &mut Chunks<u8>, size: usize) -> Result<Vec<i32>, FitsError> {
+        chunks_to_data(blocks, size, i32::from_be_bytes)
+    }
+
+    pub fn chunks_to_data_i64(blocks: &mut Chunks<u8>, size: usize) -> Result<Vec<i64>, FitsError> {
+        chunks_to_data(blocks, size, i64::from_be_bytes)
+    }
+
+    pub fn chunks_to_data_f32(blocks: &mut Chunks<u8>, size: usize) -> Result<Vec<f32>, FitsError> {
+        chunks_to_data(blocks, size, f32::from_be_bytes)
+    }
+
+    pub fn chunks_to_data_f64(blocks: &mut Chunks<u8>, size: usize) -> Result<Vec<f64>, FitsError> {
+        chunks_to_data(blocks, size, f64::from_be_bytes)
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        // The values as one padded block, like the data of an HDU
+        fn block(values: &[&[u8]]) -> Vec<u8> {
+            let mut bytes = values.concat();
+            bytes.resize(definitions::BLOCK_SIZE, 0);
+            bytes
+        }
+
+        #[test]
+        fn chunks_to_data_integer_test() {
+            let bytes = block(&[&[0x00], &[0x7f], &[0xff]]);
+            let data = chunks_to_data_u8(&mut bytes.chunks(definitions::BLOCK_SIZE), 3);
+            assert_eq!(data.unwrap(), [0, 127, 255]);
+
+            let bytes = block(&[&[0x00, 0x00], &[0x01, 0x02], &[0xff, 0xfe], &[0x80, 0x00]]);
+            let data = chunks_to_data_i16(&mut bytes.chunks(definitions::BLOCK_SIZE), 4);
+            assert_eq!(data.unwrap(), [0, 258, -2, i16::MIN]);
+
+            let bytes = block(&[&[0, 0, 0, 0], &[0x00, 0x01, 0x00, 0x00], &[0xff; 4]]);
+            let data = chunks_to_data_i32(&mut bytes.chunks(definitions::BLOCK_SIZE), 3);
+            assert_eq!(data.unwrap(), [0, 65536, -1]);
+
+            let bytes = block(&[
+                &[0; 8],
+                &[0x7f, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff],
+                &[0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x9c],
+            ]);
+            let data = chunks_to_data_i64(&mut bytes.chunks(definitions::BLOCK_SIZE), 3);
+            assert_eq!(data.unwrap(), [0, i64::MAX, -100]);
+        }
+
+        #[test]
+        fn chunks_to_data_float_test() {
+            let bytes = block(&[
+                &[0; 4],
+                &[0x3f, 0xc0, 0x00, 0x00],
+                &[0xc1, 0x20, 0x00, 0x00],
+            ]);
+            let data = chunks_to_data_f32(&mut bytes.chunks(definitions::BLOCK_SIZE), 3);
+            assert_eq!(data.unwrap(), [0.0, 1.5, -10.0]);
+
+            let bytes = block(&[&[0; 8], &(-0.25f64).to_be_bytes(), &1e300f64.to_be_bytes()]);
+            let data = chunks_to_data_f64(&mut bytes.chunks(definitions::BLOCK_SIZE), 3);
+            assert_eq!(data.unwrap(), [0.0, -0.25, 1e300]);
+        }
+
+        #[test]
+        fn chunks_to_data_blocks_test() {
+            // Values continue in the next block, and the padding of the last one is ignored
+            let mut bytes: Vec<u8> = (0..definitions::BLOCK_SIZE / 2)
+                .flat_map(|i| (i as i16).to_be_bytes())
+                .collect();
+            bytes.extend(block(&[&[0xff, 0xff]]));
+            let n = definitions::BLOCK_SIZE / 2 + 1;
+            let data = chunks_to_data_i16(&mut bytes.chunks(definitions::BLOCK_SIZE), n).unwrap();
+            assert_eq!(data.len(), n);
+            assert_eq!(data[1439], 1439);
+            assert_eq!(data[1440], -1);
+
+            let data = chunks_to_data_i16(&mut bytes.chunks(definitions::BLOCK_SIZE), 2 * n);
+            assert!(matches!(data, Err(FitsError::UnexpectedEof)));
+
+            // Sizes from a hostile header fail before anything is allocated
+            let data = chunks_to_data_i16(&mut bytes.chunks(definitions::BLOCK_SIZE), 1 << 40);
+            assert!(matches!(data, Err(FitsError::UnexpectedEof)));
+            let data = chunks_to_data_i16(&mut bytes.chunks(definitions::BLOCK_SIZE), usize::MAX);
+            assert!(matches!(data, Err(FitsError::InvalidHeader)));
+        }
     }
 }
 
-pub fn read_fits_buffer<'a>(buffer: &'a Vec<u8>) -> Option<(Header, Option<GenericData<f64>>)> {
+/// Read the primary HDU from `buffer`.
+pub fn read_fits_buffer(buffer: &[u8]) -> Result<(Header, DataArray), FitsError> {
     let mut blocks = buffer.chunks(definitions::BLOCK_SIZE);
 
     // Read header (PrimaryHDU) must always exist
-    let header = header::parse_header(&mut blocks);
+    let header = header::parse_header(&mut blocks)?;
     let header = Header::from_keyword_list(header)?;
-    // let (_simple, _naxis, axes, bitpix) = header::extract_values(&header);
-    let bitpix = header.bitpix.to_int();
-    let axes = &header.axes;
-
-    // Calculate the total number of bytes
-    let bytes: u64 = (axes.iter().product::<usize>() as u64 * (bitpix.abs() as u64)) / 8;
-    // println!("Total bytes: {}", bytes);
-    let size = axes.iter().product::<usize>();
-
-    if bitpix == -64 {
-        let data = data::chuncks_to_data_f64(&mut blocks, size, bytes);
-        let data = Array1::from_vec(data).into_dyn();
-        // let data = Tensor::from(data);
-        // Move the parsed data into the array
-        // let arr = Array::from_vec(data);
-        // let arr = arr.into_shape(axes).unwrap();
-
-        // Print some random things
-        // println!("{:?} {} {}", arr.shape(), arr.sum(), arr.mean().unwrap());
-        return Some((header, Some(data)));
-    } else {
-        println!("Other data format; bitpix {}", bitpix);
-        Some((header, None))
+    let data = read_data(&header, &mut blocks)?;
+    Ok((header, data))
+}
+
+// Read the data described by `header` from the blocks following it
+pub(crate) fn read_data(header: &Header, blocks: &mut Chunks<u8>) -> Result<DataArray, FitsError> {
+    // NAXIS = 0: there is no data array, so none of the blocks belong to this HDU
+    if header.axes.is_empty() {
+        return Ok(DataArray::empty(&header.bitpix));
+    }
+    let size = header
+        .axes
+        .iter()
+        .try_fold(1usize, |size, &n| size.checked_mul(n))
+        .ok_or(FitsError::InvalidHeader)?;
+
+    fn to_array<T>(data: Vec<T>) -> GenericData<T> {
+        Array1::from_vec(data).into_dyn()
     }
+    let data = match header.bitpix {
+        Bitpix::Int8 => to_array(data::chunks_to_data(blocks, size, u8::from_be_bytes)?).into(),
+        Bitpix::Int16 => to_array(data::chunks_to_data(blocks, size, i16::from_be_bytes)?).into(),
+        Bitpix::Int32 => to_array(data::chunks_to_data(blocks, size, i32::from_be_bytes)?).into(),
+        Bitpix::Int64 => to_array(data::chunks_to_data(blocks, size, i64::from_be_bytes)?).into(),
+        Bitpix::Float32 => to_array(data::chunks_to_data(blocks, size, f32::from_be_bytes)?).into(),
+        Bitpix::Float64 => to_array(data::chunks_to_data(blocks, size, f64::from_be_bytes)?).into(),
+    };
+    Ok(data)
 }
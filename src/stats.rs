@@ -0,0 +1,107 @@
+// Summary statistics of data arrays.
+//
+// NaN values are skipped by all functions, so blank pixels (which become NaN in the
+// physical data) do not poison the result.
+
+use crate::image::median as median_in_place;
+use crate::GenericData;
+
+// Whether `x` can be compared, i.e. is not NaN
+fn comparable<T: PartialOrd>(x: &T) -> bool {
+    x.partial_cmp(x).is_some()
+}
+
+/// The smallest value of the data.
+///
+/// Panics if the data has no values other than NaN.
+pub fn min<T: PartialOrd + Copy>(data: &GenericData<T>) -> T {
+    data.iter()
+        .copied()
+        .filter(comparable)
+        .reduce(|a, b| if b < a { b } else { a })
+        .expect("minimum of data without values")
+}
+
+/// The largest value of the data.
+///
+/// Panics if the data has no values other than NaN.
+pub fn max<T: PartialOrd + Copy>(data: &GenericData<T>) -> T {
+    data.iter()
+        .copied()
+        .filter(comparable)
+        .reduce(|a, b| if b > a { b } else { a })
+        .expect("maximum of data without values")
+}
+
+/// The mean of the data, NaN if there are no values.
+pub fn mean(data: &GenericData<f64>) -> f64 {
+    let (sum, n) = data
+        .iter()
+        .filter(|x| !x.is_nan())
+        .fold((0., 0), |(sum, n), x| (sum + x, n + 1));
+    sum / n as f64
+}
+
+/// The (population) variance of the data, NaN if there are no values.
+pub fn variance(data: &GenericData<f64>) -> f64 {
+    let avg = mean(data);
+    let (sum, n) = data
+        .iter()
+        .filter(|x| !x.is_nan())
+        .fold((0., 0), |(sum, n), x| (sum + (x - avg).powi(2), n + 1));
+    sum / n as f64
+}
+
+/// The standard deviation of the data, NaN if there are no values.
+pub fn std_dev(data: &GenericData<f64>) -> f64 {
+    variance(data).sqrt()
+}
+
+/// The median of the data, NaN if there are no values. For an even number of values this
+/// is the mean of the two middle ones.
+pub fn median(data: &GenericData<f64>) -> f64 {
+    let mut values: Vec<f64> = data.iter().copied().filter(|x| !x.is_nan()).collect();
+    median_in_place(&mut values)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn data<T>(values: Vec<T>) -> GenericData<T> {
+        GenericData::from_shape_vec(vec![values.len()], values).unwrap()
+    }
+
+    #[test]
+    fn min_max_test() {
+        let ints = data(vec![3i16, -7, 12, 0]);
+        assert_eq!(min(&ints), -7);
+        assert_eq!(max(&ints), 12);
+
+        let floats = data(vec![f64::NAN, 2.5, -1.0, f64::NAN, 4.0]);
+        assert_eq!(min(&floats), -1.0);
+        assert_eq!(max(&floats), 4.0);
+    }
+
+    #[test]
+    #[should_panic]
+    fn min_empty_test() {
+        min(&data(vec![f64::NAN]));
+    }
+
+    #[test]
+    fn moments_test() {
+        let values = data(vec![2., 4., 4., 4., 5., 5., 7., 9., f64::NAN]);
+        assert_eq!(mean(&values), 5.0);
+        assert_eq!(variance(&values), 4.0);
+        assert_eq!(std_dev(&values), 2.0);
+        assert_eq!(median(&values), 4.5);
+
+        assert_eq!(median(&data(vec![3., 1., 2.])), 2.0);
+
+        let empty = data(Vec::<f64>::new());
+        assert!(mean(&empty).is_nan());
+        assert!(std_dev(&empty).is_nan());
+        assert!(median(&empty).is_nan());
+    }
+}
@@ -0,0 +1,209 @@
+// Metadata of the columns of binary table (BINTABLE) and ASCII table (TABLE) extensions.
+// See sections 7.2 and 7.3 of the FITS standard (2018).
+
+/// Data type of a binary table column, the letter code of its TFORMn keyword.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TFormType {
+    /// Logical
+    L,
+    /// Bit
+    X,
+    /// Unsigned byte
+    B,
+    /// 16-bit integer
+    I,
+    /// 32-bit integer
+    J,
+    /// 64-bit integer
+    K,
+    /// Character
+    A,
+    /// Single precision float
+    E,
+    /// Double precision float
+    D,
+    /// Single precision complex
+    C,
+    /// Double precision complex
+    M,
+}
+
+impl TFormType {
+    pub fn from_char(c: char) -> Option<Self> {
+        match c {
+            'L' => Some(Self::L),
+            'X' => Some(Self::X),
+            'B' => Some(Self::B),
+            'I' => Some(Self::I),
+            'J' => Some(Self::J),
+            'K' => Some(Self::K),
+            'A' => Some(Self::A),
+            'E' => Some(Self::E),
+            'D' => Some(Self::D),
+            'C' => Some(Self::C),
+            'M' => Some(Self::M),
+            _ => None,
+        }
+    }
+
+    /// Size of one element in bytes. Bits (`X`) are packed, 8 to a byte.
+    pub fn size(&self) -> usize {
+        match self {
+            Self::L | Self::X | Self::B | Self::A => 1,
+            Self::I => 2,
+            Self::J | Self::E => 4,
+            Self::K | Self::D | Self::C => 8,
+            Self::M => 16,
+        }
+    }
+}
+
+/// The format of a binary table column, as given by TFORMn.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TForm {
+    /// `rT`: `r` elements of type `T` in every row (a missing `r` means 1).
+    Fixed(usize, TFormType),
+    /// `rPT(max)` or `rQT(max)`: a variable length array stored in the heap.
+    Variable(TFormType),
+}
+
+impl TForm {
+    /// Parse the value of a TFORMn keyword, e.g. `"1J"`, `"20A"` or `"1PE(100)"`.
+    pub fn from_tform(tform: &str) -> Option<Self> {
+        let tform = tform.trim();
+        let digits = tform.find(|c: char| !c.is_ascii_digit())?;
+        let (repeat, rest) = tform.split_at(digits);
+        let repeat = if repeat.is_empty() {
+            1
+        } else {
+            repeat.parse().ok()?
+        };
+
+        let mut chars = rest.chars();
+        match chars.next()? {
+            'P' | 'Q' => {
+                let kind = TFormType::from_char(chars.next()?)?;
+                // The optional maximum length, e.g. '(100)', is not needed
+                let max = chars.as_str();
+                if max.is_empty() || (max.starts_with('(') && max.ends_with(')')) {
+                    Some(TForm::Variable(kind))
+                } else {
+                    None
+                }
+            }
+            c => {
+                let kind = TFormType::from_char(c)?;
+                // Anything after the type is an additional (non-standard) specification,
+                // e.g. the 'w' in 'rAw'
+                Some(TForm::Fixed(repeat, kind))
+            }
+        }
+    }
+}
+
+/// The format of an ASCII table column, as given by TFORMn: a Fortran format with the
+/// field width `w` and, for floats, the number of decimals `d`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AsciiTForm {
+    /// `Aw`: character string
+    A(usize),
+    /// `Iw`: integer
+    I(usize),
+    /// `Fw.d`: fixed point float
+    F(usize, usize),
+    /// `Ew.d`: single precision float with exponent
+    E(usize, usize),
+    /// `Dw.d`: double precision float with exponent
+    D(usize, usize),
+}
+
+impl AsciiTForm {
+    /// Parse the value of a TFORMn keyword of an ASCII table, e.g. `"A10"` or `"F8.3"`.
+    pub fn from_tform(tform: &str) -> Option<Self> {
+        let tform = tform.trim();
+        let mut chars = tform.chars();
+        let kind = chars.next()?;
+        let rest = chars.as_str();
+        let (width, decimals) = match rest.split_once('.') {
+            Some((w, d)) => (w.parse().ok()?, Some(d.parse().ok()?)),
+            None => (rest.parse().ok()?, None),
+        };
+        match (kind, decimals) {
+            ('A', None) => Some(Self::A(width)),
+            ('I', None) => Some(Self::I(width)),
+            ('F', Some(d)) => Some(Self::F(width, d)),
+            ('E', Some(d)) => Some(Self::E(width, d)),
+            ('D', Some(d)) => Some(Self::D(width, d)),
+            _ => None,
+        }
+    }
+
+    /// The field width in characters
+    pub fn width(&self) -> usize {
+        match *self {
+            Self::A(w) | Self::I(w) | Self::F(w, _) | Self::E(w, _) | Self::D(w, _) => w,
+        }
+    }
+}
+
+/// Description of one binary table column, collected from its TTYPEn, TFORMn, TUNITn,
+/// TNULLn, TSCALn and TZEROn keywords.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColumnDescriptor {
+    /// Column number, starting at 1 like the `n` of the keywords
+    pub index: usize,
+    /// TTYPEn, or an empty string if the column has no name
+    pub name: String,
+    pub format: TForm,
+    pub unit: Option<String>,
+    /// Integer value representing an undefined value
+    pub null_value: Option<i64>,
+    pub scale: Option<f64>,
+    pub zero: Option<f64>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tform_test() {
+        assert_eq!(TForm::from_tform("1J"), Some(TForm::Fixed(1, TFormType::J)));
+        assert_eq!(
+            TForm::from_tform("20A"),
+            Some(TForm::Fixed(20, TFormType::A))
+        );
+        assert_eq!(TForm::from_tform("3E"), Some(TForm::Fixed(3, TFormType::E)));
+        assert_eq!(TForm::from_tform("1D"), Some(TForm::Fixed(1, TFormType::D)));
+        assert_eq!(TForm::from_tform("D "), Some(TForm::Fixed(1, TFormType::D)));
+        assert_eq!(TForm::from_tform("0K"), Some(TForm::Fixed(0, TFormType::K)));
+        assert_eq!(
+            TForm::from_tform("1PE(100)"),
+            Some(TForm::Variable(TFormType::E))
+        );
+        assert_eq!(TForm::from_tform("QM"), Some(TForm::Variable(TFormType::M)));
+
+        assert_eq!(TForm::from_tform(""), None);
+        assert_eq!(TForm::from_tform("12"), None);
+        assert_eq!(TForm::from_tform("1Z"), None);
+        assert_eq!(TForm::from_tform("1PE100"), None);
+    }
+
+    #[test]
+    fn ascii_tform_test() {
+        assert_eq!(AsciiTForm::from_tform("A10"), Some(AsciiTForm::A(10)));
+        assert_eq!(AsciiTForm::from_tform("I6  "), Some(AsciiTForm::I(6)));
+        assert_eq!(AsciiTForm::from_tform("F8.3"), Some(AsciiTForm::F(8, 3)));
+        assert_eq!(AsciiTForm::from_tform("E15.7"), Some(AsciiTForm::E(15, 7)));
+        assert_eq!(
+            AsciiTForm::from_tform("D25.17"),
+            Some(AsciiTForm::D(25, 17))
+        );
+        assert_eq!(AsciiTForm::D(25, 17).width(), 25);
+
+        assert_eq!(AsciiTForm::from_tform(""), None);
+        assert_eq!(AsciiTForm::from_tform("F8"), None);
+        assert_eq!(AsciiTForm::from_tform("I6.2"), None);
+        assert_eq!(AsciiTForm::from_tform("1J"), None);
+    }
+}
@@ -0,0 +1,312 @@
+// World Coordinate System (WCS) of an image, as described by its header.
+// See section 8 of the FITS standard (2018) and Calabretta & Greisen (2002), paper II.
+
+use crate::header::Header;
+
+/// The simple, linear WCS given by CRPIXn, CRVALn, CDELTn, CTYPEn and the (deprecated)
+/// rotation CROTA2, or by a CDi_j matrix for the first two axes. Projections (e.g. the
+/// TAN of `RA---TAN`) and distortions (SIP, TPV) are ignored, which is a good
+/// approximation close to the reference pixel.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WcsLinear {
+    /// Reference pixel, in FITS pixel coordinates (the center of the first pixel is 1.0)
+    pub crpix: Vec<f64>,
+    /// World coordinates of the reference pixel
+    pub crval: Vec<f64>,
+    /// World coordinate increment per pixel
+    pub cdelt: Vec<f64>,
+    /// Axis types, e.g. `RA---TAN`, without trailing spaces
+    pub ctype: Vec<String>,
+    /// Rotation of the second axis in degrees
+    pub crota: f64,
+    /// The CDi_j matrix of the first two axes, if the header has one. It replaces CDELTn
+    /// and CROTA2 for those axes.
+    pub cd: Option<[[f64; 2]; 2]>,
+}
+
+impl WcsLinear {
+    /// Read the WCS of an image with NAXIS axes. Keywords that are absent get the defaults
+    /// of the standard (CRPIXn and CRVALn 0, CDELTn 1, CTYPEn empty). `None` if the header
+    /// has none of CRPIXn, CRVALn and CTYPEn.
+    ///
+    /// With at least two axes, any of CD1_1, CD1_2, CD2_1 and CD2_2 selects the CD matrix
+    /// convention, in which missing elements are 0.
+    pub fn from_header(header: &Header) -> Option<Self> {
+        let naxis = header.axes.len();
+        let keys =
+            |key: &str| -> Vec<String> { (1..=naxis).map(|n| format!("{}{}", key, n)).collect() };
+        let floats = |key: &str, default: f64| -> Vec<f64> {
+            keys(key)
+                .iter()
+                .map(|k| header.get_float(k).unwrap_or(default))
+                .collect()
+        };
+
+        let has_wcs = ["CRPIX", "CRVAL", "CTYPE"]
+            .iter()
+            .any(|key| keys(key).iter().any(|k| header.contains(k)));
+        if !has_wcs {
+            return None;
+        }
+        Some(WcsLinear {
+            crpix: floats("CRPIX", 0.),
+            crval: floats("CRVAL", 0.),
+            cdelt: floats("CDELT", 1.),
+            ctype: keys("CTYPE")
+                .iter()
+                .map(|k| header.get_str(k).unwrap_or("").trim_end().to_string())
+                .collect(),
+            crota: header.get_float("CROTA2").unwrap_or(0.),
+            cd: cd_matrix(header),
+        })
+    }
+
+    /// The linear transformation of the first two axes as a CD matrix: the CD matrix of the
+    /// header, or else the one equivalent to CDELT1, CDELT2 and CROTA2.
+    pub fn to_cd_matrix(&self) -> [[f64; 2]; 2] {
+        if let Some(cd) = self.cd {
+            return cd;
+        }
+        let cdelt = |i: usize| self.cdelt.get(i).copied().unwrap_or(1.);
+        let (sin, cos) = self.crota.to_radians().sin_cos();
+        [
+            [cdelt(0) * cos, -cdelt(1) * sin],
+            [cdelt(0) * sin, cdelt(1) * cos],
+        ]
+    }
+
+    /// The transformation of the first two axes as `(CDELT1, CDELT2, CROTA2)`, the inverse
+    /// of [`WcsLinear::to_cd_matrix`]. CDELT2 is taken positive, so a flipped axis shows as a
+    /// negative CDELT1. A CD matrix with skew can not be represented like this, the result
+    /// is then only an approximation.
+    pub fn to_cdelt_crota(&self) -> (f64, f64, f64) {
+        let [[cd11, cd12], [cd21, cd22]] = self.to_cd_matrix();
+        let crota = (-cd12).atan2(cd22);
+        let (sin, cos) = crota.sin_cos();
+        let cdelt1 = cd11 * cos + cd21 * sin;
+        let cdelt2 = cd12.hypot(cd22);
+        (cdelt1, cdelt2, crota.to_degrees())
+    }
+
+    /// The world coordinates of a position given in FITS pixel coordinates, one for every
+    /// axis. The first two axes are transformed by the CD matrix (see
+    /// [`WcsLinear::to_cd_matrix`]), the others are scaled by CDELTn:
+    ///
+    /// `x = CD1_1 * dx1 + CD1_2 * dx2`
+    /// `y = CD2_1 * dx1 + CD2_2 * dx2`
+    ///
+    /// where `dxn = pixel[n] - CRPIXn`. Without a CD matrix in the header, CROTA2 rotates the
+    /// first two axes following the AIPS convention, i.e. `CD1_1 = CDELT1 * cos(CROTA2)`,
+    /// `CD1_2 = -CDELT2 * sin(CROTA2)`, `CD2_1 = CDELT1 * sin(CROTA2)` and
+    /// `CD2_2 = CDELT2 * cos(CROTA2)`.
+    ///
+    /// # Panics
+    /// When `pixel` does not have one coordinate per axis.
+    pub fn pixel_to_world(&self, pixel: &[f64]) -> Vec<f64> {
+        assert_eq!(
+            pixel.len(),
+            self.crpix.len(),
+            "expected one pixel coordinate per axis"
+        );
+        let mut offset: Vec<f64> = pixel
+            .iter()
+            .zip(self.crpix.iter())
+            .map(|(p, crpix)| p - crpix)
+            .collect();
+        let mut scaled = 0;
+        if let [x, y, ..] = offset[..] {
+            let [[cd11, cd12], [cd21, cd22]] = self.to_cd_matrix();
+            offset[0] = cd11 * x + cd12 * y;
+            offset[1] = cd21 * x + cd22 * y;
+            scaled = 2;
+        }
+        for (offset, cdelt) in offset.iter_mut().zip(self.cdelt.iter()).skip(scaled) {
+            *offset *= cdelt;
+        }
+        offset
+            .iter()
+            .zip(self.crval.iter())
+            .map(|(offset, crval)| crval + offset)
+            .collect()
+    }
+
+    /// The FITS pixel coordinates of a position given in world coordinates, the inverse of
+    /// [`WcsLinear::pixel_to_world`]. A singular CD matrix, or a CDELTn of 0, gives
+    /// infinite or NaN coordinates.
+    ///
+    /// # Panics
+    /// When `world` does not have one coordinate per axis.
+    pub fn world_to_pixel(&self, world: &[f64]) -> Vec<f64> {
+        assert_eq!(
+            world.len(),
+            self.crval.len(),
+            "expected one world coordinate per axis"
+        );
+        let mut offset: Vec<f64> = world
+            .iter()
+            .zip(self.crval.iter())
+            .map(|(w, crval)| w - crval)
+            .collect();
+        let mut scaled = 0;
+        if let [x, y, ..] = offset[..] {
+            let [[cd11, cd12], [cd21, cd22]] = self.to_cd_matrix();
+            let det = cd11 * cd22 - cd12 * cd21;
+            offset[0] = (cd22 * x - cd12 * y) / det;
+            offset[1] = (cd11 * y - cd21 * x) / det;
+            scaled = 2;
+        }
+        for (offset, cdelt) in offset.iter_mut().zip(self.cdelt.iter()).skip(scaled) {
+            *offset /= cdelt;
+        }
+        offset
+            .iter()
+            .zip(self.crpix.iter())
+            .map(|(offset, crpix)| crpix + offset)
+            .collect()
+    }
+}
+
+// The CDi_j matrix of the first two axes, if the header has any of its elements
+fn cd_matrix(header: &Header) -> Option<[[f64; 2]; 2]> {
+    if header.axes.len() < 2 {
+        return None;
+    }
+    let keys = [["CD1_1", "CD1_2"], ["CD2_1", "CD2_2"]];
+    if !keys.iter().flatten().any(|key| header.contains(key)) {
+        return None;
+    }
+    Some(keys.map(|row| row.map(|key| header.get_float(key).unwrap_or(0.))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header(wcs: &str) -> Header {
+        let axes = "SIMPLE  =                    T\n\
+                    BITPIX  =                  -32\n\
+                    NAXIS   =                    2\n\
+                    NAXIS1  =                  100\n\
+                    NAXIS2  =                  200\n";
+        Header::from_fits_text(&(axes.to_string() + wcs)).unwrap()
+    }
+
+    #[test]
+    fn from_header_test() {
+        let wcs = WcsLinear::from_header(&header(
+            "CTYPE1  = 'RA---TAN'\n\
+             CTYPE2  = 'DEC--TAN'\n\
+             CRPIX1  =                 50.5\n\
+             CRPIX2  =                  100\n\
+             CRVAL1  =                 10.0\n\
+             CDELT1  =              -0.0001\n\
+             CROTA2  =                 30.0\n",
+        ))
+        .unwrap();
+        assert_eq!(wcs.ctype, ["RA---TAN", "DEC--TAN"]);
+        assert_eq!(wcs.crpix, [50.5, 100.]);
+        assert_eq!(wcs.crval, [10., 0.]);
+        assert_eq!(wcs.cdelt, [-0.0001, 1.]);
+        assert_eq!(wcs.crota, 30.);
+        assert_eq!(wcs.cd, None);
+
+        assert_eq!(WcsLinear::from_header(&header("")), None);
+    }
+
+    #[test]
+    fn cd_matrix_test() {
+        let wcs = WcsLinear::from_header(&header(
+            "CRPIX1  =                   10\n\
+             CRPIX2  =                   20\n\
+             CRVAL1  =                100.0\n\
+             CRVAL2  =                -30.0\n\
+             CD1_1   =                 -0.5\n\
+             CD2_2   =                 0.25\n\
+             CDELT1  =                 99.0\n",
+        ))
+        .unwrap();
+        assert_eq!(wcs.cd, Some([[-0.5, 0.], [0., 0.25]]));
+        // CDELT1 is ignored in favour of the matrix
+        assert_eq!(wcs.pixel_to_world(&[12., 24.]), [99., -29.]);
+        assert_eq!(wcs.to_cdelt_crota(), (-0.5, 0.25, 0.));
+
+        // The matrix of a rotation gives the same coordinates as CDELTn and CROTA2
+        let mut rotated = WcsLinear {
+            crpix: vec![10., 20.],
+            crval: vec![100., -30.],
+            cdelt: vec![-0.5, 0.25],
+            ctype: vec![String::new(); 2],
+            crota: 30.,
+            cd: None,
+        };
+        let expected = rotated.pixel_to_world(&[12., 24.]);
+        rotated.cd = Some(rotated.to_cd_matrix());
+        let world = rotated.pixel_to_world(&[12., 24.]);
+        assert!(world
+            .iter()
+            .zip(&expected)
+            .all(|(a, b)| (a - b).abs() < 1e-12));
+
+        let (cdelt1, cdelt2, crota) = rotated.to_cdelt_crota();
+        assert!((cdelt1 - -0.5).abs() < 1e-12);
+        assert!((cdelt2 - 0.25).abs() < 1e-12);
+        assert!((crota - 30.).abs() < 1e-12);
+    }
+
+    #[test]
+    fn pixel_to_world_test() {
+        let mut wcs = WcsLinear {
+            crpix: vec![10., 20.],
+            crval: vec![100., -30.],
+            cdelt: vec![-0.5, 0.25],
+            ctype: vec![String::new(); 2],
+            crota: 0.,
+            cd: None,
+        };
+        assert_eq!(wcs.pixel_to_world(&[10., 20.]), [100., -30.]);
+        assert_eq!(wcs.pixel_to_world(&[12., 24.]), [99., -29.]);
+
+        // A quarter turn maps the second axis onto the first
+        wcs.crota = 90.;
+        let world = wcs.pixel_to_world(&[12., 24.]);
+        assert!((world[0] - 99.).abs() < 1e-12);
+        assert!((world[1] - -31.).abs() < 1e-12);
+
+        let wcs = WcsLinear {
+            crpix: vec![1.],
+            crval: vec![5000.],
+            cdelt: vec![2.5],
+            ctype: vec!["WAVE".to_string()],
+            crota: 45.,
+            cd: None,
+        };
+        assert_eq!(wcs.pixel_to_world(&[3.]), [5005.]);
+    }
+
+    #[test]
+    fn world_to_pixel_test() {
+        let mut wcs = WcsLinear {
+            crpix: vec![10., 20., 1.],
+            crval: vec![100., -30., 5000.],
+            cdelt: vec![-0.5, 0.25, 2.5],
+            ctype: vec![String::new(); 3],
+            crota: 0.,
+            cd: None,
+        };
+        assert_eq!(wcs.world_to_pixel(&[99., -29., 5005.]), [12., 24., 3.]);
+
+        // Also the inverse of a rotation and of a CD matrix with skew
+        wcs.crota = 30.;
+        let pixel = wcs.world_to_pixel(&wcs.pixel_to_world(&[12., 24., 3.]));
+        assert!(pixel
+            .iter()
+            .zip(&[12., 24., 3.])
+            .all(|(a, b)| (a - b).abs() < 1e-12));
+        wcs.cd = Some([[-0.5, 0.1], [0.05, 0.25]]);
+        let pixel = wcs.world_to_pixel(&wcs.pixel_to_world(&[12., 24., 3.]));
+        assert!(pixel
+            .iter()
+            .zip(&[12., 24., 3.])
+            .all(|(a, b)| (a - b).abs() < 1e-12));
+    }
+}
@@ -0,0 +1,348 @@
+// Serialization of headers and data into FITS blocks, the inverse of `parsing`.
+use crate::definitions::{
+    BLOCK_SIZE, HEADER_COMMENT_KEYWORD, HEADER_CONTINUE_KEYWORD, HEADER_END_KEYWORD,
+    HEADER_HIERARCH_KEYWORD, HEADER_HISTORY_KEYWORD, HEADER_KEYWORD_NAME_SIZE, HEADER_KEYWORD_SIZE,
+    HEADER_VALUE_INDICATOR,
+};
+use crate::parsing::header::{validate_keyword_name, Keyword, Value};
+use crate::{DataArray, FitsError, KeywordList};
+
+// Room for a string value (including its quotes) after "NAME    = "
+const STRING_SIZE: usize = HEADER_KEYWORD_SIZE - HEADER_KEYWORD_NAME_SIZE - 2;
+// Room for the text of a COMMENT or HISTORY card
+const TEXT_SIZE: usize = HEADER_KEYWORD_SIZE - HEADER_KEYWORD_NAME_SIZE;
+
+/// The 80 character records of a keyword. Usually this is a single record, but long
+/// strings are split over CONTINUE records and long COMMENT and HISTORY text over
+/// multiple records. Comments that do not fit are truncated.
+pub fn keyword_to_records(keyword: &Keyword) -> Vec<String> {
+    match keyword {
+        Keyword::History(text) => text_records(HEADER_HISTORY_KEYWORD, text),
+        Keyword::Comment(text) => text_records(HEADER_COMMENT_KEYWORD, text),
+        Keyword::Value(name, Value::Str(s), comment) => string_records(name, s, comment),
+        Keyword::Value(name, _value, _comment) if name.is_empty() => {
+            // A blank record
+            vec![format!("{:80}", "")]
+        }
+        Keyword::Value(_, _, comment)
+        | Keyword::Hierarch(_, _, comment)
+        | Keyword::Continue(_, _, comment) => vec![with_comment(bare_record(keyword), comment)],
+    }
+}
+
+/// The single 80 byte record (card) of a keyword. A comment that does not fit is truncated.
+///
+/// Fails with [`FitsError::ValueTooLong`] if the keyword needs more than one record, e.g. a
+/// string that would be continued over CONTINUE records or a long COMMENT text.
+pub fn keyword_to_card(keyword: &Keyword) -> Result<[u8; HEADER_KEYWORD_SIZE], FitsError> {
+    let too_long = || FitsError::ValueTooLong(keyword.keyword_name().to_string());
+    let single = |records: Vec<String>| match <[String; 1]>::try_from(records) {
+        Ok([record]) => Ok(record),
+        Err(_) => Err(too_long()),
+    };
+    let record = match keyword {
+        Keyword::History(_) | Keyword::Comment(_) => single(keyword_to_records(keyword))?,
+        Keyword::Value(name, _, _) if name.is_empty() => single(keyword_to_records(keyword))?,
+        Keyword::Value(name, Value::Str(s), comment) => {
+            with_comment(single(string_records(name, s, ""))?, comment)
+        }
+        Keyword::Value(_, _, comment)
+        | Keyword::Hierarch(_, _, comment)
+        | Keyword::Continue(_, _, comment) => {
+            let record = bare_record(keyword);
+            if record.len() > HEADER_KEYWORD_SIZE {
+                return Err(too_long());
+            }
+            with_comment(record, comment)
+        }
+    };
+    if record.len() > HEADER_KEYWORD_SIZE {
+        return Err(too_long());
+    }
+    let mut card = [b' '; HEADER_KEYWORD_SIZE];
+    card[..record.len()].copy_from_slice(record.as_bytes());
+    Ok(card)
+}
+
+// The record of a keyword with a value, without the comment. Other keywords have none.
+fn bare_record(keyword: &Keyword) -> String {
+    match keyword {
+        Keyword::Value(name, value, _) => {
+            let value = format_value(value);
+            format!("{:8}{}{:>20}", name, HEADER_VALUE_INDICATOR, value)
+        }
+        Keyword::Hierarch(name, value, _) => format!(
+            "{} {} = {}",
+            HEADER_HIERARCH_KEYWORD,
+            name,
+            format_value(value)
+        ),
+        Keyword::Continue(_, value, _) => {
+            format!("{:10}{}", HEADER_CONTINUE_KEYWORD, format_value(value))
+        }
+        Keyword::History(_) | Keyword::Comment(_) => String::new(),
+    }
+}
+
+// Fails with InvalidKeywordName for the first keyword with a name that can not be written
+pub(crate) fn check_keyword_names(keywords: &KeywordList) -> Result<(), FitsError> {
+    for kw in keywords.iter() {
+        match kw {
+            Keyword::Value(name, _v, _c) if !name.is_empty() => validate_keyword_name(name, false)?,
+            Keyword::Hierarch(name, _v, _c) => validate_keyword_name(name, true)?,
+            _ => (),
+        }
+    }
+    Ok(())
+}
+
+/// The header blocks for `keywords`: their records followed by END, padded with spaces to
+/// a multiple of 2880 bytes.
+pub fn header_to_bytes(keywords: &KeywordList) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    let records = keywords.iter().flat_map(keyword_to_records);
+    for record in records.chain(std::iter::once(HEADER_END_KEYWORD.to_string())) {
+        let mut record = record.into_bytes();
+        record.resize(HEADER_KEYWORD_SIZE, b' ');
+        bytes.extend_from_slice(&record);
+    }
+    bytes.resize(bytes.len().div_ceil(BLOCK_SIZE) * BLOCK_SIZE, b' ');
+    bytes
+}
+
+/// The data blocks: all values in big endian order, padded with zeros to a multiple of
+/// 2880 bytes. Empty data has no blocks at all.
+pub fn data_to_bytes(data: &DataArray) -> Vec<u8> {
+    let mut bytes: Vec<u8> = match data {
+        DataArray::U8(d) => d.iter().cloned().collect(),
+        DataArray::I16(d) => d.iter().flat_map(|x| x.to_be_bytes()).collect(),
+        // Unsigned values are stored with BZERO subtracted, i.e. with the sign bit flipped
+        DataArray::U16(d) => d.iter().flat_map(|x| (x ^ 0x8000).to_be_bytes()).collect(),
+        DataArray::I32(d) => d.iter().flat_map(|x| x.to_be_bytes()).collect(),
+        DataArray::U32(d) => d
+            .iter()
+            .flat_map(|x| (x ^ 0x8000_0000).to_be_bytes())
+            .collect(),
+        DataArray::I64(d) => d.iter().flat_map(|x| x.to_be_bytes()).collect(),
+        DataArray::F32(d) => d.iter().flat_map(|x| x.to_be_bytes()).collect(),
+        DataArray::F64(d) => d.iter().flat_map(|x| x.to_be_bytes()).collect(),
+    };
+    bytes.resize(bytes.len().div_ceil(BLOCK_SIZE) * BLOCK_SIZE, 0);
+    bytes
+}
+
+// Fixed format value, to be right justified in columns 11-30. This is the Display form of
+// the value without its padding, except that an undefined value is left out.
+fn format_value(value: &Value) -> String {
+    match value {
+        Value::Undefined => String::new(),
+        value => value.to_string().trim_start().to_string(),
+    }
+}
+
+fn with_comment(mut record: String, comment: &str) -> String {
+    if !comment.is_empty() {
+        record.push_str(" / ");
+        record.push_str(comment);
+    }
+    if record.len() > HEADER_KEYWORD_SIZE {
+        let end = (0..=HEADER_KEYWORD_SIZE)
+            .rev()
+            .find(|&i| record.is_char_boundary(i));
+        record.truncate(end.unwrap_or(0));
+    }
+    record
+}
+
+// A string value, continued over CONTINUE records when it does not fit in one. Every part
+// but the last ends with '&'. A comment that does not fit after the last part is continued
+// as well, in records with an empty string: `CONTINUE  '&' / comment`.
+fn string_records(name: &str, s: &str, comment: &str) -> Vec<String> {
+    let mut parts = vec![String::new()];
+    for c in s.chars() {
+        let escaped = if c == '\'' {
+            "''".to_string()
+        } else {
+            c.to_string()
+        };
+        let part = parts.last_mut().unwrap();
+        // Leave room for the quotes and the '&'
+        if part.len() + escaped.len() > STRING_SIZE - 3 {
+            parts.push(escaped);
+        } else {
+            part.push_str(&escaped);
+        }
+    }
+
+    let record = |i: usize, part: &str| {
+        if i == 0 {
+            format!("{:8}{}'{}'", name, HEADER_VALUE_INDICATOR, part)
+        } else {
+            format!("{:10}'{}'", HEADER_CONTINUE_KEYWORD, part)
+        }
+    };
+    let last = parts.len() - 1;
+    let mut records: Vec<String> = parts[..last]
+        .iter()
+        .enumerate()
+        .map(|(i, part)| record(i, &format!("{}&", part)))
+        .collect();
+
+    let end = record(last, &parts[last]);
+    if comment.is_empty() || end.len() + 3 + comment.len() <= HEADER_KEYWORD_SIZE {
+        records.push(with_comment(end, comment));
+        return records;
+    }
+    records.push(record(last, &format!("{}&", parts[last])));
+    let chunks = comment_chunks(comment, HEADER_KEYWORD_SIZE - record(1, "&").len() - 3);
+    let last = chunks.len() - 1;
+    for (i, chunk) in chunks.into_iter().enumerate() {
+        let part = if i == last { "" } else { "&" };
+        records.push(with_comment(record(1, part), chunk));
+    }
+    records
+}
+
+// Split a comment into pieces of at most `size` bytes. The parser trims comments, so the
+// pieces are split between two non-space characters where possible.
+fn comment_chunks(comment: &str, size: usize) -> Vec<&str> {
+    let mut chunks = Vec::new();
+    let mut rest = comment;
+    while rest.len() > size {
+        let fits = |i: usize| rest.is_char_boundary(i);
+        let keeps_spaces =
+            |i: usize| fits(i) && !rest[..i].ends_with(' ') && !rest[i..].starts_with(' ');
+        let split = (1..=size)
+            .rev()
+            .find(|&i| keeps_spaces(i))
+            .or_else(|| (1..=size).rev().find(|&i| fits(i)))
+            .unwrap_or(rest.len());
+        let (chunk, tail) = rest.split_at(split);
+        chunks.push(chunk);
+        rest = tail;
+    }
+    chunks.push(rest);
+    chunks
+}
+
+fn text_records(name: &str, text: &str) -> Vec<String> {
+    let chars: Vec<char> = text.chars().collect();
+    if chars.is_empty() {
+        return vec![name.to_string()];
+    }
+    chars
+        .chunks(TEXT_SIZE)
+        .map(|chunk| format!("{:8}{}", name, chunk.iter().collect::<String>()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn value(name: &str, value: Value, comment: &str) -> Keyword {
+        Keyword::Value(name.to_string(), value, comment.to_string())
+    }
+
+    #[test]
+    fn keyword_to_records_test() {
+        let records = keyword_to_records(&value("NAXIS", Value::Integer(2), "dimensions"));
+        assert_eq!(records, ["NAXIS   =                    2 / dimensions"]);
+
+        let records = keyword_to_records(&value("SIMPLE", Value::Boolean(true), ""));
+        assert_eq!(records, ["SIMPLE  =                    T"]);
+
+        let records = keyword_to_records(&value("EXPTIME", Value::Float(30.), ""));
+        assert_eq!(records, ["EXPTIME =                 30.0"]);
+        let records = keyword_to_records(&value("GAIN", Value::Float(1.5e-20), ""));
+        assert_eq!(records, ["GAIN    =              1.5E-20"]);
+
+        let records = keyword_to_records(&value("OBJECT", Value::Str("M'31".into()), "x"));
+        assert_eq!(records, ["OBJECT  = 'M''31' / x"]);
+
+        let records = keyword_to_records(&value("CGAIN", Value::ComplexFloat(1., -0.5), ""));
+        assert_eq!(records, ["CGAIN   =          (1.0, -0.5)"]);
+
+        let kw = Keyword::Hierarch("ESO DET WIN NX".into(), Value::Integer(512), "".into());
+        assert_eq!(keyword_to_records(&kw), ["HIERARCH ESO DET WIN NX = 512"]);
+
+        let records = keyword_to_records(&Keyword::History("flat fielded".into()));
+        assert_eq!(records, ["HISTORY flat fielded"]);
+
+        let records = keyword_to_records(&value("LONGSTR", Value::Str("a".repeat(100)), "c"));
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0], format!("LONGSTR = '{}&'", "a".repeat(67)));
+        assert_eq!(records[1], format!("CONTINUE  '{}' / c", "a".repeat(33)));
+        assert!(records.iter().all(|r| r.len() <= HEADER_KEYWORD_SIZE));
+
+        let comment = format!("{} {}", "b".repeat(63), "c".repeat(10));
+        let records = keyword_to_records(&value("LONGSTR", Value::Str("a".into()), &comment));
+        assert_eq!(
+            records,
+            [
+                "LONGSTR = 'a&'".to_string(),
+                format!("CONTINUE  '&' / {}", "b".repeat(62)),
+                format!("CONTINUE  '' / b {}", "c".repeat(10)),
+            ]
+        );
+    }
+
+    #[test]
+    fn keyword_to_card_test() {
+        let card = |kw: Keyword| String::from_utf8(kw.to_card().unwrap().to_vec()).unwrap();
+        let expected = |record: &str| format!("{:80}", record);
+
+        let kw = value("NAXIS", Value::Integer(2), "dimensions");
+        assert_eq!(
+            card(kw),
+            expected("NAXIS   =                    2 / dimensions")
+        );
+        let kw = value("OBJECT", Value::Str("M31".into()), "");
+        assert_eq!(card(kw), expected("OBJECT  = 'M31'"));
+        let kw = Keyword::Comment("a comment".into());
+        assert_eq!(card(kw), expected("COMMENT a comment"));
+        let kw = Keyword::History("flat fielded".into());
+        assert_eq!(card(kw), expected("HISTORY flat fielded"));
+        let kw = Keyword::Continue(String::new(), Value::Str("end".into()), String::new());
+        assert_eq!(card(kw), expected("CONTINUE  'end'"));
+        assert_eq!(card(value("", Value::Undefined, "")), expected(""));
+
+        // The comment is truncated, but the value has to fit
+        let kw = value("OBJECT", Value::Str("a".repeat(60)), &"c".repeat(30));
+        let text = card(kw);
+        assert_eq!(text.len(), HEADER_KEYWORD_SIZE);
+        assert!(text.starts_with(&format!("OBJECT  = '{}' / ccc", "a".repeat(60))));
+
+        for kw in [
+            value("OBJECT", Value::Str("a".repeat(100)), ""),
+            Keyword::Comment("b".repeat(100)),
+            Keyword::Hierarch("A".repeat(70), Value::Integer(1), String::new()),
+        ] {
+            assert!(matches!(kw.to_card(), Err(FitsError::ValueTooLong(_))));
+        }
+    }
+
+    #[test]
+    fn comment_chunks_test() {
+        assert_eq!(comment_chunks("abc", 5), ["abc"]);
+        assert_eq!(comment_chunks("abcdefg", 3), ["abc", "def", "g"]);
+        // Never split next to a space, as those would be trimmed
+        assert_eq!(comment_chunks("ab cd ef", 4), ["ab c", "d ef"]);
+        assert_eq!(comment_chunks("abc  def", 4), ["ab", "c  d", "ef"]);
+    }
+
+    #[test]
+    fn header_to_bytes_test() {
+        let keywords = vec![value("SIMPLE", Value::Boolean(true), "")];
+        let bytes = header_to_bytes(&keywords);
+        assert_eq!(bytes.len(), BLOCK_SIZE);
+        assert_eq!(
+            &bytes[HEADER_KEYWORD_SIZE..2 * HEADER_KEYWORD_SIZE],
+            crate::definitions::HEADER_END_KEYWORD_FULL
+        );
+
+        // 36 records fit in a block, so END goes into the next one
+        let keywords = vec![Keyword::Comment("x".into()); 36];
+        assert_eq!(header_to_bytes(&keywords).len(), 2 * BLOCK_SIZE);
+    }
+}